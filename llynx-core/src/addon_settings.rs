@@ -0,0 +1,605 @@
+//! settings addons ship in a `config.json`, in the format of
+//! [LLS-Addons](https://github.com/LuaLS/LLS-Addons), and the `plugin.lua`
+//! some of them have, which have to be wired into the settings file for the
+//! addon to fully work
+
+use crate::{
+    Addon, Config, LIB_SETTINGS_KEY,
+    enabled::{entry_path, read_other_settings, update_settings},
+    failure::Failure,
+    history::LibraryChange,
+    workspace::{self, WorkspaceState},
+};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// the setting pointing LuaLS at a plugin. There is only one
+pub const PLUGIN_KEY: &str = "Lua.runtime.plugin";
+
+/// what to do when two enabled addons set the same setting to different
+/// values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// keep the value of the addon enabled first
+    #[default]
+    FirstWins,
+
+    /// use the value of the addon enabled last
+    LastWins,
+
+    /// refuse to enable the second addon
+    Error,
+}
+
+/// decide whether `entry` may overwrite `key`, which `owner` set already
+fn settle(key: &str, owner: &str, entry: &str, policy: ConflictPolicy) -> Result<bool> {
+    match policy {
+        ConflictPolicy::FirstWins => {
+            log::warn!("'{entry}' and '{owner}' both set '{key}', keeping the value of '{owner}'");
+            Ok(false)
+        }
+        ConflictPolicy::LastWins => {
+            log::warn!("'{entry}' and '{owner}' both set '{key}', using the value of '{entry}'");
+            Ok(true)
+        }
+        ConflictPolicy::Error => bail!("'{entry}' and '{owner}' both set '{key}'"),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AddonConfig {
+    #[serde(default)]
+    settings: Map<String, Value>,
+}
+
+/// where `file` of the addon whose types are at `location` would be. Addons
+/// keep it either inside `types` or next to it
+fn addon_file_paths(types: &Path, file: &str) -> Vec<PathBuf> {
+    let mut paths = vec![types.join(file)];
+    if let Some(version_dir) = types.parent() {
+        paths.push(version_dir.join(file));
+    }
+    paths
+}
+
+/// the `plugin.lua` of the addon whose types are at `location`, if it has one
+pub fn plugin(location: &Path) -> Option<PathBuf> {
+    addon_file_paths(location, "plugin.lua")
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+/// the settings the addon whose types are at `location` asks for, if it has a
+/// `config.json`
+pub fn read(location: &Path) -> Result<Option<Map<String, Value>>> {
+    for path in addon_file_paths(location, "config.json") {
+        let contents = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            result => result.with_context(|| format!("while reading '{}'", path.display()))?,
+        };
+        let config: AddonConfig = serde_json::from_str(&contents).with_context(|| {
+            Failure::Settings.tag(format!("while parsing '{}'", path.display()))
+        })?;
+        return Ok(Some(config.settings));
+    }
+    Ok(None)
+}
+
+/// merge `addon_settings` of `entry` into `settings` like the LuaLS addon
+/// manager does. Arrays gain the values they are missing, objects gain their
+/// keys, and anything else is overwritten, unless `owners` says another addon
+/// set it and `policy` keeps it. Returns what was added, so it can be taken
+/// out again with [`unmerge`]
+pub(crate) fn merge(
+    settings: &mut Map<String, Value>,
+    addon_settings: &Map<String, Value>,
+    entry: &str,
+    owners: &BTreeMap<String, String>,
+    policy: ConflictPolicy,
+) -> Result<Map<String, Value>> {
+    let mut applied = Map::new();
+    for (key, value) in addon_settings {
+        if key == LIB_SETTINGS_KEY {
+            continue;
+        }
+        match (settings.get_mut(key), value) {
+            (Some(Value::Array(existing)), Value::Array(values)) => {
+                let added: Vec<Value> = values
+                    .iter()
+                    .filter(|value| !existing.contains(value))
+                    .cloned()
+                    .collect();
+                if !added.is_empty() {
+                    existing.extend(added.iter().cloned());
+                    applied.insert(key.clone(), Value::Array(added));
+                }
+            }
+            (Some(Value::Object(existing)), Value::Object(values)) => {
+                let added: Map<String, Value> = values
+                    .iter()
+                    .filter(|(inner_key, inner_value)| {
+                        existing.get(*inner_key) != Some(inner_value)
+                    })
+                    .map(|(inner_key, inner_value)| (inner_key.clone(), inner_value.clone()))
+                    .collect();
+                if !added.is_empty() {
+                    existing.extend(added.clone());
+                    applied.insert(key.clone(), Value::Object(added));
+                }
+            }
+            (Some(existing), value) if existing != value => {
+                match owners.get(key) {
+                    Some(owner) if !settle(key, owner, entry, policy)? => continue,
+                    Some(_) => {}
+                    None => log::warn!("overwriting '{key}', which was {existing}, with {value}"),
+                }
+                *existing = value.clone();
+                applied.insert(key.clone(), value.clone());
+            }
+            (Some(_), _) => {}
+            (None, value) => {
+                settings.insert(key.clone(), value.clone());
+                applied.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(applied)
+}
+
+/// take what [`merge`] added out of `settings` again. Anything changed since
+/// is left alone, and arrays and objects left empty are removed
+pub(crate) fn unmerge(settings: &mut Map<String, Value>, applied: &Map<String, Value>) {
+    for (key, value) in applied {
+        let now_empty = match (settings.get_mut(key), value) {
+            (Some(Value::Array(existing)), Value::Array(values)) => {
+                existing.retain(|value| !values.contains(value));
+                existing.is_empty()
+            }
+            (Some(Value::Object(existing)), Value::Object(values)) => {
+                for (inner_key, inner_value) in values {
+                    if existing.get(inner_key) == Some(inner_value) {
+                        existing.remove(inner_key);
+                    }
+                }
+                existing.is_empty()
+            }
+            (Some(existing), value) => existing == value,
+            (None, _) => false,
+        };
+        if now_empty {
+            settings.remove(key);
+        }
+    }
+}
+
+/// point [`PLUGIN_KEY`] at `plugin` of `entry`. A plugin written by hand is
+/// kept, and one set by another addon is settled by `policy`. Returns whether
+/// it was set
+pub(crate) fn claim_plugin(
+    settings: &mut Map<String, Value>,
+    plugin: &str,
+    entry: &str,
+    owners: &BTreeMap<String, String>,
+    policy: ConflictPolicy,
+) -> Result<bool> {
+    match settings.get(PLUGIN_KEY) {
+        Some(Value::String(existing)) if existing == plugin => return Ok(false),
+        Some(Value::String(existing)) if !existing.is_empty() => match owners.get(PLUGIN_KEY) {
+            Some(owner) if settle(PLUGIN_KEY, owner, entry, policy)? => {}
+            Some(_) => return Ok(false),
+            None => {
+                log::warn!("'{existing}' is already the plugin, so '{plugin}' was not enabled");
+                return Ok(false);
+            }
+        },
+        _ => {}
+    }
+    settings.insert(PLUGIN_KEY.to_string(), Value::String(plugin.to_string()));
+    Ok(true)
+}
+
+/// what an addon brings to the settings file besides its library entry
+struct AddonExtras {
+    settings: Map<String, Value>,
+    plugin: Option<String>,
+}
+
+fn read_extras(location: &Path) -> AddonExtras {
+    let settings = read(location)
+        .inspect_err(|err| {
+            log::warn!(
+                "could not read the settings of '{location}': {err:#}",
+                location = location.display()
+            )
+        })
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let plugin = plugin(location).map(|plugin| plugin.to_string_lossy().into_owned());
+    AddonExtras { settings, plugin }
+}
+
+/// how a change to the library changes the rest of the settings file
+struct SettingsChange {
+    /// the entries removed
+    removed: Vec<String>,
+
+    /// what was applied for the removed entries, to take out again
+    to_unmerge: Vec<Map<String, Value>>,
+
+    /// the entries added that bring settings or a plugin
+    added: Vec<(String, AddonExtras)>,
+
+    /// which addon set each setting, among those staying enabled
+    owners: BTreeMap<String, String>,
+}
+
+impl SettingsChange {
+    fn new(settings_file: &Path, change: &LibraryChange) -> Result<Self> {
+        let removed: Vec<String> = change
+            .before
+            .iter()
+            .filter(|entry| !change.after.contains(entry))
+            .cloned()
+            .collect();
+        let state = WorkspaceState::load(settings_file)?.unwrap_or_default();
+        let to_unmerge = removed
+            .iter()
+            .filter_map(|entry| state.applied.get(entry.as_str()).cloned())
+            .collect();
+        let added = change
+            .after
+            .iter()
+            .filter(|entry| !change.before.contains(entry))
+            .map(|entry| (entry.clone(), read_extras(&entry_path(entry))))
+            .filter(|(_, extras)| !extras.settings.is_empty() || extras.plugin.is_some())
+            .collect();
+        let mut owners: BTreeMap<String, String> = BTreeMap::new();
+        for (entry, applied) in &state.applied {
+            if !removed.contains(entry) {
+                for key in applied.keys() {
+                    owners.insert(key.clone(), entry.clone());
+                }
+            }
+        }
+        Ok(SettingsChange {
+            removed,
+            to_unmerge,
+            added,
+            owners,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.to_unmerge.is_empty() && self.added.is_empty()
+    }
+
+    /// make the change to `settings`, returning what each added entry applied
+    fn apply(
+        &self,
+        settings: &mut Map<String, Value>,
+        policy: ConflictPolicy,
+    ) -> Result<Vec<(String, Map<String, Value>)>> {
+        let mut owners = self.owners.clone();
+        let mut applied_now = vec![];
+        for applied in &self.to_unmerge {
+            unmerge(settings, applied);
+        }
+        for (entry, extras) in &self.added {
+            let mut applied = merge(settings, &extras.settings, entry, &owners, policy)?;
+            if let Some(plugin) = &extras.plugin
+                && claim_plugin(settings, plugin, entry, &owners, policy)?
+            {
+                applied.insert(PLUGIN_KEY.to_string(), Value::String(plugin.clone()));
+            }
+            for key in applied.keys() {
+                owners.insert(key.clone(), entry.clone());
+            }
+            if !applied.is_empty() {
+                applied_now.push((entry.clone(), applied));
+            }
+        }
+        Ok(applied_now)
+    }
+}
+
+/// fail if [`sync`] would refuse `change` under `policy`, without writing
+/// anything, so it can be checked before the library is written. Only
+/// [`ConflictPolicy::Error`] refuses changes
+pub(crate) fn check(
+    settings_file: &Path,
+    change: &LibraryChange,
+    policy: ConflictPolicy,
+) -> Result<()> {
+    if policy != ConflictPolicy::Error {
+        return Ok(());
+    }
+    let settings_change = SettingsChange::new(settings_file, change)?;
+    if settings_change.is_empty() {
+        return Ok(());
+    }
+    let mut settings = read_other_settings(settings_file)?;
+    settings_change.apply(&mut settings, policy).map(|_| ())
+}
+
+/// bring the rest of the settings file in line with a change to its library.
+/// The settings and plugins of added addons are applied, and whatever was
+/// applied for removed addons is taken out again. What each addon applied is
+/// kept in the workspace state
+pub(crate) fn sync(config: &Config, change: &LibraryChange, policy: ConflictPolicy) -> Result<()> {
+    let settings_file = config.settings;
+    let settings_change = SettingsChange::new(settings_file, change)?;
+    if settings_change.is_empty() {
+        return Ok(());
+    }
+
+    let mut applied_now = vec![];
+    update_settings(config, |settings| {
+        applied_now = settings_change.apply(settings, policy)?;
+        Ok(())
+    })?;
+    workspace::update(config, WorkspaceState::default, |state| {
+        for entry in &settings_change.removed {
+            state.applied.remove(entry);
+        }
+        state.applied.extend(applied_now);
+    })
+}
+
+/// two or more enabled addons setting the same setting to different values
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Conflict {
+    pub key: String,
+
+    /// the name of each addon involved and the value it wants
+    pub values: Vec<(String, Value)>,
+}
+
+/// settings `addons` disagree on. Arrays and objects are merged, so only
+/// other values and plugins can conflict
+pub fn conflicts(addons: &[Addon]) -> Vec<Conflict> {
+    let mut wanted: BTreeMap<String, Vec<(String, Value)>> = BTreeMap::new();
+    for addon in addons {
+        let Some(location) = &addon.location else {
+            continue;
+        };
+        let extras = read_extras(location);
+        let plugin = extras
+            .plugin
+            .map(|plugin| (PLUGIN_KEY.to_string(), Value::String(plugin)));
+        for (key, value) in extras.settings.into_iter().chain(plugin) {
+            if key != LIB_SETTINGS_KEY && !value.is_array() && !value.is_object() {
+                wanted
+                    .entry(key)
+                    .or_default()
+                    .push((addon.name.clone(), value));
+            }
+        }
+    }
+    wanted
+        .into_iter()
+        .filter(|(_, values)| values.iter().any(|(_, value)| *value != values[0].1))
+        .map(|(key, values)| Conflict { key, values })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_merge {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn extends_arrays_and_objects() {
+        let mut settings = object(json!({
+            "Lua.diagnostics.globals": ["vim"],
+            "Lua.runtime.special": { "include": "require" },
+            "Lua.runtime.version": "Lua 5.1",
+        }));
+        let addon_settings = object(json!({
+            "Lua.diagnostics.globals": ["vim", "love"],
+            "Lua.runtime.special": { "love.filesystem.load": "loadfile" },
+            "Lua.runtime.version": "LuaJIT",
+            "Lua.workspace.library": ["ignored"],
+        }));
+        let applied = merge(
+            &mut settings,
+            &addon_settings,
+            "love2d",
+            &BTreeMap::new(),
+            ConflictPolicy::FirstWins,
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(settings.clone()),
+            json!({
+                "Lua.diagnostics.globals": ["vim", "love"],
+                "Lua.runtime.special": {
+                    "include": "require",
+                    "love.filesystem.load": "loadfile",
+                },
+                "Lua.runtime.version": "LuaJIT",
+            })
+        );
+
+        // settings changed by hand since are kept
+        settings.insert(String::from("Lua.runtime.version"), json!("Lua 5.4"));
+        unmerge(&mut settings, &applied);
+        assert_eq!(
+            Value::Object(settings),
+            json!({
+                "Lua.diagnostics.globals": ["vim"],
+                "Lua.runtime.special": { "include": "require" },
+                "Lua.runtime.version": "Lua 5.4",
+            })
+        );
+    }
+
+    #[test]
+    fn settles_conflicts_between_addons() {
+        let owners = BTreeMap::from([(String::from("Lua.runtime.version"), String::from("first"))]);
+        let addon_settings = object(json!({ "Lua.runtime.version": "LuaJIT" }));
+        let settle_with = |policy| {
+            let mut settings = object(json!({ "Lua.runtime.version": "Lua 5.1" }));
+            merge(&mut settings, &addon_settings, "second", &owners, policy)
+                .map(|applied| (settings, applied))
+        };
+
+        let (settings, applied) = settle_with(ConflictPolicy::FirstWins).unwrap();
+        assert_eq!(settings["Lua.runtime.version"], json!("Lua 5.1"));
+        assert!(applied.is_empty());
+
+        let (settings, applied) = settle_with(ConflictPolicy::LastWins).unwrap();
+        assert_eq!(settings["Lua.runtime.version"], json!("LuaJIT"));
+        assert_eq!(
+            Value::Object(applied),
+            Value::Object(addon_settings.clone())
+        );
+
+        settle_with(ConflictPolicy::Error).unwrap_err();
+    }
+
+    #[test]
+    fn claims_an_empty_plugin_slot() {
+        let mut settings = Map::new();
+        let owners = BTreeMap::new();
+        let policy = ConflictPolicy::LastWins;
+        assert!(claim_plugin(&mut settings, "a/plugin.lua", "a", &owners, policy).unwrap());
+        // written by hand, as far as anyone knows
+        assert!(!claim_plugin(&mut settings, "b/plugin.lua", "b", &owners, policy).unwrap());
+        assert_eq!(
+            Value::Object(settings),
+            json!({ "Lua.runtime.plugin": "a/plugin.lua" })
+        );
+    }
+
+    #[test]
+    fn reads_config_next_to_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let types = dir.path().join("types");
+        fs::create_dir(&types).unwrap();
+        let location = &types;
+        assert_eq!(read(location).unwrap(), None);
+
+        fs::write(
+            dir.path().join("config.json"),
+            r#"{ "name": "love2d", "settings": { "Lua.runtime.version": "LuaJIT" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            read(location).unwrap(),
+            Some(object(json!({ "Lua.runtime.version": "LuaJIT" })))
+        );
+
+        assert_eq!(plugin(location), None);
+        fs::write(types.join("plugin.lua"), "").unwrap();
+        assert_eq!(plugin(location), Some(types.join("plugin.lua")));
+    }
+
+    #[test]
+    fn sync_takes_out_what_it_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let types = dir.path().join("love2d/12.0-1/types");
+        fs::create_dir_all(&types).unwrap();
+        fs::write(
+            types.join("config.json"),
+            r#"{ "settings": { "Lua.diagnostics.globals": ["love"] } }"#,
+        )
+        .unwrap();
+        fs::write(types.join("plugin.lua"), "").unwrap();
+        let entry = types.to_str().unwrap().to_string();
+        let settings = dir.path().join("settings.json");
+        fs::write(&settings, r#"{ "Lua.diagnostics.globals": ["vim"] }"#).unwrap();
+
+        let enable = LibraryChange {
+            settings: settings.clone(),
+            before: vec![],
+            after: vec![entry.clone()],
+        };
+        let config = Config {
+            settings: &settings,
+            ..Config::default()
+        };
+        sync(&config, &enable, ConflictPolicy::FirstWins).unwrap();
+        let applied = WorkspaceState::load(&settings).unwrap().unwrap().applied;
+        assert_eq!(
+            Value::Object(applied[&entry].clone()),
+            json!({
+                "Lua.diagnostics.globals": ["love"],
+                "Lua.runtime.plugin": types.join("plugin.lua").to_str().unwrap(),
+            })
+        );
+
+        let disable = LibraryChange {
+            settings: settings.clone(),
+            before: vec![entry],
+            after: vec![],
+        };
+        sync(&config, &disable, ConflictPolicy::FirstWins).unwrap();
+        let contents: Value =
+            serde_json::from_str(&fs::read_to_string(&settings).unwrap()).unwrap();
+        assert_eq!(contents, json!({ "Lua.diagnostics.globals": ["vim"] }));
+        assert!(
+            WorkspaceState::load(&settings)
+                .unwrap()
+                .unwrap()
+                .applied
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn finds_disagreeing_addons() {
+        let dir = tempfile::tempdir().unwrap();
+        let addon = |name: &str, settings: &str| {
+            let types = dir.path().join(name).join("types");
+            fs::create_dir_all(&types).unwrap();
+            fs::write(types.join("config.json"), settings).unwrap();
+            Addon {
+                name: name.to_string(),
+                version: String::from("1.0-1"),
+                location: Some(types.clone()),
+            }
+        };
+        let addons = [
+            addon(
+                "love2d",
+                r#"{ "settings": { "Lua.runtime.version": "LuaJIT", "Lua.diagnostics.globals": ["love"] } }"#,
+            ),
+            addon(
+                "playdate",
+                r#"{ "settings": { "Lua.runtime.version": "Lua 5.4", "Lua.diagnostics.globals": ["playdate"] } }"#,
+            ),
+            addon(
+                "luajit",
+                r#"{ "settings": { "Lua.runtime.version": "LuaJIT" } }"#,
+            ),
+        ];
+        assert_eq!(
+            conflicts(&addons),
+            vec![Conflict {
+                key: String::from("Lua.runtime.version"),
+                values: vec![
+                    (String::from("love2d"), json!("LuaJIT")),
+                    (String::from("playdate"), json!("Lua 5.4")),
+                    (String::from("luajit"), json!("LuaJIT")),
+                ],
+            }]
+        );
+        assert!(conflicts(&addons[..1]).is_empty());
+    }
+}
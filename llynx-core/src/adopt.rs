@@ -0,0 +1,120 @@
+//! taking over `Lua.workspace.library` entries that were added by hand
+
+use crate::{
+    Addon, Config, addon_settings,
+    enabled::{self, EntryStyle, is_addon_path, newest_installed, read_library, update_library},
+    failure::Failure,
+    filter::Filter,
+    history::Operation,
+    list_installed, list_online, signature,
+    workspace::{self, WorkspaceState},
+};
+use anyhow::{Result, anyhow, bail};
+
+/// directory names addons keep their definitions in, which say nothing about
+/// the addon itself
+const GENERIC_DIRS: [&str; 4] = ["library", "types", "module", "meta"];
+
+/// the addon a library entry most likely belongs to, like `busted` for
+/// `addons/busted/module/library`
+pub fn guess_name(entry: &str) -> Option<&str> {
+    entry
+        .split(['/', '\\'])
+        .rev()
+        .find(|name| !GENERIC_DIRS.contains(name) && !name.starts_with("${"))
+}
+
+/// start managing `entry`, an entry of the library written by hand. Entries
+/// pointing at an addon in the tree are kept as they are. Any other entry is
+/// replaced by the installed addon its name suggests, which gets installed
+/// first if needed
+pub fn adopt(config: &Config, entry: &str) -> Result<Addon> {
+    let library = read_library(config.settings)?;
+    if !library.iter().any(|existing| existing == entry) {
+        bail!(
+            "'{entry}' is not in the library of '{}'",
+            config.settings.display()
+        );
+    }
+
+    if is_addon_path(config, entry) {
+        let addon = list_installed(config, None)?
+            .into_iter()
+            .find(|addon| {
+                addon
+                    .location
+                    .as_deref()
+                    .is_some_and(|location| enabled::points_at(entry, location))
+            })
+            .ok_or_else(|| anyhow!("'{entry}' does not belong to an installed addon"))?;
+        // without a workspace state, addon paths are already managed
+        if WorkspaceState::load(config.settings)?.is_some() {
+            workspace::update(config, WorkspaceState::default, |state| {
+                state.managed.insert(entry.to_string());
+            })?;
+        }
+        return Ok(addon);
+    }
+
+    let name = guess_name(entry)
+        .ok_or_else(|| anyhow!("could not tell which addon '{entry}' belongs to"))?;
+    let mut installed = list_installed(config, Some(&Filter::from(name)))?;
+    if newest_installed(&installed, name).is_none() {
+        let online = list_online(config, Some(&Filter::from(name)))?;
+        if !online.iter().any(|addon| addon.name == name) {
+            return Err(Failure::NotFound
+                .tag(format!(
+                    "'{entry}' looks like addon '{name}', which is not installed or online"
+                ))
+                .into());
+        }
+        let output = signature::install(config, name, None)?;
+        if !output.status.success() {
+            return Err(Failure::LuaRocks
+                .tag(format!(
+                    "could not install '{name}':\n{}",
+                    String::from_utf8_lossy(&output.stderr).trim_end()
+                ))
+                .into());
+        }
+        installed = list_installed(config, Some(&Filter::from(name)))?;
+    }
+    let addon = newest_installed(&installed, name)
+        .ok_or_else(|| Failure::NotFound.tag(format!("addon '{name}' is not installed")))?
+        .clone();
+    let location = enabled::library_entry(addon.types_dir()?, EntryStyle::from(config))?;
+
+    let change = update_library(config, |library| {
+        let mut placed = library.contains(&location);
+        library
+            .into_iter()
+            .filter_map(|existing| match existing == entry {
+                false => Some(existing),
+                true if placed => None,
+                true => {
+                    placed = true;
+                    Some(location.clone())
+                }
+            })
+            .collect()
+    })?;
+    addon_settings::sync(config, &change, config.conflicts)?;
+    let operation = Operation::Adopt {
+        name: name.to_string(),
+    };
+    enabled::record_change(config.tree, operation, change, vec![])?;
+    Ok(addon)
+}
+
+#[cfg(test)]
+mod test_guess_name {
+    use super::*;
+
+    #[test]
+    fn skips_generic_directories() {
+        assert_eq!(guess_name("addons/busted/module/library"), Some("busted"));
+        assert_eq!(guess_name("${3rd}/luassert/library"), Some("luassert"));
+        assert_eq!(guess_name("../types/say"), Some("say"));
+        assert_eq!(guess_name("${3rd}/library"), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! addons distributed as plain archives of type definitions rather than
+//! rocks, downloaded with curl and unpacked with tar or unzip
+
+use crate::{
+    Addon, Config, network,
+    vendor::{self, Source, Vendored, find_library, run_tool, vendor_dir},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// the file extensions llynx knows how to unpack
+const EXTENSIONS: [&str; 5] = [".zip", ".tar.gz", ".tgz", ".tar.xz", ".tar"];
+
+/// archives don't carry a version, so this stands in for one
+const ARCHIVE_VERSION: &str = "scm";
+
+/// where addon `name` is unpacked to in `tree`
+pub fn unpack_dir(tree: &Path, name: &str) -> PathBuf {
+    vendor_dir(tree).join("archive").join(name)
+}
+
+/// the file name of the archive at `url` and the extension it's unpacked by,
+/// like `("addon", ".zip")` for `https://example.com/addon.zip?download=1`
+fn split_url(url: &str) -> Option<(&str, &'static str)> {
+    let path = url.split(['?', '#']).next()?.trim_end_matches('/');
+    let file_name = path.rsplit('/').next()?;
+    EXTENSIONS.iter().find_map(|extension| {
+        let stem = file_name.strip_suffix(extension)?;
+        (!stem.is_empty()).then_some((stem, *extension))
+    })
+}
+
+/// where the definitions of an unpacked archive are. Archives often wrap
+/// everything in one directory, which is looked through
+fn unpacked_library(dir: &Path) -> Option<PathBuf> {
+    if let Some(library) = find_library(dir) {
+        return Some(library);
+    }
+    let mut entries = fs::read_dir(dir).ok()?.flatten();
+    let only = entries.next().filter(|entry| entry.path().is_dir())?;
+    if entries.next().is_some() {
+        return None;
+    }
+    find_library(&only.path())
+}
+
+/// download the archive at `url` and unpack it into the tree as addon
+/// `name`, or the name its file suggests. It has to have a `library` or
+/// `types` directory
+pub fn install(config: &Config, url: &str, name: Option<&str>) -> Result<Addon> {
+    let tree = config.tree;
+    let (stem, extension) = split_url(url).ok_or_else(|| {
+        anyhow!(
+            "'{url}' is not an archive llynx can unpack, expected one of {}",
+            EXTENSIONS.join(", ")
+        )
+    })?;
+    let name = name.unwrap_or(stem);
+    vendor::check_name(name)?;
+    let dir = unpack_dir(tree, name);
+    if dir.exists() {
+        bail!("'{}' already exists, remove '{name}' first", dir.display());
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("while creating '{}'", dir.display()))?;
+
+    let result = (|| {
+        let download = tempfile::tempdir().context("while creating a download directory")?;
+        let archive = download.path().join(format!("{name}{extension}"));
+        network::download(config, url, &archive)?;
+        if extension == ".zip" {
+            run_tool(
+                config,
+                "unzip",
+                Command::new("unzip")
+                    .arg("-q")
+                    .arg(&archive)
+                    .arg("-d")
+                    .arg(&dir),
+            )?;
+        } else {
+            run_tool(
+                config,
+                "tar",
+                Command::new("tar")
+                    .arg("-xf")
+                    .arg(&archive)
+                    .arg("-C")
+                    .arg(&dir),
+            )?;
+        }
+        unpacked_library(&dir).ok_or_else(|| anyhow!("'{url}' has no library or types directory"))
+    })();
+    let location = match result {
+        Ok(location) => location,
+        Err(err) => {
+            // don't leave files behind that nothing records
+            let _ = fs::remove_dir_all(&dir);
+            return Err(err);
+        }
+    };
+
+    vendor::record(
+        tree,
+        name,
+        Vendored {
+            source: Source::Archive {
+                url: url.to_string(),
+            },
+            version: ARCHIVE_VERSION.to_string(),
+            location: location.clone(),
+        },
+    )?;
+    Ok(Addon {
+        name: name.to_string(),
+        version: ARCHIVE_VERSION.to_string(),
+        location: Some(location),
+    })
+}
+
+/// delete the unpacked files of addon `name`
+pub(crate) fn remove_unpacked(tree: &Path, name: &str) -> Result<()> {
+    let dir = unpack_dir(tree, name);
+    fs::remove_dir_all(&dir).with_context(|| format!("while removing '{}'", dir.display()))
+}
+
+#[cfg(test)]
+mod test_archive {
+    use super::*;
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(
+            split_url("https://example.com/addon.zip?download=1"),
+            Some(("addon", ".zip"))
+        );
+        assert_eq!(
+            split_url("https://example.com/types/love-11.5.tar.gz"),
+            Some(("love-11.5", ".tar.gz"))
+        );
+        assert_eq!(split_url("https://example.com/addon"), None);
+        assert_eq!(split_url("https://example.com/.zip"), None);
+    }
+
+    #[test]
+    fn looks_through_one_wrapping_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("addon-main/library")).unwrap();
+        assert_eq!(
+            unpacked_library(dir.path()),
+            Some(dir.path().join("addon-main/library"))
+        );
+
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        assert_eq!(unpacked_library(dir.path()), None);
+    }
+
+    #[test]
+    fn names_stay_in_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            ..Config::default()
+        };
+        for name in ["../../say", "..", "say/nested"] {
+            install(&config, "https://example.com/say.zip", Some(name)).unwrap_err();
+        }
+        assert!(!tree.exists());
+        assert!(!dir.path().join("say").exists());
+    }
+}
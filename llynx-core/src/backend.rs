@@ -0,0 +1,209 @@
+//! what llynx asks of LuaRocks, behind a trait so something else can stand in
+//! for the executable, like a native client or a mock in tests. Everything
+//! around it, like the journal, vendored addons and filtering, stays with
+//! llynx whatever the backend is
+
+use crate::{
+    Addon, Config,
+    filter::Filter,
+    installed::{self, execute_command, install_command, remove_command},
+    online,
+};
+use anyhow::Result;
+use std::{
+    cell::RefCell,
+    process::{ExitStatus, Output},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// lists, searches, installs and removes rocks, in the tree and on the
+/// server `config` names. Filters are hints, since the results are filtered
+/// again afterwards
+pub trait RocksBackend: Send + Sync {
+    /// the rocks installed in the tree
+    fn list(&self, config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>>;
+
+    /// the rocks on the server
+    fn search(&self, config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>>;
+
+    /// install `name` into the tree, returning what was printed
+    fn install(&self, config: &Config, name: &str, version: Option<&str>) -> Result<Output>;
+
+    /// remove `name` from the tree, returning what was printed
+    fn remove(&self, config: &Config, name: &str, version: Option<&str>) -> Result<Output>;
+}
+
+/// the LuaRocks executable the config points at, which llynx runs by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LuaRocks;
+
+impl RocksBackend for LuaRocks {
+    fn list(&self, config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+        let stdout = installed::list_tree(config, filter)?;
+        installed::parse_installed(config.tree, &stdout)
+    }
+
+    fn search(&self, config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+        online::search_luarocks(config, filter)
+    }
+
+    fn install(&self, config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+        execute_command(config, install_command(config, name, version))
+    }
+
+    fn remove(&self, config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+        execute_command(config, remove_command(config, name, version))
+    }
+}
+
+/// rocks kept in memory, for tests that shouldn't need LuaRocks. Every tree
+/// holds the same rocks and every server has the same ones online. Installs
+/// take the last version online unless one is given, and always succeed
+#[derive(Debug, Default)]
+pub struct Mock {
+    pub installed: Mutex<Vec<Addon>>,
+    pub online: Vec<Addon>,
+}
+
+impl Mock {
+    pub fn new(installed: Vec<Addon>, online: Vec<Addon>) -> Self {
+        Mock {
+            installed: Mutex::new(installed),
+            online,
+        }
+    }
+
+    /// the rocks installed so far
+    pub fn installed(&self) -> MutexGuard<'_, Vec<Addon>> {
+        self.installed.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// what a command that printed nothing and succeeded returns
+pub(crate) fn succeeded() -> Output {
+    Output {
+        status: ExitStatus::default(),
+        stdout: vec![],
+        stderr: vec![],
+    }
+}
+
+impl RocksBackend for Mock {
+    fn list(&self, _config: &Config, _filter: Option<&Filter>) -> Result<Vec<Addon>> {
+        Ok(self.installed().clone())
+    }
+
+    fn search(&self, _config: &Config, _filter: Option<&Filter>) -> Result<Vec<Addon>> {
+        Ok(self.online.clone())
+    }
+
+    fn install(&self, config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+        let version = match version {
+            Some(version) => version,
+            None => self
+                .online
+                .iter()
+                .rfind(|addon| addon.name == name)
+                .map_or("scm-1", |addon| &addon.version),
+        };
+        // where LuaRocks would've put it
+        let location = config
+            .tree
+            .join("lib/luarocks")
+            .join(format!("rocks-{}", config.lua_version.unwrap_or("5.1")))
+            .join(name)
+            .join(version)
+            .join("types");
+        self.installed().push(Addon {
+            name: name.to_string(),
+            version: version.to_string(),
+            location: Some(location),
+        });
+        Ok(succeeded())
+    }
+
+    fn remove(&self, _config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+        self.installed()
+            .retain(|addon| addon.name != name || version.is_some_and(|v| v != addon.version));
+        Ok(succeeded())
+    }
+}
+
+thread_local! {
+    /// the backend standing in for LuaRocks on this thread, if any
+    static SWAPPED: RefCell<Option<Arc<dyn RocksBackend>>> = const { RefCell::new(None) };
+}
+
+/// run `f` with `backend` standing in for LuaRocks on this thread, wherever
+/// llynx would've run the executable. The backend before is back once `f`
+/// returns or panics
+pub fn with_backend<T>(backend: Arc<dyn RocksBackend>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Arc<dyn RocksBackend>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SWAPPED.with(|swapped| swapped.replace(self.0.take()));
+        }
+    }
+    let _restore = Restore(SWAPPED.with(|swapped| swapped.replace(Some(backend))));
+    f()
+}
+
+/// the backend to use on this thread: the one swapped in, or LuaRocks
+pub fn current() -> Arc<dyn RocksBackend> {
+    SWAPPED
+        .with(|swapped| swapped.borrow().clone())
+        .unwrap_or_else(|| Arc::new(LuaRocks))
+}
+
+#[cfg(test)]
+mod test_backend {
+    use super::*;
+    use crate::addon;
+    use std::path::Path;
+
+    #[test]
+    fn mocks_stand_in_for_luarocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            tree: dir.path(),
+            luarocks: Path::new("no-luarocks"),
+            ..Config::default()
+        };
+        let mock = Arc::new(Mock::new(
+            vec![],
+            vec![addon("say", "1.4.1-3"), addon("say", "1.5-1")],
+        ));
+        with_backend(mock.clone(), || {
+            let output = installed::install(&config, "say", None);
+            assert!(output.unwrap().status.success());
+            assert_eq!(
+                installed::list_installed(&config, None).unwrap(),
+                [Addon {
+                    location: Some(dir.path().join("lib/luarocks/rocks-5.1/say/1.5-1/types")),
+                    ..addon("say", "1.5-1")
+                }]
+            );
+            installed::remove(&config, "say", Some("1.4.1-3")).unwrap();
+            assert_eq!(mock.installed().len(), 1);
+            installed::remove(&config, "say", None).unwrap();
+            assert!(mock.installed().is_empty());
+        });
+        assert!(current().list(&config, None).is_err());
+    }
+
+    #[test]
+    fn restores_the_backend_after_panics() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            tree: dir.path(),
+            luarocks: Path::new("no-luarocks"),
+            ..Config::default()
+        };
+        let mock = Arc::new(Mock::new(vec![addon("say", "1.5-1")], vec![]));
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_backend(mock, || panic!("the backend should be restored"))
+        }));
+        assert!(panicked.is_err());
+        assert!(current().list(&config, None).is_err());
+    }
+}
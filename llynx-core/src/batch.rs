@@ -0,0 +1,243 @@
+//! running many commands in one go, for scripts that set up a workspace.
+//! Installs and removals run in the order given, then every change to the
+//! library is planned against one listing of the tree and written at once
+
+use crate::{
+    Addon, Config,
+    enabled::{
+        EntryStyle, Plan, disable_in_library, enable_in_library, links_for, list_enabled,
+        read_library, resolve_addons,
+    },
+    history::{LibraryChange, Operation},
+    installed::{has_version, list_installed},
+};
+use anyhow::{Context, Result, bail};
+
+/// one command of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Install {
+        name: String,
+        version: Option<String>,
+    },
+    Remove {
+        name: String,
+        version: Option<String>,
+    },
+    Enable {
+        name: String,
+    },
+    Disable {
+        name: String,
+    },
+    Switch {
+        name: String,
+        version: String,
+    },
+}
+
+/// the steps in `contents`, one command per line like `install say 1.4.1-3`.
+/// Blank lines and everything after a `#` are skipped
+pub fn parse(contents: &str) -> Result<Vec<Step>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            (!line.is_empty()).then_some((i, line))
+        })
+        .map(|(i, line)| parse_line(line).with_context(|| format!("while reading line {}", i + 1)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<Step> {
+    let (command, rest) = split_word(line);
+    let (name, version) = split_word(rest);
+    let name = String::from(name);
+    // the rest of the line is the version, so constraints can hold spaces
+    let version = (!version.is_empty()).then(|| String::from(version));
+    Ok(match (command, version) {
+        ("install" | "remove" | "enable" | "disable" | "switch", _) if name.is_empty() => {
+            bail!("wrong arguments for '{command}': '{line}'")
+        }
+        ("install", version) => Step::Install { name, version },
+        ("remove", version) => Step::Remove { name, version },
+        ("enable", None) => Step::Enable { name },
+        ("disable", None) => Step::Disable { name },
+        ("switch", Some(version)) => Step::Switch { name, version },
+        ("enable" | "disable" | "switch", _) => {
+            bail!("wrong arguments for '{command}': '{line}'")
+        }
+        (command, _) => bail!("unknown command '{command}'"),
+    })
+}
+
+/// the first word of `line` and the trimmed rest after it
+fn split_word(line: &str) -> (&str, &str) {
+    line.split_once(char::is_whitespace)
+        .map_or((line, ""), |(word, rest)| (word, rest.trim()))
+}
+
+/// what running `steps` would change, without changing anything: installs
+/// and removals still to do, and the settings file if its library would
+/// change. The library is only planned once the tree is as `steps` leave it
+pub fn check(config: &Config, steps: &[Step]) -> Result<Vec<String>> {
+    let installed = list_installed(config, None)?;
+    let mut problems = vec![];
+    for step in steps {
+        let (name, version, wanted) = match step {
+            Step::Install { name, version } => (name, version.as_deref(), true),
+            Step::Remove { name, version } => (name, version.as_deref(), false),
+            _ => continue,
+        };
+        if has_version(&installed, name, version)? != wanted {
+            let shown = match version {
+                Some(version) => format!("'{name}' {version}"),
+                None => format!("'{name}'"),
+            };
+            problems.push(match wanted {
+                true => format!("{shown} is not installed"),
+                false => format!("{shown} is still installed"),
+            });
+        }
+    }
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+    let plans = plan_library(config, steps)?;
+    if let (Some(first), Some(last)) = (plans.first(), plans.last())
+        && first.change.before != last.change.after
+    {
+        problems.push(format!("'{}' would change", config.settings.display()));
+    }
+    for addon in plans.iter().flat_map(|plan| &plan.links) {
+        problems.push(format!(
+            "the link of '{}' would move to {}",
+            addon.name, addon.version
+        ));
+    }
+    Ok(problems)
+}
+
+/// plan the enables, disables and switches of `steps` in order, each
+/// starting from the library the one before it leaves. Steps that change
+/// nothing are left out
+pub fn plan_library(config: &Config, steps: &[Step]) -> Result<Vec<Plan>> {
+    let style = EntryStyle::from(config);
+    let installed = list_installed(config, None)?;
+    let mut enabled = list_enabled(config, None)?;
+    let mut library = read_library(config.settings)?;
+    let mut plans = vec![];
+    for step in steps {
+        let (operation, after, links) = match step {
+            Step::Install { .. } | Step::Remove { .. } => continue,
+            Step::Enable { name } if enabled.iter().any(|addon| &addon.name == name) => {
+                log::info!("addon '{name}' is already enabled");
+                continue;
+            }
+            Step::Enable { name } => {
+                let addons = resolve_addons(&installed, name, None)?;
+                let f = enable_in_library(config.tree, addons.clone(), enabled.clone(), style)?;
+                let links = links_for(config.tree, &addons, style);
+                mark_enabled(&mut enabled, addons);
+                let operation = Operation::Enable { name: name.clone() };
+                (operation, f(library.clone()), links)
+            }
+            Step::Switch { name, version } => {
+                let addons = resolve_addons(&installed, name, Some(version))?;
+                let f = enable_in_library(config.tree, addons.clone(), enabled.clone(), style)?;
+                let links = links_for(config.tree, &addons, style);
+                mark_enabled(&mut enabled, addons);
+                let operation = Operation::Switch {
+                    name: name.clone(),
+                    version: version.clone(),
+                };
+                (operation, f(library.clone()), links)
+            }
+            Step::Disable { name } => {
+                let f = disable_in_library(name, enabled.clone());
+                enabled.retain(|addon| &addon.name != name);
+                let operation = Operation::Disable { name: name.clone() };
+                (operation, f(library.clone()), vec![])
+            }
+        };
+        // switching a linked addon only moves its link
+        if after == library && links.is_empty() {
+            continue;
+        }
+        let before = std::mem::replace(&mut library, after.clone());
+        plans.push(Plan {
+            operation,
+            change: LibraryChange {
+                settings: config.settings.to_path_buf(),
+                before,
+                after,
+            },
+            links,
+            copies: vec![],
+        });
+    }
+    Ok(plans)
+}
+
+/// remember that `addons` are the enabled versions of their names now
+fn mark_enabled(enabled: &mut Vec<Addon>, addons: Vec<Addon>) {
+    enabled.retain(|other| !addons.iter().any(|addon| addon.name == other.name));
+    enabled.extend(addons);
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let contents = "# set up the workspace\ninstall say 1.4.1-3\ninstall luassert >= 1.4, < 2\n\nenable say # and its dependencies\nswitch busted 2.2.0-1\ndisable luassert\nremove penlight\n";
+        assert_eq!(
+            parse(contents).unwrap(),
+            vec![
+                Step::Install {
+                    name: String::from("say"),
+                    version: Some(String::from("1.4.1-3")),
+                },
+                Step::Install {
+                    name: String::from("luassert"),
+                    version: Some(String::from(">= 1.4, < 2")),
+                },
+                Step::Enable {
+                    name: String::from("say"),
+                },
+                Step::Switch {
+                    name: String::from("busted"),
+                    version: String::from("2.2.0-1"),
+                },
+                Step::Disable {
+                    name: String::from("luassert"),
+                },
+                Step::Remove {
+                    name: String::from("penlight"),
+                    version: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mistakes() {
+        let err = parse("enable say\nswitch say\n").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "while reading line 2: wrong arguments for 'switch': 'switch say'"
+        );
+        let err = parse("disable say 1.4.1-3").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "while reading line 1: wrong arguments for 'disable': 'disable say 1.4.1-3'"
+        );
+        let err = parse("uninstall say").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "while reading line 1: unknown command 'uninstall'"
+        );
+    }
+}
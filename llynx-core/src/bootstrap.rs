@@ -0,0 +1,205 @@
+//! downloading a standalone LuaRocks into llynx's data directory, for
+//! machines that don't have one installed
+
+use crate::{Config, network, release, vendor::run_tool};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// the environment variable that moves the data directory somewhere else
+pub const DATA_DIR_ENV: &str = "LLYNX_DATA_DIR";
+
+/// the environment variable that moves the config directory somewhere else
+pub const CONFIG_DIR_ENV: &str = "LLYNX_CONFIG_DIR";
+
+/// the version of LuaRocks that's downloaded
+pub const LUAROCKS_VERSION: &str = "3.11.1";
+
+/// where standalone releases of LuaRocks are published
+const RELEASES: &str = "https://luarocks.github.io/luarocks/releases";
+
+/// the SHA-256 checksum of the zip of each standalone release, which is
+/// checked before anything in it is unpacked. Releases without one are
+/// refused, since their contents are run
+const CHECKSUMS: &[(&str, &str)] = &[];
+
+/// the per-user data directory, following each platform's convention. It's
+/// kept apart from the cache, which may be cleaned at any time
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(DATA_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share"))
+        })
+    };
+    base.map(|base| base.join("llynx"))
+}
+
+/// the per-user config directory, following each platform's convention.
+/// What's kept there belongs to the user rather than to a project
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(CONFIG_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|base| base.join("llynx"))
+}
+
+/// the name of the standalone release for `os` and `arch`, as in
+/// [`std::env::consts`], if there is one
+fn release_name(os: &str, arch: &str) -> Option<String> {
+    let platform = match (os, arch) {
+        ("windows", "x86_64") => "windows-64",
+        ("windows", "x86") => "windows-32",
+        ("linux", "x86_64") => "linux-x86_64",
+        _ => return None,
+    };
+    Some(format!("luarocks-{LUAROCKS_VERSION}-{platform}"))
+}
+
+/// the checksum pinned for the zip of `release`, if any
+fn pinned_checksum(release: &str) -> Option<&'static str> {
+    CHECKSUMS
+        .iter()
+        .find(|(name, _)| *name == release)
+        .map(|(_, checksum)| *checksum)
+}
+
+/// where the standalone LuaRocks for this platform is kept in `data_dir`
+fn executable_in(data_dir: &Path, release: &str) -> PathBuf {
+    data_dir
+        .join(release)
+        .join(format!("luarocks{}", env::consts::EXE_SUFFIX))
+}
+
+/// whether `luarocks_path` can't be run because there's nothing there, or
+/// nothing by that name on `PATH`
+pub fn is_missing(luarocks_path: &Path) -> bool {
+    Command::new(luarocks_path)
+        .arg("--version")
+        .output()
+        .is_err_and(|err| err.kind() == io::ErrorKind::NotFound)
+}
+
+/// the LuaRocks downloaded before, if any
+pub fn bootstrapped() -> Option<PathBuf> {
+    let release = release_name(env::consts::OS, env::consts::ARCH)?;
+    Some(executable_in(&data_dir()?, &release)).filter(|path| path.is_file())
+}
+
+/// download the standalone LuaRocks for this platform into the data
+/// directory, unless it's there already, returning where it is. The zip is
+/// checked against its pinned checksum before it's unpacked
+pub fn bootstrap(config: &Config) -> Result<PathBuf> {
+    let release = release_name(env::consts::OS, env::consts::ARCH).ok_or_else(|| {
+        anyhow!(
+            "there's no standalone LuaRocks for {} on {}, install it from https://luarocks.org",
+            env::consts::ARCH,
+            env::consts::OS
+        )
+    })?;
+    let data_dir = data_dir().ok_or_else(|| anyhow!("could not find the data directory"))?;
+    let executable = executable_in(&data_dir, &release);
+    if executable.is_file() {
+        return Ok(executable);
+    }
+    let expected = pinned_checksum(&release).ok_or_else(|| {
+        anyhow!(
+            "there's no checksum to check '{release}.zip' against, install LuaRocks from https://luarocks.org"
+        )
+    })?;
+
+    fs::create_dir_all(&data_dir)
+        .with_context(|| format!("while creating '{}'", data_dir.display()))?;
+    let download = tempfile::tempdir().context("while creating a download directory")?;
+    let archive = download.path().join(format!("{release}.zip"));
+    network::download(config, &format!("{RELEASES}/{release}.zip"), &archive)?;
+    let actual = release::checksum(config, &archive)?;
+    if actual != expected {
+        bail!("the checksum of '{release}.zip' is {actual}, but {expected} was expected");
+    }
+    unzip(config, &archive, &data_dir)?;
+    if !executable.is_file() {
+        return Err(anyhow!(
+            "'{release}.zip' has no '{}'",
+            executable
+                .strip_prefix(&data_dir)
+                .unwrap_or(&executable)
+                .display()
+        ));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("while making '{}' executable", executable.display()))?;
+    }
+    Ok(executable)
+}
+
+/// unpack `archive` into `to`. Windows 10 and later ship a `tar` that reads
+/// zips, while other platforms have `unzip`
+fn unzip(config: &Config, archive: &Path, to: &Path) -> Result<()> {
+    let (tool, mut command) = if cfg!(windows) {
+        let mut tar = Command::new("tar");
+        tar.arg("-xf").arg(archive).arg("-C").arg(to);
+        ("tar", tar)
+    } else {
+        let mut unzip = Command::new("unzip");
+        unzip.args(["-q", "-o"]).arg(archive).arg("-d").arg(to);
+        ("unzip", unzip)
+    };
+    run_tool(config, tool, &mut command)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_bootstrap {
+    use super::*;
+
+    #[test]
+    fn release_names() {
+        assert_eq!(
+            release_name("linux", "x86_64").as_deref(),
+            Some("luarocks-3.11.1-linux-x86_64")
+        );
+        assert_eq!(
+            release_name("windows", "x86").as_deref(),
+            Some("luarocks-3.11.1-windows-32")
+        );
+        assert_eq!(release_name("macos", "aarch64"), None);
+    }
+
+    #[test]
+    fn pinned_checksums() {
+        for (release, checksum) in CHECKSUMS {
+            assert_eq!(pinned_checksum(release), Some(*checksum));
+            assert_eq!(checksum.len(), 64, "{release}");
+        }
+        assert_eq!(pinned_checksum("luarocks-3.11.1-macos-aarch64"), None);
+    }
+
+    #[test]
+    fn missing_executables() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(is_missing(&dir.path().join("luarocks")));
+        assert!(is_missing(Path::new("surely-not-a-luarocks-on-path")));
+    }
+}
@@ -0,0 +1,165 @@
+//! a per-user cache of what llynx fetched from servers, so listing online
+//! addons again doesn't go through the network every time. Only servers
+//! reached over HTTP are cached
+
+use anyhow::{Context, Result};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// the environment variable that moves the cache somewhere else
+pub const CACHE_DIR_ENV: &str = "LLYNX_CACHE_DIR";
+
+/// how long the search results of a server are used before searching again
+pub const SEARCH_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// the per-user cache directory, following each platform's convention
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(CACHE_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    base.map(|base| base.join("llynx"))
+}
+
+/// a file name standing for `text`, keeping letters, digits, `.` and `-`
+fn file_name(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// where things fetched from `server` are kept, or `None` if it isn't cached
+fn server_dir(server: &str) -> Option<PathBuf> {
+    let rest = server
+        .strip_prefix("https://")
+        .or_else(|| server.strip_prefix("http://"))?;
+    Some(cache_dir()?.join("servers").join(file_name(rest)))
+}
+
+/// where the results of searching `server` for `query` are kept
+pub(crate) fn search_path(server: &str, query: &str) -> Option<PathBuf> {
+    Some(server_dir(server)?.join("search").join(file_name(query)))
+}
+
+/// where the rockspec of `name` at `version` from `server` is kept
+pub(crate) fn rockspec_path(server: &str, name: &str, version: &str) -> Option<PathBuf> {
+    Some(
+        server_dir(server)?
+            .join("rockspecs")
+            .join(file_name(&format!("{name}-{version}.rockspec"))),
+    )
+}
+
+/// the contents of `path` if it's there and younger than `ttl`. Without a
+/// `ttl`, it never goes stale
+pub(crate) fn read(path: &Path, ttl: Option<Duration>) -> Option<Vec<u8>> {
+    if let Some(ttl) = ttl {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > ttl {
+            return None;
+        }
+    }
+    let contents = fs::read(path).ok()?;
+    log::debug!("using cached '{}'", path.display());
+    Some(contents)
+}
+
+/// keep `contents` at `path`. A cache that can't be written only costs time,
+/// so failures are logged rather than returned
+pub(crate) fn write(path: &Path, contents: &[u8]) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(path, contents));
+    if let Err(err) = result {
+        log::warn!("could not cache '{}': {err}", path.display());
+    }
+}
+
+/// how much is in the cache
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CacheSize {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+fn measure(dir: &Path, size: &mut CacheSize) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            measure(&entry.path(), size)?;
+        } else {
+            size.files += 1;
+            size.bytes += meta.len();
+        }
+    }
+    Ok(())
+}
+
+/// how much is in the cache at `dir`
+pub fn size_of(dir: &Path) -> Result<CacheSize> {
+    let mut size = CacheSize::default();
+    match measure(dir, &mut size) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(size),
+        result => result
+            .map(|()| size)
+            .with_context(|| format!("while reading '{}'", dir.display())),
+    }
+}
+
+/// empty the cache at `dir`, returning how much was in it
+pub fn clean(dir: &Path) -> Result<CacheSize> {
+    let size = size_of(dir)?;
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(size),
+        result => result
+            .map(|()| size)
+            .with_context(|| format!("while removing '{}'", dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod test_cache {
+    use super::*;
+
+    #[test]
+    fn only_http_servers_are_cached() {
+        assert!(server_dir("file://./tests/servers/one_addon").is_none());
+        assert!(server_dir("./servers").is_none());
+        if let Some(dir) = server_dir("https://luarocks.org/m/lls-addons") {
+            assert!(dir.ends_with("servers/luarocks.org_m_lls-addons"));
+        }
+    }
+
+    #[test]
+    fn reads_fresh_files_and_cleans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("search/--all");
+        write(&path, b"say\t1.4.1-3\trockspec\thttps://luarocks.org\n");
+        assert!(read(&path, Some(SEARCH_TTL)).is_some());
+        assert!(read(&path, Some(Duration::ZERO)).is_none());
+        assert!(read(&dir.path().join("missing"), None).is_none());
+
+        let size = clean(dir.path()).unwrap();
+        assert_eq!(size.files, 1);
+        assert_eq!(size_of(dir.path()).unwrap(), CacheSize::default());
+    }
+}
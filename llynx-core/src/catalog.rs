@@ -0,0 +1,198 @@
+//! the [LLS-Addons](https://github.com/LuaLS/LLS-Addons) catalog the VS Code
+//! addon manager uses. Each addon is a git submodule of the catalog, with its
+//! definitions in `module/library`
+
+use crate::{
+    Addon, Config,
+    failure::Failure,
+    vendor::{self, Source, Vendored, git_in, run_git, vendor_dir},
+};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// where the catalog is cloned from
+pub const CATALOG_URL: &str = "https://github.com/LuaLS/LLS-Addons.git";
+
+/// an addon listed in the catalog
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CatalogAddon {
+    /// the directory of the addon in the catalog, used to install it
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl CatalogAddon {
+    /// this addon as an [`Addon`]. The catalog has no versions, so it is
+    /// given LuaRocks' version for development code
+    pub fn to_addon(&self) -> Addon {
+        Addon {
+            name: self.name.clone(),
+            version: String::from("scm"),
+            location: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Info {
+    description: Option<String>,
+}
+
+/// where the catalog of `tree` is cloned to
+pub fn catalog_dir(tree: &Path) -> PathBuf {
+    vendor_dir(tree).join("catalog")
+}
+
+fn module_path(name: &str) -> String {
+    format!("addons/{name}/module")
+}
+
+/// clone the catalog into the tree, or bring the clone up to date
+pub fn update(config: &Config) -> Result<()> {
+    let tree = config.tree;
+    let dir = catalog_dir(tree);
+    if dir.join(".git").exists() {
+        run_git(
+            config,
+            git_in(&dir).args(["pull", "--ff-only", "--depth", "1"]),
+        )?;
+    } else {
+        fs::create_dir_all(vendor_dir(tree))
+            .with_context(|| format!("while creating '{}'", vendor_dir(tree).display()))?;
+        run_git(
+            config,
+            Command::new("git")
+                .args(["clone", "--depth", "1", CATALOG_URL])
+                .arg(&dir),
+        )?;
+    }
+    Ok(())
+}
+
+/// the addons in a clone of the catalog, read from their `info.json`
+pub(crate) fn read_catalog(dir: &Path) -> Result<Vec<CatalogAddon>> {
+    let addons_dir = dir.join("addons");
+    let entries = fs::read_dir(&addons_dir)
+        .with_context(|| format!("while reading '{}'", addons_dir.display()))?;
+    let mut addons = vec![];
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let info_path = entry.path().join("info.json");
+        let contents = match fs::read_to_string(&info_path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            result => result.with_context(|| format!("while reading '{}'", info_path.display()))?,
+        };
+        let info: Info = serde_json::from_str(&contents)
+            .with_context(|| format!("while parsing '{}'", info_path.display()))?;
+        addons.push(CatalogAddon {
+            name,
+            description: info.description,
+        });
+    }
+    addons.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(addons)
+}
+
+/// every addon in the catalog, cloning it first if needed
+pub fn list(config: &Config) -> Result<Vec<CatalogAddon>> {
+    let dir = catalog_dir(config.tree);
+    if !dir.join(".git").exists() {
+        update(config)?;
+    }
+    read_catalog(&dir)
+}
+
+/// check out the module of catalog addon `name` and record it as vendored
+pub fn install(config: &Config, name: &str) -> Result<Addon> {
+    update(config)?;
+    let dir = catalog_dir(config.tree);
+    if !dir.join("addons").join(name).is_dir() {
+        return Err(Failure::NotFound
+            .tag(format!("addon '{name}' is not in the catalog"))
+            .into());
+    }
+    run_git(
+        config,
+        git_in(&dir)
+            .args(["submodule", "update", "--init", "--depth", "1", "--"])
+            .arg(module_path(name)),
+    )?;
+
+    let module = dir.join(module_path(name));
+    let library = module.join("library");
+    if !library.is_dir() {
+        bail!("addon '{name}' has no library directory");
+    }
+    let version = run_git(
+        config,
+        git_in(&module).args(["rev-parse", "--short", "HEAD"]),
+    )?;
+    let location = library;
+    vendor::record(
+        config.tree,
+        name,
+        Vendored {
+            source: Source::Catalog,
+            version: version.clone(),
+            location: location.clone(),
+        },
+    )?;
+    Ok(Addon {
+        name: name.to_string(),
+        version,
+        location: Some(location),
+    })
+}
+
+/// take the checked out module of `name` out of the catalog again
+pub(crate) fn remove_module(config: &Config, name: &str) -> Result<()> {
+    run_git(
+        config,
+        git_in(&catalog_dir(config.tree))
+            .args(["submodule", "deinit", "--force", "--"])
+            .arg(module_path(name)),
+    )
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod test_read_catalog {
+    use super::*;
+
+    #[test]
+    fn reads_info_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let addon = |name: &str, info: &str| {
+            let addon_dir = dir.path().join("addons").join(name);
+            fs::create_dir_all(&addon_dir).unwrap();
+            fs::write(addon_dir.join("info.json"), info).unwrap();
+        };
+        addon(
+            "love2d",
+            r#"{ "name": "LÖVE", "description": "A framework for 2D games" }"#,
+        );
+        addon("busted", r#"{ "name": "busted" }"#);
+        fs::create_dir_all(dir.path().join("addons/.github")).unwrap();
+
+        assert_eq!(
+            read_catalog(dir.path()).unwrap(),
+            vec![
+                CatalogAddon {
+                    name: String::from("busted"),
+                    description: None,
+                },
+                CatalogAddon {
+                    name: String::from("love2d"),
+                    description: Some(String::from("A framework for 2D games")),
+                },
+            ]
+        );
+    }
+}
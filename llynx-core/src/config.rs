@@ -0,0 +1,507 @@
+use crate::{
+    addon_settings::ConflictPolicy,
+    bootstrap,
+    enabled::{EnableMode, PathBase, PathStyle},
+    failure::Failure,
+    hooks::Hooks,
+    installed::DepsMode,
+    network::Credentials,
+    pins::Pins,
+    release::UpdateCheck,
+    user_settings::{self, Editor},
+};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const CONFIG_PATH: &str = ".llynx.toml";
+pub const LUAROCKS_PATH: &str = "luarocks";
+pub const ADDONS_DIR: &str = ".lls_addons";
+pub const LUAROCKS_ENDPOINT: &str = "https://luarocks.org/m/lls-addons";
+pub const ROOT_SERVER: &str = "https://luarocks.org";
+pub const SETTINGS_FILE: &str = ".vscode/settings.json";
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// the name of the user config file in [`bootstrap::config_dir`]
+pub const USER_CONFIG_FILE: &str = "config.toml";
+
+/// options read from a config file or the CLI, where every option may be
+/// missing
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MaybeConfig {
+    #[serde(rename = "$schema")]
+    pub schema: Option<String>, // this is unused
+    pub luarocks: Option<PathBuf>,
+    pub tree: Option<PathBuf>,
+    pub settings: Option<PathBuf>,
+    pub user_settings: Option<Editor>,
+    pub server: Option<String>,
+    pub servers: Option<Vec<String>>,
+    pub verbose: Option<u8>,
+    pub deps_mode: Option<DepsMode>,
+    pub lua_version: Option<String>,
+    pub luarocks_args: Option<Vec<String>>,
+    pub luarocks_config: Option<PathBuf>,
+    pub conflicts: Option<ConflictPolicy>,
+    pub path_style: Option<PathStyle>,
+    pub path_base: Option<PathBase>,
+    pub enable_mode: Option<EnableMode>,
+    pub public_key: Option<String>,
+    pub retries: Option<u32>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub insecure: Option<bool>,
+    pub timeout: Option<u64>,
+    pub auth: Option<BTreeMap<String, Credentials>>,
+    pub update_check: Option<UpdateCheck>,
+    pub log_file: Option<PathBuf>,
+    pub assume_yes: Option<bool>,
+    pub hooks: Option<Hooks>,
+    pub gitignore_tree: Option<bool>,
+    pub api_key: Option<String>,
+    pub pins: Option<Pins>,
+    pub folders: Option<Vec<PathBuf>>,
+    pub store: Option<bool>,
+    pub fallback_to_root: Option<bool>,
+}
+
+/// options that belong to the user rather than to a project, read from the
+/// user config file. They're secret, so `.llynx.toml`, which is normally
+/// checked in, can't hold them
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    #[serde(rename = "$schema")]
+    pub schema: Option<String>, // this is unused
+    pub auth: Option<BTreeMap<String, Credentials>>,
+    pub api_key: Option<String>,
+}
+
+impl From<UserConfig> for MaybeConfig {
+    fn from(user_config: UserConfig) -> Self {
+        let UserConfig {
+            schema: _,
+            auth,
+            api_key,
+        } = user_config;
+        MaybeConfig {
+            auth,
+            api_key,
+            ..MaybeConfig::default()
+        }
+    }
+}
+
+/// the options every operation runs with
+#[derive(Debug, Clone, Copy)]
+pub struct Config<'a> {
+    pub luarocks: &'a Path,
+    pub tree: &'a Path,
+    pub settings: &'a Path,
+    pub server: &'a str,
+
+    /// more servers searched along with `server`, all at once. A version
+    /// found on several of them is only listed once
+    pub servers: &'a [String],
+
+    pub verbose: u8,
+
+    /// how LuaRocks installs dependencies, as in `luarocks --deps-mode`.
+    /// LuaRocks decides when this is `None`
+    pub deps_mode: Option<DepsMode>,
+
+    /// the Lua version the tree is built for, as in `luarocks --lua-version`.
+    /// LuaRocks decides when this is `None`
+    pub lua_version: Option<&'a str>,
+
+    /// arguments passed on to LuaRocks whenever it installs or removes a
+    /// rock, after the ones llynx gives it
+    pub luarocks_args: &'a [String],
+
+    /// the LuaRocks config file to use instead of the user's, as in
+    /// `LUAROCKS_CONFIG`
+    pub luarocks_config: Option<&'a Path>,
+
+    /// what to do when two enabled addons want different values for a
+    /// setting
+    pub conflicts: ConflictPolicy,
+
+    /// which separator library entries are written with
+    pub path_style: PathStyle,
+
+    /// whether library entries are relative, absolute, or start with
+    /// `${workspaceFolder}`
+    pub path_base: PathBase,
+
+    /// whether the library points at versions of addons or at links to them
+    pub enable_mode: EnableMode,
+
+    /// the minisign public key of the server, or a file holding it. Rocks
+    /// are only installed from the server when their signature matches
+    pub public_key: Option<&'a str>,
+
+    /// how many times to retry talking to the server when it times out or
+    /// fails with a server error
+    pub retries: u32,
+
+    /// the proxy to reach servers through, overriding `http_proxy` and
+    /// `https_proxy`
+    pub proxy: Option<&'a str>,
+
+    /// a file of PEM certificates to trust instead of the system's, for
+    /// TLS-intercepting proxies and internal registries
+    pub ca_bundle: Option<&'a Path>,
+
+    /// don't check the certificates of servers at all
+    pub insecure: bool,
+
+    /// how many seconds a LuaRocks, git or curl command, or a hook, may run
+    /// before it's killed. They can run forever when this is `None`
+    pub timeout: Option<u64>,
+
+    /// credentials for private servers, by the prefix of their URLs
+    pub auth: Option<&'a BTreeMap<String, Credentials>>,
+
+    /// how often to look for a newer release of llynx in the background
+    pub update_check: UpdateCheck,
+
+    /// a file the whole log is written to, down to the debug level
+    pub log_file: Option<&'a Path>,
+
+    /// delete addons and caches without asking first, even in a terminal
+    pub assume_yes: bool,
+
+    /// commands to run before and after each operation
+    pub hooks: Option<&'a Hooks>,
+
+    /// list a new tree in the `.gitignore` of the workspace
+    pub gitignore_tree: bool,
+
+    /// the luarocks.org API key addons are published with
+    pub api_key: Option<&'a str>,
+
+    /// the versions addons are held at
+    pub pins: Option<&'a Pins>,
+
+    /// workspace folders sharing the tree, each with its own settings file
+    pub folders: &'a [PathBuf],
+
+    /// keep the types of installed rocks once in the per-user store, linked
+    /// into the tree
+    pub store: bool,
+
+    /// install addons the server doesn't have from the main luarocks.org
+    /// repository instead
+    pub fallback_to_root: bool,
+
+    /// look for in-development versions like `scm-1` too, as in
+    /// `luarocks --dev`. Only the command line sets it
+    pub dev: bool,
+
+    /// refuse to change the committed state of the workspace, as `--frozen`
+    /// does. Only the command line sets it
+    pub frozen: bool,
+}
+
+impl Default for Config<'_> {
+    fn default() -> Self {
+        Config {
+            luarocks: Path::new(LUAROCKS_PATH),
+            tree: Path::new(ADDONS_DIR),
+            settings: Path::new(SETTINGS_FILE),
+            server: LUAROCKS_ENDPOINT,
+            servers: &[],
+            verbose: 0,
+            deps_mode: None,
+            lua_version: None,
+            luarocks_args: &[],
+            luarocks_config: None,
+            conflicts: ConflictPolicy::default(),
+            path_style: PathStyle::default(),
+            path_base: PathBase::default(),
+            enable_mode: EnableMode::default(),
+            public_key: None,
+            retries: DEFAULT_RETRIES,
+            proxy: None,
+            ca_bundle: None,
+            insecure: false,
+            timeout: None,
+            auth: None,
+            update_check: UpdateCheck::default(),
+            log_file: None,
+            assume_yes: false,
+            hooks: None,
+            gitignore_tree: true,
+            api_key: None,
+            pins: None,
+            folders: &[],
+            store: false,
+            fallback_to_root: false,
+            dev: false,
+            frozen: false,
+        }
+    }
+}
+
+impl<'a> Config<'a> {
+    /// override options in this config with the ones present in `maybe_config`
+    pub fn extend<'b: 'a>(self, maybe_config: &'b MaybeConfig) -> Self {
+        fn choose_str<'a>(try_str: &'a Option<String>, otherwise: &'a str) -> &'a str {
+            try_str.as_deref().unwrap_or(otherwise)
+        }
+        fn choose_path<'a>(try_path: &'a Option<PathBuf>, otherwise: &'a Path) -> &'a Path {
+            try_path.as_deref().unwrap_or(otherwise)
+        }
+
+        let MaybeConfig {
+            schema: _,
+            luarocks,
+            tree,
+            settings,
+            // already turned into `settings` by `resolve_user_settings`
+            user_settings: _,
+            server,
+            servers,
+            verbose,
+            deps_mode,
+            lua_version,
+            luarocks_args,
+            luarocks_config,
+            conflicts,
+            path_style,
+            path_base,
+            enable_mode,
+            public_key,
+            retries,
+            proxy,
+            ca_bundle,
+            insecure,
+            timeout,
+            auth,
+            update_check,
+            log_file,
+            assume_yes,
+            hooks,
+            gitignore_tree,
+            api_key,
+            pins,
+            folders,
+            store,
+            fallback_to_root,
+        } = maybe_config;
+        Config {
+            luarocks: choose_path(luarocks, self.luarocks),
+            tree: choose_path(tree, self.tree),
+            settings: choose_path(settings, self.settings),
+            server: choose_str(server, self.server),
+            servers: servers.as_deref().unwrap_or(self.servers),
+            verbose: verbose.unwrap_or(self.verbose),
+            deps_mode: deps_mode.or(self.deps_mode),
+            lua_version: lua_version.as_deref().or(self.lua_version),
+            luarocks_args: luarocks_args.as_deref().unwrap_or(self.luarocks_args),
+            luarocks_config: luarocks_config.as_deref().or(self.luarocks_config),
+            conflicts: conflicts.unwrap_or(self.conflicts),
+            path_style: path_style.unwrap_or(self.path_style),
+            path_base: path_base.unwrap_or(self.path_base),
+            enable_mode: enable_mode.unwrap_or(self.enable_mode),
+            public_key: public_key.as_deref().or(self.public_key),
+            retries: retries.unwrap_or(self.retries),
+            proxy: proxy.as_deref().or(self.proxy),
+            ca_bundle: ca_bundle.as_deref().or(self.ca_bundle),
+            insecure: insecure.unwrap_or(self.insecure),
+            timeout: timeout.or(self.timeout),
+            auth: auth.as_ref().or(self.auth),
+            update_check: update_check.unwrap_or(self.update_check),
+            log_file: log_file.as_deref().or(self.log_file),
+            assume_yes: assume_yes.unwrap_or(self.assume_yes),
+            hooks: hooks.as_ref().or(self.hooks),
+            gitignore_tree: gitignore_tree.unwrap_or(self.gitignore_tree),
+            api_key: api_key.as_deref().or(self.api_key),
+            pins: pins.as_ref().or(self.pins),
+            folders: folders.as_deref().unwrap_or(self.folders),
+            store: store.unwrap_or(self.store),
+            fallback_to_root: fallback_to_root.unwrap_or(self.fallback_to_root),
+            dev: self.dev,
+            frozen: self.frozen,
+        }
+    }
+}
+
+impl From<&Config<'_>> for MaybeConfig {
+    /// an owned copy of `config`, which [`Config::extend`] turns back into it.
+    /// `dev` and `frozen` aren't kept, since only the command line sets them
+    fn from(config: &Config<'_>) -> Self {
+        let Config {
+            luarocks,
+            tree,
+            settings,
+            server,
+            servers,
+            verbose,
+            deps_mode,
+            lua_version,
+            luarocks_args,
+            luarocks_config,
+            conflicts,
+            path_style,
+            path_base,
+            enable_mode,
+            public_key,
+            retries,
+            proxy,
+            ca_bundle,
+            insecure,
+            timeout,
+            auth,
+            update_check,
+            log_file,
+            assume_yes,
+            hooks,
+            gitignore_tree,
+            api_key,
+            pins,
+            folders,
+            store,
+            fallback_to_root,
+            dev: _,
+            frozen: _,
+        } = *config;
+        MaybeConfig {
+            schema: None,
+            luarocks: Some(luarocks.to_path_buf()),
+            tree: Some(tree.to_path_buf()),
+            settings: Some(settings.to_path_buf()),
+            user_settings: None,
+            server: Some(server.to_string()),
+            servers: Some(servers.to_vec()),
+            verbose: Some(verbose),
+            deps_mode,
+            lua_version: lua_version.map(str::to_string),
+            luarocks_args: Some(luarocks_args.to_vec()),
+            luarocks_config: luarocks_config.map(Path::to_path_buf),
+            conflicts: Some(conflicts),
+            path_style: Some(path_style),
+            path_base: Some(path_base),
+            enable_mode: Some(enable_mode),
+            public_key: public_key.map(str::to_string),
+            retries: Some(retries),
+            proxy: proxy.map(str::to_string),
+            ca_bundle: ca_bundle.map(Path::to_path_buf),
+            insecure: Some(insecure),
+            timeout,
+            auth: auth.cloned(),
+            update_check: Some(update_check),
+            log_file: log_file.map(Path::to_path_buf),
+            assume_yes: Some(assume_yes),
+            hooks: hooks.cloned(),
+            gitignore_tree: Some(gitignore_tree),
+            api_key: api_key.map(str::to_string),
+            pins: pins.cloned(),
+            folders: Some(folders.to_vec()),
+            store: Some(store),
+            fallback_to_root: Some(fallback_to_root),
+        }
+    }
+}
+
+impl MaybeConfig {
+    /// point `settings` at the user settings file of the editor picked by
+    /// `user_settings`, if any. Entries written there are absolute unless
+    /// `path_base` says otherwise, since they're used from every workspace
+    pub fn resolve_user_settings(&mut self) -> Result<()> {
+        let Some(editor) = self.user_settings else {
+            return Ok(());
+        };
+        if self.settings.is_some() {
+            bail!("'settings' and 'user_settings' can't both be set");
+        }
+        self.settings = Some(user_settings::settings_file(editor)?);
+        self.path_base.get_or_insert(PathBase::Absolute);
+        Ok(())
+    }
+}
+
+/// parse the project config file at `path`, refusing the options that only
+/// the user config file may hold
+fn parse_project(contents: &str, path: &Path) -> Result<MaybeConfig> {
+    let config = toml::from_str::<MaybeConfig>(contents).with_context(|| {
+        Failure::Settings.tag(format!("while parsing config file '{}'", path.display()))
+    })?;
+    let secret = match (&config.auth, &config.api_key) {
+        (Some(_), _) => "auth",
+        (_, Some(_)) => "api_key",
+        (None, None) => return Ok(config),
+    };
+    Err(Failure::Settings
+        .tag(format!(
+            "'{}' can't hold `{secret}`, since it's shared with the project. Put it in '{}' instead",
+            path.display(),
+            user_config_path()
+                .unwrap_or_else(|| PathBuf::from(USER_CONFIG_FILE))
+                .display()
+        ))
+        .into())
+}
+
+/// read a config file that must exist
+pub fn from_file(path: &Path) -> Result<MaybeConfig> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("while opening config file '{}'", path.display()))?;
+    parse_project(&contents, path)
+}
+
+/// read the config file in the current directory, if there is one
+pub fn from_default_file() -> Option<Result<MaybeConfig>> {
+    match fs::read_to_string(CONFIG_PATH) {
+        Err(err) => match err.kind() {
+            io::ErrorKind::NotFound => {
+                log::debug!("default config file not found, using defaults...");
+                None
+            }
+            _ => Some(
+                Err(anyhow::Error::from(err))
+                    .with_context(|| format!("while opening config file '{CONFIG_PATH}'")),
+            ),
+        },
+        Ok(contents) => Some(parse_project(&contents, Path::new(CONFIG_PATH))),
+    }
+}
+
+/// read the config file at `path`, or the default one if `path` is `None`
+pub fn get_file_overrides(path: Option<&Path>) -> Result<Option<MaybeConfig>> {
+    path.map(from_file).or_else(from_default_file).transpose()
+}
+
+/// where the user config file is, if there's a config directory
+pub fn user_config_path() -> Option<PathBuf> {
+    bootstrap::config_dir().map(|dir| dir.join(USER_CONFIG_FILE))
+}
+
+/// read the user config file at `path`, if there is one
+pub fn from_user_file(path: &Path) -> Result<Option<MaybeConfig>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("while opening config file '{}'", path.display()));
+        }
+    };
+    let user_config = toml::from_str::<UserConfig>(&contents).with_context(|| {
+        Failure::Settings.tag(format!("while parsing config file '{}'", path.display()))
+    })?;
+    Ok(Some(MaybeConfig::from(user_config)))
+}
+
+/// read the user config file, if there is one
+pub fn get_user_overrides() -> Result<Option<MaybeConfig>> {
+    match user_config_path() {
+        Some(path) => from_user_file(&path),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,274 @@
+//! `enable --vendor <dir>`: the types of an addon and its dependencies are
+//! copied into the project, each to `<dir>/<name>`, and the library points
+//! at the copies. They can be committed, so collaborators who only read the
+//! code don't need the tree at all
+
+use crate::{
+    Addon, Config,
+    enabled::{
+        self, EnableMode, EntryStyle, Plan, enable_in_library, entry_path, library_entry,
+        list_enabled, resolve_addons,
+    },
+    frozen,
+    history::Operation,
+    installed::list_installed,
+    store,
+    workspace::{self, WorkspaceState},
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// an addon copied into the project, recorded in the workspace state under
+/// the library entry pointing at the copy
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CopiedAddon {
+    pub name: String,
+    pub version: String,
+}
+
+/// an installed addon to copy into the project when a plan is applied
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AddonCopy {
+    pub addon: Addon,
+
+    /// where the copy goes
+    pub to: PathBuf,
+
+    /// the library entry pointing at the copy
+    pub entry: String,
+}
+
+/// where the copy of `name` goes in `dir`
+pub fn copy_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(name)
+}
+
+/// plan enabling `name` and its dependencies from copies of their types in
+/// `dir`. Copies of the same versions there already aren't made again
+pub fn plan_enable(config: &Config, name: &str, dir: &Path) -> Result<Plan> {
+    let enabled = list_enabled(config, None)?;
+    let installed = list_installed(config, None)?;
+    let copied = WorkspaceState::load(config.settings)?
+        .map(|state| state.copied)
+        .unwrap_or_default();
+    // the library points at the copies themselves, never at links
+    let style = EntryStyle {
+        enable_mode: EnableMode::Direct,
+        ..EntryStyle::from(config)
+    };
+
+    let mut copies = vec![];
+    let mut moved = vec![];
+    for addon in resolve_addons(&installed, name, None)? {
+        let to = copy_path(dir, &addon.name);
+        let entry = library_entry(&to, style)?;
+        let current = to.is_dir()
+            && copied
+                .get(&entry)
+                .is_some_and(|copy| copy.name == addon.name && copy.version == addon.version);
+        if !current {
+            copies.push(AddonCopy {
+                addon: addon.clone(),
+                to: to.clone(),
+                entry,
+            });
+        }
+        moved.push(Addon {
+            location: Some(to),
+            ..addon
+        });
+    }
+
+    let operation = Operation::Enable {
+        name: name.to_string(),
+    };
+    let f = enable_in_library(config.tree, moved, enabled, style)?;
+    Ok(Plan {
+        copies,
+        ..enabled::plan(config.settings, operation, f)?
+    })
+}
+
+/// make the copies of a plan and record them in the workspace state. Only
+/// directories recorded as copies are replaced; anything else in the way is
+/// left alone
+pub(crate) fn apply(config: &Config, copies: &[AddonCopy]) -> Result<()> {
+    if copies.is_empty() {
+        return Ok(());
+    }
+    let copied = WorkspaceState::load(config.settings)?
+        .map(|state| state.copied)
+        .unwrap_or_default();
+    for AddonCopy { addon, to, entry } in copies {
+        frozen::check_write(config, to, true)?;
+        let from = addon.types_dir()?;
+        if to.exists() {
+            if !copied.contains_key(entry) {
+                bail!(
+                    "'{}' is in the way of the copy of {} and wasn't copied by llynx",
+                    to.display(),
+                    addon.name
+                );
+            }
+            fs::remove_dir_all(to).with_context(|| format!("while removing '{}'", to.display()))?;
+        }
+        store::copy_dir(from, to)?;
+        log::info!(
+            "copied the types of {} {} to '{}'",
+            addon.name,
+            addon.version,
+            to.display()
+        );
+    }
+    workspace::update(config, WorkspaceState::default, |state| {
+        for AddonCopy { addon, entry, .. } in copies {
+            state.copied.insert(
+                entry.clone(),
+                CopiedAddon {
+                    name: addon.name.clone(),
+                    version: addon.version.clone(),
+                },
+            );
+        }
+    })
+}
+
+/// forget the copies `library` doesn't point at anymore, returning their
+/// entries
+pub(crate) fn forget_unused(state: &mut WorkspaceState, library: &[String]) -> Vec<String> {
+    let unused: Vec<String> = state
+        .copied
+        .keys()
+        .filter(|entry| !library.contains(entry))
+        .cloned()
+        .collect();
+    for entry in &unused {
+        state.copied.remove(entry);
+    }
+    unused
+}
+
+/// delete the copies behind `entries`, which nothing is enabled from anymore
+pub(crate) fn remove(entries: &[String]) -> Result<()> {
+    for entry in entries {
+        let path = entry_path(entry);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("while removing '{}'", path.display()))?;
+            log::info!("removed '{}', which isn't enabled anymore", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_copies {
+    use super::apply as apply_copies;
+    use super::*;
+    use crate::enabled::{apply, read_library};
+
+    #[test]
+    fn copies_and_removes() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        let types = dir.path().join("tree/say/1.4.1-3/types");
+        fs::create_dir_all(&types).unwrap();
+        fs::write(types.join("say.lua"), "---@meta say").unwrap();
+        let say = Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: Some(types),
+        };
+        let to = copy_path(&dir.path().join("types/vendor"), "say");
+        let entry = library_entry(&to, EntryStyle::default()).unwrap();
+        let copy = AddonCopy {
+            addon: say,
+            to: to.clone(),
+            entry: entry.clone(),
+        };
+
+        let plan = enabled::plan(
+            &settings,
+            Operation::Enable {
+                name: String::from("say"),
+            },
+            |mut library| {
+                library.push(entry.clone());
+                library
+            },
+        )
+        .unwrap();
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        apply(
+            &config,
+            Plan {
+                copies: vec![copy],
+                ..plan
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(to.join("say.lua")).unwrap(),
+            "---@meta say"
+        );
+        let state = WorkspaceState::load(&settings).unwrap().unwrap();
+        assert_eq!(state.copied[&entry].version, "1.4.1-3");
+        let listed = list_enabled(&config, None).unwrap();
+        assert_eq!(listed[0].name, "say");
+        assert_eq!(
+            listed[0].location.as_deref(),
+            Some(entry_path(&entry).as_path())
+        );
+
+        let plan = enabled::plan(
+            &settings,
+            Operation::Disable {
+                name: String::from("say"),
+            },
+            |_| vec![],
+        )
+        .unwrap();
+        apply(&config, plan).unwrap();
+        assert!(read_library(&settings).unwrap().is_empty());
+        assert!(!to.exists());
+        let state = WorkspaceState::load(&settings).unwrap().unwrap();
+        assert!(state.copied.is_empty());
+    }
+
+    #[test]
+    fn leaves_other_directories_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        let types = dir.path().join("tree/say/1.4.1-3/types");
+        fs::create_dir_all(&types).unwrap();
+        let to = copy_path(&dir.path().join("types/vendor"), "say");
+        fs::create_dir_all(&to).unwrap();
+        fs::write(to.join("mine.lua"), "return {}").unwrap();
+        let copy = AddonCopy {
+            addon: Addon {
+                name: String::from("say"),
+                version: String::from("1.4.1-3"),
+                location: Some(types),
+            },
+            entry: library_entry(&to, EntryStyle::default()).unwrap(),
+            to: to.clone(),
+        };
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        apply_copies(&config, &[copy]).unwrap_err();
+        assert!(to.join("mine.lua").exists());
+    }
+}
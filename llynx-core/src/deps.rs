@@ -0,0 +1,371 @@
+//! dependency trees of addons, read from their rockspecs
+
+use crate::{
+    Addon, Config,
+    failure::Failure,
+    filter::Filter,
+    list_enabled, list_installed, list_online,
+    online::fetch_rockspec,
+    rockspec::{self, Rockspec},
+    state::TreeState,
+    vendor, version,
+};
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// an addon and everything it depends on
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct DependencyNode {
+    pub name: String,
+
+    /// the version constraints the parent asked for, if any
+    pub constraint: Option<String>,
+
+    /// the version whose rockspec was read, if one was found
+    pub version: Option<String>,
+
+    pub installed: bool,
+    pub enabled: bool,
+
+    /// whether this addon already appears above itself in the tree
+    pub cycle: bool,
+
+    pub dependencies: Vec<DependencyNode>,
+}
+
+/// a rockspec found for an addon, and whether it came from the tree
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub rockspec: Rockspec,
+    pub installed: bool,
+}
+
+/// build the tree below `name`, asking `lookup` for the rockspec of each
+/// addon. Dependencies on Lua itself are left out
+pub fn resolve(
+    name: &str,
+    lookup: &mut impl FnMut(&str) -> Option<Found>,
+    is_enabled: &impl Fn(&str) -> bool,
+) -> DependencyNode {
+    fn visit(
+        node: &mut DependencyNode,
+        path: &mut Vec<String>,
+        lookup: &mut impl FnMut(&str) -> Option<Found>,
+        is_enabled: &impl Fn(&str) -> bool,
+    ) {
+        node.enabled = is_enabled(&node.name);
+        if path.contains(&node.name) {
+            node.cycle = true;
+            return;
+        }
+        let Some(found) = lookup(&node.name) else {
+            return;
+        };
+        node.version = Some(found.rockspec.version);
+        node.installed = found.installed;
+
+        path.push(node.name.clone());
+        for dependency in found.rockspec.dependencies {
+            if dependency.name == "lua" {
+                continue;
+            }
+            let mut child = DependencyNode {
+                name: dependency.name,
+                constraint: dependency.constraint,
+                ..DependencyNode::default()
+            };
+            visit(&mut child, path, lookup, is_enabled);
+            node.dependencies.push(child);
+        }
+        path.pop();
+    }
+
+    let mut root = DependencyNode {
+        name: name.to_string(),
+        ..DependencyNode::default()
+    };
+    visit(&mut root, &mut vec![], lookup, is_enabled);
+    root
+}
+
+/// the installed addons nothing in `roots` depends on, directly or not. Fails
+/// if the dependencies of an addon that is kept can't be read, since anything
+/// could be depending on it
+pub fn orphans(
+    installed: &[Addon],
+    roots: &BTreeSet<String>,
+    read: impl Fn(&Addon) -> Result<Rockspec>,
+) -> Result<Vec<String>> {
+    let mut kept: BTreeSet<&str> = BTreeSet::new();
+    let mut stack: Vec<&str> = roots.iter().map(String::as_str).collect();
+    while let Some(name) = stack.pop() {
+        if !kept.insert(name) {
+            continue;
+        }
+        for addon in installed.iter().filter(|addon| addon.name == name) {
+            let rockspec = read(addon)
+                .with_context(|| format!("while reading the dependencies of '{}'", addon.name))?;
+            for dependency in rockspec.dependencies {
+                if let Some(dependency) = installed.iter().find(|a| a.name == dependency.name) {
+                    stack.push(&dependency.name);
+                }
+            }
+        }
+    }
+
+    let orphans: BTreeSet<&str> = installed
+        .iter()
+        .map(|addon| addon.name.as_str())
+        .filter(|name| !kept.contains(name))
+        .collect();
+    Ok(orphans.into_iter().map(str::to_string).collect())
+}
+
+/// installed addons that LuaRocks pulled in as dependencies and aren't needed
+/// by anything installed explicitly or enabled anymore. Addons llynx didn't
+/// see being installed as a dependency are always kept
+pub fn autoremove_candidates(config: &Config) -> Result<Vec<String>> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let Some(state) = TreeState::load(config.tree)? else {
+        log::warn!(
+            "llynx has no record of which addons in '{}' were installed explicitly",
+            config.tree.display()
+        );
+        return Ok(vec![]);
+    };
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+
+    let roots = state
+        .explicit
+        .into_iter()
+        .chain(enabled.into_iter().map(|addon| addon.name))
+        .collect();
+    // vendored addons have no rockspec, and depend on nothing
+    let read = |addon: &Addon| match addon.location.as_deref() {
+        Some(location) if vendor::is_vendored_path(location) => Ok(Rockspec::default()),
+        _ => rockspec::read_installed(addon),
+    };
+    let mut orphans = orphans(&installed, &roots, read)?;
+    orphans.retain(|name| state.dependencies.contains(name));
+    Ok(orphans)
+}
+
+/// the newest version of `name` among `addons`
+fn newest<'a>(addons: &'a [Addon], name: &str) -> Option<&'a Addon> {
+    addons
+        .iter()
+        .filter(|addon| addon.name == name)
+        .max_by(|a, b| version::compare(&a.version, &b.version))
+}
+
+/// the dependency tree of an installed or online addon. Installed rockspecs
+/// are preferred, and the rest are downloaded from the server
+pub fn dependency_tree(config: &Config, name: &str) -> Result<DependencyNode> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+
+    // addons depended on more than once are only read once
+    let mut cache: HashMap<String, Option<Found>> = HashMap::new();
+    let mut lookup = |name: &str| -> Option<Found> {
+        if let Some(found) = cache.get(name) {
+            return found.clone();
+        }
+        let result = match newest(&installed, name) {
+            Some(addon) => rockspec::read_installed(addon).map(|rockspec| Found {
+                rockspec,
+                installed: true,
+            }),
+            None => list_online(config, Some(&Filter::from(name)))
+                .and_then(|online| {
+                    let addon = newest(&online, name).ok_or_else(|| {
+                        Failure::NotFound.tag(format!("'{name}' was not found on the server"))
+                    })?;
+                    fetch_rockspec(config, name, &addon.version)
+                })
+                .map(|rockspec| Found {
+                    rockspec,
+                    installed: false,
+                }),
+        };
+        let found = result
+            .inspect_err(|err| log::warn!("could not read the rockspec of '{name}': {err:#}"))
+            .ok();
+        cache.insert(name.to_string(), found.clone());
+        found
+    };
+    let is_enabled = |name: &str| enabled.iter().any(|addon| addon.name == name);
+
+    let tree = resolve(name, &mut lookup, &is_enabled);
+    match tree.version {
+        Some(_) => Ok(tree),
+        None => Err(anyhow!("could not find a rockspec for '{name}'")),
+    }
+}
+
+#[cfg(test)]
+mod test_orphans {
+    use super::*;
+    use crate::rockspec::Dependency;
+
+    fn installed(name: &str) -> Addon {
+        Addon {
+            name: name.to_string(),
+            version: String::from("1.0-1"),
+            location: None,
+        }
+    }
+
+    #[test]
+    fn keeps_dependencies_of_roots() {
+        let installed = [
+            installed("framework"),
+            installed("types"),
+            installed("runtime"),
+            installed("leftover"),
+            installed("leftover-dependency"),
+        ];
+        let read = |addon: &Addon| {
+            let dependencies: &[&str] = match addon.name.as_str() {
+                "framework" => &["lua >= 5.1", "types"],
+                "types" => &["runtime >= 1"],
+                "leftover" => &["leftover-dependency"],
+                _ => &[],
+            };
+            Ok(Rockspec {
+                dependencies: dependencies
+                    .iter()
+                    .filter_map(|d| Dependency::parse(d))
+                    .collect(),
+                ..Rockspec::default()
+            })
+        };
+        let roots = BTreeSet::from([String::from("framework")]);
+        assert_eq!(
+            orphans(&installed, &roots, read).unwrap(),
+            vec!["leftover", "leftover-dependency"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_resolve {
+    use super::*;
+    use crate::rockspec::Dependency;
+
+    fn rockspec(name: &str, dependencies: &[&str]) -> Rockspec {
+        Rockspec {
+            package: name.to_string(),
+            version: String::from("1.0-1"),
+            dependencies: dependencies
+                .iter()
+                .filter_map(|dependency| Dependency::parse(dependency))
+                .collect(),
+            ..Rockspec::default()
+        }
+    }
+
+    #[test]
+    fn annotates_nodes() {
+        let mut lookup = |name: &str| match name {
+            "framework" => Some(Found {
+                rockspec: rockspec("framework", &["lua >= 5.1", "types >= 1.0", "missing"]),
+                installed: true,
+            }),
+            "types" => Some(Found {
+                rockspec: rockspec("types", &["framework"]),
+                installed: false,
+            }),
+            _ => None,
+        };
+        let tree = resolve("framework", &mut lookup, &|name| name == "framework");
+        assert_eq!(
+            tree,
+            DependencyNode {
+                name: String::from("framework"),
+                version: Some(String::from("1.0-1")),
+                installed: true,
+                enabled: true,
+                dependencies: vec![
+                    DependencyNode {
+                        name: String::from("types"),
+                        constraint: Some(String::from(">= 1.0")),
+                        version: Some(String::from("1.0-1")),
+                        dependencies: vec![DependencyNode {
+                            name: String::from("framework"),
+                            enabled: true,
+                            cycle: true,
+                            ..DependencyNode::default()
+                        }],
+                        ..DependencyNode::default()
+                    },
+                    DependencyNode {
+                        name: String::from("missing"),
+                        ..DependencyNode::default()
+                    },
+                ],
+                ..DependencyNode::default()
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_autoremove {
+    use super::*;
+    use crate::{
+        backend::{self, Mock},
+        state,
+    };
+    use std::{fs, path::Path, sync::Arc};
+
+    /// an installed addon with a rockspec depending on `dependencies`
+    fn installed(tree: &Path, name: &str, dependencies: &str) -> Addon {
+        let dir = tree.join(name).join("1.0-1");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(format!("{name}-1.0-1.rockspec")),
+            format!("package = '{name}' version = '1.0-1' dependencies = {{ {dependencies} }}"),
+        )
+        .unwrap();
+        Addon {
+            name: name.to_string(),
+            version: String::from("1.0-1"),
+            location: Some(dir.join("types")),
+        }
+    }
+
+    #[test]
+    fn keeps_rocks_not_installed_as_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = tree.join("settings.json");
+        let mock = Arc::new(Mock::new(
+            vec![
+                installed(tree, "framework", "'types'"),
+                installed(tree, "types", ""),
+                installed(tree, "leftover", ""),
+                installed(tree, "preexisting", ""),
+            ],
+            vec![],
+        ));
+        let config = Config {
+            tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        backend::with_backend(mock, || {
+            // rocks installed before llynx kept any state
+            assert!(autoremove_candidates(&config).unwrap().is_empty());
+
+            state::update(tree, |state| {
+                state.explicit.insert(String::from("framework"));
+                state
+                    .dependencies
+                    .extend([String::from("types"), String::from("leftover")]);
+            })
+            .unwrap();
+            assert_eq!(autoremove_candidates(&config).unwrap(), ["leftover"]);
+        });
+    }
+}
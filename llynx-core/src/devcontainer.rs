@@ -0,0 +1,157 @@
+//! setting up addons when a dev container is created, by adding a command
+//! importing the workspace's list of addons to the `postCreateCommand` of
+//! its `devcontainer.json`. Other commands already there keep running
+//! alongside it
+
+use crate::failure::Failure;
+use anyhow::{Context, Result};
+use jsonc_parser::{ParseOptions, parse_to_serde_value};
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+
+/// where a workspace's dev container is configured
+pub const DEVCONTAINER_FILE: &str = ".devcontainer/devcontainer.json";
+
+/// the name of llynx's command when `postCreateCommand` holds several
+pub const COMMAND_NAME: &str = "llynx";
+
+/// the name given to a command that was already there when `postCreateCommand`
+/// becomes an object
+const EXISTING_NAME: &str = "existing";
+
+/// the command importing the addons listed in `list`. LuaRocks is downloaded
+/// when the container doesn't have it, since nobody is there to be asked
+pub fn command(list: &Path) -> String {
+    let list = list.display().to_string();
+    if list.contains(char::is_whitespace) {
+        format!("llynx --bootstrap import '{list}'")
+    } else {
+        format!("llynx --bootstrap import {list}")
+    }
+}
+
+/// the snippet to add to a `devcontainer.json` by hand
+pub fn snippet(command: &str) -> Result<String> {
+    let mut object = Map::new();
+    object.insert(
+        String::from("postCreateCommand"),
+        Value::String(command.into()),
+    );
+    Ok(serde_json::to_string_pretty(&Value::Object(object))?)
+}
+
+/// whether `value` is, or holds, a command running llynx
+fn runs_llynx(value: &Value) -> bool {
+    match value {
+        Value::String(command) => command.split_whitespace().next() == Some("llynx"),
+        Value::Array(args) => args.first().and_then(Value::as_str) == Some("llynx"),
+        Value::Object(commands) => commands.values().any(runs_llynx),
+        _ => false,
+    }
+}
+
+/// `post_create` with `command` running in it, or `None` if llynx already
+/// runs there. Commands already there are kept as a named command next to it
+fn with_command(post_create: Option<Value>, command: &str) -> Option<Value> {
+    let command = Value::String(command.into());
+    match post_create {
+        None | Some(Value::Null) => Some(command),
+        Some(value) if runs_llynx(&value) => None,
+        Some(Value::Object(mut commands)) => {
+            commands.insert(String::from(COMMAND_NAME), command);
+            Some(Value::Object(commands))
+        }
+        Some(existing) => {
+            let mut commands = Map::new();
+            commands.insert(String::from(EXISTING_NAME), existing);
+            commands.insert(String::from(COMMAND_NAME), command);
+            Some(Value::Object(commands))
+        }
+    }
+}
+
+/// add `command` to the `postCreateCommand` of the `devcontainer.json` at
+/// `path`. Returns whether the file changed, which it doesn't when llynx
+/// already runs there
+pub fn update(path: &Path, command: &str) -> Result<bool> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("while reading '{}'", path.display()))?;
+    let value = parse_to_serde_value(&contents, &ParseOptions::default())
+        .with_context(|| Failure::Settings.tag(format!("while parsing '{}'", path.display())))?;
+    let Some(Value::Object(mut object)) = value else {
+        return Err(Failure::Settings
+            .tag(format!("'{}' is not a JSON object", path.display()))
+            .into());
+    };
+    let Some(post_create) = with_command(object.remove("postCreateCommand"), command) else {
+        return Ok(false);
+    };
+    object.insert(String::from("postCreateCommand"), post_create);
+    let mut new = serde_json::to_string_pretty(&Value::Object(object))?;
+    new.push('\n');
+    fs::write(path, new).with_context(|| format!("while writing '{}'", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test_devcontainer {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            command(Path::new("addons.txt")),
+            "llynx --bootstrap import addons.txt"
+        );
+        assert_eq!(
+            command(Path::new("my addons.txt")),
+            "llynx --bootstrap import 'my addons.txt'"
+        );
+    }
+
+    #[test]
+    fn keeps_existing_commands() {
+        let ours = "llynx --bootstrap import addons.txt";
+        assert_eq!(with_command(None, ours), Some(json!(ours)));
+        assert_eq!(
+            with_command(Some(json!("npm ci")), ours),
+            Some(json!({ "existing": "npm ci", "llynx": ours }))
+        );
+        assert_eq!(
+            with_command(Some(json!(["make", "setup"])), ours),
+            Some(json!({ "existing": ["make", "setup"], "llynx": ours }))
+        );
+        assert_eq!(
+            with_command(Some(json!({ "npm": "npm ci" })), ours),
+            Some(json!({ "npm": "npm ci", "llynx": ours }))
+        );
+        assert_eq!(with_command(Some(json!(ours)), ours), None);
+        assert_eq!(
+            with_command(Some(json!({ "setup": ["llynx", "import", "a.txt"] })), ours),
+            None
+        );
+    }
+
+    #[test]
+    fn updates_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("devcontainer.json");
+        fs::write(
+            &path,
+            "{\n  // the image\n  \"image\": \"lua\",\n  \"postCreateCommand\": \"npm ci\",\n}\n",
+        )
+        .unwrap();
+        let ours = "llynx --bootstrap import addons.txt";
+        assert!(update(&path, ours).unwrap());
+        assert!(!update(&path, ours).unwrap());
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "image": "lua",
+                "postCreateCommand": { "existing": "npm ci", "llynx": ours },
+            })
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! how much disk space each installed addon takes, to find what's filling up
+//! a tree. Addons are measured in parallel, since big trees hold many small
+//! files
+
+use crate::{
+    Addon, Config,
+    cache::{self, CacheSize},
+    list_installed,
+    vendor::VENDOR_DIR,
+};
+use anyhow::{Context, Result};
+use std::{
+    path::{Component, Path, PathBuf},
+    thread,
+};
+
+/// the space an installed addon takes
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AddonUsage {
+    pub name: String,
+    pub version: String,
+    pub size: CacheSize,
+}
+
+/// the directory holding everything installed for the addon whose types are
+/// at `location`: the version directory of a rock, or the directory of a
+/// vendored addon
+fn addon_dir(location: &Path) -> PathBuf {
+    let components: Vec<Component> = location.components().collect();
+    match components
+        .iter()
+        .position(|component| component.as_os_str() == VENDOR_DIR)
+    {
+        // llynx-vendor/<source>/<name>
+        Some(i) if components.len() > i + 2 => components[..i + 3].iter().collect(),
+        Some(_) => location.to_path_buf(),
+        None => location.parent().unwrap_or(location).to_path_buf(),
+    }
+}
+
+/// measure `addons` on as many threads as there are cores, largest first
+fn measure(addons: Vec<Addon>) -> Result<Vec<AddonUsage>> {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    let chunk = addons.len().div_ceil(threads).max(1);
+    let mut usages = thread::scope(|scope| {
+        let workers: Vec<_> = addons
+            .chunks(chunk)
+            .map(|addons| {
+                scope.spawn(move || {
+                    addons
+                        .iter()
+                        .map(|addon| {
+                            let size = match &addon.location {
+                                Some(location) => cache::size_of(&addon_dir(location))?,
+                                None => CacheSize::default(),
+                            };
+                            Ok(AddonUsage {
+                                name: addon.name.clone(),
+                                version: addon.version.clone(),
+                                size,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("measuring an addon doesn't panic"))
+            .collect::<Result<Vec<_>>>()
+    })?
+    .concat();
+    usages.sort_by(|a, b| {
+        b.size
+            .bytes
+            .cmp(&a.size.bytes)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.version.cmp(&b.version))
+    });
+    Ok(usages)
+}
+
+/// the space every installed addon takes, largest first
+pub fn disk_usage(config: &Config) -> Result<Vec<AddonUsage>> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    measure(installed)
+}
+
+#[cfg(test)]
+mod test_disk_usage {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn addon_dirs() {
+        assert_eq!(
+            addon_dir(Path::new("tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types")),
+            Path::new("tree/lib/luarocks/rocks-5.1/say/1.4.1-3")
+        );
+        assert_eq!(
+            addon_dir(Path::new("tree/llynx-vendor/git/love2d/library")),
+            Path::new("tree/llynx-vendor/git/love2d")
+        );
+        assert_eq!(
+            addon_dir(Path::new("tree/llynx-vendor/archive/love2d")),
+            Path::new("tree/llynx-vendor/archive/love2d")
+        );
+    }
+
+    #[test]
+    fn largest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let addon = |name: &str, bytes: usize| {
+            let version = dir.path().join(name).join("1.0-1");
+            fs::create_dir_all(version.join("types")).unwrap();
+            fs::write(version.join("types/init.lua"), vec![b'-'; bytes]).unwrap();
+            fs::write(version.join(format!("{name}-1.0-1.rockspec")), "").unwrap();
+            Addon {
+                name: name.into(),
+                version: "1.0-1".into(),
+                location: Some(version.join("types")),
+            }
+        };
+        let addons = vec![addon("small", 10), addon("large", 100)];
+        let usages = measure(addons).unwrap();
+        let sizes: Vec<(&str, u64, u64)> = usages
+            .iter()
+            .map(|usage| (usage.name.as_str(), usage.size.files, usage.size.bytes))
+            .collect();
+        assert_eq!(sizes, [("large", 2, 100), ("small", 2, 10)]);
+    }
+}
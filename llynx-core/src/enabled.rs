@@ -0,0 +1,1417 @@
+use crate::{
+    Addon, Config, LIB_SETTINGS_KEY, addon_settings,
+    copies::{self, AddonCopy},
+    failure::Failure,
+    filter::{self, Filter},
+    frozen,
+    history::{self, Entry, LibraryChange, MovedLink, Operation},
+    hooks,
+    installed::{self, list_installed},
+    interrupt, links, rockspec, vendor, version,
+    workspace::{self, WorkspaceState},
+    wsl, zed,
+};
+use anyhow::{Context, Result, anyhow};
+use jsonc_parser::{ParseOptions, parse_to_serde_value};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    path::{MAIN_SEPARATOR, Path, PathBuf},
+};
+
+/// which separator paths are written into the library with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum PathStyle {
+    /// forward slashes, which every platform understands, so settings files
+    /// can be shared between them
+    #[default]
+    Slash,
+
+    /// the separator of the platform llynx runs on
+    Native,
+}
+
+/// the variable VS Code replaces with the root of the workspace
+pub const WORKSPACE_FOLDER: &str = "${workspaceFolder}";
+
+/// what paths in the library are relative to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum PathBase {
+    /// relative to the workspace, like `.lls_addons/lib/...`
+    #[default]
+    Relative,
+
+    /// starting with `${workspaceFolder}`, so they don't depend on where
+    /// LuaLS resolves relative paths from
+    WorkspaceFolder,
+
+    /// absolute, for trees outside the workspace and workspaces opened
+    /// through a symlink
+    Absolute,
+}
+
+/// what the library points at for an enabled addon
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum EnableMode {
+    /// the `types` directory of the enabled version
+    #[default]
+    Direct,
+
+    /// a link in the tree named after the addon, which is moved to the
+    /// enabled version, so switching versions leaves the settings file alone
+    Link,
+}
+
+/// how paths are written into the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryStyle {
+    pub path_style: PathStyle,
+    pub path_base: PathBase,
+
+    /// whether the settings file is read by VS Code on the other side of
+    /// WSL, so absolute paths have to be translated
+    pub across_wsl: bool,
+
+    pub enable_mode: EnableMode,
+}
+
+impl From<&Config<'_>> for EntryStyle {
+    fn from(config: &Config) -> Self {
+        EntryStyle {
+            path_style: config.path_style,
+            path_base: config.path_base,
+            across_wsl: wsl::across(config.settings),
+            enable_mode: config.enable_mode,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VSCodeSettings {
+    #[serde(
+        rename = "Lua.workspace.library",
+        skip_serializing_if = "Option::is_none"
+    )]
+    library: Option<Vec<String>>,
+
+    #[serde(flatten)]
+    rest: serde_json::Map<String, serde_json::Value>,
+
+    /// the whole file when it's a Zed settings file, to write the settings
+    /// back into
+    #[serde(skip)]
+    zed: Option<serde_json::Value>,
+}
+
+impl Default for VSCodeSettings {
+    fn default() -> Self {
+        VSCodeSettings {
+            library: None,
+            rest: serde_json::Map::new(),
+            zed: None,
+        }
+    }
+}
+
+impl VSCodeSettings {
+    /// the contents to write into `settings_file`
+    fn to_contents(&self, settings_file: &Path) -> Result<String> {
+        match &self.zed {
+            None => Ok(serde_json::to_string(self)?),
+            Some(zed) => {
+                let serde_json::Value::Object(dotted) = serde_json::to_value(self)? else {
+                    unreachable!("settings serialize to an object")
+                };
+                let nested = zed::with_dotted(zed.clone(), dotted)
+                    .with_context(|| format!("while writing '{}'", settings_file.display()))?;
+                Ok(serde_json::to_string(&nested)?)
+            }
+        }
+    }
+}
+
+/// error type for showing multiple errors
+#[derive(Debug)]
+struct AggregateError(Vec<anyhow::Error>);
+
+impl AggregateError {
+    pub fn from_results<T: fmt::Debug>(results: impl Iterator<Item = Result<T>>) -> Result<Vec<T>> {
+        let (oks, errs) =
+            results.partition::<Vec<Result<T>>, fn(&Result<T>) -> bool>(|result| result.is_ok());
+        match errs.len() {
+            0 => Ok(oks.into_iter().map(|ok| ok.unwrap()).collect()),
+            1 => Err(errs
+                .into_iter()
+                .nth(0)
+                .expect("errs is non-empty")
+                .expect_err("result was partitioned into an err")),
+            _ => Err(AggregateError(errs.into_iter().map(|err| err.unwrap_err()).collect()).into()),
+        }
+    }
+}
+
+impl fmt::Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for source in &self.0 {
+            writeln!(f, "{source}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AggregateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0[0].as_ref())
+    }
+}
+
+/// fetches from .vscode/settings.json
+pub fn list_enabled(config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let (tree, settings_file) = (config.tree, config.settings);
+    let contents = match fs::read_to_string(settings_file) {
+        Err(source) => match source.kind() {
+            io::ErrorKind::NotFound => {
+                log::warn!(
+                    "file '{settings_file}' was not found. Assuming empty...",
+                    settings_file = settings_file.display()
+                );
+                return Ok(vec![]);
+            }
+            _ => {
+                return Err(source).with_context(|| {
+                    format!(
+                        "while reading '{settings_file}'",
+                        settings_file = settings_file.display()
+                    )
+                });
+            }
+        },
+        Ok(contents) => contents,
+    };
+
+    let maybe_value_parsed = parse_to_serde_value(&contents, &ParseOptions::default())
+        .with_context(|| {
+            Failure::Settings.tag(format!(
+                "while parsing '{settings_file}'",
+                settings_file = settings_file.display()
+            ))
+        })?;
+    let value_parsed = match maybe_value_parsed {
+        None => {
+            log::warn!(
+                "file '{settings_file}' is empty. Assuming empty...",
+                settings_file = settings_file.display()
+            );
+            return Ok(vec![]);
+        }
+        Some(vscode_settings_parsed) if zed::is_zed(settings_file) => {
+            zed::to_dotted(&vscode_settings_parsed)
+        }
+        Some(vscode_settings_parsed) => vscode_settings_parsed,
+    };
+
+    let vscode_settings =
+        serde_json::from_value::<VSCodeSettings>(value_parsed).with_context(|| {
+            Failure::Settings.tag(format!(
+                "while compiling '{settings_file}'",
+                settings_file = settings_file.display()
+            ))
+        })?;
+
+    let mut library = match vscode_settings.library {
+        None => {
+            log::warn!("key '{LIB_SETTINGS_KEY}' not found. Assuming empty...");
+            return Ok(vec![]);
+        }
+        Some(lib) => lib,
+    };
+
+    // entries written by hand are left alone, once llynx keeps track of them
+    let state = WorkspaceState::load(settings_file)?;
+    if let Some(state) = &state {
+        library.retain(|entry| state.managed.contains(entry));
+    }
+
+    // copies made with `enable --vendor` are known by their entries
+    let copied = state.map(|state| state.copied).unwrap_or_default();
+    let (copied_enabled, library): (Vec<String>, Vec<String>) = library
+        .into_iter()
+        .partition(|entry| copied.contains_key(entry));
+
+    let vendored = vendor::list(tree)?;
+    let (vendored_enabled, library): (Vec<String>, Vec<String>) =
+        library.into_iter().partition(|entry| {
+            vendored
+                .iter()
+                .filter_map(|addon| addon.location.as_deref())
+                .any(|location| points_at(entry, location))
+        });
+
+    let mut addons_unfiltered: Vec<Addon> = AggregateError::from_results(
+        library
+            .into_iter()
+            .filter(|s| is_addon_path(config, s))
+            .map(|s| {
+                let path = resolved_path(&s);
+                // we start at 'types', meaning the version is its parent
+                let version = path.parent().expect("path has at least two parents");
+                // which is a child of the rock name
+                let name = version.parent().expect("path has at least one parent");
+                Ok(Addon {
+                    name: name
+                        .file_name()
+                        .expect("directory starts with addons_matcher")
+                        .to_str()
+                        .ok_or(anyhow!("version directory is not valid UTF-8"))?
+                        .to_string(),
+                    version: version
+                        .file_name()
+                        .expect("directory starts with addons_matcher")
+                        .to_str()
+                        .ok_or(anyhow!("version directory is not valid UTF-8"))?
+                        .to_string(),
+                    location: Some(path.clone()),
+                })
+            }),
+    )?;
+    addons_unfiltered.extend(vendored.into_iter().filter(|addon| {
+        vendored_enabled.iter().any(|entry| {
+            addon
+                .location
+                .as_deref()
+                .is_some_and(|location| points_at(entry, location))
+        })
+    }));
+    addons_unfiltered.extend(copied_enabled.into_iter().map(|entry| {
+        let copy = &copied[&entry];
+        Addon {
+            name: copy.name.clone(),
+            version: copy.version.clone(),
+            location: Some(entry_path(&entry)),
+        }
+    }));
+
+    Ok(filter::apply(addons_unfiltered, filter))
+}
+
+/// whether a library entry points at the types of an addon in the tree, for
+/// the Lua version it's built for
+pub(crate) fn is_addon_path(config: &Config, entry: &str) -> bool {
+    let path = absolute(&resolved_path(entry));
+    let Ok(rest) = path.strip_prefix(absolute(&config.tree.join("lib").join("luarocks"))) else {
+        return false;
+    };
+    let rocks_dir = rest.components().next();
+    rocks_dir.is_some_and(|dir| {
+        installed::is_rocks_dir(&dir.as_os_str().to_string_lossy(), config.lua_version)
+    }) && path.ends_with("types")
+}
+
+fn read_settings(settings_file: &Path) -> Result<VSCodeSettings> {
+    let contents = match fs::read_to_string(settings_file) {
+        Err(source) => match source.kind() {
+            io::ErrorKind::NotFound => String::new(),
+            _ => {
+                return Err(source).with_context(|| {
+                    format!(
+                        "while reading '{settings_file}'",
+                        settings_file = settings_file.display()
+                    )
+                });
+            }
+        },
+        Ok(contents) => contents,
+    };
+
+    let maybe_value_parsed = parse_to_serde_value(&contents, &ParseOptions::default())
+        .with_context(|| {
+            Failure::Settings.tag(format!(
+                "while parsing '{settings_file}'",
+                settings_file = settings_file.display()
+            ))
+        })?;
+    let zed = zed::is_zed(settings_file).then(|| maybe_value_parsed.clone().unwrap_or_default());
+    let mut vscode_settings = match maybe_value_parsed {
+        None => VSCodeSettings::default(),
+        Some(value_parsed) => {
+            let value_parsed = match &zed {
+                Some(zed) => zed::to_dotted(zed),
+                None => value_parsed,
+            };
+            serde_json::from_value::<VSCodeSettings>(value_parsed).with_context(|| {
+                Failure::Settings.tag(format!(
+                    "while compiling '{settings_file}'",
+                    settings_file = settings_file.display()
+                ))
+            })?
+        }
+    };
+    vscode_settings.zed = zed;
+    Ok(vscode_settings)
+}
+
+/// the library of a settings file, empty if it doesn't exist
+pub(crate) fn read_library(settings_file: &Path) -> Result<Vec<String>> {
+    Ok(read_settings(settings_file)?.library.unwrap_or_default())
+}
+
+/// the settings other than the library in a settings file
+pub(crate) fn read_other_settings(
+    settings_file: &Path,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    Ok(read_settings(settings_file)?.rest)
+}
+
+/// a setting other than the library in a settings file, if it's set
+pub(crate) fn read_setting(settings_file: &Path, key: &str) -> Result<Option<serde_json::Value>> {
+    Ok(read_settings(settings_file)?.rest.remove(key))
+}
+
+/// read from the settings file and write to it again, returning the library
+/// before and after. Entries added are marked as managed by llynx, and
+/// entries removed are forgotten
+pub(crate) fn update_library(
+    config: &Config,
+    f: impl FnOnce(Vec<String>) -> Vec<String>,
+) -> Result<LibraryChange> {
+    let settings_file = config.settings;
+    let _busy = interrupt::Busy::start();
+    let _lock = workspace::lock(settings_file)?;
+    let mut vscode_settings = read_settings(settings_file)?;
+    let before = vscode_settings.library.unwrap_or_default();
+    let after = f(before.clone());
+    if !frozen::check_write(config, settings_file, after != before)? {
+        return Ok(LibraryChange {
+            settings: settings_file.to_path_buf(),
+            before,
+            after,
+        });
+    }
+    vscode_settings.library = Some(after.clone());
+
+    let new_contents = vscode_settings.to_contents(settings_file)?;
+    fs::write(settings_file, new_contents)?;
+
+    // before llynx tracked its entries, every addon path was assumed to be
+    // its own
+    let init = || WorkspaceState {
+        managed: before
+            .iter()
+            .filter(|entry| is_addon_path(config, entry))
+            .cloned()
+            .collect(),
+        ..WorkspaceState::default()
+    };
+    let mut unused = vec![];
+    workspace::update(config, init, |state| {
+        state.managed.retain(|entry| after.contains(entry));
+        state.managed.extend(
+            after
+                .iter()
+                .filter(|entry| !before.contains(entry))
+                .cloned(),
+        );
+        unused = copies::forget_unused(state, &after);
+    })?;
+    copies::remove(&unused)?;
+
+    Ok(LibraryChange {
+        settings: settings_file.to_path_buf(),
+        before,
+        after,
+    })
+}
+
+/// change the settings other than the library in the settings file. Nothing
+/// is written if `f` fails
+pub(crate) fn update_settings(
+    config: &Config,
+    f: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>) -> Result<()>,
+) -> Result<()> {
+    let settings_file = config.settings;
+    let _busy = interrupt::Busy::start();
+    let _lock = workspace::lock(settings_file)?;
+    let mut vscode_settings = read_settings(settings_file)?;
+    let before = vscode_settings.rest.clone();
+    f(&mut vscode_settings.rest)?;
+    if !frozen::check_write(config, settings_file, vscode_settings.rest != before)? {
+        return Ok(());
+    }
+    let new_contents = vscode_settings.to_contents(settings_file)?;
+    fs::write(settings_file, new_contents).with_context(|| {
+        format!(
+            "while writing '{settings_file}'",
+            settings_file = settings_file.display()
+        )
+    })
+}
+
+/// write a library change to the journal of `tree`, unless nothing changed
+pub(crate) fn record_change(
+    tree: &Path,
+    operation: Operation,
+    change: LibraryChange,
+    links: Vec<MovedLink>,
+) -> Result<()> {
+    if change.before == change.after && links.is_empty() {
+        return Ok(());
+    }
+    let entry = Entry {
+        links,
+        ..Entry::now(operation, Some(change))
+    };
+    history::record(tree, &entry)
+}
+
+/// move the links of `addons`, returning where they pointed before
+fn move_links(tree: &Path, addons: &[Addon]) -> Result<Vec<MovedLink>> {
+    let mut moved = vec![];
+    for addon in addons {
+        moved.push(MovedLink {
+            name: addon.name.clone(),
+            before: links::target(&links::link_path(tree, &addon.name)),
+        });
+        links::link(tree, addon)?;
+    }
+    Ok(moved)
+}
+
+/// the newest installed version of `name`
+pub(crate) fn newest_installed<'a>(installed: &'a [Addon], name: &str) -> Option<&'a Addon> {
+    installed
+        .iter()
+        .filter(|addon| addon.name == name)
+        .max_by(|a, b| version::compare(&a.version, &b.version))
+}
+
+/// `name` and every installed addon it depends on, directly or not, newest
+/// versions first unless `version` picks one for `name`. Dependencies without
+/// a `types` directory are runtime rocks and get skipped
+pub(crate) fn resolve_addons(
+    installed: &[Addon],
+    name: &str,
+    version: Option<&str>,
+) -> Result<Vec<Addon>> {
+    let find = |name: &str| newest_installed(installed, name);
+    let root = match version {
+        None => find(name)
+            .ok_or_else(|| Failure::NotFound.tag(format!("addon '{name}' is not installed")))?,
+        Some(version) => installed
+            .iter()
+            .find(|addon| addon.name == name && addon.version == version)
+            .ok_or_else(|| {
+                Failure::NotFound.tag(format!(
+                    "version {version} of addon '{name}' is not installed"
+                ))
+            })?,
+    };
+
+    let mut addons = vec![];
+    let mut seen = HashSet::from([name.to_string()]);
+    let mut stack = vec![root];
+    while let Some(addon) = stack.pop() {
+        let location = addon.types_dir()?;
+        if addon.name == name || location.is_dir() {
+            addons.push(addon.clone());
+        } else {
+            log::debug!("skipping '{}', which has no types", addon.name);
+        }
+
+        // vendored addons have no rockspec to read dependencies from
+        if vendor::is_vendored_path(location) {
+            continue;
+        }
+        let rockspec = match rockspec::read_installed(addon) {
+            Ok(rockspec) => rockspec,
+            Err(err) => {
+                log::warn!(
+                    "could not read the dependencies of '{}': {err:#}",
+                    addon.name
+                );
+                continue;
+            }
+        };
+        for dependency in &rockspec.dependencies {
+            if dependency.name == "lua" || !seen.insert(dependency.name.clone()) {
+                continue;
+            }
+            match find(&dependency.name) {
+                Some(dependency) => stack.push(dependency),
+                None => log::warn!(
+                    "'{}' depends on '{}', which is not installed",
+                    addon.name,
+                    dependency.name
+                ),
+            }
+        }
+    }
+    Ok(addons)
+}
+
+/// the library entry pointing at `path`. Settings files are JSON, so paths
+/// have to be valid UTF-8 to be written to them
+pub(crate) fn library_entry(path: &Path, style: EntryStyle) -> Result<String> {
+    let path = match style.path_base {
+        PathBase::Relative => path.to_path_buf(),
+        PathBase::WorkspaceFolder if path.is_relative() => Path::new(WORKSPACE_FOLDER).join(path),
+        PathBase::WorkspaceFolder => path.to_path_buf(),
+        PathBase::Absolute => std::path::absolute(path)
+            .with_context(|| format!("while making '{}' absolute", path.display()))?,
+    };
+    let entry = path.to_str().ok_or_else(|| {
+        anyhow!(
+            "'{}' is not valid UTF-8, so it can't be written to a settings file",
+            path.display()
+        )
+    })?;
+    if style.across_wsl && path.is_absolute() {
+        let translated = match cfg!(windows) {
+            true => wsl::windows_to_mount(entry),
+            false => wsl::to_windows(entry),
+        };
+        if let Some(translated) = translated {
+            return Ok(match style.path_style {
+                PathStyle::Slash => translated.replace('\\', "/"),
+                PathStyle::Native => translated,
+            });
+        }
+    }
+    Ok(match style.path_style {
+        PathStyle::Slash => entry.replace(MAIN_SEPARATOR, "/"),
+        PathStyle::Native => entry.to_string(),
+    })
+}
+
+/// the path a library entry points at, relative to the workspace unless it's
+/// absolute. Entries may have been written on another platform, so either
+/// separator is understood, `${workspaceFolder}` is expanded, and absolute
+/// paths from the other side of WSL are translated
+pub(crate) fn entry_path(entry: &str) -> PathBuf {
+    let translated = match cfg!(windows) {
+        true => wsl::mount_to_windows(entry),
+        false if wsl::is_wsl() => wsl::windows_to_mount(entry),
+        false => None,
+    };
+    if let Some(translated) = translated {
+        return PathBuf::from(translated);
+    }
+    let entry = match entry.strip_prefix(WORKSPACE_FOLDER) {
+        Some(rest) => match rest.trim_start_matches(['/', '\\']) {
+            "" => ".",
+            rest => rest,
+        },
+        None => entry,
+    };
+    match MAIN_SEPARATOR {
+        // Windows already reads forward slashes as separators
+        '\\' => PathBuf::from(entry),
+        _ => PathBuf::from(entry.replace('\\', "/")),
+    }
+}
+
+/// the path a library entry points at, followed through the link of an
+/// addon enabled in `link` mode
+pub(crate) fn resolved_path(entry: &str) -> PathBuf {
+    let path = entry_path(entry);
+    links::target(&path).unwrap_or(path)
+}
+
+/// `path` made absolute against the current directory, or as it is if that
+/// can't be found
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// whether a library entry points at `location`, whether either of them is
+/// relative or absolute, or through the link of an addon
+pub(crate) fn points_at(entry: &str, location: &Path) -> bool {
+    let same = |path: &Path| path == location || absolute(path) == absolute(location);
+    let path = entry_path(entry);
+    same(&path) || links::target(&path).is_some_and(|target| same(&target))
+}
+
+/// add the `types` directories of `addons` to the library, or their links in
+/// `tree` in `link` mode. Only one version of an addon can be enabled, so a
+/// path of another `enabled` version is replaced where it is
+pub(crate) fn enable_in_library(
+    tree: &Path,
+    addons: Vec<Addon>,
+    enabled: Vec<Addon>,
+    style: EntryStyle,
+) -> Result<impl FnOnce(Vec<String>) -> Vec<String>> {
+    let entries = addons
+        .into_iter()
+        .map(|addon| {
+            let location = match style.enable_mode {
+                EnableMode::Direct => addon.types_dir()?.to_path_buf(),
+                EnableMode::Link => links::link_path(tree, &addon.name),
+            };
+            Ok((addon.name, library_entry(&location, style)?))
+        })
+        .collect::<Result<Vec<(String, String)>>>()?;
+    Ok(move |mut library: Vec<String>| {
+        for (name, path) in entries {
+            let stale: Vec<&Path> = enabled
+                .iter()
+                .filter(|other| other.name == name)
+                .filter_map(|other| other.location.as_deref())
+                .filter(|location| !points_at(&path, location))
+                .collect();
+
+            let mut placed = library
+                .iter()
+                .any(|entry| points_at(entry, &entry_path(&path)));
+            library = library
+                .into_iter()
+                .filter_map(|entry| {
+                    match stale.iter().any(|location| points_at(&entry, location)) {
+                        false => Some(entry),
+                        true if placed => None,
+                        true => {
+                            placed = true;
+                            Some(path.clone())
+                        }
+                    }
+                })
+                .collect();
+            if !placed {
+                library.push(path);
+            }
+        }
+        library
+    })
+}
+
+/// a change to the library that hasn't been written yet, so it can be shown
+/// first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub operation: Operation,
+    pub change: LibraryChange,
+
+    /// addons whose links are moved to them, in `link` mode
+    pub links: Vec<Addon>,
+
+    /// addons copied into the project, with `enable --vendor`
+    pub copies: Vec<AddonCopy>,
+}
+
+/// work out how `f` would change the library of `settings_file`
+pub(crate) fn plan(
+    settings_file: &Path,
+    operation: Operation,
+    f: impl FnOnce(Vec<String>) -> Vec<String>,
+) -> Result<Plan> {
+    let before = read_library(settings_file)?;
+    let after = f(before.clone());
+    Ok(Plan {
+        operation,
+        change: LibraryChange {
+            settings: settings_file.to_path_buf(),
+            before,
+            after,
+        },
+        links: vec![],
+        copies: vec![],
+    })
+}
+
+/// the addons whose links in `tree` have to move when enabling `addons` with
+/// `style`
+pub(crate) fn links_for(tree: &Path, addons: &[Addon], style: EntryStyle) -> Vec<Addon> {
+    match style.enable_mode {
+        EnableMode::Direct => vec![],
+        EnableMode::Link => addons
+            .iter()
+            .filter(|addon| !links::is_linked(tree, addon))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// write a planned change to its settings file and journal it, between the
+/// hooks of its operation. Fails if the library was edited after the change
+/// was planned
+pub fn apply(config: &Config, plan: Plan) -> Result<()> {
+    let Plan {
+        operation,
+        change,
+        links,
+        copies,
+    } = plan;
+    let (tree, conflicts) = (config.tree, config.conflicts);
+    let (pre, post) = hooks::library_events(&operation);
+    hooks::run_library(config, pre, &operation)?;
+    addon_settings::check(&change.settings, &change, conflicts)?;
+    let planned = Config {
+        settings: &change.settings,
+        ..*config
+    };
+    // copy first, so a failed copy doesn't leave the library pointing at a
+    // missing directory
+    copies::apply(&planned, &copies)?;
+    let written = update_library(&planned, |library| match library == change.before {
+        true => change.after.clone(),
+        false => library,
+    })?;
+    if written.before != change.before {
+        return Err(anyhow!(
+            "'{}' changed while the {} was being planned",
+            change.settings.display(),
+            operation.verb()
+        ));
+    }
+    let moved = move_links(tree, &links)?;
+    addon_settings::sync(&planned, &written, conflicts)?;
+    record_change(tree, operation.clone(), written, moved)?;
+    hooks::run_library(config, post, &operation)
+}
+
+/// write planned changes, each following the one before it, to their
+/// settings file at once and journal them one by one, between the hooks of
+/// every plan. Fails if the library was edited after they were planned
+pub fn apply_all(config: &Config, plans: Vec<Plan>) -> Result<()> {
+    let (Some(first), Some(last)) = (plans.first(), plans.last()) else {
+        return Ok(());
+    };
+    let operations: Vec<Operation> = plans.iter().map(|plan| plan.operation.clone()).collect();
+    for operation in &operations {
+        hooks::run_library(config, hooks::library_events(operation).0, operation)?;
+    }
+    let before = first.change.before.clone();
+    let after = last.change.after.clone();
+    let whole = LibraryChange {
+        settings: first.change.settings.clone(),
+        before: before.clone(),
+        after: after.clone(),
+    };
+    let (tree, conflicts) = (config.tree, config.conflicts);
+    addon_settings::check(&whole.settings, &whole, conflicts)?;
+    let planned = Config {
+        settings: &whole.settings,
+        ..*config
+    };
+    let written = update_library(&planned, |library| match library == before {
+        true => after,
+        false => library,
+    })?;
+    if written.before != first.change.before {
+        return Err(anyhow!(
+            "'{}' changed while the batch was being planned",
+            written.settings.display()
+        ));
+    }
+    let mut moved = vec![];
+    for plan in &plans {
+        moved.push(move_links(tree, &plan.links)?);
+    }
+    addon_settings::sync(&planned, &written, conflicts)?;
+    for (
+        Plan {
+            operation, change, ..
+        },
+        moved,
+    ) in plans.into_iter().zip(moved)
+    {
+        record_change(tree, operation, change, moved)?;
+    }
+    for operation in &operations {
+        hooks::run_library(config, hooks::library_events(operation).1, operation)?;
+    }
+    Ok(())
+}
+
+/// plan enabling `name` and its dependencies, at `version` or the newest
+/// installed
+fn plan_version(config: &Config, name: &str, version: Option<&str>) -> Result<Plan> {
+    let (tree, style) = (config.tree, EntryStyle::from(config));
+    let enabled = list_enabled(config, None)?;
+    let installed = list_installed(config, None)?;
+    let addons_to_enable = resolve_addons(&installed, name, version)?;
+    let operation = match version {
+        None => Operation::Enable {
+            name: name.to_string(),
+        },
+        Some(version) => Operation::Switch {
+            name: name.to_string(),
+            version: version.to_string(),
+        },
+    };
+    let links = links_for(tree, &addons_to_enable, style);
+    Ok(Plan {
+        links,
+        ..plan(
+            config.settings,
+            operation,
+            enable_in_library(tree, addons_to_enable, enabled, style)?,
+        )?
+    })
+}
+
+/// plan adding the addon to .vscode/settings.json, or `None` if it is already
+/// enabled
+pub fn plan_enable(config: &Config, name: &str) -> Result<Option<Plan>> {
+    if list_enabled(config, Some(&Filter::from(name)))?
+        .into_iter()
+        .any(|addon| addon.name == name)
+    {
+        log::info!("addon '{name}' is already enabled");
+        return Ok(None);
+    }
+
+    plan_version(config, name, None).map(Some)
+}
+
+/// add the addon to .vscode/settings.json
+pub fn enable(config: &Config, name: &str) -> Result<()> {
+    match plan_enable(config, name)? {
+        Some(plan) => apply(config, plan),
+        None => Ok(()),
+    }
+}
+
+/// plan enabling another installed version of the addon instead of the one
+/// enabled now
+pub fn plan_switch(config: &Config, name: &str, version: &str) -> Result<Plan> {
+    plan_version(config, name, Some(version))
+}
+
+/// enable another installed version of the addon in .vscode/settings.json,
+/// replacing the one enabled now
+pub fn switch(config: &Config, name: &str, version: &str) -> Result<()> {
+    let plan = plan_switch(config, name, version)?;
+    apply(config, plan)
+}
+
+/// remove every enabled version of `name` from the library
+pub(crate) fn disable_in_library(
+    name: &str,
+    enabled: Vec<Addon>,
+) -> impl FnOnce(Vec<String>) -> Vec<String> {
+    let paths: Vec<PathBuf> = enabled
+        .into_iter()
+        .filter(|addon| addon.name == name)
+        .filter_map(|addon| addon.location)
+        .collect();
+    move |library| {
+        library
+            .into_iter()
+            .filter(|entry| !paths.iter().any(|path| points_at(entry, path)))
+            .collect()
+    }
+}
+
+/// plan removing the addon from .vscode/settings.json, or `None` if it is
+/// already disabled
+pub fn plan_disable(config: &Config, name: &str) -> Result<Option<Plan>> {
+    let enabled = list_enabled(config, Some(&Filter::from(name)))?;
+    if !enabled.iter().any(|addon| addon.name == name) {
+        log::info!("addon '{name}' is already disabled");
+        return Ok(None);
+    }
+
+    let operation = Operation::Disable {
+        name: name.to_string(),
+    };
+    plan(
+        config.settings,
+        operation,
+        disable_in_library(name, enabled),
+    )
+    .map(Some)
+}
+
+/// remove the addon from .vscode/settings.json
+pub fn disable(config: &Config, name: &str) -> Result<()> {
+    match plan_disable(config, name)? {
+        Some(plan) => apply(config, plan),
+        None => Ok(()),
+    }
+}
+
+/// the library entry enabling `name` would write, pointing at the `types`
+/// directory of its newest installed version, or at its link in `link` mode
+pub fn which(config: &Config, name: &str) -> Result<String> {
+    let (tree, style) = (config.tree, EntryStyle::from(config));
+    let installed = list_installed(config, Some(&Filter::from(name)))?;
+    let addon = newest_installed(&installed, name)
+        .ok_or_else(|| Failure::NotFound.tag(format!("addon '{name}' is not installed")))?;
+    if style.enable_mode == EnableMode::Link {
+        return library_entry(&links::link_path(tree, name), style);
+    }
+    let location = addon.types_dir()?;
+    library_entry(location, style)
+}
+
+/// the entry of `library` enabling `name`, one of the `enabled` addons, as
+/// it's written
+fn enabled_entry(library: Vec<String>, enabled: &[Addon], name: &str) -> Option<String> {
+    let location = enabled
+        .iter()
+        .find(|addon| addon.name == name)?
+        .location
+        .as_deref()?;
+    library.into_iter().find(|entry| points_at(entry, location))
+}
+
+/// the entry in the library of the settings file enabling `name`, as it's
+/// written there
+pub fn which_enabled(config: &Config, name: &str) -> Result<String> {
+    let settings_file = config.settings;
+    let enabled = list_enabled(config, Some(&Filter::from(name)))?;
+    enabled_entry(read_library(settings_file)?, &enabled, name).ok_or_else(|| {
+        Failure::NotFound
+            .tag(format!(
+                "addon '{name}' is not enabled in '{}'",
+                settings_file.display()
+            ))
+            .into()
+    })
+}
+
+#[cfg(all(test, windows))]
+static SAY_ADDON_LOCATION: &str =
+    "tests\\trees\\one_addon\\lib\\luarocks\\rocks-5.1\\say\\1.4.1-3\\types";
+#[cfg(all(test, unix))]
+static SAY_ADDON_LOCATION: &str = "tests/trees/one_addon/lib/luarocks/rocks-5.1/say/1.4.1-3/types";
+
+#[cfg(test)]
+fn installed_at(name: &str, version: &str, location: &str) -> Addon {
+    Addon {
+        name: String::from(name),
+        version: String::from(version),
+        location: Some(PathBuf::from(location)),
+    }
+}
+
+#[cfg(test)]
+mod test_enable {
+    use super::*;
+
+    const NATIVE: EntryStyle = EntryStyle {
+        path_style: PathStyle::Native,
+        path_base: PathBase::Relative,
+        across_wsl: false,
+        enable_mode: EnableMode::Direct,
+    };
+
+    #[test]
+    fn add_from_empty() {
+        let library: Vec<String> = vec![];
+        let say = installed_at("say", "1.4.1-3", SAY_ADDON_LOCATION);
+        let func = enable_in_library(Path::new("tree"), vec![say], vec![], NATIVE).unwrap();
+        let new_library = func(library);
+        assert_eq!(new_library, vec![String::from(SAY_ADDON_LOCATION)]);
+    }
+
+    #[test]
+    fn writes_forward_slashes() {
+        let say = installed_at("say", "1.4.1-3", SAY_ADDON_LOCATION);
+        let func =
+            enable_in_library(Path::new("tree"), vec![say], vec![], EntryStyle::default()).unwrap();
+        assert_eq!(
+            func(vec![]),
+            vec![String::from(
+                "tests/trees/one_addon/lib/luarocks/rocks-5.1/say/1.4.1-3/types"
+            )]
+        );
+    }
+
+    #[test]
+    fn writes_workspace_folder() {
+        let say = installed_at("say", "1.4.1-3", "tree/say/types");
+        let style = EntryStyle {
+            path_base: PathBase::WorkspaceFolder,
+            ..EntryStyle::default()
+        };
+        let func = enable_in_library(Path::new("tree"), vec![say.clone()], vec![], style).unwrap();
+        let library = func(vec![]);
+        assert_eq!(
+            library,
+            vec![String::from("${workspaceFolder}/tree/say/types")]
+        );
+
+        // the same addon written without the variable is recognized
+        let func =
+            enable_in_library(Path::new("tree"), vec![say], vec![], EntryStyle::default()).unwrap();
+        assert_eq!(func(library.clone()), library);
+    }
+
+    #[test]
+    fn writes_absolute_paths() {
+        let say = installed_at("say", "1.4.1-3", "tree/say/types");
+        let style = EntryStyle {
+            path_base: PathBase::Absolute,
+            ..EntryStyle::default()
+        };
+        let func = enable_in_library(Path::new("tree"), vec![say.clone()], vec![], style).unwrap();
+        let library = func(vec![]);
+        let expected = std::env::current_dir().unwrap().join("tree/say/types");
+        assert_eq!(
+            library,
+            vec![expected.to_str().unwrap().replace(MAIN_SEPARATOR, "/")]
+        );
+
+        let func =
+            enable_in_library(Path::new("tree"), vec![say], vec![], EntryStyle::default()).unwrap();
+        assert_eq!(func(library.clone()), library);
+    }
+
+    #[test]
+    fn skips_enabled_paths() {
+        let library = vec![String::from("a")];
+        let func = enable_in_library(
+            Path::new("tree"),
+            vec![installed_at("b", "1", "b"), installed_at("a", "1", "a")],
+            vec![installed_at("a", "1", "a")],
+            NATIVE,
+        )
+        .unwrap();
+        assert_eq!(func(library), vec![String::from("a"), String::from("b")]);
+    }
+
+    #[test]
+    fn replaces_other_versions() {
+        let library = vec![
+            String::from("say-1"),
+            String::from("user/path"),
+            String::from("say-0"),
+        ];
+        let enabled = vec![
+            installed_at("say", "1", "say-1"),
+            installed_at("say", "0", "say-0"),
+        ];
+        let func = enable_in_library(
+            Path::new("tree"),
+            vec![installed_at("say", "2", "say-2")],
+            enabled,
+            NATIVE,
+        )
+        .unwrap();
+        assert_eq!(
+            func(library),
+            vec![String::from("say-2"), String::from("user/path")]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_disable {
+    use super::*;
+
+    #[test]
+    fn remove_from_one() {
+        let library = vec![String::from(SAY_ADDON_LOCATION)];
+        let say = installed_at("say", "1.4.1-3", SAY_ADDON_LOCATION);
+        let func = disable_in_library("say", vec![say]);
+        let new_library = func(library);
+        assert_eq!(new_library, vec![] as Vec<String>);
+    }
+
+    #[test]
+    fn either_separator() {
+        let library = vec![
+            String::from("tree\\lib\\luarocks\\rocks-5.1\\say\\1.4.1-3\\types"),
+            String::from("tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types"),
+        ];
+        let say = installed_at(
+            "say",
+            "1.4.1-3",
+            "tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types",
+        );
+        let func = disable_in_library("say", vec![say]);
+        assert_eq!(func(library), vec![] as Vec<String>);
+    }
+
+    #[test]
+    fn workspace_folder() {
+        let library = vec![String::from(
+            "${workspaceFolder}/tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types",
+        )];
+        let say = installed_at(
+            "say",
+            "1.4.1-3",
+            "tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types",
+        );
+        let func = disable_in_library("say", vec![say]);
+        assert_eq!(func(library), vec![] as Vec<String>);
+    }
+
+    #[test]
+    fn absolute_entries() {
+        let location = "tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types";
+        let absolute = std::env::current_dir().unwrap().join(location);
+        let library = vec![absolute.to_str().unwrap().to_string()];
+        let config = Config {
+            tree: Path::new("tree"),
+            ..Config::default()
+        };
+        assert!(is_addon_path(&config, &library[0]));
+        let func = disable_in_library("say", vec![installed_at("say", "1.4.1-3", location)]);
+        assert_eq!(func(library), vec![] as Vec<String>);
+    }
+}
+
+#[cfg(test)]
+mod test_which {
+    use super::*;
+
+    #[test]
+    fn entry_as_written() {
+        let location = "tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types";
+        let library = vec![
+            String::from("types/love2d"),
+            format!("${{workspaceFolder}}/{location}"),
+        ];
+        let enabled = [installed_at("say", "1.4.1-3", location)];
+        assert_eq!(
+            enabled_entry(library.clone(), &enabled, "say"),
+            Some(library[1].clone())
+        );
+        assert_eq!(enabled_entry(library, &enabled, "busted"), None);
+    }
+}
+
+#[cfg(test)]
+mod test_update_library {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_updates_keep_every_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = &dir.path().join("settings.json");
+        let config = Config {
+            tree: dir.path(),
+            settings,
+            ..Config::default()
+        };
+        thread::scope(|scope| {
+            for worker in 0..4 {
+                scope.spawn(move || {
+                    for i in 0..10 {
+                        update_library(&config, |mut library| {
+                            library.push(format!("{worker}-{i}"));
+                            library
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(read_library(settings).unwrap().len(), 40);
+    }
+}
+
+#[cfg(test)]
+mod test_apply {
+    use super::*;
+    use crate::addon_settings::ConflictPolicy;
+
+    #[test]
+    fn refuses_stale_plans() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = dir.path().join("settings.json");
+        let operation = Operation::Disable {
+            name: String::from("say"),
+        };
+        fs::write(&settings, r#"{"Lua.workspace.library": ["say", "busted"]}"#).unwrap();
+
+        let plan = plan(&settings, operation.clone(), |mut library| {
+            library.retain(|entry| entry != "say");
+            library
+        })
+        .unwrap();
+        assert_eq!(plan.change.after, vec!["busted"]);
+
+        let stale = plan.clone();
+        let config = Config {
+            tree,
+            ..Config::default()
+        };
+        apply(&config, plan).unwrap();
+        assert_eq!(read_library(&settings).unwrap(), vec!["busted"]);
+
+        fs::write(&settings, r#"{"Lua.workspace.library": ["say"]}"#).unwrap();
+        apply(&config, stale).unwrap_err();
+        assert_eq!(read_library(&settings).unwrap(), vec!["say"]);
+    }
+
+    #[test]
+    fn refuses_conflicts_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = dir.path().join("settings.json");
+        let entry = |name: &str, version: &str| {
+            let types = dir.path().join(name).join("types");
+            fs::create_dir_all(&types).unwrap();
+            let config = format!(r#"{{"settings": {{"Lua.runtime.version": "{version}"}}}}"#);
+            fs::write(types.join("config.json"), config).unwrap();
+            types.to_string_lossy().into_owned()
+        };
+        let (luajit, lua51) = (entry("luajit", "LuaJIT"), entry("lua51", "Lua 5.1"));
+        let enable = |entry: String| {
+            let operation = Operation::Enable {
+                name: String::from("addon"),
+            };
+            plan(&settings, operation, |mut library| {
+                library.push(entry);
+                library
+            })
+            .unwrap()
+        };
+
+        let config = Config {
+            tree,
+            conflicts: ConflictPolicy::Error,
+            ..Config::default()
+        };
+        apply(&config, enable(luajit.clone())).unwrap();
+        apply(&config, enable(lua51)).unwrap_err();
+        assert_eq!(read_library(&settings).unwrap(), vec![luajit]);
+        assert_eq!(history::load(tree).unwrap().len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod test_zed {
+    use super::*;
+
+    #[test]
+    fn writes_into_zed_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = dir.path().join(".zed").join("settings.json");
+        fs::create_dir(settings.parent().unwrap()).unwrap();
+        fs::write(
+            &settings,
+            r#"{
+                // kept as is
+                "tab_size": 2,
+                "lsp": { "lua-language-server": { "settings": { "Lua": { "workspace": { "library": ["say"] } } } } }
+            }"#,
+        )
+        .unwrap();
+
+        let config = Config {
+            tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        update_library(&config, |mut library| {
+            library.push(String::from("busted"));
+            library
+        })
+        .unwrap();
+        assert_eq!(read_library(&settings).unwrap(), vec!["say", "busted"]);
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&settings).unwrap()).unwrap();
+        assert_eq!(written["tab_size"], 2);
+        assert_eq!(
+            written["lsp"]["lua-language-server"]["settings"]["Lua"]["workspace"]["library"],
+            serde_json::json!(["say", "busted"])
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_managed {
+    use super::*;
+
+    #[test]
+    fn hand_written_entries_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        let config = Config {
+            tree: Path::new("tree"),
+            settings: &settings,
+            ..Config::default()
+        };
+        let old = "tree/lib/luarocks/rocks-5.1/old/1.0-1/types";
+        let manual = "tree/lib/luarocks/rocks-5.1/manual/1.0-1/types";
+        let new = "tree/lib/luarocks/rocks-5.1/new/1.0-1/types";
+        fs::write(
+            &settings,
+            format!(r#"{{"Lua.workspace.library": ["{old}", "types"]}}"#),
+        )
+        .unwrap();
+
+        // entries from before llynx tracked them are adopted
+        update_library(&config, |mut library| {
+            library.push(new.to_string());
+            library
+        })
+        .unwrap();
+        let state = WorkspaceState::load(&settings).unwrap().unwrap();
+        assert_eq!(
+            state.managed.into_iter().collect::<Vec<_>>(),
+            vec![new, old]
+        );
+
+        // but ones added by hand afterwards aren't
+        fs::write(
+            &settings,
+            format!(r#"{{"Lua.workspace.library": ["{old}", "types", "{new}", "{manual}"]}}"#),
+        )
+        .unwrap();
+        let names: Vec<String> = list_enabled(&config, None)
+            .unwrap()
+            .into_iter()
+            .map(|addon| addon.name)
+            .collect();
+        assert_eq!(names, vec!["old", "new"]);
+
+        update_library(&config, |mut library| {
+            library.retain(|entry| entry != old);
+            library
+        })
+        .unwrap();
+        let state = WorkspaceState::load(&settings).unwrap().unwrap();
+        assert_eq!(state.managed.into_iter().collect::<Vec<_>>(), vec![new]);
+    }
+}
+
+#[cfg(test)]
+mod test_resolve_addons {
+    use super::*;
+
+    /// lay out an installed addon like LuaRocks does, returning it
+    fn install(tree: &Path, name: &str, dependencies: &str, has_types: bool) -> Addon {
+        let version_dir = tree.join(name).join("1.0-1");
+        fs::create_dir_all(&version_dir).unwrap();
+        fs::write(
+            version_dir.join(format!("{name}-1.0-1.rockspec")),
+            format!("package = '{name}' version = '1.0-1' dependencies = {{ {dependencies} }}"),
+        )
+        .unwrap();
+        let types = version_dir.join("types");
+        if has_types {
+            fs::create_dir(&types).unwrap();
+        }
+        Addon {
+            name: name.to_string(),
+            version: String::from("1.0-1"),
+            location: Some(types.clone()),
+        }
+    }
+
+    #[test]
+    fn follows_dependencies() {
+        let tree = tempfile::tempdir().unwrap();
+        let installed = [
+            install(tree.path(), "framework", "'lua >= 5.1', 'types'", true),
+            install(tree.path(), "types", "'runtime', 'missing'", true),
+            install(tree.path(), "runtime", "'framework'", false),
+        ];
+        let addons = resolve_addons(&installed, "framework", None).unwrap();
+        assert_eq!(addons, vec![installed[0].clone(), installed[1].clone()]);
+    }
+}
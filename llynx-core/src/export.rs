@@ -0,0 +1,241 @@
+//! sharing a set of enabled addons as a plain list, one `name@version` per
+//! line or a JSON array, without the rest of the tree. The library can also
+//! be printed as a Lua snippet for lua_ls in Neovim
+
+use crate::{
+    Addon, Config, enable, failure::Failure, installed::has_version, list_enabled, list_installed,
+    online, pins, signature, switch, version::Constraints,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::slice;
+
+/// an addon to install and enable, and optionally which version
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Requirement {
+    pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl From<&Addon> for Requirement {
+    fn from(addon: &Addon) -> Self {
+        Requirement {
+            name: addon.name.clone(),
+            version: Some(addon.version.clone()),
+        }
+    }
+}
+
+/// one `name@version` per line
+pub fn to_text(addons: &[Addon]) -> String {
+    addons
+        .iter()
+        .map(|addon| format!("{}@{}\n", addon.name, addon.version))
+        .collect()
+}
+
+/// an array of `{ "name", "version" }` objects
+pub fn to_json(addons: &[Addon]) -> Result<String> {
+    let requirements: Vec<Requirement> = addons.iter().map(Requirement::from).collect();
+    Ok(serde_json::to_string_pretty(&requirements)?)
+}
+
+/// `s` as a Lua string literal
+fn lua_string(s: &str) -> String {
+    let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+/// a Lua module returning the config of lua_ls with the library of the
+/// addons, for `vim.lsp.config` or nvim-lspconfig. It can't be imported again
+pub fn to_nvim_lspconfig(addons: &[Addon]) -> String {
+    let mut snippet = String::from(
+        "-- the addons enabled by llynx, for lua_ls:\n\
+         --   require(\"lspconfig\").lua_ls.setup(dofile(\"llynx.lua\"))\n\
+         return {\n  settings = {\n    Lua = {\n      workspace = {\n        library = {\n",
+    );
+    for location in addons.iter().filter_map(|addon| addon.location.as_deref()) {
+        let location = location.to_string_lossy();
+        snippet.push_str(&format!("          {},\n", lua_string(&location)));
+    }
+    snippet.push_str("        },\n      },\n    },\n  },\n}\n");
+    snippet
+}
+
+/// what `export` prints the enabled addons as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Format {
+    /// one `name@version` per line
+    #[default]
+    Text,
+
+    /// a JSON array of the addons
+    Json,
+
+    /// a Lua snippet for lua_ls in Neovim
+    NvimLspconfig,
+}
+
+/// `addons` written in `format`
+pub fn render(format: Format, addons: &[Addon]) -> Result<String> {
+    match format {
+        Format::Text => Ok(to_text(addons)),
+        Format::Json => Ok(to_json(addons)? + "\n"),
+        Format::NvimLspconfig => Ok(to_nvim_lspconfig(addons)),
+    }
+}
+
+/// read a list written by [`to_text`] or [`to_json`]. Lines may leave out the
+/// version, and `#` starts a comment
+pub fn parse(contents: &str) -> Result<Vec<Requirement>> {
+    if contents.trim_start().starts_with('[') {
+        return serde_json::from_str(contents).context("while parsing the addon list");
+    }
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            (!line.is_empty()).then_some((i, line))
+        })
+        .map(|(i, line)| {
+            let (name, version) = match line.split_once('@') {
+                Some((name, version)) => (name.trim(), Some(version.trim().to_string())),
+                None => (line, None),
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                bail!("line {} is not 'name@version': '{line}'", i + 1);
+            }
+            Ok(Requirement {
+                name: name.to_string(),
+                version,
+            })
+        })
+        .collect()
+}
+
+/// install `requirement` unless it's installed already, then enable it. A
+/// version constraint picks the newest installed version matching it, or else
+/// the newest one on the server
+pub fn import(config: &Config, requirement: &Requirement) -> Result<()> {
+    let Requirement { name, version } = requirement;
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let version = match pins::version(config.pins, name, version.as_deref())? {
+        Some(constraint) if Constraints::is_constraint(constraint) => {
+            let versions = installed
+                .iter()
+                .filter(|addon| &addon.name == name)
+                .map(|addon| addon.version.as_str());
+            match Constraints::parse(constraint)?.newest(versions) {
+                Some(newest) => Some(newest.to_string()),
+                None => online::resolve_version(config, name, Some(constraint))?,
+            }
+        }
+        version => version.map(String::from),
+    };
+    let is_installed = installed
+        .iter()
+        .any(|addon| &addon.name == name && version.as_ref().is_none_or(|v| &addon.version == v));
+    if !is_installed {
+        let output = signature::install(config, name, version.as_deref())?;
+        if !output.status.success() {
+            return Err(Failure::LuaRocks
+                .tag(format!(
+                    "LuaRocks failed:\n{}",
+                    String::from_utf8_lossy(&output.stderr).trim_end()
+                ))
+                .into());
+        }
+    }
+    match version {
+        Some(version) => switch(config, name, &version),
+        None => enable(config, name),
+    }
+}
+
+/// what keeps the tree and the settings file from matching `requirements`,
+/// one problem per addon, without changing anything. Versions and
+/// constraints are only matched against the tree, so the server isn't asked
+pub fn check(config: &Config, requirements: &[Requirement]) -> Result<Vec<String>> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+    let mut problems = vec![];
+    for Requirement { name, version } in requirements {
+        let version = pins::version(config.pins, name, version.as_deref())?;
+        let shown = match version {
+            Some(version) => format!("'{name}' {version}"),
+            None => format!("'{name}'"),
+        };
+        if !has_version(&installed, name, version)? {
+            problems.push(format!("{shown} is not installed"));
+            continue;
+        }
+        match enabled.iter().find(|addon| &addon.name == name) {
+            None => problems.push(format!("{shown} is not enabled")),
+            Some(addon) if !has_version(slice::from_ref(addon), name, version)? => {
+                problems.push(format!("{shown} is not enabled, {} is", addon.version));
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn requirement(name: &str, version: Option<&str>) -> Requirement {
+        Requirement {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let addons = [Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: None,
+        }];
+        let expected = vec![requirement("say", Some("1.4.1-3"))];
+        assert_eq!(parse(&to_text(&addons)).unwrap(), expected);
+        assert_eq!(parse(&to_json(&addons).unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn nvim_lspconfig() {
+        let addons = [Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: Some(PathBuf::from(
+                ".lls_addons/lib/luarocks/rocks-5.1/say/1.4.1-3/types",
+            )),
+        }];
+        let snippet = render(Format::NvimLspconfig, &addons).unwrap();
+        assert!(snippet.contains(
+            "library = {\n          \".lls_addons/lib/luarocks/rocks-5.1/say/1.4.1-3/types\",\n        },"
+        ));
+        assert_eq!(lua_string(r#"C:\a "b""#), r#""C:\\a \"b\"""#);
+    }
+
+    #[test]
+    fn lines() {
+        let contents =
+            "# shared by the team\nbusted\n\nsay @ 1.4.1-3 # pinned\nluassert@>= 1.8, < 2\n";
+        assert_eq!(
+            parse(contents).unwrap(),
+            vec![
+                requirement("busted", None),
+                requirement("say", Some("1.4.1-3")),
+                requirement("luassert", Some(">= 1.8, < 2")),
+            ]
+        );
+        parse("two names@1.0").unwrap_err();
+    }
+}
@@ -0,0 +1,72 @@
+//! the kinds of failure scripts can tell apart by llynx's exit status. An
+//! error is of a kind when it, or any context added to it, is [`Tagged`]
+//! with that kind. Tagging doesn't change the message
+
+use std::fmt;
+
+/// what kind of thing went wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// an addon that was asked for isn't installed or online
+    NotFound,
+    /// LuaRocks ran, but failed
+    LuaRocks,
+    /// a settings file or config file couldn't be parsed
+    Settings,
+}
+
+impl Failure {
+    /// `message` as an error of this kind, to return or to add as context
+    pub fn tag(self, message: impl fmt::Display) -> Tagged {
+        Tagged {
+            failure: self,
+            message: message.to_string(),
+        }
+    }
+
+    /// the kind of failure `err` is, if it was tagged with one
+    pub fn of(err: &anyhow::Error) -> Option<Failure> {
+        err.downcast_ref::<Tagged>().map(|tagged| tagged.failure)
+    }
+}
+
+/// an error message tagged with the kind of failure it is
+#[derive(Debug)]
+pub struct Tagged {
+    pub failure: Failure,
+    message: String,
+}
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Tagged {}
+
+#[cfg(test)]
+mod test_failure {
+    use super::*;
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn found_through_context() {
+        let err = Err::<(), _>(Failure::NotFound.tag("addon 'say' is not installed"))
+            .context("while enabling 'say'")
+            .unwrap_err();
+        assert_eq!(Failure::of(&err), Some(Failure::NotFound));
+        assert_eq!(
+            format!("{err:#}"),
+            "while enabling 'say': addon 'say' is not installed"
+        );
+
+        let parsed: Result<u32> = "x"
+            .parse::<u32>()
+            .with_context(|| Failure::Settings.tag("while parsing 'settings.json'"));
+        let err = parsed.context("while listing").unwrap_err();
+        assert_eq!(Failure::of(&err), Some(Failure::Settings));
+
+        assert_eq!(Failure::of(&anyhow::anyhow!("no kind")), None);
+    }
+}
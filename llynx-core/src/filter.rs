@@ -0,0 +1,129 @@
+//! matching addon names against the filters given to the `list_*` functions
+
+use crate::Addon;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// a condition addon names have to meet
+#[derive(Debug, Clone)]
+pub enum Filter {
+    /// the name contains this string
+    Contains(String),
+
+    /// the whole name matches this glob, like `lua-*`
+    Glob(glob::Pattern),
+
+    /// the name matches this regular expression somewhere
+    Regex(Regex),
+}
+
+impl Filter {
+    /// a glob if `pattern` has any of `*?[` in it, and a plain substring
+    /// otherwise
+    pub fn parse(pattern: &str) -> Result<Filter> {
+        if pattern.contains(['*', '?', '[']) {
+            let glob = glob::Pattern::new(pattern)
+                .with_context(|| format!("while parsing glob '{pattern}'"))?;
+            Ok(Filter::Glob(glob))
+        } else {
+            Ok(Filter::Contains(pattern.to_string()))
+        }
+    }
+
+    pub fn regex(pattern: &str) -> Result<Filter> {
+        let regex =
+            Regex::new(pattern).with_context(|| format!("while parsing regex '{pattern}'"))?;
+        Ok(Filter::Regex(regex))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Filter::Contains(s) => name.contains(s.as_str()),
+            Filter::Glob(glob) => glob.matches(name),
+            Filter::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// whether `text`, like the description of an addon, mentions what this
+    /// matches. Plain strings are looked for ignoring case, and globs have
+    /// to match a whole word
+    pub fn mentioned_in(&self, text: &str) -> bool {
+        match self {
+            Filter::Contains(s) => text.to_lowercase().contains(&s.to_lowercase()),
+            Filter::Glob(glob) => text
+                .split(|c: char| c.is_whitespace() || matches!(c, ',' | '.' | '(' | ')'))
+                .any(|word| glob.matches(word)),
+            Filter::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// the argument LuaRocks can narrow its own listing with, if any. Its
+    /// results still have to be checked with [`Filter::matches`]
+    pub(crate) fn luarocks_query(&self) -> Option<&str> {
+        match self {
+            Filter::Contains(s) => Some(s),
+            Filter::Glob(_) | Filter::Regex(_) => None,
+        }
+    }
+}
+
+impl From<&str> for Filter {
+    fn from(s: &str) -> Self {
+        Filter::Contains(s.to_string())
+    }
+}
+
+/// keep the addons whose names match `filter`
+pub(crate) fn apply(addons: Vec<Addon>, filter: Option<&Filter>) -> Vec<Addon> {
+    match filter {
+        Some(filter) => addons
+            .into_iter()
+            .filter(|addon| filter.matches(&addon.name))
+            .collect(),
+        None => addons,
+    }
+}
+
+#[cfg(test)]
+mod test_matches {
+    use super::*;
+
+    #[test]
+    fn contains() {
+        let filter = Filter::parse("ust").unwrap();
+        assert!(filter.matches("busted"));
+        assert!(!filter.matches("say"));
+        assert_eq!(filter.luarocks_query(), Some("ust"));
+    }
+
+    #[test]
+    fn glob() {
+        let filter = Filter::parse("lua-*").unwrap();
+        assert!(filter.matches("lua-cjson"));
+        assert!(!filter.matches("lls-lua-cjson"));
+        assert_eq!(filter.luarocks_query(), None);
+        Filter::parse("[").unwrap_err();
+    }
+
+    #[test]
+    fn regex() {
+        let filter = Filter::regex("^lls-").unwrap();
+        assert!(filter.matches("lls-addon"));
+        assert!(!filter.matches("say-lls-"));
+        Filter::regex("(").unwrap_err();
+    }
+
+    #[test]
+    fn mentioned() {
+        let text = "Type definitions for LÖVE (love2d), the game framework";
+        assert!(Filter::parse("LOVE2D").unwrap().mentioned_in(text));
+        assert!(Filter::parse("love*").unwrap().mentioned_in(text));
+        assert!(
+            !Filter::parse("frame*work")
+                .unwrap()
+                .mentioned_in("frame work")
+        );
+        assert!(Filter::regex("game\\s").unwrap().mentioned_in(text));
+        assert!(!Filter::parse("busted").unwrap().mentioned_in(text));
+    }
+}
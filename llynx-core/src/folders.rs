@@ -0,0 +1,53 @@
+//! repositories holding several workspace folders, like a game and its
+//! tools, each with its own settings file but sharing one tree. Library
+//! entries written into a folder point back at the shared tree from there
+
+use anyhow::{Context, Result};
+use std::path::{self, Component, Path, PathBuf};
+
+/// `path` as seen from the directory `base`, going up with `..` where the two
+/// part ways. Both are made absolute first
+pub fn relative_to(path: &Path, base: &Path) -> Result<PathBuf> {
+    let path = path::absolute(path)
+        .with_context(|| format!("while making '{}' absolute", path.display()))?;
+    let base = path::absolute(base)
+        .with_context(|| format!("while making '{}' absolute", base.display()))?;
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    // paths on different Windows drives have nothing in common
+    if path.first() != base.first() {
+        return Ok(path.iter().collect());
+    }
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .collect();
+    relative.extend(&path[common..]);
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+    Ok(relative)
+}
+
+#[cfg(all(test, unix))]
+mod test_folders {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let tree = Path::new("/repo/.lls_addons");
+        assert_eq!(
+            relative_to(tree, Path::new("/repo/apps/game")).unwrap(),
+            Path::new("../../.lls_addons")
+        );
+        assert_eq!(
+            relative_to(tree, Path::new("/repo")).unwrap(),
+            Path::new(".lls_addons")
+        );
+        assert_eq!(
+            relative_to(Path::new("/repo"), Path::new("/repo")).unwrap(),
+            Path::new(".")
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! `--frozen`, for CI: the settings file and the workspace state are the
+//! committed state of a workspace, so nothing may change them, and only
+//! versions the settings file enables may be installed
+
+use crate::{
+    Config, installed, list_enabled,
+    version::{self, Constraints},
+};
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+/// fail if `path` would be changed while `config` is frozen. Returns whether it should be
+/// written, which it shouldn't when frozen, even unchanged, so its formatting
+/// is kept too
+pub(crate) fn check_write(config: &Config, path: &Path, changed: bool) -> Result<bool> {
+    should_write(config.frozen, path, changed)
+}
+
+fn should_write(frozen: bool, path: &Path, changed: bool) -> Result<bool> {
+    match (frozen, changed) {
+        (false, _) => Ok(true),
+        (true, false) => Ok(false),
+        (true, true) => bail!("'{}' would change, which --frozen forbids", path.display()),
+    }
+}
+
+/// the version of `name` to install when `config` is frozen: the enabled one the version
+/// asked for picks, or else the newest enabled one. Fails when no enabled
+/// version fits
+pub fn version(config: &Config, name: &str, version: Option<&str>) -> Result<Option<String>> {
+    if !config.frozen {
+        return Ok(version.map(String::from));
+    }
+    let package = installed::package(name);
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+    let mut versions = enabled
+        .iter()
+        .filter(|addon| addon.name == package)
+        .map(|addon| addon.version.as_str());
+    let asked = installed::local_version(name).or(version);
+    let locked = match asked {
+        None => versions.max_by(|a, b| version::compare(a, b)),
+        Some(constraint) if Constraints::is_constraint(constraint) => {
+            Constraints::parse(constraint)?.newest(versions)
+        }
+        Some(asked) => versions.find(|version| *version == asked),
+    };
+    match (locked, installed::local_package(name)) {
+        // local files hold their version already
+        (Some(_), Some(_)) => Ok(None),
+        (Some(locked), None) => Ok(Some(locked.to_string())),
+        (None, _) => bail!(
+            "'{package}'{} isn't enabled in '{}', which --frozen needs before installing it",
+            asked.map(|asked| format!(" {asked}")).unwrap_or_default(),
+            config.settings.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test_frozen {
+    use super::*;
+
+    #[test]
+    fn unchanged_files_are_kept() {
+        let path = Path::new("settings.json");
+        assert!(should_write(false, path, true).unwrap());
+        assert!(!should_write(true, path, false).unwrap());
+        assert_eq!(
+            should_write(true, path, true).unwrap_err().to_string(),
+            "'settings.json' would change, which --frozen forbids"
+        );
+    }
+}
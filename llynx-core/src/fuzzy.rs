@@ -0,0 +1,84 @@
+//! approximate name matching for filters, so `bustd` still finds `busted`
+
+use std::cmp::Reverse;
+
+/// points for each matched character
+const MATCH: i64 = 16;
+
+/// extra points when a character directly follows the previous match
+const CONSECUTIVE: i64 = 24;
+
+/// extra points when a character starts the name or a word in it
+const WORD_START: i64 = 8;
+
+/// points lost for each character skipped between matches
+const GAP: i64 = 3;
+
+/// points lost for each character skipped before the first match
+const LEADING_GAP: i64 = 1;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '-' | '_' | '.' | ' ')
+}
+
+/// how well `pattern` matches `name`, ignoring case. Every character of
+/// `pattern` has to appear in `name` in order; higher scores are better
+pub fn score(pattern: &str, name: &str) -> Option<i64> {
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+
+    for wanted in pattern.chars().flat_map(char::to_lowercase) {
+        let found = next + name[next..].iter().position(|&c| c == wanted)?;
+        score += MATCH;
+        match last_match {
+            Some(last) if last + 1 == found => score += CONSECUTIVE,
+            Some(last) => score -= GAP * (found - last - 1) as i64,
+            None => score -= LEADING_GAP * found as i64,
+        }
+        if found == 0 || is_separator(name[found - 1]) {
+            score += WORD_START;
+        }
+        last_match = Some(found);
+        next = found + 1;
+    }
+
+    // prefer names with less left over
+    Some(score - (name.len() - next) as i64)
+}
+
+/// keep the items whose names match `pattern`, best matches first. Items with
+/// equal scores are sorted by name, keeping their order otherwise
+pub fn rank<T>(items: Vec<T>, pattern: &str, name: impl Fn(&T) -> &str) -> Vec<T> {
+    let mut scored: Vec<(i64, T)> = items
+        .into_iter()
+        .filter_map(|item| Some((score(pattern, name(&item))?, item)))
+        .collect();
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        (Reverse(a_score), name(a)).cmp(&(Reverse(b_score), name(b)))
+    });
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod test_rank {
+    use super::*;
+
+    #[test]
+    fn skips_characters() {
+        assert!(score("bustd", "busted").is_some());
+        assert!(score("BUSTED", "busted").is_some());
+        assert!(score("bustd", "say").is_none());
+        assert!(score("dtsub", "busted").is_none());
+    }
+
+    #[test]
+    fn best_first() {
+        let names = vec!["lls-busted", "bustling-dns", "say", "busted", "busted"];
+        assert_eq!(
+            rank(names, "bustd", |name| name),
+            vec!["busted", "busted", "lls-busted", "bustling-dns"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! addons installed straight from a git repository, pinned to the revision
+//! that was checked out
+
+use crate::{
+    Addon, Config,
+    vendor::{self, Source, Vendored, find_library, git_in, run_git, vendor_dir},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// where addon `name` is cloned to in `tree`
+pub fn clone_dir(tree: &Path, name: &str) -> PathBuf {
+    vendor_dir(tree).join("git").join(name)
+}
+
+/// the addon name a repository URL suggests, like `busted` for
+/// `https://github.com/user/busted.git`
+pub fn name_from_url(url: &str) -> Option<&str> {
+    let last = url.trim_end_matches('/').rsplit(['/', ':']).next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    (!name.is_empty()).then_some(name)
+}
+
+/// clone `url` into the tree as addon `name`, or the name its URL suggests,
+/// and check out `rev`, or the default branch
+pub fn install(config: &Config, url: &str, rev: Option<&str>, name: Option<&str>) -> Result<Addon> {
+    let tree = config.tree;
+    let name = match name {
+        Some(name) => name,
+        None => {
+            name_from_url(url).ok_or_else(|| anyhow!("could not name an addon after '{url}'"))?
+        }
+    };
+    vendor::check_name(name)?;
+    // git would take a revision like `--upload-pack=...` for an option
+    if let Some(rev) = rev.filter(|rev| rev.starts_with('-')) {
+        bail!("'{rev}' can't name a revision");
+    }
+    let dir = clone_dir(tree, name);
+    if dir.exists() {
+        bail!("'{}' already exists, remove '{name}' first", dir.display());
+    }
+    let parent = dir.parent().expect("clone directories have a parent");
+    fs::create_dir_all(parent).with_context(|| format!("while creating '{}'", parent.display()))?;
+
+    run_git(
+        config,
+        Command::new("git")
+            .arg("clone")
+            .arg("--")
+            .arg(url)
+            .arg(&dir),
+    )?;
+    let result = (|| {
+        if let Some(rev) = rev {
+            run_git(config, git_in(&dir).args(["checkout", "--detach", rev]))?;
+        }
+        let rev = run_git(config, git_in(&dir).args(["rev-parse", "HEAD"]))?;
+        let library = find_library(&dir)
+            .ok_or_else(|| anyhow!("'{url}' has no library or types directory"))?;
+        Ok((rev, library))
+    })();
+    let (rev, location) = match result {
+        Ok(found) => found,
+        Err(err) => {
+            // don't leave a clone behind that nothing records
+            let _ = fs::remove_dir_all(&dir);
+            return Err(err);
+        }
+    };
+
+    let version = rev.chars().take(7).collect::<String>();
+    vendor::record(
+        tree,
+        name,
+        Vendored {
+            source: Source::Git {
+                url: url.to_string(),
+                rev,
+            },
+            version: version.clone(),
+            location: location.clone(),
+        },
+    )?;
+    Ok(Addon {
+        name: name.to_string(),
+        version,
+        location: Some(location),
+    })
+}
+
+/// delete the clone of addon `name`
+pub(crate) fn remove_clone(tree: &Path, name: &str) -> Result<()> {
+    let dir = clone_dir(tree, name);
+    fs::remove_dir_all(&dir).with_context(|| format!("while removing '{}'", dir.display()))
+}
+
+#[cfg(test)]
+mod test_name_from_url {
+    use super::*;
+
+    #[test]
+    fn strips_paths_and_suffixes() {
+        assert_eq!(
+            name_from_url("https://github.com/user/busted.git"),
+            Some("busted")
+        );
+        assert_eq!(name_from_url("https://github.com/user/say/"), Some("say"));
+        assert_eq!(
+            name_from_url("git@github.com:user/luassert.git"),
+            Some("luassert")
+        );
+        assert_eq!(name_from_url("https://github.com/"), Some("github.com"));
+        assert_eq!(name_from_url(""), None);
+    }
+}
+
+#[cfg(test)]
+mod test_install {
+    use super::*;
+
+    #[test]
+    fn names_stay_in_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            ..Config::default()
+        };
+        for (url, name) in [
+            ("https://github.com/user/say.git", Some("../../say")),
+            ("https://github.com/user/..", None),
+        ] {
+            install(&config, url, None, name).unwrap_err();
+        }
+        assert!(!tree.exists());
+    }
+
+    #[test]
+    fn revisions_arent_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            ..Config::default()
+        };
+        let url = "https://github.com/user/say.git";
+        install(&config, url, Some("--upload-pack=touch pwned"), None).unwrap_err();
+        assert!(!tree.exists());
+    }
+}
@@ -0,0 +1,129 @@
+//! keeping the tree out of git, by listing it in the `.gitignore` of the
+//! workspace inside a section marked as llynx's own. Lines outside the
+//! section are never touched
+
+use anyhow::{Context, Result};
+use std::{
+    fs, io,
+    path::{self, Component, Path},
+};
+
+/// the line starting llynx's section
+pub const BEGIN_MARKER: &str = "# >>> llynx";
+
+/// the line ending llynx's section
+pub const END_MARKER: &str = "# <<< llynx";
+
+/// the pattern ignoring `tree` in a `.gitignore` in `dir`, if the tree is
+/// inside it
+fn entry(dir: &Path, tree: &Path) -> Option<String> {
+    let dir = path::absolute(dir).ok()?;
+    let tree = path::absolute(tree).ok()?;
+    let relative = tree.strip_prefix(&dir).ok()?;
+    let parts = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    (!parts.is_empty()).then(|| format!("/{}/", parts.join("/")))
+}
+
+/// `contents` with llynx's section holding just `entry`, replacing the
+/// section if there is one and adding it to the end otherwise
+fn with_entry(contents: &str, entry: &str) -> String {
+    let section = format!("{BEGIN_MARKER}\n{entry}\n{END_MARKER}\n");
+    let lines: Vec<&str> = contents.lines().collect();
+    let begin = lines.iter().position(|line| line.trim() == BEGIN_MARKER);
+    let end = begin.and_then(|begin| {
+        lines[begin..]
+            .iter()
+            .position(|line| line.trim() == END_MARKER)
+            .map(|end| begin + end)
+    });
+    match (begin, end) {
+        (Some(begin), Some(end)) => {
+            let mut new = String::new();
+            for line in &lines[..begin] {
+                new.push_str(line);
+                new.push('\n');
+            }
+            new.push_str(&section);
+            for line in &lines[end + 1..] {
+                new.push_str(line);
+                new.push('\n');
+            }
+            new
+        }
+        _ if contents.is_empty() => section,
+        _ if contents.ends_with('\n') => format!("{contents}\n{section}"),
+        _ => format!("{contents}\n\n{section}"),
+    }
+}
+
+/// whether `dir` is inside a git repository
+fn in_git_repository(dir: &Path) -> bool {
+    path::absolute(dir).is_ok_and(|dir| dir.ancestors().any(|dir| dir.join(".git").exists()))
+}
+
+/// list `tree` in the `.gitignore` of `dir` when `dir` is in a git
+/// repository and the tree is inside it. Returns whether the file changed
+pub fn ignore_tree(dir: &Path, tree: &Path) -> Result<bool> {
+    let Some(entry) = entry(dir, tree) else {
+        return Ok(false);
+    };
+    if !in_git_repository(dir) {
+        return Ok(false);
+    }
+    let path = dir.join(".gitignore");
+    let contents = match fs::read_to_string(&path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        result => result.with_context(|| format!("while reading '{}'", path.display()))?,
+    };
+    let new = with_entry(&contents, &entry);
+    if new == contents {
+        return Ok(false);
+    }
+    fs::write(&path, new).with_context(|| format!("while writing '{}'", path.display()))?;
+    log::info!("added '{entry}' to '{}'", path.display());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test_gitignore {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let section = "# >>> llynx\n/.lls_addons/\n# <<< llynx\n";
+        assert_eq!(with_entry("", "/.lls_addons/"), section);
+        assert_eq!(
+            with_entry("target", "/.lls_addons/"),
+            format!("target\n\n{section}")
+        );
+        let contents = format!("target\n{section}*.log\n");
+        assert_eq!(with_entry(&contents, "/.lls_addons/"), contents);
+        assert_eq!(
+            with_entry(&contents, "/lib/addons/"),
+            "target\n# >>> llynx\n/lib/addons/\n# <<< llynx\n*.log\n"
+        );
+    }
+
+    #[test]
+    fn only_inside_repositories() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join(".lls_addons");
+        assert!(!ignore_tree(dir.path(), &tree).unwrap());
+        assert!(!dir.path().join(".gitignore").exists());
+
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        assert!(ignore_tree(dir.path(), &tree).unwrap());
+        assert!(!ignore_tree(dir.path(), &tree).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+            "# >>> llynx\n/.lls_addons/\n# <<< llynx\n"
+        );
+        assert!(!ignore_tree(dir.path(), Path::new("/elsewhere")).unwrap());
+    }
+}
@@ -0,0 +1,462 @@
+//! a journal of the changes llynx made to a tree and settings files, so they
+//! can be listed and undone
+
+use crate::{
+    Config, addon_settings, backend,
+    enabled::{read_library, update_library},
+    failure::Failure,
+    installed, links, signature,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// the name of the journal, relative to the tree. Each line is one [`Entry`]
+pub const HISTORY_FILE: &str = "llynx-history.jsonl";
+
+/// a change llynx made
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+pub enum Operation {
+    Install { name: String, versions: Vec<String> },
+    Remove { name: String, versions: Vec<String> },
+    Enable { name: String },
+    Disable { name: String },
+    Switch { name: String, version: String },
+    Adopt { name: String },
+    Repair { name: String },
+}
+
+impl Operation {
+    /// the command that made this change
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Operation::Install { .. } => "install",
+            Operation::Remove { .. } => "remove",
+            Operation::Enable { .. } => "enable",
+            Operation::Disable { .. } => "disable",
+            Operation::Switch { .. } => "switch",
+            Operation::Adopt { .. } => "adopt",
+            Operation::Repair { .. } => "repair",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Operation::Install { name, .. }
+            | Operation::Remove { name, .. }
+            | Operation::Enable { name }
+            | Operation::Disable { name }
+            | Operation::Switch { name, .. }
+            | Operation::Adopt { name }
+            | Operation::Repair { name } => name,
+        }
+    }
+
+    /// the versions this change was about, if any
+    pub fn versions(&self) -> &[String] {
+        match self {
+            Operation::Install { versions, .. } | Operation::Remove { versions, .. } => versions,
+            Operation::Switch { version, .. } => std::slice::from_ref(version),
+            Operation::Enable { .. }
+            | Operation::Disable { .. }
+            | Operation::Adopt { .. }
+            | Operation::Repair { .. } => &[],
+        }
+    }
+}
+
+/// the library before and after a change to a settings file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LibraryChange {
+    pub settings: PathBuf,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// a link moved by a change, in `link` mode
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MovedLink {
+    pub name: String,
+
+    /// what the link pointed at before, if it was there
+    pub before: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// seconds since the Unix epoch
+    pub time: u64,
+
+    #[serde(flatten)]
+    pub operation: Operation,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<LibraryChange>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<MovedLink>,
+}
+
+impl Entry {
+    pub fn now(operation: Operation, library: Option<LibraryChange>) -> Entry {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        Entry {
+            time,
+            operation,
+            library,
+            links: vec![],
+        }
+    }
+}
+
+fn history_path(tree: &Path) -> PathBuf {
+    PathBuf::from(tree).join(HISTORY_FILE)
+}
+
+/// append an entry to the journal of `tree`
+pub fn record(tree: &Path, entry: &Entry) -> Result<()> {
+    let path = history_path(tree);
+    fs::create_dir_all(tree)
+        .with_context(|| format!("while creating '{tree}'", tree = tree.display()))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("while opening '{}'", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("while writing '{}'", path.display()))
+}
+
+fn read_entries(path: &Path) -> Result<Vec<Entry>> {
+    let contents = match fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        result => result.with_context(|| format!("while reading '{}'", path.display()))?,
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("while parsing line {} of '{}'", i + 1, path.display()))
+        })
+        .collect()
+}
+
+/// every entry in the journal of `tree`, oldest first
+pub fn load(tree: &Path) -> Result<Vec<Entry>> {
+    read_entries(&history_path(tree))
+}
+
+/// take the newest entry out of the journal of `tree`
+pub fn pop(tree: &Path) -> Result<Option<Entry>> {
+    let path = history_path(tree);
+    let mut entries = read_entries(&path)?;
+    let last = entries.pop();
+    if last.is_some() {
+        let mut contents = String::new();
+        for entry in &entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("while writing '{}'", path.display()))?;
+    }
+    Ok(last)
+}
+
+/// versions of `name` in the tree, read from its directories instead of
+/// asking LuaRocks
+pub fn installed_versions(config: &Config, name: &str) -> Vec<String> {
+    let Ok(rock_dirs) = fs::read_dir(config.tree.join("lib").join("luarocks")) else {
+        return vec![];
+    };
+    let mut versions: Vec<String> = rock_dirs
+        .flatten()
+        .filter(|dir| {
+            installed::is_rocks_dir(&dir.file_name().to_string_lossy(), config.lua_version)
+        })
+        .filter_map(|dir| fs::read_dir(dir.path().join(name)).ok())
+        .flat_map(|versions| versions.flatten())
+        .filter(|version| version.path().is_dir())
+        .filter_map(|version| version.file_name().into_string().ok())
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// the names of every rock in the tree, read from its directories instead of
+/// asking LuaRocks
+pub fn installed_rocks(config: &Config) -> BTreeSet<String> {
+    let Ok(rock_dirs) = fs::read_dir(config.tree.join("lib").join("luarocks")) else {
+        return BTreeSet::new();
+    };
+    rock_dirs
+        .flatten()
+        .filter(|dir| {
+            installed::is_rocks_dir(&dir.file_name().to_string_lossy(), config.lua_version)
+        })
+        .filter_map(|dir| fs::read_dir(dir.path()).ok())
+        .flat_map(|rocks| rocks.flatten())
+        .filter(|rock| rock.path().is_dir())
+        .filter_map(|rock| rock.file_name().into_string().ok())
+        .collect()
+}
+
+/// fail with what LuaRocks said if a command for an undo failed
+fn check_luarocks(output: std::process::Output) -> Result<std::process::Output> {
+    if !output.status.success() {
+        return Err(Failure::LuaRocks
+            .tag(format!(
+                "LuaRocks failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ))
+            .into());
+    }
+    Ok(output)
+}
+
+/// reverse the newest entry in the journal of `tree` and return it, or `None`
+/// if the journal is empty. Undoing isn't journaled itself
+pub fn undo(config: &Config) -> Result<Option<Entry>> {
+    let Some(entry) = load(config.tree)?.pop() else {
+        return Ok(None);
+    };
+
+    if let Some(library) = &entry.library {
+        let current = read_library(&library.settings)?;
+        if current != library.after {
+            bail!(
+                "'{}' changed since the last {}, so it can't be undone",
+                library.settings.display(),
+                entry.operation.verb()
+            );
+        }
+    }
+
+    let backend = backend::current();
+    match &entry.operation {
+        Operation::Install { name, versions } => {
+            for version in versions {
+                let output = check_luarocks(backend.remove(config, name, Some(version))?)?;
+                installed::unmark_explicit(config, name, &output)?;
+            }
+        }
+        Operation::Remove { name, versions } => {
+            for version in versions {
+                check_luarocks(signature::reinstall(config, name, version)?)?;
+            }
+        }
+        Operation::Enable { .. }
+        | Operation::Disable { .. }
+        | Operation::Switch { .. }
+        | Operation::Adopt { .. }
+        | Operation::Repair { .. } => {}
+    }
+    if let Some(library) = &entry.library {
+        let at = Config {
+            settings: &library.settings,
+            ..*config
+        };
+        let change = update_library(&at, |_| library.before.clone())?;
+        addon_settings::sync(&at, &change, config.conflicts)?;
+    }
+    for MovedLink { name, before } in &entry.links {
+        match before {
+            Some(target) => links::link_to(config.tree, name, target)?,
+            None => links::unlink(config.tree, name)?,
+        }
+    }
+
+    pop(config.tree)?;
+    Ok(Some(entry))
+}
+
+#[cfg(test)]
+mod test_journal {
+    use super::*;
+
+    #[test]
+    fn record_and_pop() {
+        let tree = tempfile::tempdir().unwrap();
+        let tree = tree.path();
+        let install = Entry::now(
+            Operation::Install {
+                name: String::from("say"),
+                versions: vec![String::from("1.4.1-3")],
+            },
+            None,
+        );
+        let enable = Entry::now(
+            Operation::Enable {
+                name: String::from("say"),
+            },
+            Some(LibraryChange {
+                settings: PathBuf::from(".vscode/settings.json"),
+                before: vec![],
+                after: vec![String::from("say")],
+            }),
+        );
+        record(tree, &install).unwrap();
+        record(tree, &enable).unwrap();
+        assert_eq!(load(tree).unwrap(), vec![install.clone(), enable.clone()]);
+
+        assert_eq!(pop(tree).unwrap(), Some(enable));
+        assert_eq!(load(tree).unwrap(), vec![install]);
+    }
+
+    #[test]
+    fn reads_versions_from_the_tree() {
+        let tree = tempfile::tempdir().unwrap();
+        let rocks = tree.path().join("lib/luarocks/rocks-5.1/say");
+        fs::create_dir_all(rocks.join("1.4.1-3")).unwrap();
+        fs::create_dir_all(rocks.join("1.3-1")).unwrap();
+        let config = Config {
+            tree: tree.path(),
+            ..Config::default()
+        };
+        assert_eq!(installed_versions(&config, "say"), vec!["1.3-1", "1.4.1-3"]);
+        assert!(installed_versions(&config, "busted").is_empty());
+        let other_lua = Config {
+            lua_version: Some("5.4"),
+            ..config
+        };
+        assert!(installed_versions(&other_lua, "say").is_empty());
+    }
+
+    #[test]
+    fn undoes_library_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = dir.path().join("settings.json");
+        fs::write(&settings, r#"{"Lua.workspace.library": ["say"]}"#).unwrap();
+        let entry = Entry::now(
+            Operation::Enable {
+                name: String::from("say"),
+            },
+            Some(LibraryChange {
+                settings: settings.clone(),
+                before: vec![],
+                after: vec![String::from("say")],
+            }),
+        );
+        record(tree, &entry).unwrap();
+
+        let config = Config {
+            tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        assert_eq!(undo(&config).unwrap(), Some(entry));
+        assert!(read_library(&settings).unwrap().is_empty());
+        assert_eq!(undo(&config).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undoes_moved_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let rock = tree.join("lib/luarocks/rocks-5.1/say");
+        for version in ["1.3-1", "1.4.1-3"] {
+            fs::create_dir_all(rock.join(version).join("types")).unwrap();
+        }
+        links::link_to(tree, "say", &rock.join("1.4.1-3/types")).unwrap();
+        links::link_to(tree, "busted", &rock.join("1.4.1-3/types")).unwrap();
+        let entry = Entry {
+            links: vec![
+                MovedLink {
+                    name: String::from("say"),
+                    before: Some(rock.join("1.3-1/types")),
+                },
+                MovedLink {
+                    name: String::from("busted"),
+                    before: None,
+                },
+            ],
+            ..Entry::now(
+                Operation::Switch {
+                    name: String::from("say"),
+                    version: String::from("1.4.1-3"),
+                },
+                None,
+            )
+        };
+        record(tree, &entry).unwrap();
+
+        let config = Config {
+            tree,
+            ..Config::default()
+        };
+        assert_eq!(undo(&config).unwrap(), Some(entry));
+        assert_eq!(
+            links::target(&links::link_path(tree, "say")),
+            Some(rock.join("1.3-1/types"))
+        );
+        assert!(fs::symlink_metadata(links::link_path(tree, "busted")).is_err());
+    }
+
+    #[test]
+    fn refuses_stale_library_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let settings = dir.path().join("settings.json");
+        fs::write(&settings, r#"{"Lua.workspace.library": ["busted"]}"#).unwrap();
+        let entry = Entry::now(
+            Operation::Disable {
+                name: String::from("say"),
+            },
+            Some(LibraryChange {
+                settings: settings.clone(),
+                before: vec![String::from("say")],
+                after: vec![],
+            }),
+        );
+        record(tree, &entry).unwrap();
+
+        let config = Config {
+            tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        undo(&config).unwrap_err();
+        assert_eq!(load(tree).unwrap(), vec![entry]);
+    }
+
+    #[test]
+    fn undoes_installs_through_the_backend() {
+        let tree = tempfile::tempdir().unwrap();
+        let say = crate::Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: None,
+        };
+        let mock = std::sync::Arc::new(backend::Mock::new(vec![say], vec![]));
+        let entry = Entry::now(
+            Operation::Install {
+                name: String::from("say"),
+                versions: vec![String::from("1.4.1-3")],
+            },
+            None,
+        );
+        record(tree.path(), &entry).unwrap();
+
+        let config = Config {
+            tree: tree.path(),
+            luarocks: Path::new("no-luarocks"),
+            ..Config::default()
+        };
+        let undone = backend::with_backend(mock.clone(), || undo(&config));
+        assert_eq!(undone.unwrap(), Some(entry));
+        assert!(mock.installed().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+//! shell commands run before and after llynx changes a tree or settings
+//! file, set in the `[hooks]` table of the config file. They're told which
+//! addon it is through the environment
+
+use crate::{
+    Config,
+    history::Operation,
+    network::{self, Echo},
+};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::{
+    io::{self, Write},
+    process::Command,
+    sync::Arc,
+};
+
+/// the environment variable holding the name of the addon
+pub const ADDON_ENV: &str = "LLYNX_ADDON";
+
+/// the environment variable holding the version of the addon, when known
+pub const VERSION_ENV: &str = "LLYNX_VERSION";
+
+/// the environment variable holding which hook is running, like
+/// `post_enable`
+pub const HOOK_ENV: &str = "LLYNX_HOOK";
+
+/// the commands to run around each operation. A `pre_` hook that fails stops
+/// the operation, and a `post_` hook that fails fails the command
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    pub pre_install: Option<String>,
+    pub post_install: Option<String>,
+    pub pre_remove: Option<String>,
+    pub post_remove: Option<String>,
+    pub pre_enable: Option<String>,
+    pub post_enable: Option<String>,
+    pub pre_disable: Option<String>,
+    pub post_disable: Option<String>,
+}
+
+/// when a hook runs. Switching versions counts as enabling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    PreInstall,
+    PostInstall,
+    PreRemove,
+    PostRemove,
+    PreEnable,
+    PostEnable,
+    PreDisable,
+    PostDisable,
+}
+
+impl Event {
+    /// the key of the hook in the config file
+    pub fn key(self) -> &'static str {
+        match self {
+            Event::PreInstall => "pre_install",
+            Event::PostInstall => "post_install",
+            Event::PreRemove => "pre_remove",
+            Event::PostRemove => "post_remove",
+            Event::PreEnable => "pre_enable",
+            Event::PostEnable => "post_enable",
+            Event::PreDisable => "pre_disable",
+            Event::PostDisable => "post_disable",
+        }
+    }
+}
+
+impl Hooks {
+    /// the command set for `event`, if any
+    pub fn command(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::PreInstall => &self.pre_install,
+            Event::PostInstall => &self.post_install,
+            Event::PreRemove => &self.pre_remove,
+            Event::PostRemove => &self.post_remove,
+            Event::PreEnable => &self.pre_enable,
+            Event::PostEnable => &self.post_enable,
+            Event::PreDisable => &self.pre_disable,
+            Event::PostDisable => &self.post_disable,
+        }
+        .as_deref()
+    }
+}
+
+/// the shell `script` runs in
+fn shell(script: &str) -> Command {
+    let mut command;
+    if cfg!(windows) {
+        command = Command::new("cmd");
+        command.arg("/C").arg(script);
+    } else {
+        command = Command::new("sh");
+        command.arg("-c").arg(script);
+    }
+    command
+}
+
+/// run the hook `config` sets for `event` on addon `name`, if any. Whatever
+/// it prints goes to stderr, leaving stdout to llynx. Like LuaRocks, it's
+/// killed once it runs past the timeout or llynx is interrupted
+pub fn run(config: &Config, event: Event, name: &str, version: Option<&str>) -> Result<()> {
+    let Some(script) = config.hooks.and_then(|hooks| hooks.command(event)) else {
+        return Ok(());
+    };
+    let mut command = shell(script);
+    command.env(ADDON_ENV, name).env(HOOK_ENV, event.key());
+    match version {
+        Some(version) => command.env(VERSION_ENV, version),
+        None => command.env_remove(VERSION_ENV),
+    };
+    log::info!("running the {} hook: {script}", event.key());
+    let echo: Echo = Arc::new(|_, printed| {
+        let _ = io::stderr().write_all(printed);
+    });
+    let output = network::output_echoed(config, &mut command, Some(&echo))
+        .with_context(|| format!("while running the {} hook", event.key()))?;
+    if !output.status.success() {
+        bail!("the {} hook failed with {}", event.key(), output.status);
+    }
+    Ok(())
+}
+
+/// the hooks to run around `operation`, a change to the library
+pub(crate) fn library_events(operation: &Operation) -> (Event, Event) {
+    match operation {
+        Operation::Disable { .. } => (Event::PreDisable, Event::PostDisable),
+        _ => (Event::PreEnable, Event::PostEnable),
+    }
+}
+
+/// run the hook for `event` on the addon `operation` changes the library for
+pub(crate) fn run_library(config: &Config, event: Event, operation: &Operation) -> Result<()> {
+    let version = operation.versions().first().map(String::as_str);
+    run(config, event, operation.name(), version)
+}
+
+#[cfg(all(test, unix))]
+mod test_run {
+    use super::*;
+
+    #[test]
+    fn passes_the_addon() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let hooks = Hooks {
+            post_enable: Some(format!(
+                "echo \"$LLYNX_HOOK $LLYNX_ADDON $LLYNX_VERSION\" > '{}'",
+                out.display()
+            )),
+            pre_remove: Some(String::from("exit 3")),
+            ..Hooks::default()
+        };
+        let config = Config {
+            hooks: Some(&hooks),
+            ..Config::default()
+        };
+        run(&config, Event::PostEnable, "say", Some("1.4.1-3")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "post_enable say 1.4.1-3\n"
+        );
+
+        run(&config, Event::PreEnable, "say", None).unwrap();
+        run(&Config::default(), Event::PreRemove, "say", None).unwrap();
+        let err = run(&config, Event::PreRemove, "say", None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the pre_remove hook failed with exit status: 3"
+        );
+    }
+
+    #[test]
+    fn times_out() {
+        let hooks = Hooks {
+            pre_install: Some(String::from("sleep 10")),
+            ..Hooks::default()
+        };
+        let config = Config {
+            hooks: Some(&hooks),
+            timeout: Some(1),
+            ..Config::default()
+        };
+        let start = std::time::Instant::now();
+        let err = run(&config, Event::PreInstall, "say", None).unwrap_err();
+        assert!(start.elapsed().as_secs() < 5);
+        assert_eq!(err.to_string(), "while running the pre_install hook");
+    }
+}
@@ -0,0 +1,779 @@
+use crate::{
+    Addon, Config, backend,
+    failure::Failure,
+    filter::{self, Filter},
+    history::{self, Entry, Operation},
+    hooks::{self, Event},
+    namespace,
+    network::{self, Echo},
+    state, vendor,
+    version::{Constraints, Version},
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    env,
+    io::Cursor,
+    path::{self, Path, PathBuf},
+    process::{Command, Output},
+    sync::Mutex,
+};
+
+/// how LuaRocks installs the dependencies of a rock, as in
+/// `luarocks --deps-mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum DepsMode {
+    /// use dependencies from any tree LuaRocks knows about
+    All,
+
+    /// only use dependencies from the tree being installed to
+    One,
+
+    /// use dependencies from the tree being installed to and the ones
+    /// before it in the LuaRocks config
+    Order,
+
+    /// don't install dependencies at all
+    None,
+}
+
+impl DepsMode {
+    /// the mode as LuaRocks spells it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DepsMode::All => "all",
+            DepsMode::One => "one",
+            DepsMode::Order => "order",
+            DepsMode::None => "none",
+        }
+    }
+}
+
+/// a LuaRocks command, for the Lua version `config` builds trees for and the
+/// LuaRocks config file it names. The file is made absolute, since some
+/// commands run in another directory
+pub(crate) fn luarocks(config: &Config) -> Command {
+    let mut command = Command::new(config.luarocks);
+    if let Some(file) = config.luarocks_config {
+        let file = path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+        command.env("LUAROCKS_CONFIG", file);
+    }
+    if let Some(version) = config.lua_version {
+        command.arg("--lua-version").arg(version);
+    }
+    command
+}
+
+/// the oldest LuaRocks llynx works with. Older ones print listings
+/// differently and don't know `--lua-version`
+pub const MIN_LUAROCKS_VERSION: &str = "3.0.0";
+
+/// the versions of each LuaRocks executable asked so far
+static LUAROCKS_VERSIONS: Mutex<Option<HashMap<PathBuf, Version>>> = Mutex::new(None);
+
+/// the version in what `luarocks --version` printed, like `3.9.2` for
+/// `/usr/bin/luarocks 3.9.2`. Other programs print versions too, so it has to
+/// mention LuaRocks
+fn parse_luarocks_version(stdout: &str) -> Option<Version> {
+    if !stdout.to_lowercase().contains("luarocks") {
+        return None;
+    }
+    let version = stdout.lines().next()?.split_whitespace().last()?;
+    version
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then(|| Version::parse(version))
+}
+
+/// the version of the LuaRocks at `luarocks_path`. It's only asked once per
+/// process
+pub fn luarocks_version(luarocks_path: &Path) -> Result<Version> {
+    let mut versions = LUAROCKS_VERSIONS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let versions = versions.get_or_insert_with(HashMap::new);
+    if let Some(version) = versions.get(luarocks_path) {
+        return Ok(version.clone());
+    }
+
+    let output = Command::new(luarocks_path)
+        .arg("--version")
+        .output()
+        .with_context(|| {
+            Failure::LuaRocks.tag(format!(
+                "could not run LuaRocks at '{}'. Is it installed?",
+                luarocks_path.display()
+            ))
+        })?;
+    let version =
+        parse_luarocks_version(&String::from_utf8_lossy(&output.stdout)).with_context(|| {
+            format!(
+                "could not tell which version '{}' is. Is it LuaRocks?",
+                luarocks_path.display()
+            )
+        })?;
+    log::info!("found LuaRocks {version} at '{}'", luarocks_path.display());
+    versions.insert(luarocks_path.to_path_buf(), version.clone());
+    Ok(version)
+}
+
+/// fail unless the LuaRocks at `luarocks_path` is new enough for llynx
+pub(crate) fn check_luarocks(luarocks_path: &Path) -> Result<()> {
+    let version = luarocks_version(luarocks_path)?;
+    if version < Version::parse(MIN_LUAROCKS_VERSION) {
+        return Err(Failure::LuaRocks
+            .tag(format!(
+                "LuaRocks {version} at '{}' is too old, llynx needs {MIN_LUAROCKS_VERSION} or newer",
+                luarocks_path.display()
+            ))
+            .into());
+    }
+    Ok(())
+}
+
+/// whether `name` is the directory in `lib/luarocks` LuaRocks keeps the rocks
+/// for `lua_version` in, like `rocks-5.4`, or for any version when it's
+/// `None`
+pub(crate) fn is_rocks_dir(name: &str, lua_version: Option<&str>) -> bool {
+    match (name.strip_prefix("rocks-"), lua_version) {
+        (Some(version), Some(lua_version)) => version == lua_version,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// whether `dir` looks like a rocks tree, having rocks in `lib/luarocks` or
+/// the state llynx keeps in trees
+pub fn is_tree(dir: &Path) -> bool {
+    dir.join(state::STATE_FILE).is_file()
+        || std::fs::read_dir(dir.join("lib").join("luarocks")).is_ok_and(|entries| {
+            entries.flatten().any(|entry| {
+                entry.path().is_dir()
+                    && entry
+                        .file_name()
+                        .to_str()
+                        .is_some_and(|name| is_rocks_dir(name, None))
+            })
+        })
+}
+
+/// what `luarocks list` printed, by tree and LuaRocks
+type Listings = HashMap<(PathBuf, PathBuf), Vec<u8>>;
+
+/// the listings remembered so far, once listings are being remembered
+static LISTINGS: Mutex<Option<Listings>> = Mutex::new(None);
+
+/// remember what each tree holds for the rest of the process, instead of
+/// asking LuaRocks every time it's listed. Listings are forgotten whenever
+/// llynx runs LuaRocks to change a tree, but changes made by anything else
+/// go unnoticed, so this is for short-lived processes
+pub fn remember_listings() {
+    let mut listings = LISTINGS.lock().unwrap_or_else(|err| err.into_inner());
+    listings.get_or_insert_with(HashMap::new);
+}
+
+/// forget the remembered listings, since a tree is about to change
+pub(crate) fn forget_listings() {
+    let mut listings = LISTINGS.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(listings) = listings.as_mut() {
+        listings.clear();
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InstalledAddonRecord {
+    pub name: String,
+    pub version: String,
+
+    #[allow(dead_code)]
+    pub status: String,
+
+    pub location: String,
+}
+
+pub(crate) fn list_installed_command(config: &Config, filter: Option<&Filter>) -> Command {
+    let mut luarocks = luarocks(config);
+    luarocks
+        .arg("--tree")
+        .arg(config.tree)
+        .args(["list", "--porcelain"]);
+    if let Some(fil) = filter.and_then(Filter::luarocks_query) {
+        luarocks.arg(fil);
+    }
+    luarocks
+}
+
+/// fetches from the .lls_addons tree
+pub fn list_installed(config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let mut addons = backend::current().list(config, filter)?;
+    addons.extend(vendor::list(config.tree)?);
+    Ok(filter::apply(addons, filter))
+}
+
+/// ask LuaRocks what the tree holds, or reuse what it said last time if
+/// listings are being remembered
+pub(crate) fn list_tree(config: &Config, filter: Option<&Filter>) -> Result<Vec<u8>> {
+    let key = (config.tree.to_path_buf(), config.luarocks.to_path_buf());
+    let remembering = match LISTINGS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+    {
+        Some(listings) => match listings.get(&key) {
+            Some(stdout) => return Ok(stdout.clone()),
+            None => true,
+        },
+        None => false,
+    };
+
+    // a remembered listing has to hold the whole tree to serve any filter
+    let filter = filter.filter(|_| !remembering);
+    check_luarocks(config.luarocks)?;
+    let mut luarocks = list_installed_command(config, filter);
+    log::info!("executing: {luarocks:?}");
+    let output = network::output(config, &mut luarocks).context("while executing luarocks")?;
+
+    if remembering && output.status.success() {
+        let mut listings = LISTINGS.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(listings) = listings.as_mut() {
+            listings.insert(key, output.stdout.clone());
+        }
+    }
+    Ok(output.stdout)
+}
+
+/// interprets the output of `luarocks list --porcelain` for `tree`. Locations
+/// are relative to the current directory when they're inside it, or else
+/// start with `tree` when it's relative, like one shared by several folders
+pub(crate) fn parse_installed(tree: &Path, stdout: &[u8]) -> Result<Vec<Addon>> {
+    let stdout = std::str::from_utf8(stdout).context("while decoding luarocks output")?;
+
+    // because the CSV reader only reads files, a Cursor represents the string's
+    // file handle
+    // https://stackoverflow.com/a/41069910/13221687
+    let cursor = Cursor::new(stdout.as_bytes());
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b'\t')
+        .from_reader(cursor);
+
+    let cwd = env::current_dir()?;
+    // canonical, since a tree like `../../.lls_addons` keeps its `..` when
+    // it's only made absolute
+    let absolute_tree = tree
+        .is_relative()
+        .then(|| tree.canonicalize().ok())
+        .flatten();
+
+    let records: Vec<InstalledAddonRecord> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .context("while interpreting LuaRocks output")?;
+    let addons = records
+        .into_iter()
+        .map(|record| {
+            let name = record.name;
+            let version = record.version;
+            let location = record.location;
+            let mut path = PathBuf::from(location);
+            path.push(&name);
+            path.push(&version);
+            path.push("types");
+            let relative_path = match (path.strip_prefix(&cwd), &absolute_tree) {
+                (Ok(relative), _) => relative.to_path_buf(),
+                (Err(_), Some(absolute_tree)) => match path.strip_prefix(absolute_tree) {
+                    Ok(inside) => tree.join(inside),
+                    Err(_) => path.clone(),
+                },
+                (Err(_), None) => path.clone(),
+            };
+            Addon {
+                name,
+                version,
+                location: Some(relative_path),
+            }
+        })
+        .collect();
+
+    Ok(addons)
+}
+
+thread_local! {
+    /// where what LuaRocks prints goes as it's printed on this thread, if
+    /// anywhere
+    static ECHO: RefCell<Option<Echo>> = const { RefCell::new(None) };
+}
+
+/// run `f` with everything LuaRocks prints while installing or removing
+/// rocks on this thread passed to `echo` as it's printed, so it can be shown
+/// live. It's still returned once LuaRocks exits
+pub fn with_echo<T>(echo: Echo, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Echo>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            ECHO.with(|echo| echo.replace(self.0.take()));
+        }
+    }
+    let _restore = Restore(ECHO.with(|current| current.replace(Some(echo))));
+    f()
+}
+
+pub(crate) fn execute_command(config: &Config, mut command: Command) -> Result<Output> {
+    check_luarocks(Path::new(command.get_program()))?;
+    forget_listings();
+    let echo = ECHO.with(|echo| echo.borrow().clone());
+    network::run_echoed(config, &mut command, echo.as_ref()).context("while executing luarocks")
+}
+
+/// the package and version a local `.rockspec` or `.rock` file is for
+fn local_rock(name: &str) -> Option<(&str, &str)> {
+    let file_name = Path::new(name).file_name()?.to_str()?;
+    let stem = match file_name.strip_suffix(".rockspec") {
+        Some(stem) => stem,
+        // packed rocks are also named after their architecture
+        None => file_name.strip_suffix(".rock")?.rsplit_once('.')?.0,
+    };
+    let mut parts = stem.rsplitn(3, '-');
+    let (_revision, _version, package) = (parts.next()?, parts.next()?, parts.next()?);
+    (!package.is_empty()).then(|| (package, &stem[package.len() + 1..]))
+}
+
+/// the package a local `.rockspec` or `.rock` file is for, like `say` for
+/// `./say-1.4.1-3.all.rock`, or `None` if `name` isn't one
+pub fn local_package(name: &str) -> Option<&str> {
+    local_rock(name).map(|(package, _)| package)
+}
+
+/// the version of a local `.rockspec` or `.rock` file, like `1.4.1-3` for
+/// `./say-1.4.1-3.all.rock`, or `None` if `name` isn't one
+pub fn local_version(name: &str) -> Option<&str> {
+    local_rock(name).map(|(_, version)| version)
+}
+
+/// whether a version of `name` matching `version` is in `installed`. The
+/// version can be a constraint like `>= 1.4, < 2`, and any version matches
+/// when it's left out
+pub fn has_version(installed: &[Addon], name: &str, version: Option<&str>) -> Result<bool> {
+    let constraints = version.map(Constraints::parse).transpose()?;
+    Ok(installed.iter().any(|addon| {
+        addon.name == name
+            && constraints
+                .as_ref()
+                .is_none_or(|constraints| constraints.matches(&addon.version))
+    }))
+}
+
+/// the name of the rock installing `name` adds: the package of a local file,
+/// the addon of a namespaced name, or else `name` itself
+pub fn package(name: &str) -> &str {
+    local_package(name)
+        .or_else(|| namespace::split(name).map(|(_, addon)| addon))
+        .unwrap_or(name)
+}
+
+pub(crate) fn install_command(config: &Config, name: &str, version: Option<&str>) -> Command {
+    let mut install_command = luarocks(config);
+    let tree = config.tree;
+    let rockspec = Path::new(name);
+    if name.ends_with(".rockspec") {
+        // `make` builds from the sources next to the rockspec, so it runs there
+        // and needs a tree that doesn't depend on where it runs
+        let tree = path::absolute(tree).unwrap_or_else(|_| PathBuf::from(tree));
+        install_command.arg("--tree").arg(tree).arg("make");
+        if let Some(dir) = rockspec.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            install_command.current_dir(dir);
+        }
+    } else {
+        install_command.arg("--tree").arg(tree).arg("install");
+    }
+    if let Some(mode) = config.deps_mode {
+        install_command.arg(format!("--deps-mode={}", mode.as_str()));
+    }
+    if config.dev {
+        install_command.arg("--dev");
+    }
+    let namespaced = namespace::split(name);
+    if let Some((user, _)) = namespaced {
+        install_command.arg(format!("--server={}", namespace::manifest_url(user)));
+    }
+    match rockspec.file_name() {
+        Some(file_name) if name.ends_with(".rockspec") => install_command.arg(file_name),
+        _ => install_command.arg(namespaced.map_or(name, |(_, addon)| addon)),
+    };
+    if let Some(ver) = version.filter(|_| local_package(name).is_none()) {
+        install_command.arg(ver);
+    }
+    install_command.args(config.luarocks_args);
+    install_command
+}
+
+/// remember that `name` was installed explicitly, if LuaRocks succeeded
+pub(crate) fn mark_explicit(tree: &Path, name: &str, output: &Output) -> Result<()> {
+    if !output.status.success() {
+        return Ok(());
+    }
+    state::update(tree, |state| {
+        state.explicit.insert(name.to_string());
+        state.dependencies.remove(name);
+    })
+}
+
+/// remember the rocks that appeared in the tree besides `name`, which
+/// LuaRocks installed as its dependencies. `before` are the rocks in the tree
+/// before
+pub(crate) fn mark_dependencies(
+    config: &Config,
+    name: &str,
+    before: &BTreeSet<String>,
+    output: &Output,
+) -> Result<()> {
+    if !output.status.success() {
+        return Ok(());
+    }
+    let added: Vec<String> = history::installed_rocks(config)
+        .into_iter()
+        .filter(|rock| rock != name && !before.contains(rock))
+        .collect();
+    if added.is_empty() {
+        return Ok(());
+    }
+    state::update(config.tree, |state| {
+        state.dependencies.extend(added);
+    })
+}
+
+/// forget that `name` was installed explicitly once every version of it is
+/// removed
+pub(crate) fn unmark_explicit(config: &Config, name: &str, output: &Output) -> Result<()> {
+    if !output.status.success() || !history::installed_versions(config, name).is_empty() {
+        return Ok(());
+    }
+    state::update(config.tree, |state| {
+        state.explicit.remove(name);
+        state.dependencies.remove(name);
+    })
+}
+
+/// the versions in `a` that aren't in `b`
+fn difference(a: &[String], b: &[String]) -> Vec<String> {
+    a.iter().filter(|v| !b.contains(v)).cloned().collect()
+}
+
+/// remember an install in the tree's state, and in its journal when
+/// `journal` is set. `before` are the versions of `name` installed before
+/// it, and `rocks_before` the rocks in the tree
+fn record_install(
+    config: &Config,
+    name: &str,
+    before: &[String],
+    rocks_before: &BTreeSet<String>,
+    output: &Output,
+    journal: bool,
+) -> Result<()> {
+    mark_explicit(config.tree, name, output)?;
+    mark_dependencies(config, name, rocks_before, output)?;
+    let versions = difference(&history::installed_versions(config, name), before);
+    if versions.is_empty() || !journal {
+        return Ok(());
+    }
+    let name = name.to_string();
+    history::record(
+        config.tree,
+        &Entry::now(Operation::Install { name, versions }, None),
+    )
+}
+
+/// remember a removal in the tree's state and journal. `before` are the
+/// versions of `name` installed before it
+fn record_remove(config: &Config, name: &str, before: &[String], output: &Output) -> Result<()> {
+    unmark_explicit(config, name, output)?;
+    let versions = difference(before, &history::installed_versions(config, name));
+    if versions.is_empty() {
+        return Ok(());
+    }
+    let name = name.to_string();
+    history::record(
+        config.tree,
+        &Entry::now(Operation::Remove { name, versions }, None),
+    )
+}
+
+/// forward installing to LuaRocks, or the backend standing in for it,
+/// returning its captured output. Signatures, pins and hooks are left to
+/// [`crate::install`]
+pub(crate) fn install(config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+    install_with(config, name, version, &[], true)
+}
+
+/// install the local rock files `dependencies` in order, each without
+/// dependencies of its own, and then `name` like [`install`]. They're
+/// remembered as dependencies of `name`, and the install is only journaled
+/// when `journal` is set. Stops at the first rock LuaRocks fails on,
+/// returning what it said
+pub(crate) fn install_with(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    dependencies: &[String],
+    journal: bool,
+) -> Result<Output> {
+    let package = package(name);
+    let before = history::installed_versions(config, package);
+    let rocks_before = history::installed_rocks(config);
+    let backend = backend::current();
+    let alone = Config {
+        deps_mode: Some(DepsMode::None),
+        ..*config
+    };
+    for dependency in dependencies {
+        let output = backend.install(&alone, dependency, None)?;
+        if !output.status.success() {
+            return Ok(output);
+        }
+    }
+    let output = backend.install(config, name, version)?;
+    record_install(config, package, &before, &rocks_before, &output, journal)?;
+    Ok(output)
+}
+
+pub(crate) fn remove_command(config: &Config, name: &str, version: Option<&str>) -> Command {
+    let mut remove_command = luarocks(config);
+    remove_command
+        .arg("--tree")
+        .arg(config.tree)
+        .args(["remove", name]);
+    if let Some(ver) = version {
+        remove_command.arg(ver);
+    }
+    remove_command.args(config.luarocks_args);
+    remove_command
+}
+
+/// forward uninstalling to LuaRocks, or the backend standing in for it,
+/// returning its captured output. Vendored addons are removed from the tree
+/// directly, with nothing to capture. The remove hooks run around it
+pub fn remove(config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+    if vendor::find(config.tree, name)?.is_some() {
+        vendor::remove(config, name)?;
+        return Ok(backend::succeeded());
+    }
+    hooks::run(config, Event::PreRemove, name, version)?;
+    let before = history::installed_versions(config, name);
+    let output = backend::current().remove(config, name, version)?;
+    record_remove(config, name, &before, &output)?;
+    if output.status.success() {
+        hooks::run(config, Event::PostRemove, name, version)?;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test_rocks_dir {
+    use super::*;
+
+    #[test]
+    fn matches_the_lua_version() {
+        assert!(is_rocks_dir("rocks-5.1", None));
+        assert!(is_rocks_dir("rocks-5.4", Some("5.4")));
+        assert!(!is_rocks_dir("rocks-5.1", Some("5.4")));
+        assert!(!is_rocks_dir("manifests", None));
+    }
+
+    #[test]
+    fn recognizes_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!is_tree(dir.path()));
+        std::fs::create_dir_all(dir.path().join("lib/luarocks/manifests")).unwrap();
+        assert!(!is_tree(dir.path()));
+        std::fs::create_dir(dir.path().join("lib/luarocks/rocks-5.4")).unwrap();
+        assert!(is_tree(dir.path()));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(state::STATE_FILE), "{}").unwrap();
+        assert!(is_tree(dir.path()));
+    }
+}
+
+#[cfg(test)]
+mod test_luarocks_version {
+    use super::*;
+
+    #[test]
+    fn parses_the_first_line() {
+        let version = parse_luarocks_version(
+            "/usr/bin/luarocks 3.9.2\nLuaRocks main command-line interface\n",
+        );
+        assert_eq!(version, Some(Version::parse("3.9.2")));
+        assert!(
+            parse_luarocks_version("/usr/bin/luarocks 2.4.2\n").unwrap()
+                < Version::parse(MIN_LUAROCKS_VERSION)
+        );
+        assert_eq!(parse_luarocks_version("command not found\n"), None);
+        assert_eq!(parse_luarocks_version("false (GNU coreutils) 9.1\n"), None);
+        assert_eq!(parse_luarocks_version(""), None);
+    }
+}
+
+#[cfg(test)]
+mod test_install_command {
+    use super::*;
+
+    fn config(tree: &Path) -> Config<'_> {
+        Config {
+            tree,
+            luarocks: Path::new("luarocks"),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn deps_mode() {
+        let config = Config {
+            deps_mode: Some(DepsMode::None),
+            ..config(Path::new(".lls_addons"))
+        };
+        let command = install_command(&config, "say", None);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--tree",
+                ".lls_addons",
+                "install",
+                "--deps-mode=none",
+                "say"
+            ]
+        );
+    }
+
+    #[test]
+    fn luarocks_args_come_last() {
+        let luarocks_args = [String::from("--force")];
+        let config = Config {
+            luarocks_args: &luarocks_args,
+            ..config(Path::new(".lls_addons"))
+        };
+        let command = install_command(&config, "say", Some("1.4.1-3"));
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--tree",
+                ".lls_addons",
+                "install",
+                "say",
+                "1.4.1-3",
+                "--force"
+            ]
+        );
+    }
+
+    #[test]
+    fn luarocks_config_is_absolute() {
+        let config = Config {
+            luarocks_config: Some(Path::new("config.lua")),
+            ..config(Path::new(".lls_addons"))
+        };
+        let command = install_command(&config, "say", None);
+        let (_, file) = command
+            .get_envs()
+            .find(|(key, _)| *key == "LUAROCKS_CONFIG")
+            .unwrap();
+        let file = Path::new(file.unwrap());
+        assert!(file.is_absolute());
+        assert!(file.ends_with("config.lua"));
+    }
+
+    #[test]
+    fn local_files() {
+        let command = install_command(
+            &config(Path::new(".lls_addons")),
+            "./say-1.4.1-3.all.rock",
+            None,
+        );
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["--tree", ".lls_addons", "install", "./say-1.4.1-3.all.rock"]
+        );
+
+        let command = install_command(
+            &config(Path::new("/tree")),
+            "addon/say-scm-1.rockspec",
+            None,
+        );
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["--tree", "/tree", "make", "say-scm-1.rockspec"]
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("addon")));
+    }
+
+    #[test]
+    fn local_package_names() {
+        assert_eq!(local_package("./say-1.4.1-3.rockspec"), Some("say"));
+        assert_eq!(
+            local_package("lua-cjson-2.1.0-1.src.rock"),
+            Some("lua-cjson")
+        );
+        assert_eq!(
+            local_package("dir/lls-addon-scm-1.all.rock"),
+            Some("lls-addon")
+        );
+        assert_eq!(local_package("say"), None);
+        assert_eq!(local_package("say.rock"), None);
+        assert_eq!(local_version("lua-cjson-2.1.0-1.src.rock"), Some("2.1.0-1"));
+        assert_eq!(local_version("say"), None);
+    }
+
+    #[test]
+    fn versions_present() {
+        let installed = [Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: None,
+        }];
+        assert!(has_version(&installed, "say", None).unwrap());
+        assert!(has_version(&installed, "say", Some("1.4.1-3")).unwrap());
+        assert!(has_version(&installed, "say", Some(">= 1.4, < 2")).unwrap());
+        assert!(!has_version(&installed, "say", Some("1.5-1")).unwrap());
+        assert!(!has_version(&installed, "busted", None).unwrap());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test_remember_listings {
+    use super::*;
+    use crate::fake_luarocks;
+    use std::fs;
+
+    #[test]
+    fn lists_each_tree_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = dir.path().join("calls");
+        let luarocks = fake_luarocks(
+            dir.path(),
+            &format!(
+                "echo >> '{}'\nprintf 'say\\t1.4.1-3\\tinstalled\\t/tree\\n'\n",
+                calls.display()
+            ),
+        );
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            luarocks: &luarocks,
+            ..Config::default()
+        };
+        let count = || fs::read_to_string(&calls).unwrap().lines().count();
+
+        remember_listings();
+        assert_eq!(list_installed(&config, None).unwrap().len(), 1);
+        let filter = Filter::from("busted");
+        assert!(list_installed(&config, Some(&filter)).unwrap().is_empty());
+        assert_eq!(count(), 1);
+
+        forget_listings();
+        list_installed(&config, None).unwrap();
+        assert_eq!(count(), 2);
+    }
+}
@@ -0,0 +1,67 @@
+//! what happens when llynx is interrupted with Ctrl-C. The handler itself is
+//! installed by the binary, which calls [`on_signal`] from it. Running
+//! commands are killed rather than orphaned, and settings files are never
+//! left half-written
+
+use std::{
+    fmt, process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// the conventional exit status of a process stopped by SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// how many commands or settings writes are in progress
+static BUSY: AtomicUsize = AtomicUsize::new(0);
+
+/// the error operations fail with once llynx is interrupted
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// handle Ctrl-C. When nothing is in progress, llynx exits right away.
+/// Otherwise the running command is killed, a pending write is finished, and
+/// the operation fails with [`Interrupted`]. A second Ctrl-C exits regardless
+pub fn on_signal() {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) || BUSY.load(Ordering::SeqCst) == 0 {
+        process::exit(EXIT_CODE);
+    }
+}
+
+/// whether Ctrl-C was pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// fail with [`Interrupted`] if Ctrl-C was pressed
+pub(crate) fn check() -> anyhow::Result<()> {
+    match interrupted() {
+        true => Err(Interrupted.into()),
+        false => Ok(()),
+    }
+}
+
+/// keeps Ctrl-C from exiting llynx while it's alive
+pub(crate) struct Busy(());
+
+impl Busy {
+    pub(crate) fn start() -> Busy {
+        BUSY.fetch_add(1, Ordering::SeqCst);
+        Busy(())
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,148 @@
+//! Core library behind the `llynx` CLI, a thin [LuaRocks](https://luarocks.org/)
+//! wrapper for installing addons for
+//! [Lua Language Server](https://github.com/LuaLS/lua-language-server).
+//!
+//! Addons are installed into a rocks tree with LuaRocks and enabled by adding
+//! their `types` directory to `Lua.workspace.library` in a settings file.
+//! [`install`] installs like the CLI does: it minds pins and freezes, checks
+//! signatures when the server has a public key, and runs the install hooks.
+//!
+//! ```no_run
+//! use llynx_core::{Config, enable, install};
+//!
+//! let config = Config {
+//!     public_key: Some("minisign.pub"),
+//!     ..Config::default()
+//! };
+//! install(&config, "say", None)?;
+//! enable(&config, "say")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! With the `async` feature, [`nonblocking`] provides the same operations for
+//! callers running inside a tokio runtime.
+//!
+//! Listing, searching, installing and removing go through a
+//! [`backend::RocksBackend`], which can be swapped out for LuaRocks in tests.
+
+// Assumptions:
+// - Only one version of an addon can be enabled at any time, which `enable`
+//   and `switch` enforce
+
+pub mod addon_settings;
+pub mod adopt;
+pub mod archive;
+pub mod backend;
+pub mod batch;
+pub mod bootstrap;
+pub mod cache;
+pub mod catalog;
+pub mod config;
+pub mod copies;
+pub mod deps;
+pub mod devcontainer;
+pub mod disk_usage;
+pub mod enabled;
+pub mod export;
+pub mod failure;
+pub mod filter;
+pub mod folders;
+pub mod frozen;
+pub mod fuzzy;
+pub mod git;
+pub mod gitignore;
+pub mod history;
+pub mod hooks;
+pub mod installed;
+pub mod interrupt;
+pub mod licenses;
+pub mod links;
+pub mod migrate;
+pub mod namespace;
+pub mod network;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod online;
+pub mod pack;
+pub mod pins;
+pub mod publish;
+pub mod release;
+pub mod repair;
+pub mod rockspec;
+pub mod scaffold;
+pub mod search;
+pub mod settings;
+pub mod signature;
+pub mod state;
+pub mod status;
+pub mod store;
+pub mod third_party;
+pub mod user_settings;
+pub mod validate;
+pub mod vendor;
+pub mod verify;
+pub mod version;
+pub mod workspace;
+pub mod wsl;
+pub mod zed;
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+pub use crate::config::{Config, MaybeConfig};
+pub use crate::enabled::{disable, enable, list_enabled, switch};
+pub use crate::installed::{list_installed, remove};
+pub use crate::online::list_online;
+pub use crate::signature::install;
+
+/// the settings key LuaLS reads library directories from
+pub const LIB_SETTINGS_KEY: &str = "Lua.workspace.library";
+
+/// an addon found online, in the rocks tree, or in the settings file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Addon {
+    pub name: String,
+    pub version: String,
+
+    /// path to the addon's `types` directory. This is `None` for online addons
+    pub location: Option<PathBuf>,
+}
+
+impl Addon {
+    /// the `types` directory of an installed addon
+    pub(crate) fn types_dir(&self) -> anyhow::Result<&Path> {
+        self.location.as_deref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} {} isn't installed, so it has no types",
+                self.name,
+                self.version
+            )
+        })
+    }
+}
+
+/// an online addon, for tests
+#[cfg(test)]
+pub(crate) fn addon(name: &str, version: &str) -> Addon {
+    Addon {
+        name: String::from(name),
+        version: String::from(version),
+        location: None,
+    }
+}
+
+/// a stand-in for LuaRocks in `dir` that answers `--version` and otherwise
+/// runs `script`, for tests that can't count on a real one
+#[cfg(all(test, unix))]
+pub(crate) fn fake_luarocks(dir: &Path, script: &str) -> PathBuf {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let luarocks = dir.join("luarocks");
+    fs::write(
+        &luarocks,
+        format!("#!/bin/sh\n[ \"$1\" = --version ] && echo 'luarocks 3.9.2' && exit\n{script}"),
+    )
+    .unwrap();
+    fs::set_permissions(&luarocks, fs::Permissions::from_mode(0o755)).unwrap();
+    luarocks
+}
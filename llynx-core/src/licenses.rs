@@ -0,0 +1,109 @@
+//! the licenses installed addons declare in their rockspecs, for auditing an
+//! addon set before it's shared
+
+use crate::{Config, list_installed, rockspec, vendor};
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// the license an installed addon declares
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct AddonLicense {
+    pub name: String,
+    pub version: String,
+
+    /// the rockspec's `description.license`, if it has one
+    pub license: Option<String>,
+}
+
+/// the license of every installed addon. Vendored addons have no rockspec, so
+/// their licenses are unknown
+pub fn licenses(config: &Config) -> Result<Vec<AddonLicense>> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    Ok(installed
+        .into_iter()
+        .map(|addon| {
+            let license = match addon.location.as_deref() {
+                Some(location) if vendor::is_vendored_path(location) => None,
+                _ => rockspec::read_installed(&addon)
+                    .inspect_err(|err| {
+                        log::warn!("could not read the rockspec of '{}': {err:#}", addon.name)
+                    })
+                    .ok()
+                    .and_then(|rockspec| rockspec.description.license),
+            };
+            AddonLicense {
+                name: addon.name,
+                version: addon.version,
+                license,
+            }
+        })
+        .collect())
+}
+
+/// whether the license expression `license` mentions `denied`, ignoring case.
+/// `GPL-3.0` also matches `GPL-3.0-only`, `GPL-3.0-or-later` and `GPL-3.0+`
+pub fn mentions(license: &str, denied: &str) -> bool {
+    let denied = denied.to_lowercase();
+    license
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || "()/,;".contains(c))
+        .any(|id| {
+            let base = id
+                .strip_suffix("-only")
+                .or_else(|| id.strip_suffix("-or-later"))
+                .or_else(|| id.strip_suffix('+'))
+                .unwrap_or(id);
+            id == denied || base == denied
+        })
+}
+
+/// the addons whose licenses mention any of `deny`
+pub fn denied<'a>(licenses: &'a [AddonLicense], deny: &[String]) -> Vec<&'a AddonLicense> {
+    licenses
+        .iter()
+        .filter(|addon| {
+            addon
+                .license
+                .as_deref()
+                .is_some_and(|license| deny.iter().any(|denied| mentions(license, denied)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_mentions {
+    use super::*;
+
+    #[test]
+    fn matches_identifiers_in_expressions() {
+        assert!(mentions("GPL-3.0", "gpl-3.0"));
+        assert!(mentions("MIT OR GPL-3.0-or-later", "GPL-3.0"));
+        assert!(mentions("(Apache-2.0 AND GPL-3.0+)", "GPL-3.0"));
+        assert!(mentions("MIT/X11", "MIT"));
+        assert!(!mentions("LGPL-3.0", "GPL-3.0"));
+        assert!(!mentions("MIT", "GPL-3.0"));
+    }
+
+    #[test]
+    fn skips_unknown_licenses() {
+        let licenses = [
+            AddonLicense {
+                name: String::from("say"),
+                version: String::from("1.4.1-3"),
+                license: Some(String::from("MIT")),
+            },
+            AddonLicense {
+                name: String::from("copyleft"),
+                version: String::from("1.0-1"),
+                license: Some(String::from("GPL-3.0-only")),
+            },
+            AddonLicense {
+                name: String::from("unknown"),
+                version: String::from("1.0-1"),
+                license: None,
+            },
+        ];
+        let denied = denied(&licenses, &[String::from("GPL-3.0")]);
+        assert_eq!(denied, vec![&licenses[1]]);
+    }
+}
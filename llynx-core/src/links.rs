@@ -0,0 +1,146 @@
+//! `enable_mode = "link"`: each enabled addon gets a link at a path that
+//! doesn't change between its versions, `<tree>/llynx-enabled/<name>`, and
+//! the library points there instead. Switching versions only moves the link,
+//! so the settings file stays the same
+
+use crate::{Addon, folders, store};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// the directory the links live in, relative to the tree
+pub const LINKS_DIR: &str = "llynx-enabled";
+
+/// where the link to the enabled version of `name` is
+pub fn link_path(tree: &Path, name: &str) -> PathBuf {
+    tree.join(LINKS_DIR).join(name)
+}
+
+/// `path` without `.` and with `..` taken out where it follows a name
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// what `path` links to, if it's one of the links of enabled addons
+pub(crate) fn target(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    if dir.file_name()? != LINKS_DIR {
+        return None;
+    }
+    let target = fs::read_link(path).ok()?;
+    Some(normalize(&dir.join(target)))
+}
+
+/// whether the link of `addon` points at its `types` directory already
+pub(crate) fn is_linked(tree: &Path, addon: &Addon) -> bool {
+    let absolute = |path: &Path| std::path::absolute(path).ok();
+    match (target(&link_path(tree, &addon.name)), &addon.location) {
+        (Some(target), Some(location)) => absolute(&target) == absolute(location),
+        _ => false,
+    }
+}
+
+/// point the link of `addon` at its `types` directory, replacing the link to
+/// any other version
+pub(crate) fn link(tree: &Path, addon: &Addon) -> Result<()> {
+    let location = addon.types_dir()?;
+    link_to(tree, &addon.name, location)?;
+    log::info!(
+        "'{}' links to {} {} now",
+        link_path(tree, &addon.name).display(),
+        addon.name,
+        addon.version
+    );
+    Ok(())
+}
+
+/// point the link of `name` at `location`, replacing the link that's there
+pub(crate) fn link_to(tree: &Path, name: &str, location: &Path) -> Result<()> {
+    unlink(tree, name)?;
+    let link = link_path(tree, name);
+    let dir = link.parent().expect("links are inside the tree");
+    fs::create_dir_all(dir).with_context(|| format!("while creating '{}'", dir.display()))?;
+    // junctions only take absolute paths, while relative symlinks keep
+    // working when the tree is moved
+    let target = match cfg!(windows) {
+        true => std::path::absolute(location)
+            .with_context(|| format!("while making '{}' absolute", location.display()))?,
+        false => folders::relative_to(location, dir)?,
+    };
+    store::link_dir(&target, &link).with_context(|| {
+        format!(
+            "while linking '{}' to '{}'",
+            link.display(),
+            location.display()
+        )
+    })
+}
+
+/// remove the link of `name`, if there is one
+pub(crate) fn unlink(tree: &Path, name: &str) -> Result<()> {
+    let link = link_path(tree, name);
+    if fs::symlink_metadata(&link).is_ok() {
+        // junctions are removed like directories
+        fs::remove_file(&link)
+            .or_else(|_| fs::remove_dir(&link))
+            .with_context(|| format!("while removing '{}'", link.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test_links {
+    use super::*;
+
+    #[test]
+    fn links_follow_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let rock = tree.join("lib/luarocks/rocks-5.1/say");
+        for version in ["1.3-1", "1.4.1-3"] {
+            fs::create_dir_all(rock.join(version).join("types")).unwrap();
+        }
+        let at = |version: &str| Addon {
+            name: String::from("say"),
+            version: version.to_string(),
+            location: Some(rock.join(version).join("types")),
+        };
+
+        let path = link_path(&tree, "say");
+        assert!(!is_linked(&tree, &at("1.3-1")));
+        link(&tree, &at("1.3-1")).unwrap();
+        assert_eq!(target(&path), at("1.3-1").location);
+        assert!(is_linked(&tree, &at("1.3-1")));
+        assert!(!is_linked(&tree, &at("1.4.1-3")));
+        link(&tree, &at("1.4.1-3")).unwrap();
+        assert_eq!(target(&path), at("1.4.1-3").location);
+        assert_eq!(
+            fs::read_link(&path).unwrap(),
+            Path::new("../lib/luarocks/rocks-5.1/say/1.4.1-3/types")
+        );
+        assert_eq!(target(&rock.join("1.3-1/types")), None);
+    }
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(
+            normalize(Path::new("tree/llynx-enabled/../lib/./say")),
+            Path::new("tree/lib/say")
+        );
+        assert_eq!(normalize(Path::new("../tree")), Path::new("../tree"));
+    }
+}
@@ -0,0 +1,112 @@
+//! taking over from the addon manager of the VS Code Lua extension, which
+//! downloads addons from the LLS-Addons catalog into a folder of its own and
+//! points the library at them, through `${addons}` or by their full path.
+//! Addons can also sit in the folders of `Lua.workspace.userThirdParty`. Each
+//! of those entries is [adopted](crate::adopt), so the addon is installed
+//! from the server and the entry points at the tree instead
+
+use crate::{
+    adopt::guess_name,
+    enabled::{entry_path, read_library},
+    third_party,
+    user_settings::{self, Editor},
+};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// what the library says instead of the addon manager's folder
+pub const ADDONS_VARIABLE: &str = "${addons}";
+
+/// the folder the addon manager of `editor` downloads addons to
+pub fn addons_dir(editor: Editor) -> Result<PathBuf> {
+    Ok(user_settings::user_dir(editor)?
+        .join("globalStorage")
+        .join("sumneko.lua")
+        .join("addonManager")
+        .join("addons"))
+}
+
+/// what there is to migrate in a workspace
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// library entries pointing at addons of the addon manager, in the order
+    /// of the library
+    pub entries: Vec<String>,
+
+    /// addons in the addon manager's folder the library doesn't point at
+    pub unused: Vec<String>,
+}
+
+/// find the library entries of `settings_file` that point into `addons_dir`
+/// or the folders of `Lua.workspace.userThirdParty`
+pub fn find(settings_file: &Path, addons_dir: &Path) -> Result<Migration> {
+    let mut dirs = third_party::dirs(settings_file)?;
+    dirs.push(addons_dir.to_path_buf());
+    let entries: Vec<String> = read_library(settings_file)?
+        .into_iter()
+        .filter(|entry| {
+            entry.starts_with(ADDONS_VARIABLE) || {
+                let path = entry_path(entry);
+                dirs.iter().any(|dir| path.starts_with(dir))
+            }
+        })
+        .collect();
+
+    let mut unused = vec![];
+    if addons_dir.is_dir() {
+        let read_dir = fs::read_dir(addons_dir)
+            .with_context(|| format!("while reading '{}'", addons_dir.display()))?;
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry?;
+            let Ok(name) = dir_entry.file_name().into_string() else {
+                continue;
+            };
+            let used = entries.iter().any(|entry| guess_name(entry) == Some(&name));
+            if dir_entry.path().is_dir() && !used {
+                unused.push(name);
+            }
+        }
+        unused.sort();
+    }
+    Ok(Migration { entries, unused })
+}
+
+#[cfg(test)]
+mod test_find {
+    use super::*;
+    use crate::third_party::THIRD_PARTY_KEY;
+
+    #[test]
+    fn finds_entries_of_the_addon_manager() {
+        let dir = tempfile::tempdir().unwrap();
+        let addons = dir.path().join("addons");
+        for name in ["busted", "love2d", "luassert"] {
+            fs::create_dir_all(addons.join(name).join("module/library")).unwrap();
+        }
+        let third_party = dir.path().join("third-party");
+        let path = |path: PathBuf| path.to_str().unwrap().to_string();
+        let library = [
+            String::from("${addons}/busted/module/library"),
+            path(addons.join("love2d/module/library")),
+            path(third_party.join("luv/library")),
+            String::from("types"),
+        ];
+        let settings = dir.path().join("settings.json");
+        fs::write(
+            &settings,
+            serde_json::json!({
+                "Lua.workspace.library": library,
+                THIRD_PARTY_KEY: [path(third_party)],
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let migration = find(&settings, &addons).unwrap();
+        assert_eq!(migration.entries, library[..3]);
+        assert_eq!(migration.unused, ["luassert"]);
+    }
+}
@@ -0,0 +1,48 @@
+//! addons kept in other manifests on luarocks.org, named like `user/addon`,
+//! and the `<user>` shorthand for the URL of those manifests
+
+use crate::installed::local_package;
+
+/// where the manifests on luarocks.org are, each under the name of whoever
+/// keeps it
+pub const MANIFESTS: &str = "https://luarocks.org/m";
+
+/// the URL of the manifest `user` keeps on luarocks.org
+pub fn manifest_url(user: &str) -> String {
+    format!("{MANIFESTS}/{user}")
+}
+
+/// whether `part` can name a manifest or a rock
+fn is_valid_part(part: &str) -> bool {
+    part.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+/// the manifest and the addon `name` is made of, like `("hishamhm", "say")`
+/// for `hishamhm/say`, or `None` if it isn't namespaced. Paths to local
+/// rockspecs and rocks aren't namespaces
+pub fn split(name: &str) -> Option<(&str, &str)> {
+    if local_package(name).is_some() {
+        return None;
+    }
+    let (user, addon) = name.split_once('/')?;
+    (is_valid_part(user) && is_valid_part(addon)).then_some((user, addon))
+}
+
+#[cfg(test)]
+mod test_namespace {
+    use super::*;
+
+    #[test]
+    fn splits_names() {
+        assert_eq!(split("hishamhm/say"), Some(("hishamhm", "say")));
+        assert_eq!(split("say"), None);
+        assert_eq!(split("./say/say-1.4.1-3.rockspec"), None);
+        assert_eq!(split("../say"), None);
+        assert_eq!(split("a/b/c"), None);
+        assert_eq!(split("hishamhm/"), None);
+        assert_eq!(manifest_url("hishamhm"), "https://luarocks.org/m/hishamhm");
+    }
+}
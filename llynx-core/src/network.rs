@@ -0,0 +1,602 @@
+//! how llynx reaches servers. The options of the [`Config`] each command is
+//! run for apply to every LuaRocks, git and curl command it starts
+
+use crate::{Config, interrupt, vendor::run_tool};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Read,
+    path::Path,
+    process::{Command, Output, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// how long to wait before the first retry. Each retry waits twice as long
+/// as the one before
+pub const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+
+/// the longest wait between retries
+pub const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// how often to check whether a command has finished, timed out or been
+/// interrupted
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// which of its pipes a command printed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pipe {
+    Stdout,
+    Stderr,
+}
+
+/// called with everything a command prints as it's printed, on top of it
+/// being collected
+pub type Echo = Arc<dyn Fn(Pipe, &[u8]) + Send + Sync>;
+
+/// what to log into a private server with
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Credentials {
+    /// sent as `Authorization: Bearer <token>`
+    Token { token: String },
+
+    /// sent with HTTP basic authentication
+    Basic { username: String, password: String },
+}
+
+impl Credentials {
+    /// credentials from `LLYNX_TOKEN`, or `LLYNX_USERNAME` and
+    /// `LLYNX_PASSWORD`
+    pub fn from_env() -> Option<Credentials> {
+        if let Ok(token) = env::var("LLYNX_TOKEN") {
+            return Some(Credentials::Token { token });
+        }
+        Some(Credentials::Basic {
+            username: env::var("LLYNX_USERNAME").ok()?,
+            password: env::var("LLYNX_PASSWORD").ok()?,
+        })
+    }
+
+    /// a curl config file logging in with these credentials, so they don't
+    /// show up in the command line. Fails on control characters, which
+    /// would end the line or the header early
+    fn curl_config(&self) -> Result<String> {
+        fn quote(text: &str) -> Result<String> {
+            if text.chars().any(char::is_control) {
+                return Err(anyhow!("credentials can't contain control characters"));
+            }
+            Ok(text.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        Ok(match self {
+            Credentials::Token { token } => {
+                format!("header = \"Authorization: Bearer {}\"\n", quote(token)?)
+            }
+            Credentials::Basic { username, password } => {
+                format!("user = \"{}:{}\"\n", quote(username)?, quote(password)?)
+            }
+        })
+    }
+}
+
+/// the credentials of `config`, with the ones from the environment added for
+/// the server it names. Those win over the config file. They're only for
+/// that server, so this is called before any command overrides it
+pub fn auth(config: &Config) -> BTreeMap<String, Credentials> {
+    let mut auth = config.auth.cloned().unwrap_or_default();
+    if let Some(credentials) = Credentials::from_env() {
+        auth.insert(config.server.to_string(), credentials);
+    }
+    auth
+}
+
+/// how long a command may run before it's killed, if at all
+pub(crate) fn timeout(config: &Config) -> Option<Duration> {
+    config.timeout.map(Duration::from_secs)
+}
+
+/// the variables LuaRocks, git and curl read their proxy from
+const PROXY_VARIABLES: [&str; 4] = ["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY"];
+
+/// the variables curl, git and OpenSSL read a CA bundle from. LuaRocks
+/// downloads with one of them
+const CA_BUNDLE_VARIABLES: [&str; 3] = ["CURL_CA_BUNDLE", "GIT_SSL_CAINFO", "SSL_CERT_FILE"];
+
+/// pass the options of `config` on to `command` through its environment.
+/// Without a proxy, the usual `http_proxy`, `https_proxy` and `no_proxy`
+/// variables are left to LuaRocks, git and curl
+pub(crate) fn apply(config: &Config, command: &mut Command) {
+    if let Some(proxy) = config.proxy {
+        for variable in PROXY_VARIABLES {
+            command.env(variable, proxy);
+        }
+    }
+    if let Some(ca_bundle) = config.ca_bundle {
+        for variable in CA_BUNDLE_VARIABLES {
+            command.env(variable, ca_bundle);
+        }
+    }
+    if config.insecure {
+        // LuaRocks has no way to skip the check from outside its own config
+        command.env("GIT_SSL_NO_VERIFY", "true");
+    }
+}
+
+/// the HTTP status in a line like curl's `returned error: 503` or a status
+/// line like `HTTP/1.1 503 Service Unavailable`
+fn http_status(line: &str) -> Option<u16> {
+    let status = match line.split_once("returned error:") {
+        Some((_, rest)) => rest.split_whitespace().next()?,
+        None => {
+            let rest = &line[line.find("http/")?..];
+            let mut words = rest.split_whitespace();
+            words.next();
+            words.next()?
+        }
+    };
+    let status = status.trim_end_matches(|c: char| !c.is_ascii_digit());
+    (status.len() == 3).then(|| status.parse().ok()).flatten()
+}
+
+/// whether a failed command's stderr looks like a problem that might go away
+/// by itself, like a timeout or a 5xx response
+pub(crate) fn is_transient(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+    const HINTS: [&str; 7] = [
+        "timed out",
+        "timeout",
+        "temporarily unavailable",
+        "temporary failure",
+        "connection reset",
+        "connection refused",
+        "could not resolve",
+    ];
+    HINTS.iter().any(|hint| stderr.contains(hint))
+        || stderr
+            .lines()
+            .filter_map(http_status)
+            .any(|status| (500..600).contains(&status))
+}
+
+/// how long to wait before retry number `attempt`, counting from 0
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    FIRST_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+/// the error for a command that was killed after running for `timeout`
+pub(crate) fn timed_out(program: &std::ffi::OsStr, timeout: Duration) -> anyhow::Error {
+    anyhow!(
+        "{program:?} was killed after running for {}s. The server may be unreachable; \
+        raise the timeout if it's just slow",
+        timeout.as_secs_f32()
+    )
+}
+
+/// run `command` and collect its output like [`Command::output`], killing it
+/// once it runs longer than the timeout of `config` or llynx is interrupted
+pub(crate) fn output(config: &Config, command: &mut Command) -> Result<Output> {
+    output_echoed(config, command, None)
+}
+
+/// [`output`], also passing what `command` prints to `echo` as it's printed
+pub(crate) fn output_echoed(
+    config: &Config,
+    command: &mut Command,
+    echo: Option<&Echo>,
+) -> Result<Output> {
+    interrupt::check()?;
+    let _busy = interrupt::Busy::start();
+    let program = command.get_program().to_owned();
+    let timeout = timeout(config);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("while executing {program:?}"))?;
+
+    // the pipes are drained while waiting so a chatty child can't fill them
+    // up and stall
+    fn drain(
+        pipe: Option<impl Read + Send + 'static>,
+        which: Pipe,
+        echo: Option<Echo>,
+    ) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = vec![];
+            let Some(mut pipe) = pipe else {
+                return buf;
+            };
+            let mut chunk = [0; 8192];
+            loop {
+                match pipe.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => {
+                        if let Some(echo) = &echo {
+                            echo(which, &chunk[..read]);
+                        }
+                        buf.extend_from_slice(&chunk[..read]);
+                    }
+                }
+            }
+            buf
+        })
+    }
+    let stdout = drain(child.stdout.take(), Pipe::Stdout, echo.cloned());
+    let stderr = drain(child.stderr.take(), Pipe::Stderr, echo.cloned());
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let timeout = timeout.filter(|timeout| start.elapsed() >= *timeout);
+        if timeout.is_some() || interrupt::interrupted() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(match timeout {
+                Some(timeout) => timed_out(&program, timeout),
+                None => interrupt::Interrupted.into(),
+            });
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    log_output(&program, &output, start.elapsed());
+    Ok(output)
+}
+
+/// log how `program` exited after running for `elapsed` and everything it
+/// printed, for bug reports. The program, exit code and duration are also
+/// attached as fields, for structured logs
+pub(crate) fn log_output(program: &std::ffi::OsStr, output: &Output, elapsed: Duration) {
+    let subprocess = program.to_string_lossy();
+    log::debug!(
+        subprocess = &*subprocess,
+        exit_code = output.status.code(),
+        duration_ms = elapsed.as_millis() as u64;
+        "{program:?} exited with {} after {elapsed:?}\nstdout:\n{}\nstderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout).trim_end(),
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    );
+}
+
+/// run `command`, retrying with backoff while it fails transiently, as many
+/// times as `config` allows
+pub(crate) fn run(config: &Config, command: &mut Command) -> Result<Output> {
+    run_echoed(config, command, None)
+}
+
+/// [`run`], also passing what each attempt prints to `echo` as it's printed
+pub(crate) fn run_echoed(
+    config: &Config,
+    command: &mut Command,
+    echo: Option<&Echo>,
+) -> Result<Output> {
+    apply(config, command);
+    let mut attempt = 0;
+    loop {
+        log::info!("executing: {command:?}");
+        let output = output_echoed(config, command, echo)?;
+        if output.status.success() || attempt >= config.retries || !is_transient(&output.stderr) {
+            return Ok(output);
+        }
+        let wait = backoff(attempt);
+        log::warn!(
+            "{:?} failed, retrying in {wait:?}: {}",
+            command.get_program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        thread::sleep(wait);
+        attempt += 1;
+    }
+}
+
+/// the parts of a URL credentials are matched on
+#[derive(Debug, PartialEq, Eq)]
+struct UrlParts<'a> {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+    path: &'a str,
+}
+
+impl<'a> UrlParts<'a> {
+    /// split `url`, lowercasing the scheme and host and filling in the port
+    /// of HTTP and HTTPS. `None` if it isn't a URL with a host
+    fn parse(url: &'a str) -> Option<UrlParts<'a>> {
+        let (scheme, rest) = url.split_once("://")?;
+        let scheme = scheme.to_ascii_lowercase();
+        let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(end);
+        let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+        let authority = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, Some(port.parse().ok()?)),
+            _ => (authority, None),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let port = port.or(match scheme.as_str() {
+            "http" => Some(80),
+            "https" => Some(443),
+            _ => None,
+        });
+        Some(UrlParts {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        })
+    }
+
+    /// whether `url` is on the same server as this prefix, at or below its
+    /// path
+    fn covers(&self, url: &UrlParts) -> bool {
+        let path = self.path.trim_end_matches('/');
+        self.scheme == url.scheme
+            && self.host == url.host
+            && self.port == url.port
+            && (url.path.trim_end_matches('/') == path
+                || url
+                    .path
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.starts_with('/')))
+    }
+}
+
+/// the credentials `config` has for `url`, from the longest prefix that
+/// covers it
+pub fn credentials_for(config: &Config, url: &str) -> Option<Credentials> {
+    let url = UrlParts::parse(url)?;
+    config
+        .auth?
+        .iter()
+        .filter(|(prefix, _)| UrlParts::parse(prefix).is_some_and(|prefix| prefix.covers(&url)))
+        .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+        .map(|(_, credentials)| credentials.clone())
+}
+
+/// download `url` to `to` with curl, logging in if there are credentials for
+/// it. Paths without a scheme are copied instead
+pub(crate) fn download(config: &Config, url: &str, to: &Path) -> Result<()> {
+    if !url.contains("://") {
+        fs::copy(url, to).with_context(|| format!("while copying '{url}'"))?;
+        return Ok(());
+    }
+    let mut curl = curl(config);
+    // the curl config file has to outlive curl
+    let curl_config = match credentials_for(config, url) {
+        Some(credentials) => {
+            let mut file =
+                tempfile::NamedTempFile::new().context("while creating a curl config file")?;
+            std::io::Write::write_all(&mut file, credentials.curl_config()?.as_bytes())?;
+            curl.arg("--config").arg(file.path());
+            Some(file)
+        }
+        None => None,
+    };
+    run_tool(config, "curl", curl.arg("--output").arg(to).arg(url))?;
+    drop(curl_config);
+    Ok(())
+}
+
+/// a curl command that fails on HTTP errors and retries as `config` says
+pub(crate) fn curl(config: &Config) -> Command {
+    let mut curl = Command::new("curl");
+    curl.args(["--fail", "--silent", "--show-error", "--location"]);
+    if config.retries > 0 {
+        curl.arg("--retry").arg(config.retries.to_string());
+    }
+    if let Some(ca_bundle) = config.ca_bundle {
+        curl.arg("--cacert").arg(ca_bundle);
+    }
+    if config.insecure {
+        curl.arg("--insecure");
+    }
+    curl
+}
+
+#[cfg(test)]
+mod test_retries {
+    use super::*;
+
+    #[test]
+    fn recognizes_transient_failures() {
+        assert!(is_transient(
+            b"Error: Failed downloading: connection timed out"
+        ));
+        assert!(is_transient(
+            b"curl: (22) The requested URL returned error: 503"
+        ));
+        assert!(!is_transient(
+            b"curl: (22) The requested URL returned error: 404"
+        ));
+        assert!(!is_transient(
+            b"Error: No results matching query were found"
+        ));
+        assert!(is_transient(b"< HTTP/1.1 502 Bad Gateway"));
+        assert!(is_transient(b"< HTTP/2 500"));
+        assert!(!is_transient(b"< HTTP/1.1 403 Forbidden"));
+        assert!(!is_transient(
+            b"Error: say 5.1-500 requires lua >= 5.4, 500 bytes read"
+        ));
+    }
+
+    #[test]
+    fn sets_the_proxy() {
+        let config = Config {
+            proxy: Some("http://proxy.example.com:3128"),
+            ..Config::default()
+        };
+        let mut command = Command::new("luarocks");
+        apply(&config, &mut command);
+        let proxy = std::ffi::OsStr::new("http://proxy.example.com:3128");
+        assert!(
+            command
+                .get_envs()
+                .any(|(key, value)| key == "https_proxy" && value == Some(proxy))
+        );
+    }
+
+    #[test]
+    fn passes_tls_options_to_curl() {
+        let config = Config {
+            ca_bundle: Some(Path::new("corporate.pem")),
+            insecure: true,
+            ..Config::default()
+        };
+        let curl = curl(&config);
+        let mut command = Command::new("git");
+        apply(&config, &mut command);
+        let args: Vec<_> = curl.get_args().collect();
+        assert!(
+            args.windows(2)
+                .any(|pair| pair == ["--cacert", "corporate.pem"])
+        );
+        assert!(args.contains(&std::ffi::OsStr::new("--insecure")));
+        assert!(
+            command
+                .get_envs()
+                .any(|(key, value)| key == "GIT_SSL_CAINFO"
+                    && value == Some(std::ffi::OsStr::new("corporate.pem")))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_hung_commands() {
+        let config = Config {
+            timeout: Some(1),
+            ..Config::default()
+        };
+        let start = Instant::now();
+        let hung = output(&config, Command::new("sleep").arg("10"));
+        let quick = output(&config, Command::new("echo").arg("hi"));
+        hung.unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(quick.unwrap().stdout, b"hi\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn echoes_output_as_it_comes() {
+        let echoed = Arc::new(std::sync::Mutex::new(vec![]));
+        let echo: Echo = Arc::new({
+            let echoed = echoed.clone();
+            move |pipe, bytes| echoed.lock().unwrap().push((pipe, bytes.to_vec()))
+        });
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2"]);
+        let output = output_echoed(&Config::default(), &mut command, Some(&echo)).unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        let mut echoed = echoed.lock().unwrap().clone();
+        echoed.sort();
+        assert_eq!(
+            echoed,
+            [
+                (Pipe::Stdout, b"out\n".to_vec()),
+                (Pipe::Stderr, b"err\n".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_credentials_for_curl() {
+        let token = Credentials::Token {
+            token: String::from("abc"),
+        };
+        assert_eq!(
+            token.curl_config().unwrap(),
+            "header = \"Authorization: Bearer abc\"\n"
+        );
+        let basic = Credentials::Basic {
+            username: String::from("me"),
+            password: String::from("p\"w"),
+        };
+        assert_eq!(basic.curl_config().unwrap(), "user = \"me:p\\\"w\"\n");
+        for token in ["abc\nHost: evil.example.com", "abc\r", "abc\0"] {
+            Credentials::Token {
+                token: String::from(token),
+            }
+            .curl_config()
+            .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn parses_credentials() {
+        let auth: BTreeMap<String, Credentials> = toml::from_str(
+            r#"
+            "https://rocks.example.com" = { token = "abc" }
+            "https://other.example.com" = { username = "me", password = "pw" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            auth["https://rocks.example.com"],
+            Credentials::Token {
+                token: String::from("abc")
+            }
+        );
+        toml::from_str::<BTreeMap<String, Credentials>>(r#"x = { username = "me" }"#).unwrap_err();
+    }
+
+    #[test]
+    fn matches_credentials_by_server() {
+        let token = |token: &str| Credentials::Token {
+            token: token.to_string(),
+        };
+        let auth = BTreeMap::from([
+            (String::from("https://rocks.example.com"), token("server")),
+            (
+                String::from("https://rocks.example.com/m/team/"),
+                token("team"),
+            ),
+        ]);
+        let config = Config {
+            auth: Some(&auth),
+            ..Config::default()
+        };
+        let found = |url: &str| match credentials_for(&config, url) {
+            Some(Credentials::Token { token }) => Some(token),
+            _ => None,
+        };
+        assert_eq!(
+            found("https://rocks.example.com/manifest").as_deref(),
+            Some("server")
+        );
+        assert_eq!(
+            found("HTTPS://Rocks.Example.com:443/m/team/say.rockspec").as_deref(),
+            Some("team")
+        );
+        assert_eq!(
+            found("https://rocks.example.com/m/teammates").as_deref(),
+            Some("server")
+        );
+        assert_eq!(found("https://rocks.example.com.evil.io/manifest"), None);
+        assert_eq!(found("https://rocks.example.com@evil.io/manifest"), None);
+        assert_eq!(found("https://rocks.example.com:8443/manifest"), None);
+        assert_eq!(found("http://rocks.example.com/manifest"), None);
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(10), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,278 @@
+//! async variants of the addon operations, for callers running inside a
+//! [tokio](https://tokio.rs) runtime. LuaRocks is spawned with
+//! [`tokio::process`] to list and search rocks, while installs, removes and
+//! settings file access run on the blocking pool, so several servers or
+//! addons can be queried concurrently.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use llynx_core::{Config, nonblocking};
+//!
+//! let config = Config::default();
+//! let (online, installed) = tokio::try_join!(
+//!     nonblocking::list_online(&config, None),
+//!     nonblocking::list_installed(&config, None),
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    Addon, Config, backend,
+    config::MaybeConfig,
+    enabled,
+    filter::{self, Filter},
+    installed, interrupt, network, online, signature, vendor,
+};
+use anyhow::{Context, Result};
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+use tokio::task::spawn_blocking;
+
+/// run LuaRocks, retrying with backoff while it fails transiently
+async fn luarocks_output(config: &Config<'_>, mut command: Command) -> Result<Output> {
+    let luarocks_path = command.get_program().to_owned();
+    spawn_blocking(move || installed::check_luarocks(Path::new(&luarocks_path))).await??;
+    network::apply(config, &mut command);
+    let mut command = tokio::process::Command::from(command);
+    // a hung or interrupted LuaRocks is killed when its future is dropped
+    command.kill_on_drop(true);
+    let (retries, timeout) = (config.retries, network::timeout(config));
+    let mut attempt = 0;
+    loop {
+        log::info!("executing: {command:?}");
+        interrupt::check()?;
+        let _busy = interrupt::Busy::start();
+        let start = std::time::Instant::now();
+        let mut running = std::pin::pin!(command.output());
+        let output = loop {
+            if let Ok(output) = tokio::time::timeout(network::POLL_INTERVAL, &mut running).await {
+                break output.context("while executing luarocks")?;
+            }
+            interrupt::check()?;
+            if let Some(timeout) = timeout.filter(|timeout| start.elapsed() >= *timeout) {
+                return Err(network::timed_out(command.as_std().get_program(), timeout));
+            }
+        };
+        network::log_output(command.as_std().get_program(), &output, start.elapsed());
+        if output.status.success() || attempt >= retries || !network::is_transient(&output.stderr) {
+            return Ok(output);
+        }
+        let wait = network::backoff(attempt);
+        log::warn!(
+            "LuaRocks failed, retrying in {wait:?}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// an owned copy of a config, for tasks that outlive the borrow
+struct Owned {
+    config: MaybeConfig,
+    dev: bool,
+    frozen: bool,
+}
+
+impl Owned {
+    fn new(config: &Config) -> Self {
+        Owned {
+            config: MaybeConfig::from(config),
+            dev: config.dev,
+            frozen: config.frozen,
+        }
+    }
+
+    /// the config copied
+    fn config(&self) -> Config<'_> {
+        Config {
+            dev: self.dev,
+            frozen: self.frozen,
+            ..Config::default().extend(&self.config)
+        }
+    }
+}
+
+/// run `f` on the blocking pool with an owned copy of `config`
+fn blocking<T, F>(config: &Config, f: F) -> impl Future<Output = Result<T>> + use<T, F>
+where
+    T: Send + 'static,
+    F: FnOnce(&Config) -> Result<T> + Send + 'static,
+{
+    let config = Owned::new(config);
+    async move { spawn_blocking(move || f(&config.config())).await? }
+}
+
+/// the addons on the server whose names LuaRocks matched with `filter`
+async fn search(config: &Config<'_>, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    if network::credentials_for(config, config.server).is_some() {
+        return blocking(config, online::search_manifest).await;
+    }
+    if let Some(stdout) = online::cached_search(config.server, filter) {
+        return online::parse_online(&stdout);
+    }
+    let output = luarocks_output(config, online::list_online_command(config, filter)).await?;
+    online::cache_search(config.server, filter, &output);
+    online::parse_online(&output.stdout)
+}
+
+/// fetches from luarocks.org, searching every server at once
+pub async fn list_online(config: &Config<'_>, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let searches: Vec<_> = online::servers(config)
+        .into_iter()
+        .map(|server| {
+            let (config, filter) = (Owned::new(config), filter.cloned());
+            tokio::spawn(async move {
+                let config = Config {
+                    server: &server,
+                    ..config.config()
+                };
+                let addons = search(&config, filter.as_ref()).await?;
+                anyhow::Ok((server, addons))
+            })
+        })
+        .collect();
+    let mut results = vec![];
+    for search in searches {
+        results.push(search.await??);
+    }
+    let addons = online::merge_found(results)
+        .into_iter()
+        .map(|found| found.addon)
+        .collect();
+    Ok(filter::apply(addons, filter))
+}
+
+/// fetches from the .lls_addons tree
+pub async fn list_installed(config: &Config<'_>, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let output = luarocks_output(config, installed::list_installed_command(config, filter)).await?;
+    let mut addons = installed::parse_installed(config.tree, &output.stdout)?;
+    addons.extend(blocking(config, |config| vendor::list(config.tree)).await?);
+    Ok(filter::apply(addons, filter))
+}
+
+/// fetches from .vscode/settings.json
+pub async fn list_enabled(config: &Config<'_>, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let filter = filter.cloned();
+    blocking(config, move |config| {
+        enabled::list_enabled(config, filter.as_ref())
+    })
+    .await
+}
+
+/// install `name` like [`signature::install`], through LuaRocks or the
+/// backend standing in for it on the thread this is called on, returning its
+/// captured output
+pub fn install(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+) -> impl Future<Output = Result<Output>> + use<> {
+    // the backend is picked now, since the blocking pool runs on other
+    // threads, which don't see the one swapped in on this one
+    let backend = backend::current();
+    let (name, version) = (name.to_string(), version.map(str::to_string));
+    blocking(config, move |config| {
+        backend::with_backend(backend, || {
+            signature::install(config, &name, version.as_deref())
+        })
+    })
+}
+
+/// forward uninstalling to LuaRocks, or the backend standing in for it on the
+/// thread this is called on, returning its captured output
+pub fn remove(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+) -> impl Future<Output = Result<Output>> + use<> {
+    let backend = backend::current();
+    let (name, version) = (name.to_string(), version.map(str::to_string));
+    blocking(config, move |config| {
+        backend::with_backend(backend, || {
+            installed::remove(config, &name, version.as_deref())
+        })
+    })
+}
+
+/// add the addon to .vscode/settings.json
+pub async fn enable(config: &Config<'_>, name: &str) -> Result<()> {
+    let backend = backend::current();
+    let name = name.to_string();
+    blocking(config, move |config| {
+        backend::with_backend(backend, || match enabled::plan_enable(config, &name)? {
+            Some(plan) => enabled::apply(config, plan),
+            None => Ok(()),
+        })
+    })
+    .await
+}
+
+/// enable another installed version of the addon in .vscode/settings.json,
+/// replacing the one enabled now
+pub async fn switch(config: &Config<'_>, name: &str, version: &str) -> Result<()> {
+    let backend = backend::current();
+    let (name, version) = (name.to_string(), version.to_string());
+    blocking(config, move |config| {
+        backend::with_backend(backend, || {
+            enabled::apply(config, enabled::plan_switch(config, &name, &version)?)
+        })
+    })
+    .await
+}
+
+/// remove the addon from .vscode/settings.json
+pub async fn disable(config: &Config<'_>, name: &str) -> Result<()> {
+    let enabled = list_enabled(config, Some(&Filter::from(name))).await?;
+    if !enabled.iter().any(|addon| addon.name == name) {
+        log::info!("addon '{name}' is already disabled");
+        return Ok(());
+    }
+
+    let name = name.to_string();
+    blocking(config, move |config| enabled::disable(config, &name)).await
+}
+
+#[cfg(test)]
+mod test_list_enabled {
+    use super::*;
+
+    #[tokio::test]
+    async fn not_found() {
+        let config = Config {
+            tree: Path::new(".lls_addons"),
+            settings: Path::new("tests/settings/fake.json"),
+            ..Config::default()
+        };
+        let addons = list_enabled(&config, None).await.unwrap();
+        assert_eq!(addons, vec![]);
+    }
+}
+
+#[cfg(test)]
+mod test_install {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn installs_through_the_backend() {
+        let tree = tempfile::tempdir().unwrap();
+        let mock = Arc::new(backend::Mock::default());
+        let config = Config {
+            tree: tree.path(),
+            luarocks: Path::new("no-luarocks"),
+            ..Config::default()
+        };
+        let installed =
+            backend::with_backend(mock.clone(), || install(&config, "say", Some("1.5-1")));
+        installed.await.unwrap();
+        assert_eq!(mock.installed()[0].name, "say");
+
+        let removed = backend::with_backend(mock.clone(), || remove(&config, "say", None));
+        removed.await.unwrap();
+        assert!(mock.installed().is_empty());
+    }
+}
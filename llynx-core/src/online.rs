@@ -0,0 +1,569 @@
+use crate::{
+    Addon, Config,
+    backend::{self, RocksBackend},
+    cache,
+    config::ROOT_SERVER,
+    failure::Failure,
+    filter::{self, Filter},
+    installed, namespace, network,
+    rockspec::{self, LuaValue, Rockspec},
+    version::{self, Constraints},
+};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    thread,
+};
+
+#[derive(Debug, Deserialize)]
+struct OnlineAddonRecord {
+    pub name: String,
+    pub version: String,
+    pub file_type: String,
+
+    #[allow(dead_code)]
+    pub source: String,
+}
+
+pub(crate) fn list_online_command(config: &Config, filter: Option<&Filter>) -> Command {
+    let mut luarocks = installed::luarocks(config);
+    luarocks.args([
+        "--only-server",
+        config.server,
+        "search",
+        "--porcelain",
+        search_query(filter),
+    ]);
+    luarocks
+}
+
+/// what LuaRocks is asked to search for with `filter`
+pub(crate) fn search_query(filter: Option<&Filter>) -> &str {
+    filter.and_then(Filter::luarocks_query).unwrap_or("--all")
+}
+
+/// the search results of `server` for `filter`, if they were cached recently
+pub(crate) fn cached_search(server: &str, filter: Option<&Filter>) -> Option<Vec<u8>> {
+    cache::read(
+        &cache::search_path(server, search_query(filter))?,
+        Some(cache::SEARCH_TTL),
+    )
+}
+
+/// keep the search results of `server` for `filter`, if LuaRocks succeeded
+pub(crate) fn cache_search(server: &str, filter: Option<&Filter>, output: &Output) {
+    if let Some(path) = cache::search_path(server, search_query(filter))
+        && output.status.success()
+    {
+        cache::write(&path, &output.stdout);
+    }
+}
+
+/// `version`, or the newest version of `name` on the server matching it when
+/// it's a constraint like `>= 1.4, < 2`. Local files are installed as they are
+pub fn resolve_version(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+) -> Result<Option<String>> {
+    let Some(constraint) = version.filter(|version| {
+        Constraints::is_constraint(version) && installed::local_package(name).is_none()
+    }) else {
+        return Ok(version.map(String::from));
+    };
+    let constraints = Constraints::parse(constraint)?;
+    let package = installed::package(name);
+    let server = match namespace::split(name) {
+        Some((user, _)) => namespace::manifest_url(user),
+        None => install_server(config, name)?.to_string(),
+    };
+    let on_server = Config {
+        server: &server,
+        ..*config
+    };
+    let online = list_online(&on_server, Some(&Filter::from(package)))?;
+    let versions = online
+        .iter()
+        .filter(|addon| addon.name == package)
+        .map(|addon| addon.version.as_str());
+    match constraints.newest(versions) {
+        Some(newest) => {
+            log::info!("'{constraint}' picked {package} {newest}");
+            Ok(Some(newest.to_string()))
+        }
+        None => Err(Failure::NotFound
+            .tag(format!(
+                "no version of '{package}' on '{server}' matches '{constraint}'"
+            ))
+            .into()),
+    }
+}
+
+/// whether the server has any version of `name`
+fn has_addon(config: &Config, name: &str) -> Result<bool> {
+    let online = list_online(config, Some(&Filter::from(name)))?;
+    Ok(online.iter().any(|addon| addon.name == name))
+}
+
+/// the server to install `name` from: the configured one, or the main
+/// luarocks.org repository when `fallback_to_root` is set and only it has
+/// `name`. Local files and namespaced addons are never looked up
+pub fn install_server<'a>(config: &Config<'a>, name: &str) -> Result<&'a str> {
+    if !config.fallback_to_root
+        || installed::local_package(name).is_some()
+        || namespace::split(name).is_some()
+        || has_addon(config, name)?
+        || !has_addon(
+            &Config {
+                server: ROOT_SERVER,
+                ..*config
+            },
+            name,
+        )?
+    {
+        return Ok(config.server);
+    }
+    Ok(ROOT_SERVER)
+}
+
+/// where `server` keeps its in-development versions, the way `luarocks --dev`
+/// finds them
+pub fn dev_server(server: &str) -> String {
+    format!("{}/dev", server.trim_end_matches('/'))
+}
+
+/// the servers to search: the one `config` names, then the others it lists,
+/// and their in-development versions when they're being looked for
+pub(crate) fn servers(config: &Config) -> Vec<String> {
+    let mut servers = vec![config.server.to_string()];
+    for server in config.servers {
+        if !servers.contains(server) {
+            servers.push(server.clone());
+        }
+    }
+    if config.dev {
+        let dev: Vec<String> = servers.iter().map(|server| dev_server(server)).collect();
+        servers.extend(dev);
+    }
+    servers
+}
+
+/// the addons in the manifest of a server that needs credentials, which
+/// LuaRocks can't log into
+pub(crate) fn search_manifest(config: &Config) -> Result<Vec<Addon>> {
+    Ok(fetch_manifest(config)?
+        .into_iter()
+        .filter(|entry| entry.arches.iter().any(|arch| arch == "rockspec"))
+        .map(|entry| Addon {
+            name: entry.name,
+            version: entry.version,
+            location: None,
+        })
+        .collect())
+}
+
+/// the addons on the server whose names `backend` matched with `filter`
+fn search(
+    config: &Config,
+    backend: &dyn RocksBackend,
+    filter: Option<&Filter>,
+) -> Result<Vec<Addon>> {
+    if network::credentials_for(config, config.server).is_some() {
+        return search_manifest(config);
+    }
+    backend.search(config, filter)
+}
+
+/// the addons on the server whose names LuaRocks matched with `filter`
+pub(crate) fn search_luarocks(config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    if let Some(stdout) = cached_search(config.server, filter) {
+        return parse_online(&stdout);
+    }
+    installed::check_luarocks(config.luarocks)?;
+    let mut luarocks = list_online_command(config, filter);
+    let output = network::run(config, &mut luarocks).context("while executing luarocks")?;
+    cache_search(config.server, filter, &output);
+    parse_online(&output.stdout)
+}
+
+/// a version of an addon found online, and the servers that have it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    pub addon: Addon,
+    pub servers: Vec<String>,
+}
+
+/// merge the search results of each server into one version each, in the
+/// order the servers were searched in
+pub(crate) fn merge_found(results: Vec<(String, Vec<Addon>)>) -> Vec<Found> {
+    let mut found: Vec<Found> = vec![];
+    for (server, addons) in results {
+        for addon in addons {
+            match found.iter_mut().find(|found| {
+                found.addon.name == addon.name && found.addon.version == addon.version
+            }) {
+                Some(found) => found.servers.push(server.clone()),
+                None => found.push(Found {
+                    addon,
+                    servers: vec![server.clone()],
+                }),
+            }
+        }
+    }
+    found
+}
+
+/// search every server in `servers` at once, instead of the one `config`
+/// names. The same version on several of them is only listed once
+pub fn search_servers(
+    config: &Config,
+    servers: &[String],
+    filter: Option<&Filter>,
+) -> Result<Vec<Found>> {
+    let backend = backend::current();
+    let backend = &*backend;
+    let results = thread::scope(|scope| {
+        let searches: Vec<_> = servers
+            .iter()
+            .map(|server| {
+                let config = Config { server, ..*config };
+                scope.spawn(move || search(&config, backend, filter))
+            })
+            .collect();
+        servers
+            .iter()
+            .zip(searches)
+            .map(|(server, search)| {
+                let addons = search.join().expect("searching a server doesn't panic")?;
+                Ok((server.clone(), addons))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    Ok(merge_found(results))
+}
+
+/// fetches from luarocks.org
+pub fn list_online(config: &Config, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let addons = search_servers(config, &servers(config), filter)?
+        .into_iter()
+        .map(|found| found.addon)
+        .collect();
+    Ok(filter::apply(addons, filter))
+}
+
+/// interprets the output of `luarocks search --porcelain`
+pub(crate) fn parse_online(stdout: &[u8]) -> Result<Vec<Addon>> {
+    let stdout = std::str::from_utf8(stdout).context("while decoding luarocks output")?;
+
+    // because the CSV reader only reads files, a Cursor represents the string's
+    // file handle
+    // https://stackoverflow.com/a/41069910/13221687
+    let cursor = Cursor::new(stdout.as_bytes());
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b'\t')
+        .from_reader(cursor);
+
+    let records: Vec<OnlineAddonRecord> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .context("while interpreting LuaRocks output")?;
+    let addons = records
+        .into_iter()
+        .filter(|record| record.file_type == "rockspec")
+        .map(|record| Addon {
+            name: record.name,
+            version: record.version,
+            location: None,
+        })
+        .collect();
+
+    Ok(addons)
+}
+
+/// a version of an addon in a server's manifest, and the kinds of files
+/// published for it, like `rockspec`, `src` or `all`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub version: String,
+    pub arches: Vec<String>,
+}
+
+/// interprets the `manifest` file of a server
+pub(crate) fn parse_manifest(source: &str) -> Result<Vec<ManifestEntry>> {
+    let globals = rockspec::parse_globals(source).context("while reading the manifest")?;
+    let repository = globals
+        .get("repository")
+        .and_then(LuaValue::as_table)
+        .ok_or_else(|| anyhow!("the manifest has no 'repository'"))?;
+    let mut entries = vec![];
+    for (name, versions) in &repository.fields {
+        let Some(versions) = versions.as_table() else {
+            continue;
+        };
+        for (version, files) in &versions.fields {
+            let arches = files
+                .as_table()
+                .map(|files| {
+                    files
+                        .items
+                        .iter()
+                        .filter_map(|file| file.as_table()?.get_str("arch"))
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            entries.push(ManifestEntry {
+                name: name.clone(),
+                version: version.clone(),
+                arches,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// the URL of the file `path` is named after on `server`
+fn server_file(server: &str, path: &Path) -> String {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    format!("{}/{file_name}", server.trim_end_matches('/'))
+}
+
+/// download and read the manifest of the server without LuaRocks
+pub fn fetch_manifest(config: &Config) -> Result<Vec<ManifestEntry>> {
+    let server = config.server;
+    let dir = tempfile::tempdir().context("while creating a download directory")?;
+    let path = dir.path().join("manifest");
+    network::download(config, &server_file(server, &path), &path)
+        .with_context(|| format!("while fetching the manifest of '{server}'"))?;
+    let source = fs::read_to_string(&path)?;
+    parse_manifest(&source)
+}
+
+/// download a rock of `name` from the server into `dir` without LuaRocks,
+/// preferring source rocks, and return where it is
+pub fn download_rock(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let server = config.server;
+    let entry = fetch_manifest(config)?
+        .into_iter()
+        .filter(|entry| entry.name == name && version.is_none_or(|v| entry.version == v))
+        .max_by(|a, b| version::compare(&a.version, &b.version))
+        .ok_or_else(|| Failure::NotFound.tag(format!("'{name}' was not found on '{server}'")))?;
+    let arch = ["src", "all"]
+        .into_iter()
+        .find(|arch| entry.arches.iter().any(|a| a == arch))
+        .ok_or_else(|| anyhow!("'{name}' {} has no rock on '{server}'", entry.version))?;
+    let path = dir.join(format!("{name}-{}.{arch}.rock", entry.version));
+    network::download(config, &server_file(server, &path), &path)?;
+    Ok(path)
+}
+
+pub(crate) fn download_rockspec_command(config: &Config, name: &str, version: &str) -> Command {
+    let mut luarocks = installed::luarocks(config);
+    luarocks.args([
+        "--only-server",
+        config.server,
+        "download",
+        "--rockspec",
+        name,
+        version,
+    ]);
+    luarocks
+}
+
+/// download the rockspec of an addon on the server and read it
+pub fn fetch_rockspec(config: &Config, name: &str, version: &str) -> Result<Rockspec> {
+    let server = config.server;
+    if network::credentials_for(config, server).is_some() {
+        let dir = tempfile::tempdir().context("while creating a download directory")?;
+        let path = dir.path().join(format!("{name}-{version}.rockspec"));
+        network::download(config, &server_file(server, &path), &path)?;
+        return Rockspec::from_file(&path);
+    }
+
+    // published rockspecs don't change, so they never go stale
+    let cached = cache::rockspec_path(server, name, version);
+    if let Some(contents) = cached.as_deref().and_then(|path| cache::read(path, None)) {
+        return Rockspec::parse(&String::from_utf8_lossy(&contents));
+    }
+
+    let dir = tempfile::tempdir().context("while creating a download directory")?;
+    installed::check_luarocks(config.luarocks)?;
+    let mut luarocks = download_rockspec_command(config, name, version);
+    luarocks.current_dir(dir.path());
+    let output = network::run(config, &mut luarocks).context("while executing luarocks")?;
+    if !output.status.success() {
+        return Err(Failure::LuaRocks
+            .tag(format!(
+                "could not download the rockspec of '{name}' {version}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+    }
+
+    let path = dir.path().join(format!("{name}-{version}.rockspec"));
+    let rockspec = Rockspec::from_file(&path)?;
+    if let (Some(cached), Ok(contents)) = (cached, std::fs::read(&path)) {
+        cache::write(&cached, &contents);
+    }
+    Ok(rockspec)
+}
+
+#[cfg(test)]
+mod test_parse_online {
+    use super::*;
+
+    #[test]
+    fn fails_on_rows_it_cannot_read() {
+        let found = parse_online(b"say\t1.4.1-3\trockspec\thttps://luarocks.org\n").unwrap();
+        assert_eq!(found[0].name, "say");
+        parse_online(b"say\n").unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod test_parse_manifest {
+    use super::*;
+
+    #[test]
+    fn lists_files_of_each_version() {
+        let manifest = r#"
+            commands = {}
+            modules = {}
+            repository = {
+                say = {
+                    ["1.4.1-3"] = { { arch = "rockspec" }, { arch = "src" } },
+                },
+            }
+        "#;
+        assert_eq!(
+            parse_manifest(manifest).unwrap(),
+            vec![ManifestEntry {
+                name: String::from("say"),
+                version: String::from("1.4.1-3"),
+                arches: vec![String::from("rockspec"), String::from("src")],
+            }]
+        );
+        parse_manifest("commands = {}").unwrap_err();
+    }
+}
+
+#[cfg(test)]
+mod test_servers {
+    use super::*;
+
+    #[test]
+    fn adds_the_dev_server_for_dev() {
+        let config = Config {
+            server: "https://luarocks.org/",
+            ..Config::default()
+        };
+        assert_eq!(servers(&config), ["https://luarocks.org/"]);
+        let config = Config {
+            dev: true,
+            ..config
+        };
+        assert_eq!(
+            servers(&config),
+            ["https://luarocks.org/", "https://luarocks.org/dev"]
+        );
+    }
+
+    #[test]
+    fn searches_the_listed_servers_too() {
+        let mirrors = [
+            String::from("https://mirror.example.com"),
+            String::from("https://luarocks.org/"),
+        ];
+        let config = Config {
+            server: "https://luarocks.org/",
+            servers: &mirrors,
+            dev: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            servers(&config),
+            [
+                "https://luarocks.org/",
+                "https://mirror.example.com",
+                "https://luarocks.org/dev",
+                "https://mirror.example.com/dev",
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_merge_found {
+    use super::*;
+    use crate::addon;
+
+    #[test]
+    fn dedupes_versions_keeping_servers() {
+        let found = merge_found(vec![
+            (
+                String::from("a"),
+                vec![addon("say", "1.4.1-3"), addon("busted", "2.2.0-1")],
+            ),
+            (
+                String::from("b"),
+                vec![addon("say", "1.4.1-3"), addon("say", "scm-1")],
+            ),
+        ]);
+        let found: Vec<(String, Vec<String>)> = found
+            .into_iter()
+            .map(|found| {
+                let Addon { name, version, .. } = found.addon;
+                (format!("{name} {version}"), found.servers)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    String::from("say 1.4.1-3"),
+                    vec![String::from("a"), String::from("b")]
+                ),
+                (String::from("busted 2.2.0-1"), vec![String::from("a")]),
+                (String::from("say scm-1"), vec![String::from("b")]),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_install_server {
+    use super::*;
+
+    #[test]
+    fn only_looks_up_addons_by_name() {
+        let config = Config {
+            luarocks: Path::new("./no-luarocks-here"),
+            server: "file://./no-server-here",
+            fallback_to_root: true,
+            ..Config::default()
+        };
+        for name in ["./say-1.4.1-3.all.rock", "hishamhm/say"] {
+            assert_eq!(install_server(&config, name).unwrap(), config.server);
+        }
+        install_server(&config, "say").unwrap_err();
+        let config = Config {
+            fallback_to_root: false,
+            ..config
+        };
+        assert_eq!(install_server(&config, "say").unwrap(), config.server);
+    }
+}
@@ -0,0 +1,380 @@
+//! packing the addons of a tree into one archive that can be checked in or
+//! copied to an offline machine, and unpacking it into another tree. Rocks
+//! are packed with `luarocks pack` and vendored addons are copied as they are
+
+use crate::{
+    Addon, Config,
+    enabled::{self, EntryStyle, Plan, enable_in_library, links_for, read_library},
+    failure::Failure,
+    history::{LibraryChange, Operation},
+    installed::{self, DepsMode, execute_command},
+    list_enabled, list_installed,
+    state::{self, TreeState},
+    vendor::{self, Source, Vendored, run_tool, vendor_dir},
+};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{self, Path, PathBuf},
+    process::Command,
+};
+
+/// the name of the manifest inside a pack
+pub const PACK_MANIFEST: &str = "llynx-pack.json";
+
+/// an addon in a pack
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PackedAddon {
+    pub name: String,
+    pub version: String,
+
+    /// whether it was vendored rather than installed with LuaRocks
+    pub vendored: bool,
+
+    /// whether it was installed by name rather than as a dependency
+    pub explicit: bool,
+
+    /// whether it was enabled in the settings file it was packed from
+    pub enabled: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    addons: Vec<PackedAddon>,
+}
+
+/// where vendored addon `name` is unpacked to in `tree`
+pub fn packed_dir(tree: &Path, name: &str) -> PathBuf {
+    vendor_dir(tree).join("packed").join(name)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("while creating '{}'", to.display()))?;
+    for entry in
+        fs::read_dir(from).with_context(|| format!("while reading '{}'", from.display()))?
+    {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("while copying '{}'", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// fail with the output of LuaRocks if it failed
+fn check(output: std::process::Output) -> Result<()> {
+    if !output.status.success() {
+        return Err(Failure::LuaRocks
+            .tag(format!(
+                "LuaRocks failed:\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ))
+            .into());
+    }
+    Ok(())
+}
+
+/// pack `names`, or every installed addon if there are none, into a gzipped
+/// tarball at `output`
+pub fn pack(config: &Config, output: &Path, names: &[String]) -> Result<Vec<PackedAddon>> {
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    for name in names {
+        if !installed.iter().any(|addon| &addon.name == name) {
+            return Err(Failure::NotFound
+                .tag(format!("addon '{name}' is not installed"))
+                .into());
+        }
+    }
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+    let explicit = TreeState::load(config.tree)?
+        .map(|state| state.explicit)
+        .unwrap_or_default();
+
+    let staging = tempfile::tempdir().context("while creating a staging directory")?;
+    let rocks = staging.path().join("rocks");
+    fs::create_dir_all(&rocks)?;
+    // `luarocks pack` writes into the directory it runs in
+    let tree = path::absolute(config.tree)?;
+
+    let mut manifest = Manifest::default();
+    for addon in installed
+        .iter()
+        .filter(|addon| names.is_empty() || names.contains(&addon.name))
+    {
+        let vendored = vendor::find(config.tree, &addon.name)?;
+        match &vendored {
+            Some(vendored) => copy_dir(
+                Path::new(&vendored.location),
+                &staging.path().join("vendor").join(&addon.name),
+            )?,
+            None => {
+                let mut command = installed::luarocks(config);
+                command
+                    .arg("--tree")
+                    .arg(&tree)
+                    .args(["pack", &addon.name, &addon.version])
+                    .current_dir(&rocks);
+                check(execute_command(config, command)?)
+                    .with_context(|| format!("while packing '{}'", addon.name))?;
+            }
+        }
+        manifest.addons.push(PackedAddon {
+            name: addon.name.clone(),
+            version: addon.version.clone(),
+            vendored: vendored.is_some(),
+            explicit: explicit.contains(&addon.name),
+            enabled: enabled
+                .iter()
+                .any(|e| e.name == addon.name && e.version == addon.version),
+        });
+    }
+    fs::write(
+        staging.path().join(PACK_MANIFEST),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    let output = path::absolute(output)?;
+    run_tool(
+        config,
+        "tar",
+        Command::new("tar")
+            .arg("-czf")
+            .arg(&output)
+            .arg("-C")
+            .arg(staging.path())
+            .arg("."),
+    )?;
+    Ok(manifest.addons)
+}
+
+/// the version of `packed` that was unpacked, among `installed`
+fn new_version<'a>(installed: &'a [Addon], packed: &PackedAddon) -> Option<&'a Addon> {
+    installed
+        .iter()
+        .find(|addon| addon.name == packed.name && addon.version == packed.version)
+}
+
+/// read the manifest of a pack unpacked into `staging`, failing if any addon
+/// in it has a name that would lead out of the tree
+fn read_manifest(staging: &Path, archive: &Path) -> Result<Manifest> {
+    let manifest_path = staging.join(PACK_MANIFEST);
+    let contents = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("'{}' is not a pack made by llynx", archive.display()))?;
+    let manifest: Manifest = serde_json::from_str(&contents)
+        .with_context(|| format!("while parsing '{}'", manifest_path.display()))?;
+    for packed in &manifest.addons {
+        vendor::check_name(&packed.name)
+            .with_context(|| format!("while reading '{}'", archive.display()))?;
+    }
+    Ok(manifest)
+}
+
+/// install everything in the pack at `archive` into the tree and enable what
+/// was enabled when it was packed, with library paths pointing into this tree
+pub fn unpack(config: &Config, archive: &Path) -> Result<Vec<PackedAddon>> {
+    let staging = tempfile::tempdir().context("while creating a staging directory")?;
+    run_tool(
+        config,
+        "tar",
+        Command::new("tar")
+            .arg("-xzf")
+            .arg(archive)
+            .arg("-C")
+            .arg(staging.path()),
+    )?;
+    let manifest = read_manifest(staging.path(), archive)?;
+
+    let mut rocks: Vec<PathBuf> = fs::read_dir(staging.path().join("rocks"))
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    rocks.sort();
+    for rock in &rocks {
+        let rock = rock
+            .to_str()
+            .ok_or_else(|| anyhow!("'{}' is not valid UTF-8", rock.display()))?;
+        // everything the rocks depend on is in the pack too
+        let config = Config {
+            deps_mode: Some(DepsMode::None),
+            ..*config
+        };
+        check(installed::install(&config, rock, None)?)
+            .with_context(|| format!("while installing '{rock}'"))?;
+    }
+
+    for packed in manifest.addons.iter().filter(|packed| packed.vendored) {
+        let dir = packed_dir(config.tree, &packed.name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("while replacing '{}'", dir.display()))?;
+        }
+        copy_dir(&staging.path().join("vendor").join(&packed.name), &dir)?;
+        vendor::record(
+            config.tree,
+            &packed.name,
+            Vendored {
+                source: Source::Packed,
+                version: packed.version.clone(),
+                location: dir,
+            },
+        )?;
+    }
+    state::update(config.tree, |state| {
+        for packed in manifest.addons.iter().filter(|packed| !packed.explicit) {
+            state.explicit.remove(&packed.name);
+            state.dependencies.insert(packed.name.clone());
+        }
+    })?;
+
+    let plans = plan_enables(config, &manifest.addons)?;
+    enabled::apply_all(config, plans)?;
+    Ok(manifest.addons)
+}
+
+/// plan enabling the unpacked versions of the addons that were enabled where
+/// the pack was made, each starting from the library the one before it
+/// leaves
+fn plan_enables(config: &Config, addons: &[PackedAddon]) -> Result<Vec<Plan>> {
+    let style = EntryStyle::from(config);
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let mut enabled = list_enabled(config, None)?;
+    let mut library = read_library(config.settings)?;
+    let mut plans = vec![];
+    for packed in addons.iter().filter(|packed| packed.enabled) {
+        let addon = new_version(&installed, packed)
+            .ok_or_else(|| anyhow!("'{}' was not installed from the pack", packed.name))?;
+        let addons = vec![addon.clone()];
+        let f = enable_in_library(config.tree, addons.clone(), enabled.clone(), style)?;
+        let links = links_for(config.tree, &addons, style);
+        let after = f(library.clone());
+        enabled.retain(|other| other.name != addon.name);
+        enabled.extend(addons);
+        if after == library && links.is_empty() {
+            continue;
+        }
+        let before = std::mem::replace(&mut library, after.clone());
+        plans.push(Plan {
+            operation: Operation::Enable {
+                name: packed.name.clone(),
+            },
+            change: LibraryChange {
+                settings: config.settings.to_path_buf(),
+                before,
+                after,
+            },
+            links,
+            copies: vec![],
+        });
+    }
+    Ok(plans)
+}
+
+/// delete the unpacked files of vendored addon `name`
+pub(crate) fn remove_unpacked(tree: &Path, name: &str) -> Result<()> {
+    let dir = packed_dir(tree, name);
+    fs::remove_dir_all(&dir).with_context(|| format!("while removing '{}'", dir.display()))
+}
+
+#[cfg(test)]
+mod test_pack {
+    use super::*;
+
+    #[test]
+    fn copies_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        fs::create_dir_all(from.join("library/nested")).unwrap();
+        fs::write(from.join("library/nested/types.lua"), "---@meta").unwrap();
+        copy_dir(&from, &dir.path().join("to")).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("to/library/nested/types.lua")).unwrap(),
+            "---@meta"
+        );
+    }
+
+    #[test]
+    fn finds_new_versions() {
+        let installed = [Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: Some(PathBuf::from(
+                "tree/lib/luarocks/rocks-5.4/say/1.4.1-3/types",
+            )),
+        }];
+        let packed = PackedAddon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            vendored: false,
+            explicit: true,
+            enabled: true,
+        };
+        assert_eq!(new_version(&installed, &packed), installed.first());
+        let other = PackedAddon {
+            version: String::from("1.3-1"),
+            ..packed
+        };
+        assert_eq!(new_version(&installed, &other), None);
+    }
+
+    #[test]
+    fn plans_enabling_what_was_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        let types = dir
+            .path()
+            .join("tree/lib/luarocks/rocks-5.1/say/1.4.1-3/types");
+        let say = Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: Some(types.clone()),
+        };
+        let packed = |name: &str, enabled| PackedAddon {
+            name: String::from(name),
+            version: String::from("1.4.1-3"),
+            vendored: false,
+            explicit: true,
+            enabled,
+        };
+        let tree = dir.path().join("tree");
+        let config = Config {
+            tree: &tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        let mock = std::sync::Arc::new(crate::backend::Mock::new(vec![say], vec![]));
+        let plans = crate::backend::with_backend(mock, || {
+            plan_enables(&config, &[packed("say", true), packed("busted", false)])
+        })
+        .unwrap();
+        assert_eq!(plans.len(), 1);
+        assert_eq!(
+            plans[0].change.after,
+            [enabled::library_entry(&types, EntryStyle::from(&config)).unwrap()]
+        );
+    }
+
+    #[test]
+    fn rejects_names_leaving_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = Path::new("addons.tar.gz");
+        let manifest = |name: &str| {
+            let addon = serde_json::json!({
+                "name": name,
+                "version": "1.0-1",
+                "vendored": true,
+                "explicit": true,
+                "enabled": false,
+            });
+            let manifest = serde_json::json!({ "addons": [addon] });
+            fs::write(dir.path().join(PACK_MANIFEST), manifest.to_string()).unwrap();
+            read_manifest(dir.path(), archive)
+        };
+        assert_eq!(manifest("say").unwrap().addons[0].name, "say");
+        manifest("../../..").unwrap_err();
+        manifest("nested/say").unwrap_err();
+    }
+}
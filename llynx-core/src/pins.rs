@@ -0,0 +1,122 @@
+//! holding addons at one version with `pins` in `.llynx.toml`, like after an
+//! addon regressed its definitions. Pinned addons are installed at their pin
+//! unless another version is asked for, which is refused
+
+use crate::{installed, status::AddonStatus, version::Constraints};
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+
+/// the version each pinned addon is held at, by name
+pub type Pins = BTreeMap<String, String>;
+
+/// the version to install `name` at: the one asked for, or else its pin.
+/// Asking for another version than the pin fails
+pub fn version<'a>(
+    pins: Option<&'a Pins>,
+    name: &str,
+    version: Option<&'a str>,
+) -> Result<Option<&'a str>> {
+    let package = installed::package(name);
+    let Some(pin) = pins.and_then(|pins| pins.get(package)) else {
+        return Ok(version);
+    };
+    let asked = match installed::local_version(name) {
+        Some(local) => Some(local),
+        None => version,
+    };
+    match asked {
+        None => Ok(Some(pin)),
+        Some(asked) if asked == pin => Ok(version),
+        // constraints the pin satisfies pick the pin
+        Some(asked)
+            if Constraints::is_constraint(asked)
+                && installed::local_package(name).is_none()
+                && Constraints::parse(asked).is_ok_and(|constraints| constraints.matches(pin)) =>
+        {
+            Ok(Some(pin))
+        }
+        Some(asked) => bail!(
+            "'{package}' is pinned to {pin}, so {asked} isn't installed. Pass --unpin to install it anyway"
+        ),
+    }
+}
+
+/// the pinned addons whose newest installed or enabled version isn't their
+/// pin, with that version
+pub fn diverging<'a>(
+    pins: &'a Pins,
+    statuses: &'a [AddonStatus],
+) -> Vec<(&'a str, &'a str, &'a str)> {
+    statuses
+        .iter()
+        .filter_map(|status| {
+            let pin = pins.get(&status.name)?;
+            let version = status.enabled.as_deref().or(status.newest_installed())?;
+            (version != pin).then_some((status.name.as_str(), pin.as_str(), version))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_pins {
+    use super::*;
+
+    fn pins() -> Pins {
+        Pins::from([(String::from("say"), String::from("1.4.1-3"))])
+    }
+
+    #[test]
+    fn holds_pinned_addons() {
+        let pins = pins();
+        assert_eq!(version(Some(&pins), "say", None).unwrap(), Some("1.4.1-3"));
+        assert_eq!(
+            version(Some(&pins), "say", Some("1.4.1-3")).unwrap(),
+            Some("1.4.1-3")
+        );
+        assert_eq!(
+            version(Some(&pins), "hishamhm/say", None).unwrap(),
+            Some("1.4.1-3")
+        );
+        let err = version(Some(&pins), "say", Some("1.5-1")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'say' is pinned to 1.4.1-3, so 1.5-1 isn't installed. Pass --unpin to install it anyway"
+        );
+        version(Some(&pins), "./say-1.5-1.rockspec", None).unwrap_err();
+        assert_eq!(
+            version(Some(&pins), "say", Some(">= 1.4, < 2")).unwrap(),
+            Some("1.4.1-3")
+        );
+        version(Some(&pins), "say", Some(">= 1.5")).unwrap_err();
+        assert_eq!(
+            version(Some(&pins), "busted", Some("2.2.0-1")).unwrap(),
+            Some("2.2.0-1")
+        );
+        assert_eq!(version(None, "say", Some("1.5-1")).unwrap(), Some("1.5-1"));
+    }
+
+    #[test]
+    fn finds_diverging_addons() {
+        let statuses = [
+            AddonStatus {
+                name: String::from("say"),
+                installed: vec![String::from("1.4.1-3"), String::from("1.5-1")],
+                ..AddonStatus::default()
+            },
+            AddonStatus {
+                name: String::from("busted"),
+                installed: vec![String::from("2.2.0-1")],
+                ..AddonStatus::default()
+            },
+        ];
+        assert_eq!(diverging(&pins(), &statuses), [("say", "1.4.1-3", "1.5-1")]);
+
+        let enabled = [AddonStatus {
+            name: String::from("say"),
+            installed: vec![String::from("1.4.1-3"), String::from("1.5-1")],
+            enabled: Some(String::from("1.4.1-3")),
+            ..AddonStatus::default()
+        }];
+        assert!(diverging(&pins(), &enabled).is_empty());
+    }
+}
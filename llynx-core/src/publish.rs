@@ -0,0 +1,128 @@
+//! publishing an addon to luarocks.org with `luarocks upload`, which packs
+//! the source rock from the rockspec's `source.url` and uploads both. The
+//! API key is never logged
+
+use crate::{
+    Config,
+    config::LUAROCKS_ENDPOINT,
+    installed::{self, check_luarocks},
+    network,
+    rockspec::Rockspec,
+};
+use anyhow::{Result, anyhow, bail};
+use std::{env, path::Path, process::Output};
+
+/// the environment variable holding the luarocks.org API key, which wins
+/// over `api_key` in the user config file
+pub const API_KEY_ENV: &str = "LLYNX_API_KEY";
+
+/// the label addons in the lls-addons manifest carry
+pub const ADDON_LABEL: &str = "lls-addon";
+
+/// the API key to upload with, from the environment or else the user
+/// config. When there's none, LuaRocks uses the one it saved last time
+fn api_key(from_env: Option<String>, from_config: Option<&str>) -> Option<String> {
+    from_env
+        .filter(|key| !key.is_empty())
+        .or_else(|| from_config.map(String::from))
+}
+
+/// fail unless `rockspec` follows the conventions of the lls-addons manifest
+fn check_conventions(rockspec: &Rockspec) -> Result<()> {
+    if !rockspec
+        .description
+        .labels
+        .iter()
+        .any(|label| label == ADDON_LABEL)
+    {
+        bail!(
+            "the rockspec of '{}' isn't labeled '{ADDON_LABEL}', add it to description.labels",
+            rockspec.package
+        );
+    }
+    Ok(())
+}
+
+/// upload the rock of `rockspec` to luarocks.org, returning what LuaRocks
+/// said. The addon should have been validated first
+pub fn publish(config: &Config, rockspec_path: &Path) -> Result<Output> {
+    let rockspec = Rockspec::from_file(rockspec_path)?;
+    check_conventions(&rockspec)?;
+    if rockspec.source.is_none() {
+        bail!(
+            "the rockspec of '{}' has no source.url to pack the rock from",
+            rockspec.package
+        );
+    }
+    let file_name = rockspec_path
+        .file_name()
+        .ok_or_else(|| anyhow!("'{}' is not a rockspec", rockspec_path.display()))?;
+
+    let mut command = installed::luarocks(config);
+    command.arg("upload").arg(file_name);
+    // `upload` packs the rock in the directory it runs in
+    if let Some(dir) = rockspec_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        command.current_dir(dir);
+    }
+    let key = api_key(env::var(API_KEY_ENV).ok(), config.api_key);
+    if let Some(key) = &key {
+        command.arg(format!("--api-key={key}"));
+    }
+    network::apply(config, &mut command);
+    check_luarocks(config.luarocks)?;
+    // uploads aren't retried, and the command is logged without the key
+    log::info!(
+        "executing: {} upload {}{}",
+        config.luarocks.display(),
+        rockspec_path.display(),
+        if key.is_some() {
+            " --api-key=<hidden>"
+        } else {
+            ""
+        }
+    );
+    let output = network::output(config, &mut command)?;
+    if output.status.success() {
+        log::info!(
+            "published '{}', ask for it to be added to {LUAROCKS_ENDPOINT} if it isn't there yet",
+            rockspec_path.display()
+        );
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test_publish {
+    use super::*;
+
+    #[test]
+    fn environment_wins() {
+        assert_eq!(
+            api_key(Some(String::from("env")), Some("config")).as_deref(),
+            Some("env")
+        );
+        assert_eq!(
+            api_key(Some(String::new()), Some("config")).as_deref(),
+            Some("config")
+        );
+        assert_eq!(api_key(None, None), None);
+    }
+
+    #[test]
+    fn needs_the_label() {
+        let labeled = Rockspec::parse(
+            r#"package = "say" version = "dev-1" description = { labels = { "lls-addon" } }"#,
+        )
+        .unwrap();
+        check_conventions(&labeled).unwrap();
+        let unlabeled = Rockspec::parse(r#"package = "say" version = "dev-1""#).unwrap();
+        let err = check_conventions(&unlabeled).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the rockspec of 'say' isn't labeled 'lls-addon', add it to description.labels"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! finding releases of llynx itself and replacing the running executable
+//! with the binary of a newer one. Every release carries a binary for each
+//! platform, named like `llynx-x86_64-linux`, next to its SHA-256 checksum
+//! in `llynx-x86_64-linux.sha256`
+
+use crate::{Config, cache, config::MaybeConfig, network, vendor::run_tool, version};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use std::{
+    cmp::Ordering,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+/// where the newest release of llynx is described
+pub const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/goldenstein64/llynx/releases/latest";
+
+/// the extension of checksum files
+const CHECKSUM_EXTENSION: &str = "sha256";
+
+/// how often to look for a newer release of llynx while running other
+/// commands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateCheck {
+    #[default]
+    Never,
+    Daily,
+    Weekly,
+}
+
+impl UpdateCheck {
+    /// how long the newest release found is trusted before looking again
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            UpdateCheck::Never => None,
+            UpdateCheck::Daily => Some(Duration::from_secs(24 * 60 * 60)),
+            UpdateCheck::Weekly => Some(Duration::from_secs(7 * 24 * 60 * 60)),
+        }
+    }
+}
+
+/// a published release of llynx
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    /// the tag it was released under, like `v0.2.0`
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+/// a file attached to a release
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// the version it releases, like `0.2.0`
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    /// whether it's newer than `current`
+    pub fn is_newer_than(&self, current: &str) -> bool {
+        version::compare(self.version(), current) == Ordering::Greater
+    }
+
+    /// the attached file called `name`
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("release {} has no '{name}'", self.tag_name))
+    }
+}
+
+/// the name of the binary released for this platform
+pub fn asset_name() -> String {
+    format!(
+        "llynx-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+/// ask the release feed for the newest release
+pub fn latest(config: &Config) -> Result<Release> {
+    let mut curl = network::curl(config);
+    curl.args(["--header", "Accept: application/vnd.github+json"])
+        .arg(LATEST_RELEASE_URL);
+    let body = run_tool(config, "curl", &mut curl).context("while fetching the latest release")?;
+    serde_json::from_str(&body).context("while reading the latest release")
+}
+
+/// where the version of the newest release found is kept in the cache
+fn latest_version_path() -> Option<PathBuf> {
+    Some(cache::cache_dir()?.join("latest-release"))
+}
+
+/// look for the newest release on another thread once the version found
+/// last time is older than `interval`, so it's known by the next run. The
+/// thread is never waited for
+pub fn refresh_in_background(config: &Config, interval: Duration) {
+    let Some(path) = latest_version_path() else {
+        return;
+    };
+    if cache::read(&path, Some(interval)).is_some() {
+        return;
+    }
+    let config = MaybeConfig::from(config);
+    thread::spawn(move || match latest(&Config::default().extend(&config)) {
+        Ok(release) => cache::write(&path, release.version().as_bytes()),
+        Err(err) => log::debug!("could not look for a new release: {err:#}"),
+    });
+}
+
+/// the version of the newest release found so far, if it's newer than
+/// `current`
+pub fn cached_newer(current: &str) -> Option<String> {
+    let contents = cache::read(&latest_version_path()?, None)?;
+    let latest = String::from_utf8(contents).ok()?.trim().to_string();
+    (version::compare(&latest, current) == Ordering::Greater).then_some(latest)
+}
+
+/// the checksum in a line printed by `sha256sum`, `shasum` or `certutil`,
+/// the first run of 64 hexadecimal digits
+fn parse_checksum(stdout: &str) -> Option<String> {
+    stdout
+        .split_whitespace()
+        .find(|word| word.len() == 64 && word.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+}
+
+/// the SHA-256 checksum of `file`, from the tool this platform has for it
+pub(crate) fn checksum(config: &Config, file: &Path) -> Result<String> {
+    let (tool, mut command) = if cfg!(windows) {
+        let mut certutil = Command::new("certutil");
+        certutil.arg("-hashfile").arg(file).arg("SHA256");
+        ("certutil", certutil)
+    } else if cfg!(target_os = "macos") {
+        let mut shasum = Command::new("shasum");
+        shasum.args(["-a", "256"]).arg(file);
+        ("shasum", shasum)
+    } else {
+        let mut sha256sum = Command::new("sha256sum");
+        sha256sum.arg(file);
+        ("sha256sum", sha256sum)
+    };
+    let stdout = run_tool(config, tool, &mut command)?;
+    parse_checksum(&stdout).ok_or_else(|| anyhow!("{tool} printed no checksum"))
+}
+
+/// download the binary of `release` for this platform, check it against its
+/// checksum, and put it in place of `executable`
+pub fn install(config: &Config, release: &Release, executable: &Path) -> Result<()> {
+    let name = asset_name();
+    let binary = release.asset(&name)?;
+    let checksum_file = release.asset(&format!("{name}.{CHECKSUM_EXTENSION}"))?;
+
+    // downloading next to the executable lets it be renamed into place
+    let dir = executable
+        .parent()
+        .ok_or_else(|| anyhow!("'{}' is not in a directory", executable.display()))?;
+    let download = tempfile::Builder::new()
+        .prefix(".llynx-update")
+        .tempdir_in(dir)
+        .with_context(|| format!("while creating a download directory in '{}'", dir.display()))?;
+    let new = download.path().join(&name);
+    let expected = download.path().join(&checksum_file.name);
+    network::download(config, &binary.browser_download_url, &new)?;
+    network::download(config, &checksum_file.browser_download_url, &expected)?;
+
+    let expected = parse_checksum(&fs::read_to_string(&expected)?)
+        .ok_or_else(|| anyhow!("'{}' holds no checksum", checksum_file.name))?;
+    let actual = checksum(config, &new)?;
+    if actual != expected {
+        bail!("the checksum of '{name}' is {actual}, but the release says {expected}");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("while making '{}' executable", new.display()))?;
+    }
+
+    // Windows won't replace a running executable, but it lets it be renamed
+    let old = old_executable(executable);
+    if cfg!(windows) {
+        let _ = fs::remove_file(&old);
+        fs::rename(executable, &old)
+            .with_context(|| format!("while moving '{}' aside", executable.display()))?;
+    }
+    fs::rename(&new, executable)
+        .with_context(|| format!("while replacing '{}'", executable.display()))
+}
+
+/// where the replaced executable is moved to on Windows, to be removed by
+/// the next update
+fn old_executable(executable: &Path) -> PathBuf {
+    executable.with_extension("old")
+}
+
+#[cfg(test)]
+mod test_release {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        let sum = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(
+            parse_checksum(&format!("{sum}  llynx-x86_64-linux\n")).as_deref(),
+            Some(sum)
+        );
+        assert_eq!(
+            parse_checksum(&format!(
+                "SHA256 hash of llynx.exe:\r\n{}\r\nCertUtil: -hashfile command completed successfully.\r\n",
+                sum.to_uppercase()
+            ))
+            .as_deref(),
+            Some(sum)
+        );
+        assert_eq!(parse_checksum("no checksum here"), None);
+    }
+
+    #[test]
+    fn newer_releases() {
+        let release = Release {
+            tag_name: String::from("v0.2.0"),
+            assets: vec![],
+        };
+        assert_eq!(release.version(), "0.2.0");
+        assert!(release.is_newer_than("0.1.0"));
+        assert!(!release.is_newer_than("0.2.0"));
+        assert!(!release.is_newer_than("0.10.0"));
+    }
+}
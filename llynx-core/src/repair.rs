@@ -0,0 +1,182 @@
+//! fixing what [`verify`](crate::verify) finds where it can: installing
+//! versions that went missing again, pointing entries at the version that
+//! replaced theirs, and writing separators the way the config asks. Entries
+//! nothing can fix are taken out separately, so they can be confirmed first
+
+use crate::{
+    Addon, Config,
+    adopt::guess_name,
+    enabled::{
+        EnableMode, EntryStyle, PathStyle, Plan, entry_path, is_addon_path, library_entry,
+        newest_installed, read_library, resolved_path,
+    },
+    history::{LibraryChange, Operation},
+    links, list_installed,
+    verify::{EntryCheck, Problem, addon_at, verify},
+};
+use anyhow::Result;
+use std::path::{MAIN_SEPARATOR, Path};
+
+/// what repairing the library of a workspace takes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Repair {
+    /// addons to install again, whose entries work once they are
+    pub reinstall: Vec<Addon>,
+
+    /// changes fixing entries, each following the one before it
+    pub fixes: Vec<Plan>,
+
+    /// the entries nothing can fix
+    pub hopeless: Vec<EntryCheck>,
+
+    /// changes taking out the `hopeless` entries, following `fixes`
+    pub prunes: Vec<Plan>,
+}
+
+/// `entry` with the separators of `style`
+fn normalize(entry: &str, style: PathStyle) -> String {
+    match (style, MAIN_SEPARATOR) {
+        (PathStyle::Native, '\\') => entry.replace('/', "\\"),
+        _ => entry.replace('\\', "/"),
+    }
+}
+
+/// the addon `entry` belongs to, for the journal and hooks
+fn addon_name(config: &Config, entry: &str) -> String {
+    let name = match is_addon_path(config, entry) {
+        true => addon_at(&resolved_path(entry)).map(|(name, _)| name),
+        false => guess_name(entry).map(String::from),
+    };
+    name.unwrap_or_else(|| entry.to_string())
+}
+
+/// `library` with `entry` replaced by `replacement`, or just taken out if
+/// the library has `replacement` already
+fn replace(library: &[String], entry: &str, replacement: &str) -> Vec<String> {
+    let present = library.iter().any(|existing| existing == replacement);
+    library
+        .iter()
+        .filter_map(|existing| match existing == entry {
+            false => Some(existing.clone()),
+            true if present => None,
+            true => Some(replacement.to_string()),
+        })
+        .collect()
+}
+
+/// chain a change of `library` to `after` onto the plans before it
+fn step(
+    settings_file: &Path,
+    library: &mut Vec<String>,
+    operation: Operation,
+    after: Vec<String>,
+    links: Vec<Addon>,
+) -> Plan {
+    let before = std::mem::replace(library, after.clone());
+    Plan {
+        operation,
+        change: LibraryChange {
+            settings: settings_file.to_path_buf(),
+            before,
+            after,
+        },
+        links,
+        copies: vec![],
+    }
+}
+
+/// work out how to repair the library of `config.settings`
+pub fn plan_repair(config: &Config) -> Result<Repair> {
+    let checks = verify(config)?;
+    let installed = list_installed(config, None)?;
+    let style = EntryStyle::from(config);
+    let mut library = read_library(config.settings)?;
+    let mut repair = Repair::default();
+
+    for EntryCheck { entry, problem } in checks {
+        match problem {
+            Some(Problem::NotInstalled { name, version }) => {
+                let Some(newest) = newest_installed(&installed, &name) else {
+                    repair.reinstall.push(Addon {
+                        name,
+                        version,
+                        location: None,
+                    });
+                    continue;
+                };
+                let operation = Operation::Switch {
+                    name,
+                    version: newest.version.clone(),
+                };
+                // links stay where they are and only point elsewhere
+                let (after, links) = match links::target(&entry_path(&entry)) {
+                    Some(_) => (library.clone(), vec![newest.clone()]),
+                    None => {
+                        let location = newest.types_dir()?;
+                        let direct = EntryStyle {
+                            enable_mode: EnableMode::Direct,
+                            ..style
+                        };
+                        let replacement = library_entry(location, direct)?;
+                        (replace(&library, &entry, &replacement), vec![])
+                    }
+                };
+                let plan = step(config.settings, &mut library, operation, after, links);
+                repair.fixes.push(plan);
+            }
+            Some(problem) => repair.hopeless.push(EntryCheck {
+                entry,
+                problem: Some(problem),
+            }),
+            None => {
+                let normalized = normalize(&entry, style.path_style);
+                if normalized != entry {
+                    let operation = Operation::Repair {
+                        name: addon_name(config, &entry),
+                    };
+                    let after = replace(&library, &entry, &normalized);
+                    let plan = step(config.settings, &mut library, operation, after, vec![]);
+                    repair.fixes.push(plan);
+                }
+            }
+        }
+    }
+
+    for EntryCheck { entry, .. } in &repair.hopeless {
+        let operation = Operation::Disable {
+            name: addon_name(config, entry),
+        };
+        let after = library
+            .iter()
+            .filter(|existing| *existing != entry)
+            .cloned()
+            .collect();
+        let plan = step(config.settings, &mut library, operation, after, vec![]);
+        repair.prunes.push(plan);
+    }
+    Ok(repair)
+}
+
+#[cfg(test)]
+mod test_repair {
+    use super::*;
+
+    #[test]
+    fn normalizes_separators() {
+        assert_eq!(
+            normalize("types\\vendor\\say", PathStyle::Slash),
+            "types/vendor/say"
+        );
+        assert_eq!(
+            normalize("${workspaceFolder}/types", PathStyle::Slash),
+            "${workspaceFolder}/types"
+        );
+    }
+
+    #[test]
+    fn replaces_entries() {
+        let library = [String::from("a"), String::from("b"), String::from("c")];
+        assert_eq!(replace(&library, "b", "d"), ["a", "d", "c"]);
+        assert_eq!(replace(&library, "b", "c"), ["a", "c"]);
+    }
+}
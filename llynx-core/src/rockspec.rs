@@ -0,0 +1,465 @@
+//! reading rockspec files, which are Lua scripts assigning a few globals
+
+use crate::{Addon, Config, failure::Failure, filter::Filter, online, version};
+use anyhow::{Context, Result, anyhow, bail};
+use mlua::{HookTriggers, Lua};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// how many Lua instructions a rockspec may run before it's considered stuck
+const INSTRUCTION_LIMIT: u32 = 10_000_000;
+
+/// how deeply tables in a rockspec may nest
+const DEPTH_LIMIT: usize = 32;
+
+/// how many bytes a rockspec, or the manifest of a server, may allocate
+const MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// the string methods rockspecs may call, like `("%s-%s"):format(...)`.
+/// Everything else, `rep` included, is taken off the string metatable
+const STRING_METHODS: &str = r#"
+local methods = {}
+for _, name in ipairs({
+  "byte", "char", "find", "format", "gmatch", "gsub",
+  "len", "lower", "match", "reverse", "sub", "upper",
+}) do
+  methods[name] = string[name]
+end
+getmetatable("").__index = methods
+"#;
+
+/// a value in a rockspec
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Table(LuaTable),
+}
+
+/// a Lua table, split into its positional items and its named fields
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LuaTable {
+    pub items: Vec<LuaValue>,
+    pub fields: BTreeMap<String, LuaValue>,
+}
+
+impl LuaValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            LuaValue::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+impl LuaTable {
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.fields.get(key)
+    }
+
+    /// a named field, if it is a string
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(LuaValue::as_str)
+    }
+
+    /// the positional items that are strings
+    pub fn strings(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().filter_map(LuaValue::as_str)
+    }
+}
+
+/// the `description` table of a rockspec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Description {
+    pub summary: Option<String>,
+    pub detailed: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub labels: Vec<String>,
+}
+
+impl Description {
+    /// whether the summary or detailed description mentions what `filter`
+    /// matches
+    pub fn mentions(&self, filter: &Filter) -> bool {
+        [&self.summary, &self.detailed]
+            .into_iter()
+            .flatten()
+            .any(|text| filter.mentioned_in(text))
+    }
+}
+
+/// an entry of a rockspec's `dependencies`, like `say >= 1.4`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+
+    /// the version constraints after the name, if any
+    pub constraint: Option<String>,
+}
+
+impl Dependency {
+    pub fn parse(source: &str) -> Option<Dependency> {
+        let source = source.trim();
+        let end = source
+            .find(|c: char| c.is_whitespace() || "<>=~".contains(c))
+            .unwrap_or(source.len());
+        let (name, constraint) = source.split_at(end);
+        if name.is_empty() {
+            return None;
+        }
+        let constraint = constraint.trim();
+        Some(Dependency {
+            name: name.to_string(),
+            constraint: (!constraint.is_empty()).then(|| constraint.to_string()),
+        })
+    }
+}
+
+/// where a rockspec's sources come from, as in its `source` table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    pub url: String,
+    pub tag: Option<String>,
+    pub branch: Option<String>,
+
+    /// the directory the sources unpack into, when it isn't named after the
+    /// URL
+    pub dir: Option<String>,
+}
+
+impl Source {
+    fn from_table(table: &LuaTable) -> Option<Source> {
+        Some(Source {
+            url: table.get_str("url")?.to_string(),
+            tag: table.get_str("tag").map(str::to_string),
+            branch: table.get_str("branch").map(str::to_string),
+            dir: table.get_str("dir").map(str::to_string),
+        })
+    }
+}
+
+/// the parts of a rockspec llynx cares about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rockspec {
+    pub package: String,
+    pub version: String,
+
+    /// `None` when the rockspec has no `source.url`
+    pub source: Option<Source>,
+    pub description: Description,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl Rockspec {
+    /// interpret the globals assigned by a rockspec
+    pub fn from_globals(globals: &LuaTable) -> Result<Rockspec> {
+        let package = globals
+            .get_str("package")
+            .ok_or_else(|| anyhow!("rockspec has no 'package'"))?;
+        let version = globals
+            .get_str("version")
+            .ok_or_else(|| anyhow!("rockspec has no 'version'"))?;
+
+        let source = globals
+            .get("source")
+            .and_then(LuaValue::as_table)
+            .and_then(Source::from_table);
+
+        let description = match globals.get("description").and_then(LuaValue::as_table) {
+            None => Description::default(),
+            Some(table) => Description {
+                summary: table.get_str("summary").map(str::to_string),
+                detailed: table.get_str("detailed").map(str::to_string),
+                homepage: table.get_str("homepage").map(str::to_string),
+                license: table.get_str("license").map(str::to_string),
+                labels: table
+                    .get("labels")
+                    .and_then(LuaValue::as_table)
+                    .map(|labels| labels.strings().map(str::to_string).collect())
+                    .unwrap_or_default(),
+            },
+        };
+
+        let dependencies = globals
+            .get("dependencies")
+            .and_then(LuaValue::as_table)
+            .map(|dependencies| {
+                dependencies
+                    .strings()
+                    .filter_map(Dependency::parse)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Rockspec {
+            package: package.to_string(),
+            version: version.to_string(),
+            source,
+            description,
+            dependencies,
+        })
+    }
+
+    /// run the contents of a rockspec file
+    pub fn parse(source: &str) -> Result<Rockspec> {
+        Rockspec::from_globals(&parse_globals(source)?)
+    }
+
+    /// read and run a rockspec file
+    pub fn from_file(path: &Path) -> Result<Rockspec> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("while reading '{}'", path.display()))?;
+        Rockspec::parse(&contents).with_context(|| format!("while parsing '{}'", path.display()))
+    }
+}
+
+/// where LuaRocks keeps the rockspec of an installed addon, next to its
+/// `types` directory
+pub fn installed_rockspec_path(addon: &Addon) -> Option<PathBuf> {
+    let location = Path::new(addon.location.as_ref()?);
+    let version_dir = location.parent()?;
+    Some(version_dir.join(format!("{}-{}.rockspec", addon.name, addon.version)))
+}
+
+/// read the rockspec of an installed addon
+pub fn read_installed(addon: &Addon) -> Result<Rockspec> {
+    let path = installed_rockspec_path(addon)
+        .ok_or_else(|| Failure::NotFound.tag(format!("addon '{}' is not installed", addon.name)))?;
+    Rockspec::from_file(&path)
+}
+
+/// the summary of the newest version of each addon, read from the tree for
+/// installed addons and downloaded from the server otherwise. Addons whose
+/// rockspec can't be read are left out
+pub fn summaries(addons: &[Addon], config: &Config) -> BTreeMap<String, String> {
+    descriptions(addons, config)
+        .into_iter()
+        .filter_map(|(name, description)| Some((name, description.summary?)))
+        .collect()
+}
+
+/// the names of `addons` whose summary or detailed description mentions
+/// `filter`, read like [`summaries`]
+pub fn described(addons: &[Addon], filter: &Filter, config: &Config) -> BTreeSet<String> {
+    descriptions(addons, config)
+        .into_iter()
+        .filter(|(_, description)| description.mentions(filter))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// the names of `addons` labeled with every one of `labels`, ignoring case,
+/// read like [`summaries`]
+pub fn labeled(addons: &[Addon], labels: &[String], config: &Config) -> BTreeSet<String> {
+    descriptions(addons, config)
+        .into_iter()
+        .filter(|(_, description)| {
+            labels.iter().all(|wanted| {
+                description
+                    .labels
+                    .iter()
+                    .any(|label| label.eq_ignore_ascii_case(wanted))
+            })
+        })
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// the description of the newest version of each addon, read like
+/// [`summaries`]
+pub fn descriptions(addons: &[Addon], config: &Config) -> BTreeMap<String, Description> {
+    let mut newest: BTreeMap<&str, &Addon> = BTreeMap::new();
+    for addon in addons {
+        let is_newer = newest.get(addon.name.as_str()).is_none_or(|other| {
+            version::compare(&addon.version, &other.version) == Ordering::Greater
+        });
+        if is_newer {
+            newest.insert(&addon.name, addon);
+        }
+    }
+
+    newest
+        .into_iter()
+        .filter_map(|(name, addon)| {
+            let rockspec = match addon.location {
+                Some(_) => read_installed(addon),
+                None => online::fetch_rockspec(config, name, &addon.version),
+            };
+            match rockspec {
+                Ok(rockspec) => Some((name.to_string(), rockspec.description)),
+                Err(err) => {
+                    log::warn!("could not read the rockspec of '{name}': {err:#}");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// run a rockspec in an empty environment like LuaRocks does, returning the
+/// globals it sets
+pub fn parse_globals(source: &str) -> Result<LuaTable> {
+    let lua = Lua::new();
+    lua.load(STRING_METHODS).exec().map_err(lua_error)?;
+    lua.set_memory_limit(MEMORY_LIMIT).map_err(lua_error)?;
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(INSTRUCTION_LIMIT),
+        |_, _| Err(mlua::Error::runtime("rockspec took too long to run")),
+    );
+    let env = lua.create_table().map_err(lua_error)?;
+    lua.load(source)
+        .set_name("rockspec")
+        .set_environment(env.clone())
+        .exec()
+        .map_err(lua_error)?;
+    convert_table(env, 0)
+}
+
+fn lua_error(err: mlua::Error) -> anyhow::Error {
+    anyhow!("{err}")
+}
+
+fn convert_value(value: mlua::Value, depth: usize) -> Result<LuaValue> {
+    Ok(match value {
+        mlua::Value::Boolean(b) => LuaValue::Boolean(b),
+        mlua::Value::Integer(n) => LuaValue::Number(n as f64),
+        mlua::Value::Number(n) => LuaValue::Number(n),
+        mlua::Value::String(s) => LuaValue::String(s.to_string_lossy().into_owned()),
+        mlua::Value::Table(table) => LuaValue::Table(convert_table(table, depth + 1)?),
+        // functions, userdata, and the like can't be described in a rockspec
+        _ => LuaValue::Nil,
+    })
+}
+
+fn convert_table(table: mlua::Table, depth: usize) -> Result<LuaTable> {
+    if depth > DEPTH_LIMIT {
+        bail!("rockspec tables are nested too deeply");
+    }
+    let mut converted = LuaTable::default();
+    let len = table.raw_len();
+    for pair in table.clone().pairs::<mlua::Value, mlua::Value>() {
+        let (key, value) = pair.map_err(lua_error)?;
+        match key {
+            mlua::Value::Integer(i) if i >= 1 && (i as usize) <= len => {}
+            mlua::Value::Integer(i) => {
+                let value = convert_value(value, depth)?;
+                converted.fields.insert(i.to_string(), value);
+            }
+            mlua::Value::String(s) => {
+                let value = convert_value(value, depth)?;
+                converted
+                    .fields
+                    .insert(s.to_string_lossy().into_owned(), value);
+            }
+            _ => {}
+        }
+    }
+    for i in 1..=len {
+        let value = table.raw_get::<_, mlua::Value>(i).map_err(lua_error)?;
+        converted.items.push(convert_value(value, depth)?);
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::*;
+
+    #[test]
+    fn say() {
+        let rockspec =
+            Rockspec::parse(include_str!("../../tests/assets/say-1.4.1-3.rockspec")).unwrap();
+        assert_eq!(rockspec.package, "say");
+        assert_eq!(rockspec.version, "1.4.1-3");
+        assert_eq!(
+            rockspec.source,
+            Some(Source {
+                url: String::from("git+https://github.com/lunarmodules/say.git"),
+                tag: Some(String::from("v1.4.1")),
+                branch: None,
+                dir: None,
+            })
+        );
+        assert_eq!(
+            rockspec.description.summary.as_deref(),
+            Some("Lua string hashing/indexing library")
+        );
+        assert_eq!(rockspec.description.license.as_deref(), Some("MIT"));
+        assert_eq!(
+            rockspec.description.homepage.as_deref(),
+            Some("https://lunarmodules.github.io/say")
+        );
+        assert_eq!(
+            rockspec.dependencies,
+            vec![Dependency {
+                name: String::from("lua"),
+                constraint: Some(String::from(">= 5.1")),
+            }]
+        );
+    }
+
+    #[test]
+    fn dependencies() {
+        assert_eq!(
+            Dependency::parse("lls-busted"),
+            Some(Dependency {
+                name: String::from("lls-busted"),
+                constraint: None,
+            })
+        );
+        assert_eq!(
+            Dependency::parse("say>=1.4,<2"),
+            Some(Dependency {
+                name: String::from("say"),
+                constraint: Some(String::from(">=1.4,<2")),
+            })
+        );
+        assert_eq!(Dependency::parse("  "), None);
+    }
+
+    #[test]
+    fn tables() {
+        let globals = parse_globals(
+            r#"
+            description = {
+                labels = { "lls-addon", "types" },
+                ["weird key"] = -2.5, [10] = true,
+            }
+            "#,
+        )
+        .unwrap();
+        let description = globals.get("description").unwrap().as_table().unwrap();
+        let labels = description.get("labels").unwrap().as_table().unwrap();
+        assert_eq!(
+            labels.strings().collect::<Vec<_>>(),
+            vec!["lls-addon", "types"]
+        );
+        assert_eq!(description.get("weird key"), Some(&LuaValue::Number(-2.5)));
+        assert_eq!(description.get("10"), Some(&LuaValue::Boolean(true)));
+    }
+
+    #[test]
+    fn sandboxed() {
+        parse_globals("os.exit(1)").unwrap_err();
+        parse_globals("while true do end").unwrap_err();
+        parse_globals("t = {} t.t = t").unwrap_err();
+        parse_globals("s = ('x'):rep(2^40)").unwrap_err();
+        parse_globals("s = 'x' for i = 1, 40 do s = s .. s end").unwrap_err();
+        let globals = parse_globals("s = ('%s-%s'):format('say', ('1'):upper())").unwrap();
+        assert_eq!(globals.get_str("s"), Some("say-1"));
+    }
+}
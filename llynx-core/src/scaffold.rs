@@ -0,0 +1,193 @@
+//! starting a new addon from a skeleton that's ready to publish to the
+//! lls-addons manifest: a rockspec copying `types` into the rock, an example
+//! definition file, and optionally the `config.json` and `plugin.lua` llynx
+//! reads from next to the definitions
+
+use anyhow::{Context, Result, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// the extra files an addon can start with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extras {
+    /// a `config.json` with settings to apply when the addon is enabled
+    pub config: bool,
+
+    /// a `plugin.lua` for LuaLS to run
+    pub plugin: bool,
+}
+
+/// whether `name` can be the name of a rock, which LuaRocks lowercases and
+/// puts in file names
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+}
+
+/// `name` as a Lua identifier, for the table the example definitions go in
+fn identifier(name: &str) -> String {
+    let identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("_{identifier}"),
+        false => identifier,
+    }
+}
+
+fn rockspec(name: &str) -> String {
+    format!(
+        r#"rockspec_format = "3.0"
+package = "{name}"
+version = "dev-1"
+
+source = {{
+  url = "git+https://github.com/<user>/{name}.git",
+}}
+
+description = {{
+  summary = "LuaLS definitions for {name}",
+  license = "MIT",
+  labels = {{ "lls-addon", "types" }},
+}}
+
+dependencies = {{
+  "lua >= 5.1",
+}}
+
+build = {{
+  type = "none",
+  copy_directories = {{ "types" }},
+}}
+"#
+    )
+}
+
+fn definitions(name: &str) -> String {
+    let identifier = identifier(name);
+    format!(
+        r#"---@meta {name}
+
+---@class {identifier}
+local {identifier} = {{}}
+
+---An example function. Replace it with the real definitions
+---@param message string
+---@return string
+function {identifier}.example(message) end
+
+return {identifier}
+"#
+    )
+}
+
+fn config(name: &str) -> String {
+    format!(
+        r#"{{
+  "name": "{name}",
+  "words": [],
+  "files": [],
+  "settings": {{}}
+}}
+"#
+    )
+}
+
+const PLUGIN: &str = r#"-- run by LuaLS when the addon is enabled, see https://luals.github.io/wiki/plugins/
+
+---@param uri string
+---@param text string
+---@return { start: integer, finish: integer, text: string }[]?
+function OnSetText(uri, text)
+end
+"#;
+
+/// the files of a new addon `name`, relative to its directory
+pub fn files(name: &str, extras: Extras) -> Vec<(PathBuf, String)> {
+    let types = Path::new("types");
+    let mut files = vec![
+        (
+            PathBuf::from(format!("{name}-dev-1.rockspec")),
+            rockspec(name),
+        ),
+        (types.join(format!("{name}.lua")), definitions(name)),
+    ];
+    if extras.config {
+        files.push((types.join("config.json"), config(name)));
+    }
+    if extras.plugin {
+        files.push((types.join("plugin.lua"), String::from(PLUGIN)));
+    }
+    files
+}
+
+/// create addon `name` in a new directory `dir`, returning the paths of the
+/// files written
+pub fn create(dir: &Path, name: &str, extras: Extras) -> Result<Vec<PathBuf>> {
+    if !is_valid_name(name) {
+        bail!("'{name}' can't name a rock, use lowercase letters, digits, '-', '_' and '.'");
+    }
+    if dir.exists() {
+        bail!("'{}' already exists", dir.display());
+    }
+    let mut written = vec![];
+    for (path, contents) in files(name, extras) {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("while creating '{}'", parent.display()))?;
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("while writing '{}'", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test_scaffold {
+    use super::*;
+    use crate::rockspec::Rockspec;
+
+    #[test]
+    fn names() {
+        assert!(is_valid_name("love2d"));
+        assert!(is_valid_name("lua-cjson"));
+        assert!(!is_valid_name("Love2D"));
+        assert!(!is_valid_name("-love"));
+        assert!(!is_valid_name("love 2d"));
+        assert_eq!(identifier("lua-cjson"), "lua_cjson");
+        assert_eq!(identifier("51degrees"), "_51degrees");
+    }
+
+    #[test]
+    fn creates_an_addon() {
+        let dir = tempfile::tempdir().unwrap();
+        let addon = dir.path().join("lua-cjson");
+        let extras = Extras {
+            config: true,
+            plugin: false,
+        };
+        let written = create(&addon, "lua-cjson", extras).unwrap();
+        assert_eq!(written.len(), 3);
+        assert!(addon.join("types/lua-cjson.lua").is_file());
+        assert!(!addon.join("types/plugin.lua").exists());
+        let config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(addon.join("types/config.json")).unwrap())
+                .unwrap();
+        assert_eq!(config["name"], "lua-cjson");
+
+        let rockspec = Rockspec::from_file(&addon.join("lua-cjson-dev-1.rockspec")).unwrap();
+        assert_eq!(rockspec.package, "lua-cjson");
+        assert_eq!(rockspec.version, "dev-1");
+        assert_eq!(rockspec.description.labels, ["lls-addon", "types"]);
+
+        let err = create(&addon, "lua-cjson", extras).unwrap_err();
+        assert!(err.to_string().ends_with("already exists"));
+    }
+}
@@ -0,0 +1,98 @@
+//! finding addons on the server by name and by what their rockspecs say
+//! about them, best matches first
+
+use crate::{
+    Config,
+    filter::Filter,
+    fuzzy, list_enabled, list_installed, list_online,
+    rockspec::{self, Description},
+    status::{self, AddonStatus},
+};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// an addon found by [`search`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub status: AddonStatus,
+
+    /// the description in the rockspec of its newest version
+    pub description: Description,
+}
+
+/// the names fuzzily matching `query`, best first, followed by the rest
+/// whose descriptions mention it, by name
+fn rank<'a>(
+    names: BTreeSet<&'a str>,
+    query: &str,
+    descriptions: &BTreeMap<String, Description>,
+) -> Vec<&'a str> {
+    let wanted = Filter::from(query);
+    let (named, rest): (Vec<&str>, Vec<&str>) = names
+        .into_iter()
+        .partition(|name| fuzzy::score(query, name).is_some());
+    let mut ranked = fuzzy::rank(named, query, |name| name);
+    ranked.extend(rest.into_iter().filter(|name| {
+        descriptions
+            .get(*name)
+            .is_some_and(|description| description.mentions(&wanted))
+    }));
+    ranked
+}
+
+/// search the server for addons whose names match `query` or whose
+/// descriptions mention it. The rockspec of every addon on the server is
+/// read for it, so the first search takes a while until they're cached
+pub fn search(config: &Config, query: &str) -> Result<Vec<SearchResult>> {
+    let online = list_online(config, None)
+        .with_context(|| format!("while searching '{}'", config.server))?;
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+    let mut descriptions = rockspec::descriptions(&online, config);
+
+    let names = online.iter().map(|addon| addon.name.as_str()).collect();
+    let ranked = rank(names, query, &descriptions);
+    let mut statuses: BTreeMap<String, AddonStatus> = status::merge(&installed, &enabled, &online)
+        .into_iter()
+        .map(|status| (status.name.clone(), status))
+        .collect();
+    Ok(ranked
+        .into_iter()
+        .filter_map(|name| {
+            Some(SearchResult {
+                status: statuses.remove(name)?,
+                description: descriptions.remove(name).unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test_rank {
+    use super::*;
+
+    #[test]
+    fn names_before_descriptions() {
+        let names = BTreeSet::from(["love2d-types", "lls-love", "busted", "say"]);
+        let descriptions = BTreeMap::from([
+            (
+                String::from("busted"),
+                Description {
+                    summary: Some(String::from("Testing, also for LÖVE and Love2D")),
+                    ..Description::default()
+                },
+            ),
+            (
+                String::from("say"),
+                Description {
+                    summary: Some(String::from("Lua string hashing")),
+                    ..Description::default()
+                },
+            ),
+        ]);
+        assert_eq!(
+            rank(names, "love", &descriptions),
+            ["love2d-types", "lls-love", "busted"]
+        );
+    }
+}
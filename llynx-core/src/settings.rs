@@ -0,0 +1,111 @@
+//! reading and changing LuaLS settings other than the library, like
+//! `Lua.runtime.version`, in whatever settings file llynx is configured to
+//! write, Zed's included
+
+use crate::{
+    Config, LIB_SETTINGS_KEY,
+    enabled::{read_setting, update_settings},
+};
+use anyhow::{Result, bail};
+use serde_json::Value;
+use std::path::Path;
+
+/// fail unless `key` is a LuaLS setting llynx leaves to the user
+fn check_key(key: &str) -> Result<()> {
+    if key == LIB_SETTINGS_KEY {
+        bail!("'{key}' is changed with `enable` and `disable`");
+    }
+    if !key.starts_with("Lua.") {
+        bail!("'{key}' is not a LuaLS setting, which start with 'Lua.'");
+    }
+    Ok(())
+}
+
+/// `value` read as JSON, or as a string if it isn't valid JSON, so strings
+/// don't need quotes
+pub fn parse_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+/// the value of `key` in `settings_file`, if it's set
+pub fn get(settings_file: &Path, key: &str) -> Result<Option<Value>> {
+    check_key(key)?;
+    read_setting(settings_file, key)
+}
+
+/// set `key` to `value` in the settings file
+pub fn set(config: &Config, key: &str, value: Value) -> Result<()> {
+    check_key(key)?;
+    update_settings(config, |settings| {
+        settings.insert(key.to_string(), value);
+        Ok(())
+    })
+}
+
+/// remove `key` from the settings file, returning whether it was set
+pub fn unset(config: &Config, key: &str) -> Result<bool> {
+    check_key(key)?;
+    let mut removed = false;
+    update_settings(config, |settings| {
+        removed = settings.remove(key).is_some();
+        Ok(())
+    })?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test_settings {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn sets_and_unsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        fs::write(
+            &settings,
+            "{\n  // the version in use\n  \"Lua.runtime.version\": \"Lua 5.1\"\n}\n",
+        )
+        .unwrap();
+        let config = Config {
+            settings: &settings,
+            ..Config::default()
+        };
+
+        set(&config, "Lua.runtime.version", parse_value("LuaJIT")).unwrap();
+        set(&config, "Lua.hint.enable", parse_value("true")).unwrap();
+        assert_eq!(
+            get(&settings, "Lua.runtime.version").unwrap(),
+            Some(Value::from("LuaJIT"))
+        );
+        assert_eq!(
+            get(&settings, "Lua.hint.enable").unwrap(),
+            Some(Value::Bool(true))
+        );
+        assert!(unset(&config, "Lua.hint.enable").unwrap());
+        assert!(!unset(&config, "Lua.hint.enable").unwrap());
+        assert_eq!(get(&settings, "Lua.hint.enable").unwrap(), None);
+        get(&settings, LIB_SETTINGS_KEY).unwrap_err();
+        get(&settings, "editor.tabSize").unwrap_err();
+    }
+
+    #[test]
+    fn frozen_settings_stay() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        fs::write(&settings, "{ \"Lua.hint.enable\": true }").unwrap();
+        let config = Config {
+            settings: &settings,
+            frozen: true,
+            ..Config::default()
+        };
+
+        set(&config, "Lua.hint.enable", Value::Bool(true)).unwrap();
+        set(&config, "Lua.hint.enable", Value::Bool(false)).unwrap_err();
+        unset(&config, "Lua.hint.enable").unwrap_err();
+        assert_eq!(
+            fs::read_to_string(&settings).unwrap(),
+            "{ \"Lua.hint.enable\": true }"
+        );
+    }
+}
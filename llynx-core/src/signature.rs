@@ -0,0 +1,440 @@
+//! checking detached minisign signatures of rocks from servers that publish
+//! them, like private mirrors. The signature of `<server>/<file>` is expected
+//! at `<server>/<file>.minisig`. Every rock an install needs is checked, so
+//! LuaRocks is never left to fetch dependencies itself
+
+use crate::{
+    Addon, Config,
+    failure::Failure,
+    frozen,
+    hooks::{self, Event},
+    installed::{self, DepsMode, local_package},
+    list_installed, namespace, network, online, pins,
+    rockspec::{Dependency, Rockspec},
+    store,
+    vendor::run_tool,
+    version::Constraints,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// the extension of signature files
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// where the signature of `file_name` is published on `server`
+fn signature_url(server: &str, file_name: &str) -> String {
+    format!(
+        "{}/{file_name}.{SIGNATURE_EXTENSION}",
+        server.trim_end_matches('/')
+    )
+}
+
+/// the arguments giving minisign `public_key`, which is either the key
+/// itself or a file holding it
+fn key_args(public_key: &str) -> [&str; 2] {
+    if Path::new(public_key).is_file() {
+        ["-p", public_key]
+    } else {
+        ["-P", public_key]
+    }
+}
+
+/// check `file` against `signature` with minisign, failing if it doesn't
+/// match `public_key`
+pub fn verify(config: &Config, file: &Path, signature: &Path, public_key: &str) -> Result<()> {
+    let mut minisign = Command::new("minisign");
+    minisign
+        .arg("-V")
+        .arg("-q")
+        .args(key_args(public_key))
+        .arg("-m")
+        .arg(file)
+        .arg("-x")
+        .arg(signature);
+    run_tool(config, "minisign", &mut minisign).with_context(|| {
+        format!(
+            "the signature of '{}' does not match the server's public key",
+            file.display()
+        )
+    })?;
+    Ok(())
+}
+
+/// download the source rock of `name` from the server into `dir`, returning
+/// where it is. Servers with credentials are downloaded from directly, since
+/// LuaRocks can't log into them
+fn download_rock(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    dir: &Path,
+) -> Result<PathBuf> {
+    if network::credentials_for(config, config.server).is_some() {
+        return online::download_rock(config, name, version, dir);
+    }
+    let mut luarocks = installed::luarocks(config);
+    luarocks
+        .args(["--only-server", config.server, "download", "--source", name])
+        .args(version)
+        .current_dir(dir);
+    let output = installed::execute_command(config, luarocks)?;
+    if !output.status.success() {
+        return Err(Failure::LuaRocks
+            .tag(format!(
+                "could not download '{name}': {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+    }
+    fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| local_package(path.to_str().unwrap_or_default()) == Some(name))
+        .ok_or_else(|| anyhow!("LuaRocks downloaded no rock of '{name}'"))
+}
+
+/// check the signature of `rock`, published next to it on the server
+pub fn verify_published(config: &Config, public_key: &str, rock: &Path, dir: &Path) -> Result<()> {
+    let file_name = rock
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .ok_or_else(|| anyhow!("'{}' is not a valid rock name", rock.display()))?;
+    let signature = dir.join(format!("{file_name}.{SIGNATURE_EXTENSION}"));
+    network::download(config, &signature_url(config.server, file_name), &signature)
+        .with_context(|| format!("while fetching the signature of '{file_name}'"))?;
+    verify(config, rock, &signature, public_key)
+}
+
+/// install `name` with LuaRocks, checking its signature and the
+/// signatures of everything it depends on first when the server has a public
+/// key, and downloading it with llynx when the server needs credentials.
+/// Local files are installed as they are, though their dependencies are
+/// checked too. With `store`, the tree is linked to the store afterwards.
+/// The install hooks run around it
+pub fn install(config: &Config, name: &str, version: Option<&str>) -> Result<Output> {
+    let version = pins::version(config.pins, name, version)?;
+    let version = frozen::version(config, name, version)?;
+    let version = online::resolve_version(config, name, version.as_deref())?;
+    let version = version.as_deref();
+    hooks::run(config, Event::PreInstall, name, version)?;
+    let output = install_journaled(config, name, version, true)?;
+    if output.status.success() {
+        hooks::run(config, Event::PostInstall, name, version)?;
+    }
+    Ok(output)
+}
+
+/// install `version` of `name` like [`install`], as it was before, without
+/// journaling it or minding pins and freezes. Undoing a removal puts it back
+/// like this
+pub(crate) fn reinstall(config: &Config, name: &str, version: &str) -> Result<Output> {
+    install_journaled(config, name, Some(version), false)
+}
+
+fn install_journaled(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    journal: bool,
+) -> Result<Output> {
+    let output = install_rock(config, name, version, journal)?;
+    if config.store && output.status.success() {
+        store::link_tree(config.tree).context("while linking the tree to the store")?;
+    }
+    Ok(output)
+}
+
+fn install_rock(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    journal: bool,
+) -> Result<Output> {
+    let server = online::install_server(config, name)?;
+    if server != config.server && config.public_key.is_some() {
+        // a typo or a name taken there would get past the signature check
+        bail!(
+            "'{name}' isn't on {}, and it can't be installed from {server} without checking its signature",
+            config.server
+        );
+    }
+    if server != config.server {
+        log::warn!(
+            "'{name}' isn't on {}, installing it from {server}, where it may not be an addon for LuaLS",
+            config.server
+        );
+        // LuaRocks looks for its dependencies there too
+        let mut luarocks_args = vec![format!("--server={server}")];
+        luarocks_args.extend_from_slice(config.luarocks_args);
+        let config = Config {
+            server,
+            luarocks_args: &luarocks_args,
+            fallback_to_root: false,
+            ..*config
+        };
+        return install_downloaded(&config, name, version, journal);
+    }
+    if let (Some(public_key), Some(_)) = (config.public_key, local_package(name)) {
+        let rockspec = match name.ends_with(".rockspec") {
+            true => Rockspec::from_file(Path::new(name))?,
+            false => packed_rockspec(config, Path::new(name))?,
+        };
+        let dir = tempfile::tempdir().context("while creating a download directory")?;
+        let dependencies = verified_dependencies(config, public_key, &rockspec, dir.path())?;
+        let config = Config {
+            deps_mode: Some(DepsMode::None),
+            ..*config
+        };
+        return installed::install_with(&config, name, version, &dependencies, journal);
+    }
+    let needs_download =
+        config.public_key.is_some() || network::credentials_for(config, config.server).is_some();
+    if local_package(name).is_some() || !needs_download {
+        return installed::install_with(config, name, version, &[], journal);
+    }
+    if let Some((user, addon)) = namespace::split(name) {
+        let server = namespace::manifest_url(user);
+        return install_rock(
+            &Config {
+                server: &server,
+                ..*config
+            },
+            addon,
+            version,
+            journal,
+        );
+    }
+    install_downloaded(config, name, version, journal)
+}
+
+/// the path of a downloaded rock, as LuaRocks is given it
+fn rock_path(rock: &Path) -> Result<String> {
+    rock.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("'{}' is not valid UTF-8", rock.display()))
+}
+
+/// download `name` from the server and install the rock. When the server has
+/// a public key, its signature and the signatures of the rocks it depends on
+/// are checked first, and each is installed on its own
+fn install_downloaded(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    journal: bool,
+) -> Result<Output> {
+    let dir = tempfile::tempdir().context("while creating a download directory")?;
+    let rock = download_rock(config, name, version, dir.path())?;
+    let (deps_mode, dependencies) = match config.public_key {
+        Some(public_key) => {
+            verify_published(config, public_key, &rock, dir.path())?;
+            let rockspec = packed_rockspec(config, &rock)?;
+            let dependencies = verified_dependencies(config, public_key, &rockspec, dir.path())?;
+            (Some(DepsMode::None), dependencies)
+        }
+        None => (config.deps_mode, vec![]),
+    };
+    installed::install_with(
+        &Config {
+            deps_mode,
+            ..*config
+        },
+        &rock_path(&rock)?,
+        None,
+        &dependencies,
+        journal,
+    )
+}
+
+/// read the rockspec packed into `rock`, which sits at its root
+fn packed_rockspec(config: &Config, rock: &Path) -> Result<Rockspec> {
+    let (package, version) = installed::local_package(&rock_path(rock)?)
+        .zip(installed::local_version(&rock_path(rock)?))
+        .map(|(package, version)| (package.to_string(), version.to_string()))
+        .ok_or_else(|| anyhow!("'{}' is not named like a rock", rock.display()))?;
+    let source = run_tool(
+        config,
+        "unzip",
+        Command::new("unzip")
+            .arg("-p")
+            .arg(rock)
+            .arg(format!("{package}-{version}.rockspec")),
+    )?;
+    Rockspec::parse(&source)
+        .with_context(|| format!("while reading the rockspec in '{}'", rock.display()))
+}
+
+/// the rocks `rockspec` depends on that aren't installed yet, directly or
+/// not, downloaded into `dir` and checked against `public_key`. Each comes
+/// after the ones it depends on
+fn verified_dependencies(
+    config: &Config,
+    public_key: &str,
+    rockspec: &Rockspec,
+    dir: &Path,
+) -> Result<Vec<String>> {
+    let installed = list_installed(config, None)?;
+    let rocks = dependency_closure(rockspec, &installed, &mut |dependency| {
+        // a bare version in a rockspec matches it exactly, like LuaRocks does
+        let constraint = dependency.constraint.as_deref().map(|constraint| {
+            match Constraints::is_constraint(constraint) {
+                true => constraint.to_string(),
+                false => format!("== {constraint}"),
+            }
+        });
+        let version = online::resolve_version(config, &dependency.name, constraint.as_deref())?;
+        let rock = download_rock(config, &dependency.name, version.as_deref(), dir)?;
+        verify_published(config, public_key, &rock, dir)
+            .with_context(|| format!("while checking '{}'", dependency.name))?;
+        let rockspec = packed_rockspec(config, &rock)?;
+        Ok((rock, rockspec))
+    })?;
+    rocks.iter().map(|rock| rock_path(rock)).collect()
+}
+
+/// the rocks `rockspec` depends on that `installed` doesn't satisfy,
+/// directly or not, each after the ones it depends on. `fetch` gets a rock
+/// for a dependency and the rockspec inside it
+fn dependency_closure(
+    rockspec: &Rockspec,
+    installed: &[Addon],
+    fetch: &mut impl FnMut(&Dependency) -> Result<(PathBuf, Rockspec)>,
+) -> Result<Vec<PathBuf>> {
+    fn visit(
+        rockspec: &Rockspec,
+        installed: &[Addon],
+        fetch: &mut impl FnMut(&Dependency) -> Result<(PathBuf, Rockspec)>,
+        seen: &mut BTreeSet<String>,
+        rocks: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        for dependency in &rockspec.dependencies {
+            if dependency.name == "lua"
+                || !seen.insert(dependency.name.clone())
+                || installed::has_version(
+                    installed,
+                    &dependency.name,
+                    dependency.constraint.as_deref(),
+                )?
+            {
+                continue;
+            }
+            let (rock, rockspec) = fetch(dependency)?;
+            visit(&rockspec, installed, fetch, seen, rocks)?;
+            rocks.push(rock);
+        }
+        Ok(())
+    }
+
+    let mut seen = BTreeSet::from([rockspec.package.clone()]);
+    let mut rocks = vec![];
+    visit(rockspec, installed, fetch, &mut seen, &mut rocks)?;
+    Ok(rocks)
+}
+
+#[cfg(test)]
+mod test_signature {
+    use super::*;
+    use crate::{
+        addon,
+        backend::{self, RocksBackend},
+        config::ROOT_SERVER,
+        filter::Filter,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn signatures_sit_next_to_rocks() {
+        assert_eq!(
+            signature_url("https://rocks.example.com/", "say-1.4.1-3.src.rock"),
+            "https://rocks.example.com/say-1.4.1-3.src.rock.minisig"
+        );
+    }
+
+    #[test]
+    fn keys_can_be_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("server.pub");
+        fs::write(&file, "untrusted comment: minisign public key\nRWQ...").unwrap();
+        let file = file.to_str().unwrap();
+        assert_eq!(key_args(file), ["-p", file]);
+        assert_eq!(key_args("RWQf6LRCGA9i53"), ["-P", "RWQf6LRCGA9i53"]);
+    }
+
+    fn rockspec(package: &str, dependencies: &str) -> Rockspec {
+        Rockspec::parse(&format!(
+            r#"package = "{package}" version = "1.0-1" dependencies = {{ {dependencies} }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn dependencies_come_before_their_dependents() {
+        let busted = rockspec(
+            "busted",
+            r#""lua >= 5.1", "say >= 1.4", "luassert", "busted""#,
+        );
+        let installed = [Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: None,
+        }];
+        let mut fetched = vec![];
+        let rocks = dependency_closure(&busted, &installed, &mut |dependency| {
+            fetched.push(dependency.name.clone());
+            let rockspec = match dependency.name.as_str() {
+                "luassert" => rockspec("luassert", r#""say", "lua-term""#),
+                name => rockspec(name, ""),
+            };
+            Ok((PathBuf::from(&dependency.name), rockspec))
+        })
+        .unwrap();
+        assert_eq!(fetched, ["luassert", "lua-term"]);
+        assert_eq!(
+            rocks,
+            [PathBuf::from("lua-term"), PathBuf::from("luassert")]
+        );
+    }
+
+    /// a server without addons, next to a luarocks.org with one
+    struct OnlyOnRoot;
+
+    impl RocksBackend for OnlyOnRoot {
+        fn list(&self, _config: &Config, _filter: Option<&Filter>) -> Result<Vec<Addon>> {
+            Ok(vec![])
+        }
+
+        fn search(&self, config: &Config, _filter: Option<&Filter>) -> Result<Vec<Addon>> {
+            Ok(match config.server {
+                ROOT_SERVER => vec![addon("say", "1.4.1-3")],
+                _ => vec![],
+            })
+        }
+
+        fn install(&self, _config: &Config, name: &str, _version: Option<&str>) -> Result<Output> {
+            panic!("'{name}' was installed without checking its signature")
+        }
+
+        fn remove(&self, _config: &Config, name: &str, _version: Option<&str>) -> Result<Output> {
+            panic!("'{name}' was removed")
+        }
+    }
+
+    #[test]
+    fn keeps_signed_servers_from_falling_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            tree: dir.path(),
+            public_key: Some("RWQf6LRCGA9i53"),
+            fallback_to_root: true,
+            ..Config::default()
+        };
+        let err = backend::with_backend(Arc::new(OnlyOnRoot), || install(&config, "say", None))
+            .unwrap_err();
+        assert!(err.to_string().contains("without checking its signature"));
+    }
+}
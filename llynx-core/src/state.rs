@@ -0,0 +1,80 @@
+//! what llynx remembers about a rocks tree, kept in a file inside it
+
+use crate::vendor::Vendored;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// the name of the state file, relative to the tree
+pub const STATE_FILE: &str = "llynx-state.json";
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TreeState {
+    /// addons installed by name, rather than as a dependency of another
+    #[serde(default)]
+    pub explicit: BTreeSet<String>,
+
+    /// addons LuaRocks installed as dependencies of an install llynx ran.
+    /// Only these are ever autoremoved, so rocks installed before llynx kept
+    /// state or with plain LuaRocks stay
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub dependencies: BTreeSet<String>,
+
+    /// addons kept in the tree without LuaRocks, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vendored: BTreeMap<String, Vendored>,
+}
+
+fn state_path(tree: &Path) -> PathBuf {
+    PathBuf::from(tree).join(STATE_FILE)
+}
+
+impl TreeState {
+    /// read the state of `tree`, or `None` if llynx hasn't written one yet
+    pub fn load(tree: &Path) -> Result<Option<TreeState>> {
+        let path = state_path(tree);
+        let contents = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            result => result.with_context(|| format!("while reading '{}'", path.display()))?,
+        };
+        serde_json::from_str(&contents)
+            .with_context(|| format!("while parsing '{}'", path.display()))
+            .map(Some)
+    }
+
+    pub fn save(&self, tree: &Path) -> Result<()> {
+        let path = state_path(tree);
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&path, contents).with_context(|| format!("while writing '{}'", path.display()))
+    }
+}
+
+/// read the state of `tree`, change it, and write it again
+pub fn update(tree: &Path, f: impl FnOnce(&mut TreeState)) -> Result<()> {
+    let mut state = TreeState::load(tree)?.unwrap_or_default();
+    f(&mut state);
+    state.save(tree)
+}
+
+#[cfg(test)]
+mod test_update {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let tree = tempfile::tempdir().unwrap();
+        let tree = tree.path();
+        assert_eq!(TreeState::load(tree).unwrap(), None);
+
+        update(tree, |state| {
+            state.explicit.insert(String::from("say"));
+        })
+        .unwrap();
+        let state = TreeState::load(tree).unwrap().unwrap();
+        assert!(state.explicit.contains("say"));
+    }
+}
@@ -0,0 +1,154 @@
+//! combine online, installed, and enabled listings into one view
+
+use crate::{
+    Addon, Config, filter::Filter, list_enabled, list_installed, list_online, pins, third_party,
+    version::Version,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// everything known about one addon
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct AddonStatus {
+    pub name: String,
+
+    /// installed versions, oldest first
+    pub installed: Vec<String>,
+
+    /// the version in the settings file, if any
+    pub enabled: Option<String>,
+
+    /// the newest version on the server, if it was searched
+    pub latest: Option<String>,
+}
+
+impl AddonStatus {
+    /// the newest installed version, if any
+    pub fn newest_installed(&self) -> Option<&str> {
+        self.installed.last().map(String::as_str)
+    }
+
+    /// whether the server has a newer version than every installed one
+    pub fn update_available(&self) -> bool {
+        match (self.newest_installed(), &self.latest) {
+            (Some(installed), Some(latest)) => Version::parse(latest) > Version::parse(installed),
+            _ => false,
+        }
+    }
+}
+
+/// merge listings into one status per addon name, sorted by name
+pub fn merge(installed: &[Addon], enabled: &[Addon], online: &[Addon]) -> Vec<AddonStatus> {
+    fn entry<'a, 'b>(
+        statuses: &'b mut BTreeMap<&'a str, AddonStatus>,
+        name: &'a str,
+    ) -> &'b mut AddonStatus {
+        statuses.entry(name).or_insert_with(|| AddonStatus {
+            name: name.to_string(),
+            ..AddonStatus::default()
+        })
+    }
+
+    let mut statuses: BTreeMap<&str, AddonStatus> = BTreeMap::new();
+    for addon in installed {
+        entry(&mut statuses, &addon.name)
+            .installed
+            .push(addon.version.clone());
+    }
+    for addon in enabled {
+        entry(&mut statuses, &addon.name).enabled = Some(addon.version.clone());
+    }
+    for addon in online {
+        let status = entry(&mut statuses, &addon.name);
+        let is_newer = match &status.latest {
+            Some(latest) => Version::parse(&addon.version) > Version::parse(latest),
+            None => true,
+        };
+        if is_newer {
+            status.latest = Some(addon.version.clone());
+        }
+    }
+
+    statuses
+        .into_values()
+        .map(|mut status| {
+            status
+                .installed
+                .sort_by(|a, b| Version::parse(a).cmp(&Version::parse(b)));
+            status
+        })
+        .collect()
+}
+
+/// list installed, enabled, and online addons together, including the ones
+/// enabled through `Lua.workspace.userThirdParty`, warning about pinned
+/// addons that aren't at their pin. If the server can't be searched, addons
+/// are listed without their latest versions
+pub fn list_all(config: &Config, filter: Option<&Filter>) -> Result<Vec<AddonStatus>> {
+    let installed = list_installed(config, filter).context("while listing installed addons")?;
+    // what llynx enabled comes last, taking precedence
+    let mut enabled = third_party::list_enabled(config.settings, filter)
+        .context("while listing addons enabled through userThirdParty")?;
+    enabled.extend(list_enabled(config, filter).context("while listing enabled addons")?);
+    let online = list_online(config, filter).unwrap_or_else(|err| {
+        log::warn!("could not search '{}': {err:#}", config.server);
+        vec![]
+    });
+    let statuses = merge(&installed, &enabled, &online);
+    if let Some(pins) = config.pins {
+        for (name, pin, version) in pins::diverging(pins, &statuses) {
+            log::warn!("'{name}' is pinned to {pin}, but {version} is in use");
+        }
+    }
+    Ok(statuses)
+}
+
+/// list the installed addons the server has newer versions of. The search
+/// results of the server are reused while they're cached
+pub fn list_outdated(config: &Config, filter: Option<&Filter>) -> Result<Vec<AddonStatus>> {
+    let installed = list_installed(config, filter).context("while listing installed addons")?;
+    let enabled = list_enabled(config, filter).context("while listing enabled addons")?;
+    let online = list_online(config, filter)
+        .with_context(|| format!("while searching '{}'", config.server))?;
+    Ok(merge(&installed, &enabled, &online)
+        .into_iter()
+        .filter(AddonStatus::update_available)
+        .collect())
+}
+
+#[cfg(test)]
+mod test_merge {
+    use super::*;
+    use crate::addon;
+
+    #[test]
+    fn combines_sources() {
+        let installed = [addon("say", "1.4.1-3"), addon("say", "1.3-1")];
+        let enabled = [addon("say", "1.4.1-3")];
+        let online = [
+            addon("say", "1.4.1-3"),
+            addon("say", "1.10-1"),
+            addon("busted", "2.2.0-1"),
+        ];
+        let statuses = merge(&installed, &enabled, &online);
+        assert_eq!(
+            statuses,
+            vec![
+                AddonStatus {
+                    name: String::from("busted"),
+                    latest: Some(String::from("2.2.0-1")),
+                    ..AddonStatus::default()
+                },
+                AddonStatus {
+                    name: String::from("say"),
+                    installed: vec![String::from("1.3-1"), String::from("1.4.1-3")],
+                    enabled: Some(String::from("1.4.1-3")),
+                    latest: Some(String::from("1.10-1")),
+                },
+            ]
+        );
+        assert!(!statuses[0].update_available());
+        assert!(statuses[1].update_available());
+    }
+}
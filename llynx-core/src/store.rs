@@ -0,0 +1,376 @@
+//! a per-user store holding the types of each rock once, keyed by their
+//! contents, so projects with the same addons don't each keep a copy. The
+//! `types` directory of a rock in a tree becomes a link into the store, a
+//! symlink or, on Windows, a junction. LuaRocks removes the link rather than
+//! what it points at, so the store is only ever emptied by [`gc`]
+
+use crate::{
+    bootstrap,
+    cache::{self, CacheSize},
+};
+use anyhow::{Context, Result, anyhow};
+use std::{
+    collections::HashSet,
+    fs::{self, File, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// the file in the store listing the trees linked to it, one per line
+const TREES_FILE: &str = "trees";
+
+/// the file in the store locked while a tree is linked to it or it's cleaned
+const LOCK_FILE: &str = ".lock";
+
+/// the per-user store, inside the data directory
+pub fn store_dir() -> Option<PathBuf> {
+    Some(bootstrap::data_dir()?.join("store"))
+}
+
+/// wait until no other llynx is changing `store`, and keep others out until
+/// the returned file is dropped
+fn lock(store: &Path) -> Result<File> {
+    let path = store.join(LOCK_FILE);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("while opening '{}'", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            log::warn!("waiting for another llynx to finish with the store...");
+            file.lock()
+                .with_context(|| format!("while locking '{}'", path.display()))?;
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(err).with_context(|| format!("while locking '{}'", path.display()));
+        }
+    }
+    Ok(file)
+}
+
+/// every file below `dir`, by its path relative to `dir` with forward
+/// slashes, sorted
+fn files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &format!("{name}/"), files)?;
+            } else {
+                files.push((name, entry.path()));
+            }
+        }
+        Ok(())
+    }
+    let mut found = Vec::new();
+    walk(dir, "", &mut found).with_context(|| format!("while reading '{}'", dir.display()))?;
+    found.sort();
+    Ok(found)
+}
+
+/// a 64-bit FNV-1a hash of the names and contents of the files below `dir`.
+/// Entries with the same hash are compared before they're shared, so it only
+/// has to be quick, not collision-free
+fn hash_dir(dir: &Path) -> Result<String> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for (name, path) in files(dir)? {
+        let contents =
+            fs::read(&path).with_context(|| format!("while reading '{}'", path.display()))?;
+        feed(name.as_bytes());
+        feed(&[0]);
+        feed(&(contents.len() as u64).to_le_bytes());
+        feed(&contents);
+    }
+    Ok(format!("{hash:016x}"))
+}
+
+/// whether the directories `a` and `b` hold the same files
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    let (a, b) = (files(a)?, files(b)?);
+    if a.len() != b.len() || a.iter().zip(&b).any(|((a, _), (b, _))| a != b) {
+        return Ok(false);
+    }
+    for ((_, a), (_, b)) in a.iter().zip(&b) {
+        if fs::read(a)? != fs::read(b)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// copy the files below `from` into `to`, creating it
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("while creating '{}'", to.display()))?;
+    for (name, path) in files(from)? {
+        let target = to.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&path, &target).with_context(|| format!("while copying '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// move `dir` into the store, or drop it if the store has it already,
+/// returning the entry holding it
+fn store_contents(store: &Path, dir: &Path) -> Result<PathBuf> {
+    let hash = hash_dir(dir)?;
+    for n in 0.. {
+        let entry = match n {
+            0 => store.join(&hash),
+            n => store.join(format!("{hash}-{n}")),
+        };
+        if entry.exists() {
+            if same_contents(&entry, dir)? {
+                fs::remove_dir_all(dir)
+                    .with_context(|| format!("while removing '{}'", dir.display()))?;
+                return Ok(entry);
+            }
+            continue;
+        }
+        // the store may be on another drive than the tree
+        if fs::rename(dir, &entry).is_err() {
+            let staged = tempfile::tempdir_in(store)
+                .with_context(|| format!("while staging in '{}'", store.display()))?;
+            copy_dir(dir, staged.path())?;
+            fs::rename(staged.keep(), &entry)
+                .with_context(|| format!("while adding '{}'", entry.display()))?;
+            fs::remove_dir_all(dir)
+                .with_context(|| format!("while removing '{}'", dir.display()))?;
+        }
+        return Ok(entry);
+    }
+    unreachable!("the loop above only ends by returning")
+}
+
+#[cfg(unix)]
+pub(crate) fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub(crate) fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+    // junctions don't need the privileges symlinks do
+    let status = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(target)
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("mklink /J failed with {status}"))),
+    }
+}
+
+/// the `types` directories of every rock in `tree`, for any version of Lua
+fn types_dirs(tree: &Path) -> Vec<PathBuf> {
+    let children = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default()
+    };
+    children(&tree.join("lib").join("luarocks"))
+        .into_iter()
+        .filter(|dir| {
+            dir.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("rocks-"))
+        })
+        .flat_map(|rocks| children(&rocks))
+        .flat_map(|rock| children(&rock))
+        .map(|version| version.join("types"))
+        .collect()
+}
+
+fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// read the trees linked to `store`
+fn read_trees(store: &Path) -> Result<Vec<PathBuf>> {
+    match fs::read_to_string(store.join(TREES_FILE)) {
+        Ok(contents) => Ok(contents.lines().map(PathBuf::from).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err).context("while reading the trees linked to the store"),
+    }
+}
+
+fn write_trees(store: &Path, trees: &[PathBuf]) -> Result<()> {
+    let contents: String = trees
+        .iter()
+        .map(|tree| format!("{}\n", tree.display()))
+        .collect();
+    fs::write(store.join(TREES_FILE), contents)
+        .context("while writing the trees linked to the store")
+}
+
+/// move the types of every rock in `tree` into `store`, leaving links behind.
+/// Types linked already are left alone
+pub fn link_tree_into(store: &Path, tree: &Path) -> Result<()> {
+    fs::create_dir_all(store).with_context(|| format!("while creating '{}'", store.display()))?;
+    let store = store
+        .canonicalize()
+        .with_context(|| format!("while resolving '{}'", store.display()))?;
+    let _lock = lock(&store)?;
+
+    // the tree is listed first, so `gc` never deletes what it links to, even
+    // when linking stops halfway
+    let tree = tree
+        .canonicalize()
+        .with_context(|| format!("while resolving '{}'", tree.display()))?;
+    let mut trees = read_trees(&store)?;
+    if !trees.contains(&tree) {
+        trees.push(tree.clone());
+        write_trees(&store, &trees)?;
+    }
+
+    for types in types_dirs(&tree) {
+        if is_link(&types) || !types.is_dir() {
+            continue;
+        }
+        let entry = store_contents(&store, &types)?;
+        link_dir(&entry, &types).with_context(|| {
+            format!(
+                "while linking '{}' to '{}'",
+                types.display(),
+                entry.display()
+            )
+        })?;
+        log::debug!("linked '{}' to '{}'", types.display(), entry.display());
+    }
+    Ok(())
+}
+
+/// link `tree` to the per-user store
+pub fn link_tree(tree: &Path) -> Result<()> {
+    let store = store_dir().ok_or_else(|| {
+        anyhow!(
+            "no data directory for the store, set {} to choose one",
+            bootstrap::DATA_DIR_ENV
+        )
+    })?;
+    link_tree_into(&store, tree)
+}
+
+/// delete the entries of `store` that no tree links to anymore, and forget
+/// trees that are gone, returning how much was deleted
+pub fn gc(store: &Path) -> Result<CacheSize> {
+    if !store.is_dir() {
+        return Ok(CacheSize::default());
+    }
+    let _lock = lock(store)?;
+    let trees: Vec<PathBuf> = read_trees(store)?
+        .into_iter()
+        .filter(|tree| tree.is_dir())
+        .collect();
+    let used: HashSet<_> = trees
+        .iter()
+        .flat_map(|tree| types_dirs(tree))
+        .filter_map(|types| fs::read_link(types).ok())
+        .filter_map(|target| target.file_name().map(ToOwned::to_owned))
+        .collect();
+
+    let mut removed = CacheSize::default();
+    let entries = match fs::read_dir(store) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(removed),
+        Err(err) => return Err(err).context("while reading the store"),
+    };
+    for entry in entries {
+        let entry = entry.context("while reading the store")?;
+        // entries being staged start with a dot
+        let staged = entry.file_name().to_string_lossy().starts_with('.');
+        if !entry.file_type()?.is_dir() || staged || used.contains(&entry.file_name()) {
+            continue;
+        }
+        let size = cache::size_of(&entry.path())?;
+        fs::remove_dir_all(entry.path())
+            .with_context(|| format!("while removing '{}'", entry.path().display()))?;
+        removed.files += size.files;
+        removed.bytes += size.bytes;
+    }
+    write_trees(store, &trees)?;
+    Ok(removed)
+}
+
+#[cfg(all(test, unix))]
+mod test_store {
+    use super::*;
+
+    fn install(tree: &Path, name: &str, contents: &str) -> PathBuf {
+        let types = tree
+            .join("lib/luarocks/rocks-5.1")
+            .join(name)
+            .join("1.0-1/types");
+        fs::create_dir_all(&types).unwrap();
+        fs::write(types.join("init.lua"), contents).unwrap();
+        types
+    }
+
+    #[test]
+    fn shares_types_between_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("store");
+        let (game, tools) = (dir.path().join("game"), dir.path().join("tools"));
+        let game_say = install(&game, "say", "---@meta say");
+        let tools_say = install(&tools, "say", "---@meta say");
+        let busted = install(&tools, "busted", "---@meta busted");
+        link_tree_into(&store, &game).unwrap();
+        link_tree_into(&store, &tools).unwrap();
+        link_tree_into(&store, &tools).unwrap();
+
+        assert!(is_link(&game_say) && is_link(&tools_say) && is_link(&busted));
+        assert_eq!(
+            fs::read_link(&game_say).unwrap(),
+            fs::read_link(&tools_say).unwrap()
+        );
+        assert_eq!(
+            fs::read_to_string(busted.join("init.lua")).unwrap(),
+            "---@meta busted"
+        );
+        assert_eq!(read_trees(&store).unwrap().len(), 2);
+
+        // nothing is deleted while a tree links to it
+        assert_eq!(gc(&store).unwrap(), CacheSize::default());
+        fs::remove_dir_all(&tools).unwrap();
+        assert_eq!(
+            gc(&store).unwrap(),
+            CacheSize {
+                files: 1,
+                bytes: 15
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(game_say.join("init.lua")).unwrap(),
+            "---@meta say"
+        );
+        assert_eq!(read_trees(&store).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn compares_before_sharing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("init.lua"), "a").unwrap();
+        fs::write(b.join("init.lua"), "b").unwrap();
+        assert_ne!(hash_dir(&a).unwrap(), hash_dir(&b).unwrap());
+        assert!(!same_contents(&a, &b).unwrap());
+        fs::write(b.join("init.lua"), "a").unwrap();
+        assert_eq!(hash_dir(&a).unwrap(), hash_dir(&b).unwrap());
+        assert!(same_contents(&a, &b).unwrap());
+    }
+}
@@ -0,0 +1,90 @@
+//! addons enabled through `Lua.workspace.userThirdParty`, which lists folders
+//! of addons in the LLS-Addons format, each at `<folder>/<name>`. LuaLS
+//! offers to enable them when code looks like it uses them, then adds their
+//! `library` directory to the library itself
+
+use crate::{
+    Addon,
+    enabled::{entry_path, read_library, read_setting},
+    filter::{self, Filter},
+};
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// the setting listing the folders
+pub const THIRD_PARTY_KEY: &str = "Lua.workspace.userThirdParty";
+
+/// stands in for the version of these addons, which don't have one
+pub const THIRD_PARTY_VERSION: &str = "third-party";
+
+/// the folders of `Lua.workspace.userThirdParty` in `settings_file`
+pub(crate) fn dirs(settings_file: &Path) -> Result<Vec<PathBuf>> {
+    let dirs = read_setting(settings_file, THIRD_PARTY_KEY)?.unwrap_or_default();
+    Ok(match dirs {
+        serde_json::Value::Array(dirs) => dirs
+            .iter()
+            .filter_map(|dir| dir.as_str())
+            .map(entry_path)
+            .collect(),
+        _ => vec![],
+    })
+}
+
+/// the addons of `settings_file` whose library entries point into one of its
+/// `Lua.workspace.userThirdParty` folders
+pub fn list_enabled(settings_file: &Path, filter: Option<&Filter>) -> Result<Vec<Addon>> {
+    let dirs = dirs(settings_file)?;
+    let addons = read_library(settings_file)?
+        .into_iter()
+        .filter_map(|entry| {
+            let path = entry_path(&entry);
+            let name = dirs.iter().find_map(|dir| {
+                match path.strip_prefix(dir).ok()?.components().next()? {
+                    Component::Normal(name) => Some(name.to_str()?.to_string()),
+                    _ => None,
+                }
+            })?;
+            Some(Addon {
+                name,
+                version: THIRD_PARTY_VERSION.to_string(),
+                location: Some(path),
+            })
+        })
+        .collect();
+    Ok(filter::apply(addons, filter))
+}
+
+#[cfg(test)]
+mod test_list_enabled {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn lists_entries_in_third_party_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let third_party = dir.path().join("third-party");
+        let path = |path: PathBuf| path.to_str().unwrap().to_string();
+        let settings = dir.path().join("settings.json");
+        fs::write(
+            &settings,
+            serde_json::json!({
+                "Lua.workspace.library": [
+                    path(third_party.join("luv/library")),
+                    "types",
+                ],
+                THIRD_PARTY_KEY: [path(third_party.clone())],
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            list_enabled(&settings, None).unwrap(),
+            [Addon {
+                name: String::from("luv"),
+                version: String::from(THIRD_PARTY_VERSION),
+                location: Some(third_party.join("luv/library")),
+            }]
+        );
+    }
+}
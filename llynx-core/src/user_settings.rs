@@ -0,0 +1,125 @@
+//! finding the user-level settings file of VS Code and its variants, for
+//! enabling addons in every workspace at once
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// which editor's user settings to use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum Editor {
+    /// the first of the editors below that has been run on this machine
+    #[default]
+    Auto,
+
+    /// VS Code
+    Code,
+
+    /// VS Code Insiders
+    Insiders,
+
+    /// VSCodium
+    Codium,
+}
+
+impl Editor {
+    /// the directory the editor keeps its configuration in, inside the
+    /// per-user configuration directory
+    fn dir_name(self) -> Option<&'static str> {
+        match self {
+            Editor::Auto => None,
+            Editor::Code => Some("Code"),
+            Editor::Insiders => Some("Code - Insiders"),
+            Editor::Codium => Some("VSCodium"),
+        }
+    }
+}
+
+/// the per-user configuration directory, following each platform's
+/// convention
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }
+}
+
+/// the `User` directory of `editor` inside `config_dir`. With
+/// [`Editor::Auto`], the first editor that has one is picked
+fn user_dir_in(config_dir: &Path, editor: Editor) -> Result<PathBuf> {
+    let user_dir = |editor: Editor| {
+        config_dir
+            .join(editor.dir_name().expect("editor is not auto"))
+            .join("User")
+    };
+    let editor = match editor {
+        Editor::Auto => [Editor::Code, Editor::Insiders, Editor::Codium]
+            .into_iter()
+            .find(|&editor| user_dir(editor).is_dir())
+            .ok_or_else(|| {
+                anyhow!(
+                    "no VS Code, VS Code Insiders or VSCodium settings were found in '{}'",
+                    config_dir.display()
+                )
+            })?,
+        editor => editor,
+    };
+    Ok(user_dir(editor))
+}
+
+/// the user settings file of `editor` inside `config_dir`
+fn settings_file_in(config_dir: &Path, editor: Editor) -> Result<PathBuf> {
+    Ok(user_dir_in(config_dir, editor)?.join("settings.json"))
+}
+
+/// the directory `editor` keeps the user's settings and the data of its
+/// extensions in on this machine
+pub fn user_dir(editor: Editor) -> Result<PathBuf> {
+    let config_dir =
+        config_dir().ok_or_else(|| anyhow!("could not find the user configuration directory"))?;
+    user_dir_in(&config_dir, editor)
+}
+
+/// the user settings file of `editor` on this machine
+pub fn settings_file(editor: Editor) -> Result<PathBuf> {
+    let config_dir =
+        config_dir().ok_or_else(|| anyhow!("could not find the user configuration directory"))?;
+    settings_file_in(&config_dir, editor)
+}
+
+#[cfg(test)]
+mod test_settings_file {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn picks_the_first_editor_found() {
+        let dir = tempfile::tempdir().unwrap();
+        settings_file_in(dir.path(), Editor::Auto).unwrap_err();
+
+        fs::create_dir_all(dir.path().join("VSCodium/User")).unwrap();
+        assert_eq!(
+            settings_file_in(dir.path(), Editor::Auto).unwrap(),
+            dir.path().join("VSCodium/User/settings.json")
+        );
+        fs::create_dir_all(dir.path().join("Code/User")).unwrap();
+        assert_eq!(
+            settings_file_in(dir.path(), Editor::Auto).unwrap(),
+            dir.path().join("Code/User/settings.json")
+        );
+        assert_eq!(
+            settings_file_in(dir.path(), Editor::Insiders).unwrap(),
+            dir.path().join("Code - Insiders/User/settings.json")
+        );
+    }
+}
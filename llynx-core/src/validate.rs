@@ -0,0 +1,294 @@
+//! checking an addon for packaging mistakes before it's published: that its
+//! rockspec parses and copies its definitions into the rock, that the
+//! definitions are LuaCATS, that its `config.json` is JSON, and that the rock
+//! installs into an empty tree
+
+use crate::{
+    Config,
+    enabled::newest_installed,
+    failure::Failure,
+    list_installed,
+    rockspec::{self, LuaValue, Rockspec},
+    signature,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// the directories definitions can be kept in
+const DEFINITION_DIRS: [&str; 2] = ["types", "library"];
+
+/// one thing checked about an addon, and what's wrong with it, if anything
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub problem: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<()>) -> Check {
+        Check {
+            name: name.to_string(),
+            problem: result.err().map(|err| format!("{err:#}")),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+/// the addon being checked: its rockspec and where its definitions are
+struct Target {
+    rockspec: Option<PathBuf>,
+    definitions: Option<PathBuf>,
+
+    /// whether it's already installed, so installing it isn't checked
+    installed: bool,
+}
+
+/// the only `.rockspec` in `dir`
+fn find_rockspec(dir: &Path) -> Result<PathBuf> {
+    let mut rockspecs = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("while reading '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "rockspec") {
+            rockspecs.push(path);
+        }
+    }
+    match rockspecs.len() {
+        0 => bail!("no rockspec in '{}'", dir.display()),
+        1 => Ok(rockspecs.remove(0)),
+        _ => bail!(
+            "several rockspecs in '{}', name the one to check",
+            dir.display()
+        ),
+    }
+}
+
+/// what `target` names: a rockspec, a directory holding one, or an
+/// installed addon
+fn resolve(config: &Config, target: &str) -> Result<(Target, Vec<Check>)> {
+    let path = Path::new(target);
+    if path.is_file() {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let target = Target {
+            rockspec: Some(path.to_path_buf()),
+            definitions: definitions_dir(dir),
+            installed: false,
+        };
+        return Ok((target, vec![]));
+    }
+    if path.is_dir() {
+        let (rockspec, checks) = match find_rockspec(path) {
+            Ok(rockspec) => (Some(rockspec), vec![]),
+            Err(err) => (None, vec![Check::new("rockspec parses", Err(err))]),
+        };
+        let target = Target {
+            rockspec,
+            definitions: definitions_dir(path),
+            installed: false,
+        };
+        return Ok((target, checks));
+    }
+    let installed = list_installed(config, None).context("while listing installed addons")?;
+    let addon = newest_installed(&installed, target).ok_or_else(|| {
+        Failure::NotFound.tag(format!(
+            "'{target}' is neither a path nor an installed addon"
+        ))
+    })?;
+    let target = Target {
+        rockspec: rockspec::installed_rockspec_path(addon),
+        definitions: addon.location.clone().filter(|location| location.is_dir()),
+        installed: true,
+    };
+    Ok((target, vec![]))
+}
+
+/// the directory of definitions in `dir`, if it has one
+fn definitions_dir(dir: &Path) -> Option<PathBuf> {
+    DEFINITION_DIRS
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_dir())
+}
+
+/// whether the rockspec with `globals` copies `dir` into the rock
+fn copies(globals: &rockspec::LuaTable, dir: &str) -> bool {
+    globals
+        .get("build")
+        .and_then(LuaValue::as_table)
+        .and_then(|build| build.get("copy_directories"))
+        .and_then(LuaValue::as_table)
+        .is_some_and(|dirs| dirs.strings().any(|copied| copied == dir))
+}
+
+/// the `.lua` files under `dir`
+pub(crate) fn lua_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("while reading '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            lua_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "lua")
+            && path.file_name().is_some_and(|name| name != "plugin.lua")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// whether `contents` has a LuaCATS annotation, like `---@class`
+fn is_annotated(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.trim_start().starts_with("---@"))
+}
+
+fn check_definitions(dir: Option<&Path>) -> Result<()> {
+    let Some(dir) = dir else {
+        bail!("no {} directory", DEFINITION_DIRS.join(" or "));
+    };
+    let mut files = vec![];
+    lua_files(dir, &mut files)?;
+    if files.is_empty() {
+        bail!("no definition files in '{}'", dir.display());
+    }
+    let unannotated: Vec<String> = files
+        .iter()
+        .filter(|path| fs::read_to_string(path).is_ok_and(|contents| !is_annotated(&contents)))
+        .map(|path| format!("'{}'", path.display()))
+        .collect();
+    if !unannotated.is_empty() {
+        bail!("no LuaCATS annotations in {}", unannotated.join(", "));
+    }
+    Ok(())
+}
+
+fn check_config(dir: Option<&Path>) -> Result<()> {
+    let Some(dir) = dir else {
+        return Ok(());
+    };
+    let mut paths = vec![dir.join("config.json")];
+    paths.extend(dir.parent().map(|parent| parent.join("config.json")));
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("while reading '{}'", path.display()))?;
+        let value: serde_json::Value = serde_json::from_str(&contents)
+            .with_context(|| format!("while parsing '{}'", path.display()))?;
+        if !value.is_object() {
+            bail!("'{}' is not a JSON object", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// install `rockspec` into an empty tree, failing with what LuaRocks said
+fn check_install(config: &Config, rockspec: &Path) -> Result<()> {
+    let tree = tempfile::tempdir().context("while creating an empty tree")?;
+    let rockspec = rockspec
+        .to_str()
+        .ok_or_else(|| anyhow!("'{}' is not valid UTF-8", rockspec.display()))?;
+    let config = Config {
+        tree: tree.path(),
+        pins: None,
+        store: false,
+        // nothing is installed into the project's tree
+        hooks: None,
+        ..*config
+    };
+    let output = signature::install(&config, rockspec, None)?;
+    if !output.status.success() {
+        bail!(
+            "LuaRocks failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(())
+}
+
+/// check the addon `target` names: a rockspec, a directory holding one, or an
+/// installed addon. Checks that can't run because an earlier one failed are
+/// left out
+pub fn validate(config: &Config, target: &str) -> Result<Vec<Check>> {
+    let (target, mut checks) = resolve(config, target)?;
+    let definitions = target.definitions.as_deref();
+
+    let globals = target.rockspec.as_deref().map(|path| {
+        fs::read_to_string(path)
+            .with_context(|| format!("while reading '{}'", path.display()))
+            .and_then(|contents| rockspec::parse_globals(&contents))
+            .and_then(|globals| Rockspec::from_globals(&globals).map(|_| globals))
+            .with_context(|| format!("while parsing '{}'", path.display()))
+    });
+    let globals = match globals {
+        Some(Ok(globals)) => {
+            checks.push(Check::new("rockspec parses", Ok(())));
+            Some(globals)
+        }
+        Some(Err(err)) => {
+            checks.push(Check::new("rockspec parses", Err(err)));
+            None
+        }
+        None => None,
+    };
+
+    checks.push(Check::new("definitions", check_definitions(definitions)));
+    // installed rocks hold just what their rockspec copied
+    if let (Some(globals), Some(dir), false) = (&globals, definitions, target.installed) {
+        let name = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let copied = match copies(globals, name) {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "the rockspec doesn't list '{name}' in build.copy_directories"
+            )),
+        };
+        checks.push(Check::new("definitions are packed", copied));
+    }
+    checks.push(Check::new("config.json", check_config(definitions)));
+    if let (Some(_), Some(rockspec), false) = (&globals, &target.rockspec, target.installed) {
+        checks.push(Check::new("installs", check_install(config, rockspec)));
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod test_validate {
+    use super::*;
+    use crate::scaffold::{self, Extras};
+
+    #[test]
+    fn annotations() {
+        assert!(is_annotated("---@meta\n"));
+        assert!(is_annotated("local M = {}\n  ---@param x string\n"));
+        assert!(!is_annotated("-- a comment\nlocal M = {}\n"));
+    }
+
+    #[test]
+    fn scaffolded_addon() {
+        let dir = tempfile::tempdir().unwrap();
+        let addon = dir.path().join("say");
+        scaffold::create(&addon, "say", Extras::default()).unwrap();
+        let rockspec = find_rockspec(&addon).unwrap();
+        let globals = rockspec::parse_globals(&fs::read_to_string(&rockspec).unwrap()).unwrap();
+        assert!(copies(&globals, "types"));
+        assert!(!copies(&globals, "library"));
+
+        let types = addon.join("types");
+        check_definitions(Some(&types)).unwrap();
+        check_config(Some(&types)).unwrap();
+
+        fs::write(types.join("extra.lua"), "return {}\n").unwrap();
+        fs::write(types.join("config.json"), "[1, 2").unwrap();
+        let err = check_definitions(Some(&types)).unwrap_err();
+        assert!(err.to_string().starts_with("no LuaCATS annotations in"));
+        check_config(Some(&types)).unwrap_err();
+        let err = check_definitions(None).unwrap_err();
+        assert_eq!(err.to_string(), "no types or library directory");
+    }
+}
@@ -0,0 +1,248 @@
+//! addons kept in the tree without LuaRocks, like ones from the LLS-Addons
+//! catalog. They are recorded in the tree's state so listing, enabling and
+//! removing them works like it does for rocks
+
+use crate::{
+    Addon, Config,
+    hooks::{self, Event},
+    state::{self, TreeState},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+/// the directory vendored addons live in, relative to the tree
+pub const VENDOR_DIR: &str = "llynx-vendor";
+
+/// where a vendored addon came from
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Source {
+    /// a module of the LLS-Addons catalog
+    Catalog,
+
+    /// a clone of a git repository, pinned to `rev`
+    Git { url: String, rev: String },
+
+    /// an archive downloaded from `url` and unpacked
+    Archive { url: String },
+
+    /// unpacked from a pack made by `llynx pack`
+    Packed,
+}
+
+/// a vendored addon, as recorded in the tree's state
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Vendored {
+    #[serde(flatten)]
+    pub source: Source,
+
+    /// the revision that was checked out, standing in for a version
+    pub version: String,
+
+    /// the directory to add to the library
+    pub location: PathBuf,
+}
+
+/// the directory vendored addons of `tree` live in
+pub fn vendor_dir(tree: &Path) -> PathBuf {
+    tree.join(VENDOR_DIR)
+}
+
+/// fail unless `name` is a single plain directory name. Vendored addons are
+/// kept in a directory named after them, which mustn't lead out of the tree
+pub fn check_name(name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => bail!("'{name}' can't name an addon, it has to be a single directory name"),
+    }
+}
+
+/// whether `location` belongs to a vendored addon rather than a rock
+pub(crate) fn is_vendored_path(location: &Path) -> bool {
+    location
+        .components()
+        .any(|component| component.as_os_str() == VENDOR_DIR)
+}
+
+/// every vendored addon of `tree`
+pub fn list(tree: &Path) -> Result<Vec<Addon>> {
+    let Some(state) = TreeState::load(tree)? else {
+        return Ok(vec![]);
+    };
+    Ok(state
+        .vendored
+        .into_iter()
+        .map(|(name, vendored)| Addon {
+            name,
+            version: vendored.version,
+            location: Some(vendored.location),
+        })
+        .collect())
+}
+
+/// the record of vendored addon `name` in `tree`, if there is one
+pub fn find(tree: &Path, name: &str) -> Result<Option<Vendored>> {
+    Ok(TreeState::load(tree)?.and_then(|mut state| state.vendored.remove(name)))
+}
+
+/// remember that `name` was vendored into `tree` and installed explicitly
+pub(crate) fn record(tree: &Path, name: &str, vendored: Vendored) -> Result<()> {
+    state::update(tree, |state| {
+        state.explicit.insert(name.to_string());
+        state.vendored.insert(name.to_string(), vendored);
+    })
+}
+
+/// remove vendored addon `name` from the tree, between the remove hooks
+pub fn remove(config: &Config, name: &str) -> Result<()> {
+    let tree = config.tree;
+    let vendored = find(tree, name)?.ok_or_else(|| {
+        anyhow!(
+            "addon '{name}' is not vendored in '{tree}'",
+            tree = tree.display()
+        )
+    })?;
+    let version = Some(vendored.version.as_str());
+    hooks::run(config, Event::PreRemove, name, version)?;
+    match vendored.source {
+        Source::Catalog => crate::catalog::remove_module(config, name)?,
+        Source::Git { .. } => crate::git::remove_clone(tree, name)?,
+        Source::Archive { .. } => crate::archive::remove_unpacked(tree, name)?,
+        Source::Packed => crate::pack::remove_unpacked(tree, name)?,
+    }
+    state::update(tree, |state| {
+        state.explicit.remove(name);
+        state.vendored.remove(name);
+    })?;
+    hooks::run(config, Event::PostRemove, name, version)
+}
+
+/// directories addons keep their definitions in, most likely first
+const LIBRARY_DIRS: [&str; 3] = ["library", "types", "module/library"];
+
+/// the directory to add to the library for an addon unpacked at `dir`
+pub(crate) fn find_library(dir: &Path) -> Option<PathBuf> {
+    LIBRARY_DIRS
+        .iter()
+        .map(|library| dir.join(library))
+        .find(|library| library.is_dir())
+}
+
+/// run `tool` with the network options of `config`, failing with its output
+/// if it fails
+pub(crate) fn run_tool(config: &Config, tool: &str, command: &mut Command) -> Result<String> {
+    crate::network::apply(config, command);
+    log::info!("executing: {command:?}");
+    let output = crate::network::output(config, command)
+        .with_context(|| format!("while executing {tool}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{tool} failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// run git, failing with its output if it fails
+pub(crate) fn run_git(config: &Config, command: &mut Command) -> Result<String> {
+    run_tool(config, "git", command)
+}
+
+/// a git command running in `dir`
+pub(crate) fn git_in(dir: &Path) -> Command {
+    let mut git = Command::new("git");
+    git.arg("-C").arg(dir);
+    git
+}
+
+#[cfg(test)]
+mod test_list {
+    use super::*;
+    use crate::{Config, list_enabled};
+    use std::fs;
+
+    #[test]
+    fn vendored_addons_can_be_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let location = tree
+            .join(VENDOR_DIR)
+            .join("catalog/addons/love2d/module/library");
+        record(
+            tree,
+            "love2d",
+            Vendored {
+                source: Source::Catalog,
+                version: String::from("abc1234"),
+                location: location.clone(),
+            },
+        )
+        .unwrap();
+        let love2d = Addon {
+            name: String::from("love2d"),
+            version: String::from("abc1234"),
+            location: Some(location.clone()),
+        };
+        assert_eq!(list(tree).unwrap(), vec![love2d.clone()]);
+        assert!(is_vendored_path(&location));
+
+        let settings = dir.path().join("settings.json");
+        fs::write(
+            &settings,
+            format!(r#"{{"Lua.workspace.library": ["{}"]}}"#, location.display()),
+        )
+        .unwrap();
+        let config = Config {
+            tree,
+            settings: &settings,
+            ..Config::default()
+        };
+        assert_eq!(list_enabled(&config, None).unwrap(), vec![love2d]);
+    }
+
+    #[test]
+    fn vendored_addons_can_be_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path();
+        let unpacked = crate::pack::packed_dir(tree, "love2d");
+        fs::create_dir_all(unpacked.join("library")).unwrap();
+        record(
+            tree,
+            "love2d",
+            Vendored {
+                source: Source::Packed,
+                version: String::from("packed"),
+                location: unpacked.join("library"),
+            },
+        )
+        .unwrap();
+        let config = Config {
+            tree,
+            ..Config::default()
+        };
+        let output = crate::remove(&config, "love2d", None).unwrap();
+        assert!(output.status.success());
+        assert!(!unpacked.exists());
+        assert_eq!(list(tree).unwrap(), vec![]);
+    }
+}
+
+#[cfg(test)]
+mod test_check_name {
+    use super::*;
+
+    #[test]
+    fn names_stay_in_the_tree() {
+        check_name("say").unwrap();
+        check_name("lua-cjson").unwrap();
+        for name in ["", ".", "..", "../../..", "say/nested", "/etc", "./say"] {
+            check_name(name).unwrap_err();
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! checking that the library entries llynx manages still work: each has to
+//! point at a directory with definitions in it, and the addons enabled from
+//! the tree have to be installed in it. Broken entries otherwise only show
+//! up as completions that quietly went missing
+
+use crate::{
+    Config,
+    enabled::{is_addon_path, read_library, resolved_path},
+    list_installed,
+    validate::lua_files,
+    workspace::WorkspaceState,
+};
+use anyhow::Result;
+use std::{fmt, path::Path};
+
+/// what's wrong with a library entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// it points at a version of an addon the tree doesn't have
+    NotInstalled { name: String, version: String },
+
+    /// the directory it points at doesn't exist
+    Missing,
+
+    /// the directory it points at has no `.lua` files
+    NoDefinitions,
+
+    /// the directory it points at can't be read
+    Unreadable(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NotInstalled { name, version } => {
+                write!(f, "{name} {version} isn't installed in the tree")
+            }
+            Problem::Missing => write!(f, "the directory doesn't exist"),
+            Problem::NoDefinitions => write!(f, "there are no .lua files in the directory"),
+            Problem::Unreadable(err) => write!(f, "the directory can't be read: {err}"),
+        }
+    }
+}
+
+/// a library entry llynx manages, and what's wrong with it, if anything
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryCheck {
+    pub entry: String,
+    pub problem: Option<Problem>,
+}
+
+/// the name and version of the addon whose `types` directory is `path`
+pub(crate) fn addon_at(path: &Path) -> Option<(String, String)> {
+    let version = path.parent()?;
+    let name = version.parent()?;
+    Some((
+        name.file_name()?.to_str()?.to_string(),
+        version.file_name()?.to_str()?.to_string(),
+    ))
+}
+
+/// check every library entry of the settings file that llynx manages, in the
+/// order of the library. Copies made with `enable --vendor` don't need the
+/// tree
+pub fn verify(config: &Config) -> Result<Vec<EntryCheck>> {
+    let library = read_library(config.settings)?;
+    let state = WorkspaceState::load(config.settings)?;
+    // before llynx tracked its entries, every addon path was its own
+    let managed: Vec<String> = library
+        .into_iter()
+        .filter(|entry| match &state {
+            Some(state) => state.managed.contains(entry),
+            None => is_addon_path(config, entry),
+        })
+        .collect();
+    let copied = state.map(|state| state.copied).unwrap_or_default();
+    let installed = list_installed(config, None)?;
+
+    Ok(managed
+        .into_iter()
+        .map(|entry| {
+            let path = resolved_path(&entry);
+            let missing_addon = match is_addon_path(config, &entry) {
+                true => addon_at(&path).filter(|(name, version)| {
+                    !installed
+                        .iter()
+                        .any(|addon| &addon.name == name && &addon.version == version)
+                }),
+                false => None,
+            };
+            let problem = if let Some((name, version)) =
+                missing_addon.filter(|_| !copied.contains_key(&entry))
+            {
+                Some(Problem::NotInstalled { name, version })
+            } else if !path.is_dir() {
+                Some(Problem::Missing)
+            } else {
+                let mut files = vec![];
+                match lua_files(&path, &mut files) {
+                    Ok(()) if files.is_empty() => Some(Problem::NoDefinitions),
+                    Ok(()) => None,
+                    Err(err) => Some(Problem::Unreadable(format!("{err:#}"))),
+                }
+            };
+            EntryCheck { entry, problem }
+        })
+        .collect())
+}
+
+#[cfg(all(test, unix))]
+mod test_verify {
+    use super::*;
+    use crate::fake_luarocks;
+    use std::{collections::BTreeSet, fs};
+
+    #[test]
+    fn finds_broken_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        let rocks = tree.join("lib/luarocks/rocks-5.1");
+        let luarocks = fake_luarocks(
+            dir.path(),
+            &format!(
+                "printf 'say\\t1.4.1-3\\tinstalled\\t{}\\n'\n",
+                rocks.display()
+            ),
+        );
+        let say = rocks.join("say/1.4.1-3/types");
+        fs::create_dir_all(&say).unwrap();
+        fs::write(say.join("say.lua"), "---@meta say").unwrap();
+        let empty = dir.path().join("empty");
+        fs::create_dir_all(&empty).unwrap();
+
+        let entry = |path: &Path| path.to_str().unwrap().to_string();
+        let library = [
+            entry(&say),
+            entry(&rocks.join("say/1.3-1/types")),
+            entry(&empty),
+            entry(&dir.path().join("gone")),
+        ];
+        let settings = dir.path().join("settings.json");
+        fs::write(
+            &settings,
+            serde_json::json!({ "Lua.workspace.library": library }).to_string(),
+        )
+        .unwrap();
+        WorkspaceState {
+            managed: BTreeSet::from_iter(library.iter().cloned()),
+            ..WorkspaceState::default()
+        }
+        .save(&settings)
+        .unwrap();
+
+        let config = Config {
+            tree: &tree,
+            luarocks: &luarocks,
+            settings: &settings,
+            ..Config::default()
+        };
+        let problems: Vec<Option<Problem>> = verify(&config)
+            .unwrap()
+            .into_iter()
+            .map(|check| check.problem)
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                None,
+                Some(Problem::NotInstalled {
+                    name: String::from("say"),
+                    version: String::from("1.3-1"),
+                }),
+                Some(Problem::NoDefinitions),
+                Some(Problem::Missing),
+            ]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! LuaRocks version ordering, following `luarocks.core.vers`
+
+use anyhow::{Result, bail};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// a parsed LuaRocks version like `1.4.1-3`
+#[derive(Debug, Clone)]
+pub struct Version {
+    source: String,
+    parts: Vec<f64>,
+    revision: Option<u64>,
+}
+
+/// how words in a version compare to numbers, taken from LuaRocks
+fn word_delta(word: &str) -> f64 {
+    match word {
+        "dev" => 120000000.0,
+        "scm" => 110000000.0,
+        "cvs" => 100000000.0,
+        "rc" => -1000.0,
+        "pre" => -10000.0,
+        "beta" => -100000.0,
+        "alpha" => -1000000.0,
+        _ => f64::from(word.as_bytes()[0]) / 1000.0,
+    }
+}
+
+impl Version {
+    /// parse a version. Unlike LuaRocks, unrecognized characters are skipped
+    /// instead of ending the version
+    pub fn parse(source: &str) -> Version {
+        let (main, revision) = match source.rsplit_once('-') {
+            Some((main, revision)) if revision.bytes().all(|b| b.is_ascii_digit()) => {
+                (main, revision.parse().ok())
+            }
+            _ => (source, None),
+        };
+
+        let mut parts = Vec::new();
+        let mut rest = main.trim_start();
+        while !rest.is_empty() {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let letters = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+            if digits > 0 {
+                parts.push(rest[..digits].parse::<f64>().unwrap_or(0.0));
+                rest = &rest[digits..];
+            } else if letters > 0 {
+                parts.push(word_delta(&rest[..letters]));
+                rest = &rest[letters..];
+            } else {
+                let next = rest.chars().next().map_or(0, char::len_utf8);
+                rest = &rest[next..];
+            }
+        }
+
+        Version {
+            source: source.to_string(),
+            parts,
+            revision,
+        }
+    }
+
+    /// whether this is a development version like `scm-1` or `dev-1`
+    pub fn is_dev(&self) -> bool {
+        self.parts
+            .first()
+            .is_some_and(|&part| part >= word_delta("cvs"))
+    }
+}
+
+impl FromStr for Version {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Version::parse(s))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.parts.len().max(other.parts.len());
+        for i in 0..len {
+            let a = self.parts.get(i).copied().unwrap_or(0.0);
+            let b = other.parts.get(i).copied().unwrap_or(0.0);
+            match a.total_cmp(&b) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        self.revision.unwrap_or(0).cmp(&other.revision.unwrap_or(0))
+    }
+}
+
+/// compare two version strings
+pub fn compare(a: &str, b: &str) -> Ordering {
+    Version::parse(a).cmp(&Version::parse(b))
+}
+
+/// how a version is compared to the one in a [`Constraint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+
+    /// `~>`, matching the parts of the version that are given
+    Tilde,
+}
+
+/// one of the comma-separated parts of a LuaRocks version constraint, like
+/// `>= 1.4`
+#[derive(Debug, Clone)]
+struct Constraint {
+    operator: Operator,
+    version: Version,
+}
+
+impl Constraint {
+    fn parse(source: &str) -> Result<Constraint> {
+        let source = source.trim();
+        let (operator, rest) = [
+            ("==", Operator::Eq),
+            ("~=", Operator::Ne),
+            ("<=", Operator::Le),
+            (">=", Operator::Ge),
+            ("~>", Operator::Tilde),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+            ("=", Operator::Eq),
+        ]
+        .into_iter()
+        .find_map(|(symbol, operator)| Some((operator, source.strip_prefix(symbol)?)))
+        .unwrap_or((Operator::Eq, source));
+        let rest = rest.trim();
+        if !rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            bail!("'{source}' is not a version constraint");
+        }
+        Ok(Constraint {
+            operator,
+            version: Version::parse(rest),
+        })
+    }
+
+    fn matches(&self, version: &Version) -> bool {
+        // like LuaRocks, a constraint without a revision matches every one
+        let version = match self.version.revision {
+            Some(_) => version.clone(),
+            None => Version {
+                revision: None,
+                ..version.clone()
+            },
+        };
+        match self.operator {
+            Operator::Eq => version == self.version,
+            Operator::Ne => version != self.version,
+            Operator::Lt => version < self.version,
+            Operator::Le => version <= self.version,
+            Operator::Gt => version > self.version,
+            Operator::Ge => version >= self.version,
+            Operator::Tilde => {
+                self.version
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .all(|(i, part)| version.parts.get(i).copied().unwrap_or(0.0) == *part)
+                    && (self.version.revision.is_none()
+                        || self.version.revision == version.revision)
+            }
+        }
+    }
+}
+
+/// LuaRocks version constraints like `>= 1.4, < 2`, which every matching
+/// version satisfies
+#[derive(Debug, Clone)]
+pub struct Constraints(Vec<Constraint>);
+
+impl Constraints {
+    /// whether `source` holds constraints rather than a version
+    pub fn is_constraint(source: &str) -> bool {
+        source.trim_start().starts_with(['=', '~', '<', '>']) || source.contains(',')
+    }
+
+    pub fn parse(source: &str) -> Result<Constraints> {
+        source
+            .split(',')
+            .map(Constraint::parse)
+            .collect::<Result<_>>()
+            .map(Constraints)
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        let version = Version::parse(version);
+        self.0.iter().all(|constraint| constraint.matches(&version))
+    }
+
+    /// the newest of `versions` matching every constraint
+    pub fn newest<'a>(&self, versions: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        versions
+            .into_iter()
+            .filter(|version| self.matches(version))
+            .max_by(|a, b| compare(a, b))
+    }
+}
+
+#[cfg(test)]
+mod test_compare {
+    use super::*;
+
+    #[test]
+    fn numeric_parts() {
+        assert_eq!(compare("1.10-1", "1.9-1"), Ordering::Greater);
+        assert_eq!(compare("1.4.1-3", "1.4-3"), Ordering::Greater);
+        assert_eq!(compare("1.4.0-1", "1.4-1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn revisions() {
+        assert_eq!(compare("1.4.1-3", "1.4.1-10"), Ordering::Less);
+        assert_eq!(compare("1.4.1", "1.4.1-1"), Ordering::Less);
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(compare("2.0rc1-1", "2.0-1"), Ordering::Less);
+        assert_eq!(compare("2.0beta-1", "2.0rc1-1"), Ordering::Less);
+        assert_eq!(compare("scm-1", "99.0-1"), Ordering::Greater);
+        assert_eq!(compare("dev-1", "scm-1"), Ordering::Greater);
+        assert!(Version::parse("scm-1").is_dev());
+        assert!(!Version::parse("1.0-1").is_dev());
+    }
+}
+
+#[cfg(test)]
+mod test_constraints {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let constraints = Constraints::parse(">= 1.4, < 2").unwrap();
+        assert!(constraints.matches("1.4-1"));
+        assert!(constraints.matches("1.10.2-1"));
+        assert!(!constraints.matches("1.3-1"));
+        assert!(!constraints.matches("2.0-1"));
+        assert_eq!(
+            constraints.newest(["1.3-1", "1.4.1-3", "1.9-1", "2.0-1"]),
+            Some("1.9-1")
+        );
+        assert_eq!(constraints.newest(["2.0-1"]), None);
+    }
+
+    #[test]
+    fn operators() {
+        assert!(Constraints::parse("== 1.4.1").unwrap().matches("1.4.1-3"));
+        assert!(!Constraints::parse("== 1.4.1-2").unwrap().matches("1.4.1-3"));
+        assert!(Constraints::parse("~= 1.4").unwrap().matches("1.5-1"));
+        assert!(Constraints::parse("~> 1.4").unwrap().matches("1.4.9-1"));
+        assert!(!Constraints::parse("~> 1.4").unwrap().matches("1.5-1"));
+        assert!(Constraints::parse("1.4").unwrap().matches("1.4-2"));
+        assert!(Constraints::parse(">= ").is_err());
+        assert!(Constraints::is_constraint(">= 1.4, < 2"));
+        assert!(!Constraints::is_constraint("1.4.1-3"));
+    }
+}
@@ -0,0 +1,144 @@
+//! what llynx remembers about a workspace, kept in a file next to its
+//! settings file
+
+use crate::{Config, copies::CopiedAddon, frozen};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// the name of the workspace state file, next to the settings file
+pub const WORKSPACE_FILE: &str = "llynx-workspace.json";
+
+/// the name of the file locked while a settings file is being changed, next
+/// to the settings file. It's locked instead of the settings file itself,
+/// since Windows locks keep everyone else from even reading a file
+pub const LOCK_FILE: &str = "llynx-workspace.lock";
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WorkspaceState {
+    /// entries of `Lua.workspace.library` that llynx added, as opposed to
+    /// ones written by hand
+    #[serde(default)]
+    pub managed: BTreeSet<String>,
+
+    /// settings applied for each library entry, taken from the addon's
+    /// `config.json` and `plugin.lua`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub applied: BTreeMap<String, Map<String, Value>>,
+
+    /// addons copied into the project with `enable --vendor`, by the library
+    /// entry pointing at each copy
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub copied: BTreeMap<String, CopiedAddon>,
+}
+
+fn workspace_path(settings_file: &Path) -> PathBuf {
+    PathBuf::from(settings_file).with_file_name(WORKSPACE_FILE)
+}
+
+impl WorkspaceState {
+    /// read the state of the workspace `settings_file` belongs to, or `None`
+    /// if llynx hasn't written one yet
+    pub fn load(settings_file: &Path) -> Result<Option<WorkspaceState>> {
+        let path = workspace_path(settings_file);
+        let contents = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            result => result.with_context(|| format!("while reading '{}'", path.display()))?,
+        };
+        serde_json::from_str(&contents)
+            .with_context(|| format!("while parsing '{}'", path.display()))
+            .map(Some)
+    }
+
+    pub fn save(&self, settings_file: &Path) -> Result<()> {
+        let path = workspace_path(settings_file);
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&path, contents).with_context(|| format!("while writing '{}'", path.display()))
+    }
+}
+
+/// wait until no other llynx is changing the workspace `settings_file`
+/// belongs to, and keep others out until the returned file is dropped. The
+/// lock is advisory, so only other llynx processes respect it
+pub(crate) fn lock(settings_file: &Path) -> Result<File> {
+    let path = PathBuf::from(settings_file).with_file_name(LOCK_FILE);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("while opening '{}'", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            log::warn!(
+                "waiting for another llynx to finish changing '{settings_file}'...",
+                settings_file = settings_file.display()
+            );
+            file.lock()
+                .with_context(|| format!("while locking '{}'", path.display()))?;
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(err).with_context(|| format!("while locking '{}'", path.display()));
+        }
+    }
+    Ok(file)
+}
+
+/// read the state of the workspace of the settings file, change it, and write
+/// it again. `init` builds the state when there is none yet
+pub fn update(
+    config: &Config,
+    init: impl FnOnce() -> WorkspaceState,
+    f: impl FnOnce(&mut WorkspaceState),
+) -> Result<()> {
+    let settings_file = config.settings;
+    let before = WorkspaceState::load(settings_file)?;
+    let mut state = before.clone().unwrap_or_else(init);
+    f(&mut state);
+    if !frozen::check_write(
+        config,
+        &workspace_path(settings_file),
+        before.as_ref() != Some(&state),
+    )? {
+        return Ok(());
+    }
+    state.save(settings_file)
+}
+
+#[cfg(test)]
+mod test_update {
+    use super::*;
+
+    #[test]
+    fn lives_next_to_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        assert_eq!(WorkspaceState::load(&settings).unwrap(), None);
+
+        let seed = || WorkspaceState {
+            managed: BTreeSet::from([String::from("old")]),
+            ..WorkspaceState::default()
+        };
+        let config = Config {
+            settings: &settings,
+            ..Config::default()
+        };
+        update(&config, seed, |state| {
+            state.managed.insert(String::from("new"));
+        })
+        .unwrap();
+        assert!(dir.path().join(WORKSPACE_FILE).is_file());
+        let state = WorkspaceState::load(&settings).unwrap().unwrap();
+        assert_eq!(
+            state.managed,
+            BTreeSet::from([String::from("new"), String::from("old")])
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! translating library entries between WSL and Windows, for settings files
+//! read by VS Code on the other side. Windows drives are mounted in WSL as
+//! `/mnt/c`, and WSL distributions are shared with Windows as `\\wsl$\<name>`
+
+use std::{
+    fs,
+    path::{self, Path},
+    sync::OnceLock,
+};
+
+/// the directory WSL mounts Windows drives in
+const MOUNT_DIR: &str = "/mnt/";
+
+/// the shares Windows reaches WSL distributions through
+const SHARES: [&str; 2] = ["\\\\wsl$\\", "\\\\wsl.localhost\\"];
+
+/// whether llynx runs inside WSL
+pub fn is_wsl() -> bool {
+    static IS_WSL: OnceLock<bool> = OnceLock::new();
+    *IS_WSL.get_or_init(|| {
+        cfg!(target_os = "linux")
+            && (std::env::var_os("WSL_DISTRO_NAME").is_some()
+                || fs::read_to_string("/proc/sys/kernel/osrelease")
+                    .is_ok_and(|release| release.to_lowercase().contains("microsoft")))
+    })
+}
+
+/// a path on a mounted drive as Windows sees it, like `C:\Users` for
+/// `/mnt/c/Users`
+pub fn mount_to_windows(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(MOUNT_DIR)?;
+    let (drive, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let mut chars = drive.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    if chars.next().is_some() {
+        return None;
+    }
+    Some(format!(
+        "{}:\\{}",
+        letter.to_ascii_uppercase(),
+        rest.replace('/', "\\")
+    ))
+}
+
+/// an absolute path in WSL as Windows sees it, either on a mounted drive or
+/// through the share of this distribution
+pub fn to_windows(path: &str) -> Option<String> {
+    mount_to_windows(path).or_else(|| {
+        let distro = std::env::var("WSL_DISTRO_NAME").ok()?;
+        share_path(&distro, path)
+    })
+}
+
+/// an absolute path in the WSL distribution `distro`, through its share
+fn share_path(distro: &str, path: &str) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    Some(format!(
+        "{}{distro}\\{}",
+        SHARES[1],
+        rest.replace('/', "\\")
+    ))
+}
+
+/// a Windows path as WSL sees it, like `/mnt/c/Users` for `C:\Users` or
+/// `/home` for `\\wsl$\Ubuntu\home`
+pub fn windows_to_mount(path: &str) -> Option<String> {
+    if let Some(rest) = SHARES.iter().find_map(|share| {
+        path.strip_prefix(share)
+            .or_else(|| path.strip_prefix(&share.replace('\\', "/")))
+    }) {
+        let (_distro, rest) = rest.split_once(['\\', '/']).unwrap_or((rest, ""));
+        return Some(format!("/{}", rest.replace('\\', "/")));
+    }
+
+    let mut chars = path.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    if chars.next() != Some(':') || !matches!(chars.next(), Some('\\' | '/')) {
+        return None;
+    }
+    let rest = chars.as_str().replace('\\', "/");
+    Some(format!("{MOUNT_DIR}{}/{rest}", letter.to_ascii_lowercase()))
+}
+
+/// whether `settings_file` is read by VS Code on the other side of WSL from
+/// llynx, so absolute entries written to it have to be translated
+pub fn across(settings_file: &Path) -> bool {
+    let Some(settings_file) = path::absolute(settings_file)
+        .ok()
+        .and_then(|path| path.to_str().map(str::to_string))
+    else {
+        return false;
+    };
+    match cfg!(windows) {
+        true => SHARES.iter().any(|share| settings_file.starts_with(share)),
+        false => is_wsl() && mount_to_windows(&settings_file).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod test_translate {
+    use super::*;
+
+    #[test]
+    fn mounts_to_drives() {
+        assert_eq!(
+            mount_to_windows("/mnt/c/Users/me/.lls_addons").as_deref(),
+            Some("C:\\Users\\me\\.lls_addons")
+        );
+        assert_eq!(mount_to_windows("/mnt/d").as_deref(), Some("D:\\"));
+        assert_eq!(mount_to_windows("/mnt/wsl/share"), None);
+        assert_eq!(mount_to_windows("/home/me"), None);
+        assert_eq!(
+            share_path("Ubuntu", "/home/me").as_deref(),
+            Some("\\\\wsl.localhost\\Ubuntu\\home\\me")
+        );
+    }
+
+    #[test]
+    fn drives_to_mounts() {
+        assert_eq!(
+            windows_to_mount("C:\\Users\\me\\.lls_addons").as_deref(),
+            Some("/mnt/c/Users/me/.lls_addons")
+        );
+        assert_eq!(
+            windows_to_mount("C:/Users/me").as_deref(),
+            Some("/mnt/c/Users/me")
+        );
+        assert_eq!(
+            windows_to_mount("\\\\wsl$\\Ubuntu\\home\\me").as_deref(),
+            Some("/home/me")
+        );
+        assert_eq!(
+            windows_to_mount("//wsl.localhost/Ubuntu/home/me").as_deref(),
+            Some("/home/me")
+        );
+        assert_eq!(windows_to_mount(".lls_addons/lib"), None);
+        assert_eq!(windows_to_mount("C:relative"), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Zed's `.zed/settings.json`, which keeps the settings of
+//! lua-language-server nested under `lsp.lua-language-server.settings`
+//! instead of as dotted keys like VS Code. They're read as dotted keys and
+//! written back nested, leaving the rest of the file alone
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// the directory Zed keeps the settings of a workspace in
+pub const ZED_DIR: &str = ".zed";
+
+/// the name Zed knows lua-language-server by
+pub const SERVER: &str = "lua-language-server";
+
+/// how deep setting names like `Lua.workspace.library` go
+const DEPTH: usize = 3;
+
+/// whether `settings_file` holds the settings of a Zed workspace
+pub fn is_zed(settings_file: &Path) -> bool {
+    settings_file.file_name() == Some("settings.json".as_ref())
+        && settings_file.parent().and_then(Path::file_name) == Some(ZED_DIR.as_ref())
+}
+
+fn flatten(
+    prefix: &str,
+    settings: &Map<String, Value>,
+    depth: usize,
+    dotted: &mut Map<String, Value>,
+) {
+    for (key, value) in settings {
+        let key = format!("{prefix}{key}");
+        match value {
+            Value::Object(nested) if depth + 1 < DEPTH => {
+                flatten(&format!("{key}."), nested, depth + 1, dotted);
+            }
+            _ => {
+                dotted.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// the settings of lua-language-server in a Zed settings file, as dotted
+/// keys like `Lua.workspace.library`
+pub(crate) fn to_dotted(zed: &Value) -> Value {
+    let mut dotted = Map::new();
+    let settings = zed
+        .get("lsp")
+        .and_then(|lsp| lsp.get(SERVER))
+        .and_then(|server| server.get("settings"))
+        .and_then(Value::as_object);
+    if let Some(settings) = settings {
+        flatten("", settings, 0, &mut dotted);
+    }
+    Value::Object(dotted)
+}
+
+/// `zed` with the settings of lua-language-server replaced by the dotted
+/// keys of `dotted`, nested again
+pub(crate) fn with_dotted(mut zed: Value, dotted: Map<String, Value>) -> Result<Value> {
+    let mut settings = Map::new();
+    for (key, value) in dotted {
+        let parts: Vec<&str> = key.splitn(DEPTH, '.').collect();
+        let (last, parents) = parts.split_last().expect("split yields a part");
+        let mut node = &mut settings;
+        for part in parents {
+            let child = node
+                .entry(*part)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            node = child.as_object_mut().expect("made an object above");
+        }
+        node.insert(last.to_string(), value);
+    }
+
+    if zed.is_null() {
+        zed = Value::Object(Map::new());
+    }
+    if !zed.is_object() {
+        bail!("the Zed settings aren't an object");
+    }
+    let mut node = &mut zed;
+    let mut path = String::new();
+    for key in ["lsp", SERVER] {
+        node = node
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("'{path}' isn't an object in the Zed settings"))?
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()));
+        path = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        };
+    }
+    node.as_object_mut()
+        .ok_or_else(|| anyhow!("'{path}' isn't an object in the Zed settings"))?
+        .insert(String::from("settings"), Value::Object(settings));
+    Ok(zed)
+}
+
+#[cfg(test)]
+mod test_zed {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finds_zed_settings() {
+        assert!(is_zed(Path::new(".zed/settings.json")));
+        assert!(is_zed(Path::new("game/.zed/settings.json")));
+        assert!(!is_zed(Path::new(".vscode/settings.json")));
+        assert!(!is_zed(Path::new(".zed/tasks.json")));
+    }
+
+    #[test]
+    fn nests_dotted_keys() {
+        let zed = json!({
+            "tab_size": 2,
+            "lsp": {
+                "lua-language-server": {
+                    "binary": { "path": "lua-language-server" },
+                    "settings": {
+                        "Lua": {
+                            "workspace": { "library": ["say"] },
+                            "runtime": { "special": { "include": "require" } }
+                        }
+                    }
+                }
+            }
+        });
+        let dotted = to_dotted(&zed);
+        assert_eq!(
+            dotted,
+            json!({
+                "Lua.workspace.library": ["say"],
+                "Lua.runtime.special": { "include": "require" }
+            })
+        );
+
+        let mut dotted = dotted.as_object().unwrap().clone();
+        dotted.insert(String::from("Lua.workspace.library"), json!(["busted"]));
+        let nested = with_dotted(zed.clone(), dotted).unwrap();
+        assert_eq!(nested["tab_size"], 2);
+        assert_eq!(
+            nested["lsp"][SERVER]["binary"]["path"],
+            "lua-language-server"
+        );
+        assert_eq!(
+            nested["lsp"][SERVER]["settings"]["Lua"]["workspace"]["library"],
+            json!(["busted"])
+        );
+        assert_eq!(
+            nested["lsp"][SERVER]["settings"]["Lua"]["runtime"]["special"],
+            json!({ "include": "require" })
+        );
+    }
+
+    #[test]
+    fn starts_empty_files() {
+        let dotted = Map::from_iter([(String::from("Lua.workspace.library"), json!(["say"]))]);
+        assert_eq!(
+            with_dotted(Value::Null, dotted.clone()).unwrap(),
+            json!({ "lsp": { SERVER: { "settings": { "Lua": { "workspace": { "library": ["say"] } } } } } })
+        );
+        assert_eq!(
+            with_dotted(json!({ "lsp": [] }), dotted)
+                .unwrap_err()
+                .to_string(),
+            "'lsp' isn't an object in the Zed settings"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use clap::ValueEnum;
+use llynx_core::history::LibraryChange;
+use serde::Serialize;
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// how `--notify` writes events to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NotifyFormat {
+    /// One JSON object per line
+    Json,
+}
+
+/// whether events are written for the rest of the process
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// write events to stderr from now on
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// something an editor wrapping llynx can show progress with
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// llynx started waiting on something, like LuaRocks or the server
+    Started { phase: &'a str },
+
+    /// `done` of the `total` steps of a longer operation are done
+    Progress {
+        phase: &'a str,
+        done: usize,
+        total: usize,
+        percent: usize,
+    },
+
+    /// what llynx was waiting on is done
+    Finished { phase: &'a str, elapsed_ms: u128 },
+
+    /// library entries were added to or removed from a settings file
+    SettingsUpdated {
+        settings: &'a Path,
+        added: Vec<&'a str>,
+        removed: Vec<&'a str>,
+    },
+
+    /// the command finished, failing with `error` unless `ok`
+    Done { ok: bool, error: Option<String> },
+}
+
+/// write `event` to stderr as a line of JSON, if events were asked for
+pub fn emit(event: Event) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    match serde_json::to_string(&event) {
+        Ok(line) => eprintln!("{line}"),
+        Err(err) => log::debug!("could not write the event {event:?}: {err}"),
+    }
+}
+
+/// the entries of `entries` that `other` doesn't have
+fn missing_from<'a>(entries: &'a [String], other: &[String]) -> Vec<&'a str> {
+    entries
+        .iter()
+        .filter(|entry| !other.contains(entry))
+        .map(String::as_str)
+        .collect()
+}
+
+/// the entries `change` added to and removed from its settings file
+pub fn library_changed(change: &LibraryChange) {
+    emit(Event::SettingsUpdated {
+        settings: &change.settings,
+        added: missing_from(&change.after, &change.before),
+        removed: missing_from(&change.before, &change.after),
+    });
+}
+
+/// `done` of `total` steps of `phase` are done
+pub fn progress(phase: &str, done: usize, total: usize) {
+    emit(Event::Progress {
+        phase,
+        done,
+        total,
+        percent: (done * 100).checked_div(total).unwrap_or(100),
+    });
+}
+
+#[cfg(test)]
+mod test_event {
+    use super::*;
+
+    #[test]
+    fn serializes_tagged() {
+        let event = Event::Progress {
+            phase: "installing addons",
+            done: 1,
+            total: 4,
+            percent: 25,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"progress","phase":"installing addons","done":1,"total":4,"percent":25}"#
+        );
+    }
+}
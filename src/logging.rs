@@ -0,0 +1,228 @@
+//! where log records go: to stderr at the verbosity asked for, as text or
+//! JSON, and, with a log file, to that file down to the debug level
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::{
+    Level, LevelFilter, Log, Metadata, Record,
+    kv::{self, Key, VisitSource, VisitValue},
+};
+use serde_json::{Map, Value};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use stderrlog::StdErrLog;
+use termcolor::ColorChoice;
+
+/// the lowest level written to a log file, whatever the verbosity
+const FILE_LEVEL: Level = Level::Debug;
+
+/// how log records are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Lines of text, for people
+    #[default]
+    Text,
+
+    /// One JSON object per line, for tools
+    Json,
+}
+
+struct Logger {
+    stderr: StdErrLog,
+    format: LogFormat,
+    file: Option<Mutex<File>>,
+}
+
+/// seconds since the Unix epoch, to the millisecond
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:03}", now.as_secs(), now.subsec_millis())
+}
+
+/// collects the key-value pairs of a record as JSON fields
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json = Json(Value::Null);
+        value.visit(&mut json)?;
+        self.0.insert(key.to_string(), json.0);
+        Ok(())
+    }
+}
+
+/// turns a key-value value into JSON, keeping numbers, booleans and
+/// missing values as they are and writing everything else as a string
+struct Json(Value);
+
+impl<'v> VisitValue<'v> for Json {
+    fn visit_any(&mut self, value: kv::Value) -> Result<(), kv::Error> {
+        self.0 = Value::String(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+/// a record as one line of JSON, with its time, level, target and message,
+/// and its key-value pairs as further fields
+fn json_line(record: &Record) -> String {
+    let mut object = Map::new();
+    object.insert(String::from("time"), Value::String(timestamp()));
+    object.insert(
+        String::from("level"),
+        Value::String(record.level().as_str().to_lowercase()),
+    );
+    object.insert(
+        String::from("target"),
+        Value::String(record.target().into()),
+    );
+    object.insert(
+        String::from("message"),
+        Value::String(record.args().to_string()),
+    );
+    // a pair that can't be read is left out rather than losing the record
+    let _ = record.key_values().visit(&mut Fields(&mut object));
+    Value::Object(object).to_string()
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata) || (self.file.is_some() && metadata.level() <= FILE_LEVEL)
+    }
+
+    fn log(&self, record: &Record) {
+        match self.format {
+            LogFormat::Text => self.stderr.log(record),
+            LogFormat::Json if self.stderr.enabled(record.metadata()) => {
+                let _ = writeln!(io::stderr().lock(), "{}", json_line(record));
+            }
+            LogFormat::Json => {}
+        }
+        if let Some(file) = &self.file
+            && record.level() <= FILE_LEVEL
+        {
+            let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+            // a log file that can't be written to isn't worth failing over
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                timestamp(),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap_or_else(|err| err.into_inner()).flush();
+        }
+    }
+}
+
+/// log to stderr down to `verbosity` in `format`, and to the end of
+/// `log_file` down to the debug level when one is given
+pub fn init(
+    verbosity: LevelFilter,
+    format: LogFormat,
+    color: ColorChoice,
+    log_file: Option<&Path>,
+) -> Result<()> {
+    // commands run in several folders set logging up once
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    if STARTED.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let file = log_file
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("while opening the log file '{}'", path.display()))
+        })
+        .transpose()?;
+
+    let mut stderr = stderrlog::new();
+    stderr
+        .timestamp(stderrlog::Timestamp::Off)
+        .verbosity(verbosity)
+        .color(color);
+    let max_level = match file {
+        Some(_) => verbosity.max(FILE_LEVEL.to_level_filter()),
+        None => verbosity,
+    };
+    log::set_boxed_logger(Box::new(Logger {
+        stderr,
+        format,
+        file: file.map(Mutex::new),
+    }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_json_line {
+    use super::*;
+
+    #[test]
+    fn includes_key_values() {
+        let pairs: [(&str, kv::Value); 3] = [
+            ("subprocess", kv::Value::from("luarocks")),
+            ("duration_ms", kv::Value::from(12u64)),
+            ("exit_code", kv::Value::null()),
+        ];
+        let line = json_line(
+            &Record::builder()
+                .level(Level::Debug)
+                .target("llynx_core::network")
+                .args(format_args!("luarocks exited"))
+                .key_values(&pairs)
+                .build(),
+        );
+        let object: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(object["level"], "debug");
+        assert_eq!(object["target"], "llynx_core::network");
+        assert_eq!(object["message"], "luarocks exited");
+        assert_eq!(object["subprocess"], "luarocks");
+        assert_eq!(object["duration_ms"], 12);
+        assert_eq!(object["exit_code"], Value::Null);
+        assert!(!line.contains('\n'));
+    }
+}
@@ -6,131 +6,281 @@
 // - `enable <name>` -> Result<(), Error>
 // - `disable <name>` -> Result<(), Error>
 
-// Assumptions:
-// - Only one version of an addon can be enabled at any time
-
-mod enabled;
-mod installed;
-mod online;
-
-use crate::enabled::{disable, enable, list_enabled};
-use crate::installed::{install, list_installed, remove};
-use crate::online::list_online;
-use anyhow::{Context, Result};
+mod events;
+mod logging;
+mod output;
+mod pager;
+mod pick;
+mod progress;
+mod serve;
+mod template;
+
+use anyhow::{Context, Result, anyhow, bail};
 use clap::{CommandFactory, Parser, Subcommand};
+use events::NotifyFormat;
+use llynx_core::{
+    Addon, Config, MaybeConfig, addon_settings, adopt, archive,
+    batch::{self, Step},
+    bootstrap, cache,
+    catalog::{self, CatalogAddon},
+    config, copies, deps, devcontainer, disk_usage,
+    enabled::{self, Plan},
+    export,
+    failure::Failure,
+    filter::Filter,
+    folders, fuzzy, git, gitignore,
+    history::{self, LibraryChange},
+    installed::{self, DepsMode},
+    interrupt, licenses, list_enabled, list_installed, list_online, migrate, namespace,
+    network::{self, Echo, Pipe},
+    pack, publish, release, remove, repair, rockspec, scaffold,
+    search::{self, SearchResult},
+    settings, signature,
+    status::{self, AddonStatus},
+    store,
+    third_party::{self, THIRD_PARTY_KEY, THIRD_PARTY_VERSION},
+    user_settings::Editor,
+    validate::{self, Check},
+    vendor, verify,
+};
+use log::LevelFilter;
+use logging::LogFormat;
+use output::{ColorWhen, ListFormat, Porcelain, SortKey, Summaries};
 use serde::Deserialize;
-use std::{fs, io};
-use toml;
+use std::{
+    collections::BTreeSet,
+    env, fmt, fs,
+    io::{self, IsTerminal, Write},
+    path::{self, Path, PathBuf},
+    process::{self, ExitStatus, Output},
+    sync::Arc,
+};
+use template::Template;
+use termcolor::StandardStream;
 
 #[cfg(test)]
 use std::sync::LazyLock;
 
-const CONFIG_PATH: &str = ".llynx.toml";
-const LUAROCKS_PATH: &str = "luarocks";
-const ADDONS_DIR: &str = ".lls_addons";
-const LUAROCKS_ENDPOINT: &str = "https://luarocks.org/m/lls-addons";
-const SETTINGS_FILE: &str = ".vscode/settings.json";
-const LIB_SETTINGS_KEY: &str = "Lua.workspace.library";
-
-#[derive(Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-struct MaybeConfig {
-    #[allow(dead_code)]
-    #[serde(rename = "$schema")]
-    schema: Option<String>, // this is unused
-    luarocks: Option<String>,
-    tree: Option<String>,
-    settings: Option<String>,
-    server: Option<String>,
-    verbose: Option<u8>,
-}
-
-#[derive(Debug)]
-struct Config<'a> {
-    luarocks: &'a str,
-    tree: &'a str,
-    settings: &'a str,
-    server: &'a str,
-    verbose: u8,
-}
-
-impl<'a> Default for Config<'a> {
-    fn default() -> Self {
-        Config {
-            luarocks: LUAROCKS_PATH,
-            tree: ADDONS_DIR,
-            settings: SETTINGS_FILE,
-            server: LUAROCKS_ENDPOINT,
-            verbose: 0,
-        }
-    }
-}
-
-impl<'a> Config<'a> {
-    fn extend<'b: 'a>(self, maybe_config: &'b MaybeConfig) -> Self {
-        fn choose_str<'a>(try_str: &'a Option<String>, otherwise: &'a str) -> &'a str {
-            try_str.as_ref().map(String::as_str).unwrap_or(otherwise)
-        }
-
-        let MaybeConfig {
-            schema: _,
-            luarocks,
-            tree,
-            settings,
-            server,
-            verbose,
-        } = maybe_config;
-        Config {
-            luarocks: choose_str(luarocks, self.luarocks),
-            tree: choose_str(tree, self.tree),
-            settings: choose_str(settings, self.settings),
-            server: choose_str(server, self.server),
-            verbose: verbose.unwrap_or(self.verbose),
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-struct Addon {
-    name: String,
-    version: String,
-    location: Option<String>,
-}
-
 /// adds a LuaLS addon using LuaRocks
 #[derive(Debug, Parser)]
 #[command(long_about = None)]
 struct Cli {
     /// configuration file for specifying frequently used flags. Defaults to ".llynx.toml"
     #[arg(short, long, value_name = "file-path")]
-    config: Option<String>,
+    config: Option<PathBuf>,
 
     /// Set the path to the LuaRocks executable. Looks on PATH by default
     #[arg(short, long, value_name = "file-path")]
-    luarocks: Option<String>,
+    luarocks: Option<PathBuf>,
+
+    /// Make LuaRocks read this config file instead of the user's, through
+    /// LUAROCKS_CONFIG
+    #[arg(long, value_name = "file-path")]
+    luarocks_config: Option<PathBuf>,
 
     /// Set a custom rocks tree directory. Defaults to "./.lls_addons"
     #[arg(short, long, value_name = "dir-path")]
-    tree: Option<String>,
+    tree: Option<PathBuf>,
 
     /// Modify this settings file. Defaults to "./.vscode/settings.json"
     #[arg(long, value_name = "file-path")]
-    settings: Option<String>,
+    settings: Option<PathBuf>,
+
+    /// Modify the user settings of VS Code, VS Code Insiders or VSCodium
+    /// instead, enabling addons in every workspace. Picks the first one found
+    /// by default
+    #[arg(
+        long,
+        value_name = "editor",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto",
+        value_enum,
+        conflicts_with = "settings"
+    )]
+    user_settings: Option<Editor>,
 
     /// Make LuaRocks look for addons in this server first. Defaults to "https://luarocks.org/m/lls-addons"
     #[arg(long, value_name = "url")]
     server: Option<String>,
 
+    /// Look for addons in the manifest this user keeps on luarocks.org
+    /// instead, like "https://luarocks.org/m/<user>"
+    #[arg(long, value_name = "user", conflicts_with = "server")]
+    server_manifest: Option<String>,
+
+    /// Control how LuaRocks installs dependencies
+    #[arg(long, value_name = "mode", value_enum)]
+    deps_mode: Option<DepsMode>,
+
+    /// Build the tree for this version of Lua, passed to LuaRocks as
+    /// --lua-version. LuaRocks decides by default
+    #[arg(long, value_name = "version", value_parser = ["5.1", "5.2", "5.3", "5.4"])]
+    lua_version: Option<String>,
+
+    /// Choose which addon wins when two set the same setting. Defaults to "first-wins"
+    #[arg(long, value_name = "policy", value_enum)]
+    conflicts: Option<addon_settings::ConflictPolicy>,
+
+    /// Write library entries with forward slashes, or with the separator of
+    /// this platform. Defaults to "slash"
+    #[arg(long, value_name = "style", value_enum)]
+    path_style: Option<enabled::PathStyle>,
+
+    /// Write library entries relative to the workspace, starting with
+    /// "${workspaceFolder}", or as absolute paths. Defaults to "relative"
+    #[arg(long, value_name = "base", value_enum)]
+    path_base: Option<enabled::PathBase>,
+
+    /// Point the library at the enabled version of each addon, or at a link
+    /// to it in the tree that switching versions moves. Defaults to "direct"
+    #[arg(long, value_name = "mode", value_enum)]
+    enable_mode: Option<enabled::EnableMode>,
+
+    /// Only install rocks from the server with a minisign signature matching
+    /// this public key, or the key in this file
+    #[arg(long, value_name = "key")]
+    public_key: Option<String>,
+
+    /// Retry talking to the server this many times when it times out or fails
+    /// with a server error. Defaults to 2
+    #[arg(long, value_name = "count")]
+    retries: Option<u32>,
+
+    /// Reach servers through this proxy instead of the one in http_proxy and
+    /// https_proxy
+    #[arg(long, value_name = "url")]
+    proxy: Option<String>,
+
+    /// Trust the certificates in this PEM file instead of the system's
+    #[arg(long, value_name = "file-path")]
+    ca_bundle: Option<PathBuf>,
+
+    /// Don't check the certificates of servers. Only for servers you trust
+    #[arg(long)]
+    insecure: bool,
+
+    /// Keep the types of installed rocks once in the per-user store and link
+    /// them into the tree
+    #[arg(long)]
+    store: bool,
+
+    /// Kill LuaRocks, git and curl when they run longer than this many
+    /// seconds. They can run forever by default
+    #[arg(long, value_name = "secs")]
+    timeout: Option<u64>,
+
+    /// Look for a newer release of llynx in the background this often, and
+    /// mention it after a command. Defaults to "never"
+    #[arg(long, value_name = "when", value_enum)]
+    update_check: Option<release::UpdateCheck>,
+
+    /// Download a standalone LuaRocks without asking when LuaRocks can't be
+    /// found
+    #[arg(long)]
+    bootstrap: bool,
+
+    /// Remove addons, the tree and the cache without asking first
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Fail instead of changing the settings file or the workspace state, and
+    /// only install versions the settings file enables. For CI
+    #[arg(long)]
+    frozen: bool,
+
     /// Increase verbosity; can be repeated
     #[arg(short, action = clap::ArgAction::Count)]
     verbose: u8,
 
+    /// Also write the whole log, down to every LuaRocks command and what it
+    /// printed, to the end of this file
+    #[arg(long, value_name = "file-path")]
+    log_file: Option<PathBuf>,
+
+    /// How to write log messages to stderr
+    #[arg(long, value_name = "format", default_value = "text")]
+    log_format: LogFormat,
+
+    /// Also write events to stderr when phases start and finish, steps are
+    /// done and settings are updated, for editors to show progress with
+    #[arg(long, value_name = "format")]
+    notify: Option<NotifyFormat>,
+
+    /// Only print results and errors, without warnings, spinners, or what
+    /// LuaRocks says when it succeeds
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Color output. Respects the NO_COLOR environment variable when automatic
+    #[arg(long, value_name = "when", default_value = "auto")]
+    color: ColorWhen,
+
+    /// Print tab-separated fields for scripts instead, in a format that only
+    /// changes with its version
+    #[arg(
+        long,
+        value_name = "version",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1"
+    )]
+    porcelain: Option<Porcelain>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
 
-#[derive(Debug, Subcommand, PartialEq, Eq)]
+#[derive(Debug, Clone, Subcommand, PartialEq, Eq)]
+enum CacheCommand {
+    /// Print how many files the cache holds and how large they are
+    Size,
+
+    /// Print where the cache is
+    Dir,
+
+    /// Delete everything in the cache
+    Clean,
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq, Eq)]
+enum SettingsCommand {
+    /// Print the value of a setting, as JSON unless it's a string
+    Get {
+        /// The setting, like "Lua.runtime.version"
+        key: String,
+    },
+
+    /// Change a setting
+    Set {
+        /// The setting, like "Lua.runtime.version"
+        key: String,
+        /// The value, read as JSON, or as a string if it isn't valid JSON
+        value: String,
+    },
+
+    /// Remove a setting
+    Unset {
+        /// The setting, like "Lua.runtime.version"
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq, Eq)]
+enum StoreCommand {
+    /// Print where the store is
+    Dir,
+
+    /// Delete what no tree links to anymore
+    Gc,
+}
+
+#[derive(Debug, Clone, Subcommand, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum ListSource {
+    /// List installed, enabled, and online addons together, one line per addon
+    All,
+
     /// List every addon in the LuaRocks manifest
     Online,
 
@@ -139,26 +289,139 @@ enum ListSource {
 
     /// List every enabled addon
     Enabled,
+
+    /// List every addon in the LLS-Addons catalog, cloning or updating it
+    /// first
+    Catalog,
 }
 
-#[derive(Debug, Subcommand, PartialEq, Eq)]
+#[derive(Debug, Clone, Subcommand, PartialEq, Eq)]
 enum Command {
     /// List all installed, online, or enabled addons
     List {
         #[command(subcommand)]
         source: Option<ListSource>,
 
-        /// Only include addons with this string in their names
+        /// Only include addons with names roughly matching this, best matches
+        /// first. Globs like 'lua-*' have to match whole names instead
         #[arg(short, long)]
         filter: Option<String>,
+
+        /// Only include addons with names matching this regular expression
+        #[arg(long, value_name = "pattern", conflicts_with = "filter")]
+        regex: Option<String>,
+
+        /// How to print the addons
+        #[arg(short, long, value_name = "format", default_value = "grouped")]
+        output: ListFormat,
+
+        /// Show a short description of each addon, read from its rockspec
+        #[arg(long)]
+        long: bool,
+
+        /// How to order the addons. Defaults to the best matches first when
+        /// filtering fuzzily, and to names otherwise
+        #[arg(long, value_name = "key")]
+        sort: Option<SortKey>,
+
+        /// List the addons in the opposite order
+        #[arg(long)]
+        reverse: bool,
+
+        /// Include in-development versions like `scm-1` when listing online
+        /// addons, from the server's dev manifest
+        #[arg(long)]
+        dev: bool,
+
+        /// Only list installed addons the server has newer versions of
+        #[arg(long, global = true)]
+        outdated: bool,
+
+        /// List at most this many addons, counting their versions as one
+        #[arg(long, value_name = "n", global = true, value_parser = clap::value_parser!(u64).range(1..))]
+        limit: Option<u64>,
+
+        /// Which page of `--limit` addons to list, starting at 1
+        #[arg(long, value_name = "n", global = true, requires = "limit", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
+
+        /// Print straight to the terminal instead of through `$PAGER`
+        #[arg(long, global = true)]
+        no_pager: bool,
+
+        /// Match the filter against the summaries and descriptions of addons
+        /// too, read from their rockspecs
+        #[arg(long, global = true)]
+        in_descriptions: bool,
+
+        /// Only include addons with this label in their rockspec, like
+        /// `lls-addon`. Given more than once, addons need every label
+        #[arg(long, value_name = "label", global = true)]
+        label: Vec<String>,
+
+        /// Print each addon on a line following this template instead, like
+        /// '{name}\t{version}'. The fields are {name}, {version}, {location}
+        /// and {summary}, or {name}, {installed}, {enabled}, {latest} and
+        /// {summary} with `list all` and --outdated
+        #[arg(long, value_name = "template", global = true)]
+        format: Option<Template>,
+
+        /// End each addon with a NUL byte instead of a line break, for
+        /// `xargs -0`. Implies --porcelain unless --format is given
+        #[arg(short = '0', long, global = true)]
+        print0: bool,
+    },
+
+    /// Search the server for addons by name and description, best matches
+    /// first
+    Search {
+        /// What to look for
+        query: String,
+        /// Show at most this many addons
+        #[arg(long, value_name = "n", default_value_t = 10)]
+        limit: usize,
+        /// Include in-development versions like `scm-1`, from the server's
+        /// dev manifest
+        #[arg(long)]
+        dev: bool,
     },
 
     /// Install an addon
     Install {
-        /// The addon to install
-        name: String,
-        /// The version to install
+        /// The addon to install, or a local `.rockspec` or `.rock` file. Picks
+        /// one from the server when left out in a terminal
+        name: Option<String>,
+        /// The version to install, or LuaRocks constraints like '>= 1.4, < 2'
+        /// to install the newest version on the server matching them
         version: Option<String>,
+        /// Don't install the addon's dependencies. Same as `--deps-mode none`
+        #[arg(long)]
+        no_deps: bool,
+        /// Install the addon from the LLS-Addons catalog instead of LuaRocks
+        #[arg(long, conflicts_with_all = ["version", "no_deps"])]
+        catalog: bool,
+        /// Clone the addon from this git repository instead. Its name is taken
+        /// from the URL unless one is given
+        #[arg(long, value_name = "URL", conflicts_with_all = ["version", "no_deps", "catalog"])]
+        git: Option<String>,
+        /// The branch, tag, or commit to check out of `--git`
+        #[arg(long, value_name = "REF", requires = "git")]
+        rev: Option<String>,
+        /// Download and unpack the addon from this archive instead. Its name is
+        /// taken from the file name unless one is given
+        #[arg(long, value_name = "URL", conflicts_with_all = ["version", "no_deps", "catalog", "git"])]
+        url: Option<String>,
+        /// Look for in-development versions like `scm-1` too, like
+        /// `luarocks install --dev`
+        #[arg(long, conflicts_with_all = ["catalog", "git", "url"])]
+        dev: bool,
+        /// Install another version than the one the addon is pinned to
+        #[arg(long, conflicts_with_all = ["catalog", "git", "url"])]
+        unpin: bool,
+        /// Arguments passed on to `luarocks install` as they are, after the
+        /// ones from the config file
+        #[arg(last = true, value_name = "LUAROCKS_ARGS")]
+        luarocks_args: Vec<String>,
     },
 
     /// Remove an addon
@@ -167,150 +430,2040 @@ enum Command {
         name: String,
         /// The specific version of addon to remove
         version: Option<String>,
+        /// Arguments passed on to `luarocks remove` as they are, after the
+        /// ones from the config file
+        #[arg(last = true, value_name = "LUAROCKS_ARGS")]
+        luarocks_args: Vec<String>,
     },
 
     /// Enable an addon for the current workspace
     Enable {
         /// The addon to enable
         name: String,
+        /// Ask before writing the settings file
+        #[arg(long)]
+        confirm: bool,
+        /// Copy the types of the addon and its dependencies into this
+        /// directory and enable them from there, so they can be committed
+        #[arg(long, value_name = "dir")]
+        vendor: Option<PathBuf>,
     },
 
     /// Disable an addon for the current workspace
     Disable {
         /// The addon to disable
         name: String,
+        /// Ask before writing the settings file
+        #[arg(long)]
+        confirm: bool,
+    },
+
+    /// Enable another installed version of an addon instead of the current one
+    Switch {
+        /// The addon to switch
+        name: String,
+        /// The installed version to enable
+        version: String,
+        /// Ask before writing the settings file
+        #[arg(long)]
+        confirm: bool,
+    },
+
+    /// Let llynx manage a library entry that was added by hand, installing
+    /// the addon it belongs to if needed
+    Adopt {
+        /// The entry of `Lua.workspace.library` to adopt
+        path: String,
+    },
+
+    /// Take over the addons the VS Code Lua extension's addon manager
+    /// enabled, installing each from the server and pointing the library at
+    /// the tree instead
+    Migrate {
+        /// The folder the addon manager downloads addons to. Defaults to the
+        /// one of the first editor found
+        #[arg(long, value_name = "dir")]
+        from: Option<PathBuf>,
+    },
+
+    /// Remove addons that were only installed as dependencies of addons that
+    /// are gone now
+    Autoremove {
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Reverse the most recent install, remove, enable, disable, or switch
+    Undo,
+
+    /// Print the changes llynx has made to the tree and settings files
+    History,
+
+    /// Pack installed addons into one archive that can be checked in or copied
+    /// to an offline machine
+    Pack {
+        /// The addons to pack. Packs every installed addon when left out
+        names: Vec<String>,
+        /// Where to write the archive
+        #[arg(short, long, default_value = "llynx-pack.tar.gz")]
+        output: PathBuf,
+    },
+
+    /// Install and enable the addons of an archive made by `pack`
+    Unpack {
+        /// The archive to unpack
+        archive: PathBuf,
+    },
+
+    /// Print the enabled addons as `name@version` lines, for `import`
+    Export {
+        /// Print a JSON array instead, like `--format json`
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+        /// What to print the addons as. "nvim-lspconfig" prints a Lua snippet
+        /// for lua_ls in Neovim, which `import` can't read
+        #[arg(long, value_name = "format", value_enum)]
+        format: Option<export::Format>,
+        /// Write to this file instead of printing
+        #[arg(short, long, value_name = "file-path")]
+        output: Option<PathBuf>,
+    },
+
+    /// Install and enable every addon in a list made by `export`
+    Import {
+        /// The list to read, or "-" for stdin
+        file: PathBuf,
+        /// Fail if the tree or the settings file doesn't match the list,
+        /// without changing anything
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Import a list of addons whenever the workspace's dev container is
+    /// created
+    Devcontainer {
+        /// The list to import. Made from the enabled addons if it's missing
+        #[arg(long, value_name = "file-path", default_value = "addons.txt")]
+        list: PathBuf,
+
+        /// Print the snippet to add instead of changing devcontainer.json
+        #[arg(long)]
+        print: bool,
+    },
+
+    /// Run one install, remove, enable, disable, or switch per line of a
+    /// file, writing the settings file once at the end
+    Batch {
+        /// The file to read, or "-" for stdin
+        file: PathBuf,
+        /// Fail if running the file would change the tree or the settings
+        /// file, without changing anything
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Delete the tree and every addon installed in it
+    Clean {
+        /// Also disable its addons, taking their entries out of the settings
+        /// file
+        #[arg(long = "settings", conflicts_with = "cache")]
+        from_settings: bool,
+
+        /// Empty the per-user cache instead
+        #[arg(long)]
+        cache: bool,
+    },
+
+    /// Print the types directory of an installed addon, as enabling it would
+    /// write it to the settings file
+    Which {
+        /// The addon to look for
+        name: String,
+
+        /// Print the entry enabling it in the settings file instead
+        #[arg(long)]
+        enabled: bool,
+    },
+
+    /// Start a new addon in a directory of its name, with a rockspec and
+    /// example definitions ready to publish
+    New {
+        /// The name of the addon, as it will be published
+        name: String,
+
+        /// Also add a config.json with settings to apply when it's enabled
+        #[arg(long)]
+        with_config: bool,
+
+        /// Also add a plugin.lua for LuaLS to run
+        #[arg(long)]
+        with_plugin: bool,
+    },
+
+    /// Check an addon for packaging mistakes before publishing it
+    Validate {
+        /// A rockspec, a directory holding one, or the name of an installed
+        /// addon
+        #[arg(default_value = ".")]
+        target: String,
+    },
+
+    /// Check that every library entry llynx manages points at definitions,
+    /// and that the addons enabled from the tree are installed in it
+    Verify,
+
+    /// Fix the library entries `verify` finds broken where possible: install
+    /// missing addons again, point entries at the version that replaced
+    /// theirs and write separators as configured. Entries nothing can fix
+    /// are removed after asking
+    Repair,
+
+    /// Validate an addon, then upload it to luarocks.org with `luarocks
+    /// upload`. The API key comes from LLYNX_API_KEY or api_key in the user
+    /// config file
+    Publish {
+        /// The rockspec of the addon
+        rockspec: PathBuf,
+    },
+
+    /// Print how much disk space each installed addon takes, largest first,
+    /// and how large the cache is
+    Du,
+
+    /// Print the license each installed addon declares in its rockspec
+    Licenses {
+        /// Fail if any addon uses this license, like "GPL-3.0". Can be repeated
+        #[arg(long, value_name = "license")]
+        deny: Vec<String>,
+    },
+
+    /// Print the dependency tree of an installed or online addon
+    Deps {
+        /// The addon to inspect
+        name: String,
+    },
+
+    /// Inspect or empty the per-user cache of search results and rockspecs
+    Cache {
+        #[command(subcommand)]
+        command: Option<CacheCommand>,
+    },
+
+    /// Read or change LuaLS settings other than the library in the settings
+    /// file
+    Settings {
+        #[command(subcommand)]
+        command: SettingsCommand,
+    },
+
+    /// Inspect or clean up the per-user store of rocks shared between trees
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+
+    /// Answer JSON-RPC requests over stdio, for editor integrations
+    Serve,
+
+    /// Replace this executable with the newest release of llynx
+    SelfUpdate {
+        /// Only tell whether there's a newer release
+        #[arg(long)]
+        check: bool,
     },
 }
 
-fn print_addons_list(mut addons: Vec<Addon>) -> () {
-    if addons.is_empty() {
-        log::error!("no addons found matching criteria");
-        return;
+/// forward the output of a finished LuaRocks command to our own stdout and
+/// stderr. Porcelain output has stdout to itself, so all of it goes to stderr,
+/// and quiet runs drop it unless LuaRocks failed
+fn write_output(output: Output, porcelain: bool, quiet: bool) -> Result<()> {
+    if quiet && output.status.success() {
+        return Ok(());
+    }
+    let status = output.status;
+    if porcelain {
+        io::stderr()
+            .write_all(&output.stdout)
+            .context("while writing to stderr")?;
+    } else {
+        io::stdout()
+            .write_all(&output.stdout)
+            .context("while writing out stdout")?;
+    }
+    io::stderr()
+        .write_all(&output.stderr)
+        .context("while writing to stderr")?;
+    check_status(status)
+}
+
+/// fail unless LuaRocks exited successfully
+fn check_status(status: ExitStatus) -> Result<()> {
+    if !status.success() {
+        return Err(Failure::LuaRocks
+            .tag(format!("LuaRocks failed with {status}"))
+            .into());
+    }
+    Ok(())
+}
+
+/// run `f`, which runs LuaRocks, with a spinner below what LuaRocks prints
+/// as it's printed. Porcelain output has stdout to itself, so all of it goes
+/// to stderr. Quiet runs draw nothing and only print it if LuaRocks failed.
+/// Fails if LuaRocks did
+fn run_luarocks(
+    phase: String,
+    porcelain: bool,
+    quiet: bool,
+    f: impl FnOnce() -> Result<Output>,
+) -> Result<()> {
+    if quiet {
+        // spinners are hidden under --quiet, so this only emits the events
+        let output = progress::spin(phase, f)?;
+        return write_output(output, porcelain, quiet);
+    }
+    progress::announce(phase, |printer| {
+        let echo: Echo = Arc::new(move |pipe, bytes| {
+            // there's nowhere left to report a broken stdout or stderr to
+            let _ = printer.print(|| match pipe {
+                Pipe::Stdout if !porcelain => {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(bytes).and_then(|_| stdout.flush())
+                }
+                _ => io::stderr().write_all(bytes),
+            });
+        });
+        let output = installed::with_echo(echo, f)?;
+        check_status(output.status)
+    })
+}
+
+/// check the addon `target` names, print how each check went, and fail if
+/// any of them did
+fn validate_addon(config: &Config, target: &str, color: ColorWhen, porcelain: bool) -> Result<()> {
+    let checks = progress::spin(format!("validating '{target}'"), || {
+        validate::validate(config, target)
+    })?;
+    if porcelain {
+        output::porcelain_checks(&mut io::stdout(), &checks)?;
+    } else {
+        let mut stdout = StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+        output::print_checks(&mut stdout, &checks)?;
+    }
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    if failed > 0 {
+        bail!("{failed} of {} checks failed", checks.len());
     }
-    addons.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
-    let mut last_addon: &Addon = addons.first().expect("already checked if it's empty");
-    println!("{}", last_addon.name);
-    println!("\t{}", last_addon.version);
-    for addon in addons.iter().skip(1) {
-        if last_addon.name != addon.name {
-            last_addon = &addon;
-            println!("\n{}", addon.name);
+    Ok(())
+}
+
+/// print what a command did, as `message` or, with `--porcelain`, as
+/// tab-separated `fields`
+fn report(porcelain: bool, fields: &[&str], message: fmt::Arguments) -> Result<()> {
+    if porcelain {
+        output::write_fields(&mut io::stdout(), fields).context("while writing out stdout")?;
+    } else {
+        println!("{message}");
+    }
+    Ok(())
+}
+
+/// print the settings enabled addons disagree on, if any
+fn report_conflicts(config: &Config, color: ColorWhen, porcelain: bool) -> Result<()> {
+    let enabled = list_enabled(config, None).context("while listing enabled addons")?;
+    let conflicts = addon_settings::conflicts(&enabled);
+    if porcelain {
+        output::porcelain_conflicts(&mut io::stdout(), &conflicts)?;
+        return Ok(());
+    }
+    let mut stdout = StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+    output::print_conflicts(&mut stdout, &conflicts)?;
+    Ok(())
+}
+
+/// whether `action` may look for a newer release of llynx while it runs.
+/// Updating looks anyway, and the server's stdout is for JSON-RPC alone
+fn checks_for_updates(action: Option<&Command>) -> bool {
+    !matches!(action, Some(Command::SelfUpdate { .. } | Command::Serve))
+}
+
+/// a LuaRocks to use instead of `luarocks` when it can't be found: one
+/// downloaded before, or one downloaded now if `bootstrap` is set or the user
+/// agrees to it
+fn find_luarocks(config: &Config, bootstrap: bool) -> Result<Option<PathBuf>> {
+    let luarocks = config.luarocks;
+    if !bootstrap::is_missing(luarocks) {
+        return Ok(None);
+    }
+    if let Some(path) = bootstrap::bootstrapped() {
+        log::info!(
+            "'{}' was not found, using '{}'",
+            luarocks.display(),
+            path.display()
+        );
+        return Ok(Some(path));
+    }
+    let prompt = format!(
+        "'{}' was not found. Download LuaRocks {}?",
+        luarocks.display(),
+        bootstrap::LUAROCKS_VERSION
+    );
+    let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+    let agreed = bootstrap || (interactive && pick::confirm(&prompt)?);
+    if !agreed {
+        // running it fails with an error explaining the rest
+        return Ok(None);
+    }
+    let path = progress::spin(
+        format!("downloading LuaRocks {}", bootstrap::LUAROCKS_VERSION),
+        || bootstrap::bootstrap(config),
+    )?;
+    log::info!("downloaded LuaRocks to '{}'", path.display());
+    Ok(Some(path))
+}
+
+/// whether to ask before deleting anything, which is only done in a terminal
+/// and unless `assume_yes` is set, so scripts keep working
+fn asks_first(assume_yes: bool) -> bool {
+    !assume_yes && io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// what removing `name` deletes from the tree, one line per version, or
+/// nothing when it isn't installed
+fn removed_by(config: &Config, name: &str, version: Option<&str>) -> Result<Vec<String>> {
+    if let Some(vendored) = vendor::find(config.tree, name)? {
+        return Ok(vec![format!(
+            "{name} {} in '{}'",
+            vendored.version,
+            vendored.location.display()
+        )]);
+    }
+    let installed = list_installed(config, Some(&Filter::from(name)))?;
+    Ok(installed
+        .into_iter()
+        .filter(|addon| addon.name == name && version.is_none_or(|v| addon.version == v))
+        .map(|addon| match addon.location {
+            Some(location) => format!("{name} {} in '{}'", addon.version, location.display()),
+            None => format!("{name} {}", addon.version),
+        })
+        .collect())
+}
+
+/// make the dev container import `list` when it's created, listing the
+/// enabled addons in it first if it's missing
+fn set_up_devcontainer(config: &Config, list: &Path, print: bool, porcelain: bool) -> Result<()> {
+    let command = devcontainer::command(list);
+    let path = Path::new(devcontainer::DEVCONTAINER_FILE);
+    if print {
+        println!("{}", devcontainer::snippet(&command)?);
+        return Ok(());
+    }
+    if !list.exists() {
+        let enabled = list_enabled(config, None).with_context(|| {
+            format!(
+                "while listing addons enabled in '{settings}'",
+                settings = config.settings.display()
+            )
+        })?;
+        fs::write(list, export::to_text(&enabled))
+            .with_context(|| format!("while writing '{}'", list.display()))?;
+        log::info!(
+            "listed {} enabled addons in '{}'",
+            enabled.len(),
+            list.display()
+        );
+    }
+    if !path.exists() {
+        log::warn!(
+            "'{}' was not found, add this to your dev container's config",
+            path.display()
+        );
+        println!("{}", devcontainer::snippet(&command)?);
+    } else if devcontainer::update(path, &command)? {
+        report(
+            porcelain,
+            &["updated", &path.display().to_string()],
+            format_args!("added '{command}' to '{}'", path.display()),
+        )?;
+    } else {
+        log::info!("llynx already runs in '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// install `name` with LuaRocks, and print what LuaRocks said
+fn install_addon(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    porcelain: bool,
+    quiet: bool,
+) -> Result<()> {
+    run_luarocks(format!("installing '{name}'"), porcelain, quiet, || {
+        signature::install(config, name, version)
+    })?;
+    if porcelain {
+        output::write_fields(
+            &mut io::stdout(),
+            &["installed", name, version.unwrap_or_default()],
+        )?;
+    }
+    Ok(())
+}
+
+/// remove `name` from the tree, whether LuaRocks installed it or not, and
+/// print what LuaRocks said
+fn remove_addon(
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    porcelain: bool,
+    quiet: bool,
+) -> Result<()> {
+    run_luarocks(format!("removing '{name}'"), porcelain, quiet, || {
+        remove(config, name, version)
+    })?;
+    if porcelain {
+        output::write_fields(
+            &mut io::stdout(),
+            &["removed", name, version.unwrap_or_default()],
+        )?;
+    }
+    Ok(())
+}
+
+/// keep the addons whose names match `filter`, or fuzzily match `pattern`
+/// with the best matches first, followed by the ones only `described`
+/// mentions
+fn match_addons(
+    addons: Vec<Addon>,
+    filter: Option<&Filter>,
+    pattern: Option<&str>,
+    described: &BTreeSet<String>,
+) -> Vec<Addon> {
+    match pattern {
+        Some(pattern) => {
+            let (named, mut rest): (Vec<Addon>, Vec<Addon>) = addons
+                .into_iter()
+                .partition(|addon| fuzzy::score(pattern, &addon.name).is_some());
+            let mut ranked = fuzzy::rank(named, pattern, |addon| &addon.name);
+            rest.retain(|addon| described.contains(&addon.name));
+            rest.sort_by(|a, b| a.name.cmp(&b.name));
+            ranked.extend(rest);
+            ranked
         }
-        println!("\t{}", addon.version);
+        None => addons
+            .into_iter()
+            .filter(|addon| {
+                filter.is_none_or(|filter| filter.matches(&addon.name))
+                    || described.contains(&addon.name)
+            })
+            .collect(),
     }
 }
 
-fn get_cli_config_file_overrides(path: &str) -> Result<MaybeConfig> {
-    let contents =
-        fs::read_to_string(path).with_context(|| format!("while opening config file '{path}'"))?;
-    toml::from_str::<MaybeConfig>(&contents)
-        .with_context(|| format!("while parsing config file '{path}'"))
+/// print how `plan` changes the settings file, then write it unless it is
+/// empty or, when `confirm` is set, turned down
+fn apply_plan(
+    config: &Config,
+    plan: Plan,
+    confirm: bool,
+    color: ColorWhen,
+    porcelain: bool,
+) -> Result<()> {
+    if plan.change.before == plan.change.after && plan.links.is_empty() && plan.copies.is_empty() {
+        log::info!("'{}' is already up to date", plan.change.settings.display());
+        return Ok(());
+    }
+    if porcelain {
+        output::porcelain_library_diff(&mut io::stdout(), &plan.change)?;
+    } else {
+        let mut stdout = StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+        output::print_library_diff(&mut stdout, &plan.change)?;
+    }
+    if confirm && !pick::confirm(&format!("Write '{}'?", plan.change.settings.display()))? {
+        log::info!("left '{}' unchanged", plan.change.settings.display());
+        return Ok(());
+    }
+    let change = plan.change.clone();
+    enabled::apply(config, plan)?;
+    events::library_changed(&change);
+    report_conflicts(config, color, porcelain)
 }
 
-fn get_default_config_file_overrides() -> Option<Result<MaybeConfig>> {
-    match fs::read_to_string(CONFIG_PATH) {
-        Err(err) => match err.kind() {
-            io::ErrorKind::NotFound => {
-                log::debug!("default config file not found, using defaults...");
-                None
-            }
-            _ => Some(
-                Err(anyhow::Error::from(err))
-                    .with_context(|| format!("while opening config file '{CONFIG_PATH}'")),
-            ),
-        },
-        Ok(contents) => Some(
-            toml::from_str::<MaybeConfig>(&contents)
-                .with_context(|| format!("while parsing config file '{CONFIG_PATH}'")),
+/// print how the workspace differs from what `file` declares, failing if it
+/// does at all
+fn report_differences(file: &Path, problems: Vec<String>, porcelain: bool) -> Result<()> {
+    if problems.is_empty() {
+        log::info!("the workspace matches '{}'", file.display());
+        return Ok(());
+    }
+    if porcelain {
+        for problem in &problems {
+            output::write_fields(&mut io::stdout(), &["differs", problem])?;
+        }
+    }
+    let problems: Vec<String> = problems
+        .iter()
+        .map(|problem| format!("  {problem}"))
+        .collect();
+    bail!(
+        "the workspace doesn't match '{}':\n{}",
+        file.display(),
+        problems.join("\n")
+    )
+}
+
+/// print the combined change `plans` make to the settings file, then write
+/// it once
+fn apply_plans(config: &Config, plans: Vec<Plan>, color: ColorWhen, porcelain: bool) -> Result<()> {
+    let settings = config.settings;
+    let (Some(first), Some(last)) = (plans.first(), plans.last()) else {
+        log::info!("'{}' is already up to date", settings.display());
+        return Ok(());
+    };
+    let change = LibraryChange {
+        settings: settings.to_path_buf(),
+        before: first.change.before.clone(),
+        after: last.change.after.clone(),
+    };
+    // steps that cancel out are still journaled
+    if change.before == change.after {
+        log::info!("'{}' is already up to date", settings.display());
+    } else if porcelain {
+        output::porcelain_library_diff(&mut io::stdout(), &change)?;
+    } else {
+        let mut stdout = StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+        output::print_library_diff(&mut stdout, &change)?;
+    }
+    enabled::apply_all(config, plans)?;
+    events::library_changed(&change);
+    report_conflicts(config, color, porcelain)
+}
+
+/// the per-user cache, or an error saying how to choose one
+fn cache_dir() -> Result<PathBuf> {
+    cache::cache_dir().ok_or_else(|| {
+        anyhow!(
+            "no cache directory, set {} to choose one",
+            cache::CACHE_DIR_ENV
+        )
+    })
+}
+
+/// empty the cache at `dir`, asking first in a terminal
+fn clean_cache(dir: &Path, assume_yes: bool, porcelain: bool) -> Result<()> {
+    if asks_first(assume_yes) {
+        let size = cache::size_of(dir)?;
+        let prompt = format!(
+            "Remove {} files, {} in '{}'?",
+            size.files,
+            output::format_size(size.bytes),
+            dir.display()
+        );
+        if size.files > 0 && !pick::confirm(&prompt)? {
+            log::info!("left the cache as it is");
+            return Ok(());
+        }
+    }
+    let size = cache::clean(dir)?;
+    report(
+        porcelain,
+        &["removed", &size.files.to_string(), &size.bytes.to_string()],
+        format_args!(
+            "removed {} files, {}",
+            size.files,
+            output::format_size(size.bytes)
         ),
+    )
+}
+
+/// delete the tree, asking first in a terminal. With `from_settings`, every
+/// addon enabled from it is disabled first, taking its library entries and
+/// settings out of the settings file
+fn clean_tree(
+    config: &Config,
+    from_settings: bool,
+    assume_yes: bool,
+    color: ColorWhen,
+    porcelain: bool,
+) -> Result<()> {
+    let Config { tree, settings, .. } = *config;
+    if !tree.exists() {
+        log::info!("'{}' does not exist", tree.display());
+        return Ok(());
+    }
+    let workspace = path::absolute(".").context("while finding the current directory")?;
+    if workspace.starts_with(path::absolute(tree)?) {
+        bail!(
+            "'{}' holds the current directory, refusing to delete it",
+            tree.display()
+        );
+    }
+    if !installed::is_tree(tree) {
+        bail!(
+            "'{}' doesn't look like a rocks tree, refusing to delete it",
+            tree.display()
+        );
+    }
+    let size = cache::size_of(tree)?;
+    if asks_first(assume_yes) {
+        let also = if from_settings {
+            format!(" and disable its addons in '{}'", settings.display())
+        } else {
+            String::new()
+        };
+        let prompt = format!(
+            "Delete '{}' with {} files, {}{also}?",
+            tree.display(),
+            size.files,
+            output::format_size(size.bytes)
+        );
+        if !pick::confirm(&prompt)? {
+            log::info!("left '{}' as it is", tree.display());
+            return Ok(());
+        }
+    }
+    if from_settings {
+        let steps: Vec<Step> = list_enabled(config, None)
+            .context("while listing enabled addons")?
+            .into_iter()
+            .map(|addon| Step::Disable { name: addon.name })
+            .collect();
+        let plans = progress::spin("planning library changes", || {
+            batch::plan_library(config, &steps)
+        })?;
+        apply_plans(config, plans, color, porcelain)?;
     }
+    let size = progress::spin(format!("deleting '{}'", tree.display()), || {
+        cache::clean(tree)
+    })?;
+    report(
+        porcelain,
+        &[
+            "removed",
+            &size.files.to_string(),
+            &size.bytes.to_string(),
+            &tree.to_string_lossy(),
+        ],
+        format_args!(
+            "deleted '{}', {} files, {}",
+            tree.display(),
+            size.files,
+            output::format_size(size.bytes)
+        ),
+    )
+}
+
+/// whether `command` changes the settings file of each folder when the
+/// workspace has several
+fn spans_folders(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Enable { .. } | Command::Disable { .. } | Command::Switch { .. }
+    )
 }
 
-fn get_file_overrides(path: Option<&str>) -> Result<Option<MaybeConfig>> {
-    path.map(get_cli_config_file_overrides)
-        .or_else(get_default_config_file_overrides)
-        .transpose()
+/// run `command` in each of `config.folders` in turn, on the settings file of
+/// each and the tree they share
+fn run_in_folders(
+    command: Command,
+    config: Config,
+    color: ColorWhen,
+    log_format: LogFormat,
+    bootstrap: bool,
+    porcelain: bool,
+    quiet: bool,
+) -> Result<()> {
+    let root = env::current_dir().context("while finding the current directory")?;
+    let absolute = |path: &Path| {
+        path::absolute(path).with_context(|| format!("while making '{}' absolute", path.display()))
+    };
+    let tree = absolute(config.tree)?;
+    // a bare name is still looked up on PATH
+    let luarocks = match config.luarocks.components().count() {
+        1 => config.luarocks.to_path_buf(),
+        _ => absolute(config.luarocks)?,
+    };
+    let luarocks_config = config.luarocks_config.map(absolute).transpose()?;
+    let ca_bundle = config.ca_bundle.map(absolute).transpose()?;
+    let log_file = config.log_file.map(absolute).transpose()?;
+    let result = config.folders.iter().try_for_each(|folder| {
+        let dir = root.join(folder);
+        env::set_current_dir(&dir)
+            .with_context(|| format!("while entering folder '{}'", folder.display()))?;
+        let tree = folders::relative_to(&tree, &dir)?;
+        let config = Config {
+            tree: &tree,
+            luarocks: &luarocks,
+            luarocks_config: luarocks_config.as_deref(),
+            ca_bundle: ca_bundle.as_deref(),
+            log_file: log_file.as_deref(),
+            ..config
+        };
+        run_command(
+            Some(command.clone()),
+            config,
+            color,
+            log_format,
+            bootstrap,
+            porcelain,
+            quiet,
+        )
+        .with_context(|| format!("in folder '{}'", folder.display()))?;
+        log::info!("updated folder '{}'", folder.display());
+        Ok(())
+    });
+    env::set_current_dir(&root).context("while leaving the folders")?;
+    result
 }
 
-fn run_command(action: Option<Command>, config: Config) -> Result<()> {
+fn run_command(
+    action: Option<Command>,
+    config: Config,
+    color: ColorWhen,
+    log_format: LogFormat,
+    bootstrap: bool,
+    porcelain: bool,
+    quiet: bool,
+) -> Result<()> {
     let Config {
-        luarocks,
+        luarocks: _,
         tree,
         settings,
         server,
+        servers: _,
         verbose,
+        deps_mode,
+        lua_version: _,
+        luarocks_args,
+        luarocks_config: _,
+        conflicts: _,
+        path_style: _,
+        path_base: _,
+        enable_mode: _,
+        public_key: _,
+        retries: _,
+        proxy: _,
+        ca_bundle: _,
+        insecure: _,
+        timeout: _,
+        auth: _,
+        update_check,
+        log_file,
+        assume_yes,
+        hooks: _,
+        gitignore_tree: _,
+        api_key: _,
+        pins: _,
+        folders: _,
+        store: _,
+        fallback_to_root: _,
+        dev: _,
+        frozen: _,
     } = config;
+    // a command runs briefly, so the tree can't change behind its back, but
+    // the server keeps running
+    if !matches!(action, Some(Command::Serve)) {
+        installed::remember_listings();
+    }
 
-    stderrlog::new()
-        .timestamp(stderrlog::Timestamp::Off)
-        .verbosity(verbose as usize)
-        .init()?;
+    if quiet {
+        progress::hide();
+    }
+    let verbosity = match (quiet, verbose) {
+        // errors are all that's left when quiet
+        (true, _) | (false, 0) => LevelFilter::Error,
+        (false, 1) => LevelFilter::Warn,
+        (false, 2) => LevelFilter::Info,
+        (false, 3) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    logging::init(
+        verbosity,
+        log_format,
+        color.choice(io::stderr().is_terminal()),
+        log_file,
+    )?;
+    log::debug!(
+        "llynx {} running {:?}",
+        env!("CARGO_PKG_VERSION"),
+        env::args().collect::<Vec<_>>()
+    );
+    if config.insecure {
+        log::warn!("certificates of servers aren't being checked");
+    }
+
+    if let Some(interval) = update_check.interval()
+        && checks_for_updates(action.as_ref())
+    {
+        release::refresh_in_background(&config, interval);
+    }
+
+    // LuaRocks is looked for before anything runs it, so one can be
+    // downloaded first
+    let bootstrapped;
+    let config = match &action {
+        None
+        | Some(
+            Command::Cache { .. }
+            | Command::Store { .. }
+            | Command::SelfUpdate { .. }
+            | Command::Devcontainer { .. }
+            | Command::Clean {
+                from_settings: false,
+                ..
+            }
+            | Command::Which { enabled: true, .. }
+            | Command::New { .. },
+        ) => config,
+        Some(_) => match find_luarocks(&config, bootstrap)? {
+            Some(path) => {
+                bootstrapped = path;
+                Config {
+                    luarocks: &bootstrapped,
+                    ..config
+                }
+            }
+            None => config,
+        },
+    };
 
     match action {
         None => Cli::command().print_help().unwrap(),
         Some(action) => match action {
-            Command::List { source, filter } => {
-                let filter = filter.as_ref().map(String::as_str);
-                let addons = match source.unwrap_or(ListSource::Installed) {
-                    ListSource::Enabled => list_enabled(tree, settings, filter),
-                    ListSource::Installed => list_installed(tree, luarocks, filter),
-                    ListSource::Online => list_online(server, luarocks, filter),
+            Command::List {
+                source,
+                filter,
+                regex,
+                output,
+                long,
+                sort,
+                reverse,
+                dev,
+                outdated,
+                limit,
+                page,
+                no_pager,
+                in_descriptions,
+                label,
+                format,
+                print0,
+            } => {
+                let config = Config { dev, ..config };
+                let porcelain = porcelain || print0;
+                let end = if print0 { '\0' } else { '\n' };
+                // porcelain lines are written as usual, then ended by NUL
+                let records = || -> Box<dyn Write> {
+                    match print0 {
+                        true => Box::new(output::NulTerminated(io::stdout())),
+                        false => Box::new(io::stdout()),
+                    }
+                };
+                let source = source.unwrap_or(ListSource::Installed);
+                if let Some(format) = &format {
+                    match outdated || source == ListSource::All {
+                        true => format.check(&output::STATUS_FIELDS)?,
+                        false => format.check(&output::ADDON_FIELDS)?,
+                    }
+                }
+                let long = long || format.as_ref().is_some_and(|format| format.uses("summary"));
+                if outdated && source != ListSource::Installed {
+                    bail!("--outdated only works with `list installed`");
+                }
+                if in_descriptions && (outdated || source == ListSource::All) {
+                    bail!("--in-descriptions doesn't work with `list all` or --outdated");
+                }
+                if !label.is_empty()
+                    && (outdated || matches!(source, ListSource::All | ListSource::Catalog))
+                {
+                    bail!("--label doesn't work with `list all`, `list catalog` or --outdated");
+                }
+                // plain filters are matched fuzzily here, while globs and
+                // regexes are left to llynx-core
+                let (filter, pattern) = match (regex, filter) {
+                    (Some(regex), _) => (Some(Filter::regex(&regex)?), None),
+                    (None, Some(filter)) => match Filter::parse(&filter)? {
+                        Filter::Contains(pattern) => (None, Some(pattern)),
+                        filter => (Some(filter), None),
+                    },
+                    (None, None) => (None, None),
+                };
+                let filter = filter.as_ref();
+                // descriptions can mention addons of any name, so everything
+                // is listed before matching them
+                let wanted = match in_descriptions {
+                    true => filter
+                        .cloned()
+                        .or_else(|| pattern.as_deref().map(Filter::from)),
+                    false => None,
+                };
+                let list_filter = match wanted {
+                    Some(_) => None,
+                    None => filter,
+                };
+                // the catalog describes its addons itself
+                let mut descriptions: Option<Summaries> = None;
+                let show_statuses = |statuses: Vec<AddonStatus>| -> Result<()> {
+                    let mut statuses = match pattern.as_deref() {
+                        Some(pattern) => fuzzy::rank(statuses, pattern, |status| &status.name),
+                        None => statuses,
+                    };
+                    match sort {
+                        Some(key) => output::sort_statuses(&mut statuses, key, reverse),
+                        None if reverse => statuses.reverse(),
+                        None => {}
+                    }
+                    let statuses = output::page(statuses, |status| &status.name, limit, page);
+                    let summaries = if long {
+                        let addons: Vec<Addon> = statuses
+                            .iter()
+                            .filter_map(|status| {
+                                let version =
+                                    status.latest.as_deref().or(status.newest_installed())?;
+                                Some(Addon {
+                                    name: status.name.clone(),
+                                    version: version.to_string(),
+                                    location: None,
+                                })
+                            })
+                            .collect();
+                        progress::spin("reading rockspecs", || {
+                            rockspec::summaries(&addons, &config)
+                        })
+                    } else {
+                        Summaries::new()
+                    };
+                    if let Some(format) = &format {
+                        output::format_statuses(
+                            &mut io::stdout(),
+                            &statuses,
+                            &summaries,
+                            format,
+                            end,
+                        )?;
+                    } else if porcelain {
+                        output::porcelain_statuses(&mut records(), &statuses, &summaries)?;
+                    } else {
+                        let mut stdout = pager::stdout(color, !no_pager);
+                        output::print_status_list(&mut stdout, &statuses, &summaries)?;
+                    }
+                    Ok(())
+                };
+                let addons = match source {
+                    ListSource::All => {
+                        let statuses = progress::spin("gathering addons", || {
+                            status::list_all(&config, filter)
+                        })
+                        .context("while listing addons")?;
+                        show_statuses(statuses)?;
+                        // conflicts aren't addons to pass on
+                        if !print0 {
+                            report_conflicts(&config, color, porcelain)?;
+                        }
+                        return Ok(());
+                    }
+                    ListSource::Installed if outdated => {
+                        let statuses =
+                            progress::spin(format!("checking {server} for updates"), || {
+                                status::list_outdated(&config, filter)
+                            })
+                            .context("while listing outdated addons")?;
+                        if statuses.is_empty() {
+                            log::info!("no installed addon has a newer version on {server}");
+                            return Ok(());
+                        }
+                        return show_statuses(statuses);
+                    }
+                    ListSource::Enabled => list_enabled(&config, list_filter).and_then(|mut addons| {
+                        let third_party = third_party::list_enabled(settings, list_filter)?;
+                        if !third_party.is_empty() && !porcelain && io::stderr().is_terminal() {
+                            eprintln!(
+                                "addons enabled through `{THIRD_PARTY_KEY}` are listed as {THIRD_PARTY_VERSION}, run `llynx migrate` to let llynx manage them"
+                            );
+                        }
+                        addons.extend(third_party);
+                        Ok(addons)
+                    }),
+                    ListSource::Installed => progress::spin("reading the tree", || {
+                        list_installed(&config, list_filter)
+                    }),
+                    ListSource::Online => progress::spin(format!("searching {server}"), || {
+                        list_online(&config, list_filter)
+                    }),
+                    ListSource::Catalog => progress::spin("updating the catalog", || {
+                        catalog::update(&config)?;
+                        catalog::list(&config)
+                    })
+                    .map(|catalog| {
+                        let catalog: Vec<CatalogAddon> = catalog
+                            .into_iter()
+                            .filter(|addon| list_filter.is_none_or(|f| f.matches(&addon.name)))
+                            .collect();
+                        let addons = catalog.iter().map(CatalogAddon::to_addon).collect();
+                        descriptions = Some(
+                            catalog
+                                .into_iter()
+                                .filter_map(|addon| Some((addon.name, addon.description?)))
+                                .collect(),
+                        );
+                        addons
+                    }),
                 }
                 .context("while listing addons")?;
-
-                print_addons_list(addons);
+                let described = match (&wanted, &descriptions) {
+                    (Some(wanted), Some(descriptions)) => descriptions
+                        .iter()
+                        .filter(|(_, description)| wanted.mentioned_in(description))
+                        .map(|(name, _)| name.clone())
+                        .collect(),
+                    (Some(wanted), None) => progress::spin("reading rockspecs", || {
+                        rockspec::described(&addons, wanted, &config)
+                    }),
+                    (None, _) => BTreeSet::new(),
+                };
+                let mut addons = match_addons(addons, filter, pattern.as_deref(), &described);
+                if !label.is_empty() {
+                    let labeled = progress::spin("reading rockspecs", || {
+                        rockspec::labeled(&addons, &label, &config)
+                    });
+                    addons.retain(|addon| labeled.contains(&addon.name));
+                }
+                match (sort, &pattern) {
+                    (Some(key), _) => output::sort_addons(&mut addons, key, reverse),
+                    (None, Some(_)) if reverse => addons.reverse(),
+                    (None, Some(_)) => {}
+                    (None, None) => output::sort_addons(&mut addons, SortKey::Name, reverse),
+                }
+                let addons = output::page(addons, |addon| &addon.name, limit, page);
+
+                let summaries = match descriptions {
+                    Some(descriptions) if long => descriptions,
+                    // labels are shown to people, but scripts get the
+                    // summary alone
+                    None if long => output::summaries(
+                        progress::spin("reading rockspecs", || {
+                            rockspec::descriptions(&addons, &config)
+                        }),
+                        !porcelain && format.is_none(),
+                    ),
+                    _ => Summaries::new(),
+                };
+                if let Some(format) = &format {
+                    output::format_addons(&mut io::stdout(), &addons, &summaries, format, end)?;
+                    return Ok(());
+                }
+                if porcelain {
+                    output::porcelain_addons(&mut records(), &addons, &summaries)?;
+                    return Ok(());
+                }
+                match output {
+                    ListFormat::Grouped => {
+                        let mut stdout = pager::stdout(color, !no_pager);
+                        output::print_addons_list(&mut stdout, addons, &summaries)?
+                    }
+                    ListFormat::Table => {
+                        let enabled =
+                            list_enabled(&config, None).context("while listing enabled addons")?;
+                        let max_width = io::stdout()
+                            .is_terminal()
+                            .then(terminal_size::terminal_size)
+                            .flatten()
+                            .map(|(width, _)| width.0 as usize);
+                        let mut stdout = pager::stdout(color, !no_pager);
+                        output::print_addons_table(
+                            &mut stdout,
+                            addons,
+                            &enabled,
+                            &summaries,
+                            max_width,
+                        )?
+                    }
+                }
             }
-            Command::Install { name, version } => {
-                let version = version.as_ref().map(String::as_str);
-                install(tree, luarocks, &name, version)?;
+            Command::Search { query, limit, dev } => {
+                let config = Config { dev, ..config };
+                let results = progress::spin(format!("searching {server}"), || {
+                    search::search(&config, &query)
+                })
+                .context("while searching addons")?;
+                let results: Vec<SearchResult> = results.into_iter().take(limit).collect();
+                let summaries = output::summaries(
+                    results
+                        .iter()
+                        .map(|result| (result.status.name.clone(), result.description.clone()))
+                        .collect(),
+                    !porcelain,
+                );
+                let statuses: Vec<AddonStatus> =
+                    results.into_iter().map(|result| result.status).collect();
+                if porcelain {
+                    output::porcelain_statuses(&mut io::stdout(), &statuses, &summaries)?;
+                    return Ok(());
+                }
+                let mut stdout = StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                output::print_status_list(&mut stdout, &statuses, &summaries)?;
+                if let Some(best) = statuses.iter().find(|status| status.installed.is_empty())
+                    && io::stderr().is_terminal()
+                {
+                    eprintln!(
+                        "run `llynx install {name}` and `llynx enable {name}` to use '{name}'",
+                        name = best.name
+                    );
+                }
             }
-            Command::Remove { name, version } => {
-                let version = version.as_ref().map(String::as_str);
+            Command::Install {
+                name,
+                version,
+                no_deps,
+                catalog,
+                git,
+                rev,
+                url,
+                dev,
+                unpin,
+                luarocks_args: extra_args,
+            } => {
+                let luarocks_args = [luarocks_args, &extra_args].concat();
+                let config = Config {
+                    luarocks_args: &luarocks_args,
+                    dev,
+                    ..config
+                };
+                if let Some(url) = url {
+                    let addon = progress::spin(format!("downloading '{url}'"), || {
+                        archive::install(&config, &url, name.as_deref())
+                    })
+                    .with_context(|| format!("while installing '{url}'"))?;
+                    report(
+                        porcelain,
+                        &["installed", &addon.name, &addon.version],
+                        format_args!("installed {} {}", addon.name, addon.version),
+                    )?;
+                    return Ok(());
+                }
+                if let Some(url) = git {
+                    let addon = progress::spin(format!("cloning '{url}'"), || {
+                        git::install(&config, &url, rev.as_deref(), name.as_deref())
+                    })
+                    .with_context(|| format!("while installing '{url}'"))?;
+                    report(
+                        porcelain,
+                        &["installed", &addon.name, &addon.version],
+                        format_args!("installed {} {}", addon.name, addon.version),
+                    )?;
+                    return Ok(());
+                }
+                let name = match name {
+                    Some(name) => name,
+                    None => {
+                        if !(io::stdin().is_terminal() && io::stderr().is_terminal()) {
+                            Cli::command()
+                                .error(
+                                    clap::error::ErrorKind::MissingRequiredArgument,
+                                    "no addon to install was given",
+                                )
+                                .exit();
+                        }
+                        let addons = if catalog {
+                            progress::spin("updating the catalog", || {
+                                catalog::update(&config)?;
+                                catalog::list(&config)
+                            })
+                            .context("while listing the catalog")?
+                            .iter()
+                            .map(CatalogAddon::to_addon)
+                            .collect()
+                        } else {
+                            progress::spin(format!("searching {server}"), || {
+                                list_online(&config, None)
+                            })
+                            .context("while listing online addons")?
+                        };
+                        match pick::pick_addon(&addons)? {
+                            Some(name) => name,
+                            None => return Ok(()),
+                        }
+                    }
+                };
+                if catalog {
+                    let addon = progress::spin(format!("installing '{name}'"), || {
+                        catalog::install(&config, &name)
+                    })
+                    .with_context(|| format!("while installing '{name}' from the catalog"))?;
+                    report(
+                        porcelain,
+                        &["installed", &addon.name, &addon.version],
+                        format_args!("installed {} {}", addon.name, addon.version),
+                    )?;
+                    return Ok(());
+                }
+                let deps_mode = if no_deps {
+                    Some(DepsMode::None)
+                } else {
+                    deps_mode
+                };
+                install_addon(
+                    &Config {
+                        deps_mode,
+                        pins: config.pins.filter(|_| !unpin),
+                        ..config
+                    },
+                    &name,
+                    version.as_deref(),
+                    porcelain,
+                    quiet,
+                )?;
+            }
+            Command::Remove {
+                name,
+                version,
+                luarocks_args: extra_args,
+            } => {
+                let luarocks_args = [luarocks_args, &extra_args].concat();
+                let config = Config {
+                    luarocks_args: &luarocks_args,
+                    ..config
+                };
+                let version = version.as_deref();
+                if asks_first(assume_yes) {
+                    let removed = removed_by(&config, &name, version)?;
+                    // LuaRocks explains what's wrong when nothing matches
+                    if !removed.is_empty() {
+                        for line in &removed {
+                            eprintln!("  {line}");
+                        }
+                        if !pick::confirm(&format!("Remove '{name}'?"))? {
+                            log::info!("left '{name}' installed");
+                            return Ok(());
+                        }
+                    }
+                }
                 #[cfg(feature = "disable_before_remove")]
                 {
                     log::info!("disabling '{name}' first...");
-                    disable(&tree, &luarocks, &settings, &name)
+                    enabled::disable(&config, &name)
                         .with_context(|| format!("while disabling '{name}' before uninstalling"))?;
                 }
-                remove(tree, luarocks, &name, version)?;
+                remove_addon(&config, &name, version, porcelain, quiet)?;
+            }
+            Command::Enable {
+                name,
+                confirm,
+                vendor: None,
+            } => {
+                let plan = progress::spin(format!("enabling '{name}'"), || {
+                    enabled::plan_enable(&config, &name)
+                })?;
+                if let Some(plan) = plan {
+                    apply_plan(&config, plan, confirm, color, porcelain)?;
+                }
+            }
+            Command::Enable {
+                name,
+                confirm,
+                vendor: Some(dir),
+            } => {
+                let plan =
+                    progress::spin(format!("copying '{name}' to '{}'", dir.display()), || {
+                        copies::plan_enable(&config, &name, &dir)
+                    })?;
+                apply_plan(&config, plan, confirm, color, porcelain)?;
+            }
+            Command::Disable { name, confirm } => {
+                let plan = progress::spin(format!("disabling '{name}'"), || {
+                    enabled::plan_disable(&config, &name)
+                })?;
+                if let Some(plan) = plan {
+                    apply_plan(&config, plan, confirm, color, porcelain)?;
+                }
+            }
+            Command::Switch {
+                name,
+                version,
+                confirm,
+            } => {
+                let plan = progress::spin(format!("switching '{name}' to {version}"), || {
+                    enabled::plan_switch(&config, &name, &version)
+                })?;
+                apply_plan(&config, plan, confirm, color, porcelain)?;
+            }
+            Command::Pack { names, output } => {
+                let packed =
+                    progress::spin("packing addons", || pack::pack(&config, &output, &names))
+                        .context("while packing addons")?;
+                if porcelain {
+                    for addon in &packed {
+                        output::write_fields(
+                            &mut io::stdout(),
+                            &["packed", &addon.name, &addon.version],
+                        )?;
+                    }
+                } else {
+                    println!("packed {} addons into '{}'", packed.len(), output.display());
+                }
+            }
+            Command::Unpack { archive } => {
+                let packed = progress::spin(format!("unpacking '{}'", archive.display()), || {
+                    pack::unpack(&config, &archive)
+                })
+                .with_context(|| format!("while unpacking '{}'", archive.display()))?;
+                for addon in packed {
+                    report(
+                        porcelain,
+                        &["installed", &addon.name, &addon.version],
+                        format_args!("installed {} {}", addon.name, addon.version),
+                    )?;
+                }
+            }
+            Command::Export {
+                json,
+                format,
+                output,
+            } => {
+                let enabled = list_enabled(&config, None).with_context(|| {
+                    format!(
+                        "while listing addons enabled in '{settings}'",
+                        settings = settings.display()
+                    )
+                })?;
+                let format = match format {
+                    Some(format) => format,
+                    None if json => export::Format::Json,
+                    None => export::Format::Text,
+                };
+                let exported = export::render(format, &enabled)?;
+                match output {
+                    Some(output) => fs::write(&output, exported)
+                        .with_context(|| format!("while writing '{}'", output.display()))?,
+                    None => print!("{exported}"),
+                }
+            }
+            Command::Import { file, check } => {
+                let contents = if file.as_os_str() == "-" {
+                    io::read_to_string(io::stdin()).context("while reading stdin")?
+                } else {
+                    fs::read_to_string(&file)
+                        .with_context(|| format!("while reading '{}'", file.display()))?
+                };
+                let requirements = export::parse(&contents)?;
+                if check {
+                    let problems = progress::spin("checking the workspace", || {
+                        export::check(&config, &requirements)
+                    })?;
+                    return report_differences(&file, problems, porcelain);
+                }
+                for requirement in requirements {
+                    let name = &requirement.name;
+                    progress::spin(format!("importing '{name}'"), || {
+                        export::import(&config, &requirement)
+                    })
+                    .with_context(|| format!("while importing '{name}'"))?;
+                    let version = requirement.version.as_deref();
+                    let shown = match version {
+                        Some(version) => format!("{name} {version}"),
+                        None => name.clone(),
+                    };
+                    report(
+                        porcelain,
+                        &["enabled", name, version.unwrap_or_default()],
+                        format_args!("enabled {shown}"),
+                    )?;
+                }
+            }
+            Command::Devcontainer { list, print } => {
+                set_up_devcontainer(&config, &list, print, porcelain)?;
+            }
+            Command::Batch { file, check } => {
+                let contents = if file.as_os_str() == "-" {
+                    io::read_to_string(io::stdin()).context("while reading stdin")?
+                } else {
+                    fs::read_to_string(&file)
+                        .with_context(|| format!("while reading '{}'", file.display()))?
+                };
+                let steps = batch::parse(&contents)
+                    .with_context(|| format!("while reading '{}'", file.display()))?;
+                if check {
+                    let problems =
+                        progress::spin("checking the workspace", || batch::check(&config, &steps))?;
+                    return report_differences(&file, problems, porcelain);
+                }
+                for (done, step) in steps.iter().enumerate() {
+                    events::progress("running the batch", done, steps.len());
+                    match step {
+                        Step::Install { name, version } => {
+                            install_addon(&config, name, version.as_deref(), porcelain, quiet)?;
+                        }
+                        Step::Remove { name, version } => {
+                            remove_addon(&config, name, version.as_deref(), porcelain, quiet)?;
+                        }
+                        Step::Enable { .. } | Step::Disable { .. } | Step::Switch { .. } => {}
+                    }
+                }
+
+                let plans = progress::spin("planning library changes", || {
+                    batch::plan_library(&config, &steps)
+                })?;
+                apply_plans(&config, plans, color, porcelain)?;
+            }
+            Command::Clean { cache: true, .. } => {
+                clean_cache(&cache_dir()?, assume_yes, porcelain)?
+            }
+            Command::Clean { from_settings, .. } => {
+                clean_tree(&config, from_settings, assume_yes, color, porcelain)?;
+            }
+            Command::Which { name, enabled } => {
+                let entry = if enabled {
+                    enabled::which_enabled(&config, &name)?
+                } else {
+                    enabled::which(&config, &name)?
+                };
+                println!("{entry}");
+            }
+            Command::New {
+                name,
+                with_config,
+                with_plugin,
+            } => {
+                let extras = scaffold::Extras {
+                    config: with_config,
+                    plugin: with_plugin,
+                };
+                for path in scaffold::create(Path::new(&name), &name, extras)? {
+                    report(
+                        porcelain,
+                        &["created", &path.to_string_lossy()],
+                        format_args!("created '{}'", path.display()),
+                    )?;
+                }
+            }
+            Command::Validate { target } => {
+                validate_addon(&config, &target, color, porcelain)?;
+            }
+            Command::Verify => {
+                let checks = progress::spin("verifying the library", || verify::verify(&config))
+                    .context("while verifying the library")?;
+                let checks: Vec<Check> = checks
+                    .into_iter()
+                    .map(|check| Check {
+                        name: check.entry,
+                        problem: check.problem.map(|problem| problem.to_string()),
+                    })
+                    .collect();
+                if porcelain {
+                    output::porcelain_checks(&mut io::stdout(), &checks)?;
+                } else {
+                    let mut stdout =
+                        StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                    output::print_checks(&mut stdout, &checks)?;
+                }
+                let broken = checks.iter().filter(|check| !check.passed()).count();
+                if broken > 0 {
+                    bail!("{broken} of {} library entries are broken", checks.len());
+                }
+            }
+            Command::Repair => {
+                let repair =
+                    progress::spin("verifying the library", || repair::plan_repair(&config))
+                        .context("while verifying the library")?;
+                let mut broken = 0;
+                for (done, addon) in repair.reinstall.iter().enumerate() {
+                    events::progress("installing missing addons", done, repair.reinstall.len());
+                    let installed =
+                        install_addon(&config, &addon.name, Some(&addon.version), porcelain, quiet);
+                    if let Err(err) = installed {
+                        log::error!(
+                            "could not install {} {}: {err:#}",
+                            addon.name,
+                            addon.version
+                        );
+                        broken += 1;
+                    }
+                }
+                apply_plans(&config, repair.fixes, color, porcelain)?;
+                if !repair.prunes.is_empty() {
+                    let mut prune = true;
+                    if asks_first(assume_yes) {
+                        let checks: Vec<Check> = repair
+                            .hopeless
+                            .iter()
+                            .map(|check| Check {
+                                name: check.entry.clone(),
+                                problem: check.problem.as_ref().map(ToString::to_string),
+                            })
+                            .collect();
+                        let mut stderr = StandardStream::stderr(color.choice(true));
+                        output::print_checks(&mut stderr, &checks)?;
+                        let prompt = format!(
+                            "Remove {} entries nothing can fix from '{}'?",
+                            checks.len(),
+                            settings.display()
+                        );
+                        prune = pick::confirm(&prompt)?;
+                    }
+                    match prune {
+                        true => apply_plans(&config, repair.prunes, color, porcelain)?,
+                        false => broken += repair.hopeless.len(),
+                    }
+                }
+                if broken > 0 {
+                    bail!("{broken} library entries are still broken");
+                }
+            }
+            Command::Publish { rockspec } => {
+                let target = rockspec.to_string_lossy();
+                validate_addon(&config, &target, color, porcelain)?;
+                let output = progress::spin(format!("publishing '{target}'"), || {
+                    publish::publish(&config, &rockspec)
+                })?;
+                write_output(output, porcelain, quiet)?;
+                if porcelain {
+                    output::write_fields(&mut io::stdout(), &["published", &target])?;
+                }
+            }
+            Command::Du => {
+                let usages = progress::spin("measuring addons", || disk_usage::disk_usage(&config))
+                    .context("while measuring addons")?;
+                let cache = match cache::cache_dir() {
+                    Some(dir) => {
+                        let size = cache::size_of(&dir)?;
+                        Some((dir, size))
+                    }
+                    None => None,
+                };
+                let cache = cache.as_ref().map(|(dir, size)| (dir.as_path(), *size));
+                if porcelain {
+                    output::porcelain_disk_usage(&mut io::stdout(), &usages, cache)?;
+                } else {
+                    let mut stdout =
+                        StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                    output::print_disk_usage(&mut stdout, &usages, cache)?;
+                }
+            }
+            Command::Licenses { deny } => {
+                let found = progress::spin("reading rockspecs", || licenses::licenses(&config))
+                    .context("while reading licenses")?;
+                let denied = licenses::denied(&found, &deny);
+                if porcelain {
+                    output::porcelain_licenses(&mut io::stdout(), &found, &denied)?;
+                } else {
+                    let mut stdout =
+                        StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                    output::print_licenses(&mut stdout, &found, &denied)?;
+                }
+                if !denied.is_empty() {
+                    let names: Vec<String> = denied
+                        .iter()
+                        .map(|addon| format!("'{}'", addon.name))
+                        .collect();
+                    bail!("denied licenses are used by {}", names.join(", "));
+                }
+            }
+            Command::Cache { command } => {
+                let dir = cache_dir()?;
+                match command.unwrap_or(CacheCommand::Size) {
+                    CacheCommand::Size => {
+                        let size = cache::size_of(&dir)?;
+                        report(
+                            porcelain,
+                            &[
+                                &size.files.to_string(),
+                                &size.bytes.to_string(),
+                                &dir.to_string_lossy(),
+                            ],
+                            format_args!(
+                                "{} files, {} in '{}'",
+                                size.files,
+                                output::format_size(size.bytes),
+                                dir.display()
+                            ),
+                        )?;
+                    }
+                    CacheCommand::Dir => println!("{}", dir.display()),
+                    CacheCommand::Clean => clean_cache(&dir, assume_yes, porcelain)?,
+                }
+            }
+            Command::Settings { command } => match command {
+                SettingsCommand::Get { key } => {
+                    let value = settings::get(settings, &key)?
+                        .ok_or_else(|| anyhow!("'{key}' is not set in '{}'", settings.display()))?;
+                    match value {
+                        serde_json::Value::String(value) => println!("{value}"),
+                        value => println!("{value}"),
+                    }
+                }
+                SettingsCommand::Set { key, value } => {
+                    let value = settings::parse_value(&value);
+                    settings::set(&config, &key, value.clone())?;
+                    report(
+                        porcelain,
+                        &["set", &key, &value.to_string()],
+                        format_args!("set '{key}' to {value} in '{}'", settings.display()),
+                    )?;
+                }
+                SettingsCommand::Unset { key } => {
+                    if settings::unset(&config, &key)? {
+                        report(
+                            porcelain,
+                            &["unset", &key],
+                            format_args!("removed '{key}' from '{}'", settings.display()),
+                        )?;
+                    } else {
+                        log::info!("'{key}' is not set in '{}'", settings.display());
+                    }
+                }
+            },
+            Command::Store { command } => {
+                let dir = store::store_dir().ok_or_else(|| {
+                    anyhow!(
+                        "no data directory for the store, set {} to choose one",
+                        bootstrap::DATA_DIR_ENV
+                    )
+                })?;
+                match command {
+                    StoreCommand::Dir => println!("{}", dir.display()),
+                    StoreCommand::Gc => {
+                        let size = store::gc(&dir)?;
+                        report(
+                            porcelain,
+                            &["removed", &size.files.to_string(), &size.bytes.to_string()],
+                            format_args!(
+                                "removed {} files, {}",
+                                size.files,
+                                output::format_size(size.bytes)
+                            ),
+                        )?;
+                    }
+                }
+            }
+            Command::Adopt { path } => {
+                let addon = progress::spin(format!("adopting '{path}'"), || {
+                    adopt::adopt(&config, &path)
+                })
+                .with_context(|| format!("while adopting '{path}'"))?;
+                report(
+                    porcelain,
+                    &["adopted", &path, &addon.name, &addon.version],
+                    format_args!("adopted '{path}' as {} {}", addon.name, addon.version),
+                )?;
+            }
+            Command::Migrate { from } => {
+                let from = match from {
+                    Some(from) => from,
+                    None => migrate::addons_dir(Editor::Auto)?,
+                };
+                let migration = migrate::find(settings, &from)?;
+                if migration.entries.is_empty() {
+                    log::info!("no library entries of the addon manager were found");
+                }
+                let mut failed = 0;
+                for (done, entry) in migration.entries.iter().enumerate() {
+                    events::progress("migrating entries", done, migration.entries.len());
+                    let adopted = progress::spin(format!("migrating '{entry}'"), || {
+                        adopt::adopt(&config, entry)
+                    });
+                    match adopted {
+                        Ok(addon) => report(
+                            porcelain,
+                            &["migrated", entry, &addon.name, &addon.version],
+                            format_args!("migrated '{entry}' to {} {}", addon.name, addon.version),
+                        )?,
+                        Err(err) => {
+                            log::error!("could not migrate '{entry}': {err:#}");
+                            failed += 1;
+                        }
+                    }
+                }
+                if !migration.unused.is_empty() && io::stderr().is_terminal() {
+                    eprintln!(
+                        "the addon manager also has {}, which this workspace doesn't use",
+                        migration.unused.join(", ")
+                    );
+                }
+                if failed > 0 {
+                    bail!(
+                        "{failed} of {} entries could not be migrated",
+                        migration.entries.len()
+                    );
+                }
+            }
+            Command::Autoremove { dry_run } => {
+                let orphans = progress::spin("reading dependencies", || {
+                    deps::autoremove_candidates(&config)
+                })
+                .context("while looking for unneeded addons")?;
+                if orphans.is_empty() {
+                    log::info!("no addons to remove");
+                } else if !dry_run && asks_first(assume_yes) {
+                    for name in &orphans {
+                        eprintln!("  {name}");
+                    }
+                    if !pick::confirm(&format!("Remove {} unneeded addons?", orphans.len()))? {
+                        log::info!("left the unneeded addons installed");
+                        return Ok(());
+                    }
+                }
+                for name in orphans {
+                    if dry_run {
+                        report(
+                            porcelain,
+                            &["would-remove", &name],
+                            format_args!("would remove '{name}'"),
+                        )?;
+                        continue;
+                    }
+                    remove_addon(&config, &name, None, porcelain, quiet)?;
+                }
+            }
+            Command::Undo => {
+                let undone = progress::spin("undoing the last change", || history::undo(&config))
+                    .context("while undoing the last change")?;
+                match undone {
+                    Some(entry) => report(
+                        porcelain,
+                        &["undid", entry.operation.verb(), entry.operation.name()],
+                        format_args!(
+                            "undid {} '{}'",
+                            entry.operation.verb(),
+                            entry.operation.name()
+                        ),
+                    )?,
+                    None => log::info!("nothing to undo"),
+                }
+            }
+            Command::History => {
+                let entries = history::load(tree).context("while reading the history")?;
+                if porcelain {
+                    output::porcelain_history(&mut io::stdout(), &entries)?;
+                } else {
+                    let mut stdout =
+                        StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                    output::print_history(&mut stdout, &entries)?;
+                }
+            }
+            Command::Deps { name } => {
+                let tree = progress::spin(format!("reading the dependencies of '{name}'"), || {
+                    deps::dependency_tree(&config, &name)
+                })?;
+                if porcelain {
+                    output::porcelain_dependency_tree(&mut io::stdout(), &tree)?;
+                } else {
+                    let mut stdout =
+                        StandardStream::stdout(color.choice(io::stdout().is_terminal()));
+                    output::print_dependency_tree(&mut stdout, &tree)?;
+                }
+            }
+            Command::Serve => serve::serve(&config)?,
+            Command::SelfUpdate { check } => {
+                let current = env!("CARGO_PKG_VERSION");
+                let latest =
+                    progress::spin("checking for a new release", || release::latest(&config))?;
+                if !latest.is_newer_than(current) {
+                    return report(
+                        porcelain,
+                        &["up-to-date", current],
+                        format_args!("llynx {current} is up to date"),
+                    );
+                }
+                if check {
+                    return report(
+                        porcelain,
+                        &["available", latest.version()],
+                        format_args!("llynx {} is out, this is {current}", latest.version()),
+                    );
+                }
+                let executable =
+                    env::current_exe().context("while finding the running executable")?;
+                progress::spin(format!("downloading llynx {}", latest.version()), || {
+                    release::install(&config, &latest, &executable)
+                })?;
+                report(
+                    porcelain,
+                    &["updated", current, latest.version()],
+                    format_args!("updated llynx {current} -> {}", latest.version()),
+                )?;
             }
-            Command::Enable { name } => enable(tree, luarocks, settings, &name)?,
-            Command::Disable { name } => disable(tree, luarocks, settings, &name)?,
         },
     };
 
     Ok(())
 }
 
-fn main() -> Result<()> {
+/// the exit status llynx fails with for `err`. Usage errors exit with 2,
+/// which clap does itself
+fn exit_code(err: &anyhow::Error) -> i32 {
+    match Failure::of(err) {
+        Some(Failure::NotFound) => 3,
+        Some(Failure::LuaRocks) => 4,
+        Some(Failure::Settings) => 5,
+        None => 1,
+    }
+}
+
+fn main() {
     let cli = Cli::parse();
+    let log_format = cli.log_format;
+    let result = run(cli);
+    events::emit(events::Event::Done {
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(|err| format!("{err:#}")),
+    });
+    if let Err(err) = result {
+        // tools reading JSON logs get the error as one more record, once the
+        // logger is there to write it
+        if log_format == LogFormat::Json && log::max_level() != LevelFilter::Off {
+            log::error!("{err:#}");
+        } else {
+            eprintln!("Error: {err:?}");
+        }
+        process::exit(exit_code(&err));
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    ctrlc::set_handler(interrupt::on_signal).context("while handling Ctrl-C")?;
+    // events take the place of spinners
+    if cli.notify.is_some() {
+        events::enable();
+        progress::hide();
+    }
 
     // config should be calculated like this:
     // (CLI args) overrides (Config args) overrides (defaults)
     let default_config = Config::default();
-    let file_overrides: Option<MaybeConfig> =
-        get_file_overrides(cli.config.as_ref().map(String::as_str))?;
-    let cli_overrides = MaybeConfig {
+    // secrets belong to the user, so they only come from the user config
+    let user_overrides = config::get_user_overrides()?;
+    let mut file_overrides: Option<MaybeConfig> =
+        config::get_file_overrides(cli.config.as_deref())?;
+    let mut cli_overrides = MaybeConfig {
         schema: None,
         luarocks: cli.luarocks,
         tree: cli.tree,
         settings: cli.settings,
-        server: cli.server,
+        user_settings: cli.user_settings,
+        server: cli
+            .server
+            .or_else(|| cli.server_manifest.as_deref().map(namespace::manifest_url)),
+        servers: None,
         verbose: match cli.verbose {
             0 => None,
             _ => Some(cli.verbose),
         },
+        deps_mode: cli.deps_mode,
+        lua_version: cli.lua_version,
+        // only given after `--` to the commands that run LuaRocks
+        luarocks_args: None,
+        luarocks_config: cli.luarocks_config,
+        conflicts: cli.conflicts,
+        path_style: cli.path_style,
+        path_base: cli.path_base,
+        enable_mode: cli.enable_mode,
+        public_key: cli.public_key,
+        retries: cli.retries,
+        proxy: cli.proxy,
+        ca_bundle: cli.ca_bundle,
+        insecure: cli.insecure.then_some(true),
+        timeout: cli.timeout,
+        // credentials don't belong on the command line
+        auth: None,
+        update_check: cli.update_check,
+        log_file: cli.log_file,
+        assume_yes: cli.yes.then_some(true),
+        // commands to run only come from config files
+        hooks: None,
+        gitignore_tree: None,
+        // secrets don't belong on the command line either
+        api_key: None,
+        // pins are kept with the project, and `install --unpin` ignores them
+        pins: None,
+        // the layout of the repository belongs with it
+        folders: None,
+        store: cli.store.then_some(true),
+        fallback_to_root: None,
     };
+    // a settings file given on the command line wins over user settings
+    // picked in the config file, which may not even exist here
+    if let Some(overrides) = file_overrides
+        .as_mut()
+        .filter(|_| cli_overrides.settings.is_none())
+    {
+        overrides.resolve_user_settings()?;
+    }
+    cli_overrides.resolve_user_settings()?;
 
-    let config = match file_overrides {
+    let user_config = match user_overrides {
         Some(ref overrides) => default_config.extend(overrides),
         None => default_config,
+    };
+    let config = match file_overrides {
+        Some(ref overrides) => user_config.extend(overrides),
+        None => user_config,
     }
     .extend(&cli_overrides);
+    // credentials from the environment are for the configured server, so
+    // they're settled before any command points somewhere else
+    let auth = network::auth(&config);
+    let config = Config {
+        frozen: cli.frozen,
+        auth: Some(&auth),
+        // deleting the tree takes `-y` itself, a config file checked in with
+        // the project mustn't be enough
+        assume_yes: if matches!(cli.command, Some(Command::Clean { cache: false, .. })) {
+            cli.yes
+        } else {
+            config.assume_yes
+        },
+        ..config
+    };
 
-    run_command(cli.command, config)?;
-
-    Ok(())
+    let notify = !cli.quiet
+        && config.update_check.interval().is_some()
+        && checks_for_updates(cli.command.as_ref())
+        && io::stderr().is_terminal();
+    // there's only one version of the porcelain format so far
+    let porcelain = cli.porcelain.is_some();
+    let fresh_tree = !config.tree.exists();
+    let result = match cli.command {
+        Some(command) if !config.folders.is_empty() && spans_folders(&command) => run_in_folders(
+            command,
+            config,
+            cli.color,
+            cli.log_format,
+            cli.bootstrap,
+            porcelain,
+            cli.quiet,
+        ),
+        command => run_command(
+            command,
+            config,
+            cli.color,
+            cli.log_format,
+            cli.bootstrap,
+            porcelain,
+            cli.quiet,
+        ),
+    };
+    if interrupt::interrupted() {
+        eprintln!("interrupted");
+        process::exit(interrupt::EXIT_CODE);
+    }
+    // the first install creates the tree, which isn't meant to be committed
+    if result.is_ok()
+        && fresh_tree
+        && config.gitignore_tree
+        && !config.frozen
+        && config.tree.exists()
+    {
+        gitignore::ignore_tree(Path::new("."), config.tree)?;
+    }
+    // found by an earlier run, so this one never waits on the server
+    if notify
+        && result.is_ok()
+        && let Some(newer) = release::cached_newer(env!("CARGO_PKG_VERSION"))
+    {
+        eprintln!("llynx {newer} is out, run `llynx self-update` to update");
+    }
+    result
 }
 
 #[cfg(all(test, windows))]
@@ -323,15 +2476,18 @@ static SAY_ADDON_LOCATION: &str = "tests/trees/one_addon/lib/luarocks/rocks-5.1/
 static SAY_ADDON: LazyLock<Addon, fn() -> Addon> = LazyLock::new(|| Addon {
     name: String::from("say"),
     version: String::from("1.4.1-3"),
-    location: Some(String::from(SAY_ADDON_LOCATION)),
+    location: Some(PathBuf::from(SAY_ADDON_LOCATION)),
 });
 
+/// an online addon, for tests
 #[cfg(test)]
-static ONLINE_SAY_ADDON: LazyLock<Addon, fn() -> Addon> = LazyLock::new(|| Addon {
-    name: String::from("say"),
-    version: String::from("1.4.1-3"),
-    location: None,
-});
+fn addon(name: &str, version: &str) -> Addon {
+    Addon {
+        name: String::from(name),
+        version: String::from(version),
+        location: None,
+    }
+}
 
 #[cfg(test)]
 mod test_config {
@@ -339,7 +2495,7 @@ mod test_config {
 
     #[test]
     fn empty() {
-        let empty_config = get_cli_config_file_overrides("tests/configs/empty.toml").unwrap();
+        let empty_config = config::from_file(Path::new("tests/configs/empty.toml")).unwrap();
         assert_eq!(empty_config.schema, None);
         assert_eq!(empty_config.luarocks, None);
         assert_eq!(empty_config.server, None);
@@ -351,7 +2507,7 @@ mod test_config {
     #[test]
     fn schema() {
         let schema_config =
-            get_cli_config_file_overrides("tests/configs/empty_schema.toml").unwrap();
+            config::from_file(Path::new("tests/configs/empty_schema.toml")).unwrap();
         assert_eq!(schema_config.schema, Some(String::from("")));
         assert_eq!(schema_config.luarocks, None);
         assert_eq!(schema_config.server, None);
@@ -362,10 +2518,10 @@ mod test_config {
 
     #[test]
     fn some_args() {
-        let some_config = get_cli_config_file_overrides("tests/configs/some_args.toml").unwrap();
+        let some_config = config::from_file(Path::new("tests/configs/some_args.toml")).unwrap();
         assert_eq!(some_config.schema, Some(String::from("some_schema")));
-        assert_eq!(some_config.luarocks, Some(String::from("some_luarocks")));
-        assert_eq!(some_config.tree, Some(String::from("some_tree")));
+        assert_eq!(some_config.luarocks, Some(PathBuf::from("some_luarocks")));
+        assert_eq!(some_config.tree, Some(PathBuf::from("some_tree")));
         assert_eq!(some_config.server, None);
         assert_eq!(some_config.settings, None);
         assert_eq!(some_config.verbose, None);
@@ -373,18 +2529,91 @@ mod test_config {
 
     #[test]
     fn all_args() {
-        let all_config = get_cli_config_file_overrides("tests/configs/all_args.toml").unwrap();
+        let all_config = config::from_file(Path::new("tests/configs/all_args.toml")).unwrap();
         assert_eq!(all_config.schema, Some(String::from("some_schema")));
-        assert_eq!(all_config.luarocks, Some(String::from("some_luarocks")));
+        assert_eq!(all_config.luarocks, Some(PathBuf::from("some_luarocks")));
         assert_eq!(all_config.server, Some(String::from("some_server")));
-        assert_eq!(all_config.settings, Some(String::from("some_settings")));
-        assert_eq!(all_config.tree, Some(String::from("some_tree")));
+        assert_eq!(all_config.settings, Some(PathBuf::from("some_settings")));
+        assert_eq!(all_config.user_settings, Some(Editor::Codium));
+        assert_eq!(all_config.tree, Some(PathBuf::from("some_tree")));
         assert_eq!(all_config.verbose, Some(8));
+        assert_eq!(all_config.deps_mode, Some(DepsMode::None));
+        assert_eq!(all_config.lua_version, Some(String::from("5.4")));
+        assert_eq!(
+            all_config.luarocks_args,
+            Some(vec![String::from("--force")])
+        );
+        assert_eq!(
+            all_config.luarocks_config,
+            Some(PathBuf::from("some_luarocks_config"))
+        );
+        assert_eq!(
+            all_config.conflicts,
+            Some(addon_settings::ConflictPolicy::Error)
+        );
+        assert_eq!(all_config.path_style, Some(enabled::PathStyle::Native));
+        assert_eq!(
+            all_config.path_base,
+            Some(enabled::PathBase::WorkspaceFolder)
+        );
+        assert_eq!(all_config.enable_mode, Some(enabled::EnableMode::Link));
+        assert_eq!(all_config.public_key, Some(String::from("some_key")));
+        assert_eq!(all_config.retries, Some(5));
+        assert_eq!(all_config.proxy, Some(String::from("some_proxy")));
+        assert_eq!(all_config.ca_bundle, Some(PathBuf::from("some_bundle")));
+        assert_eq!(all_config.insecure, Some(true));
+        assert_eq!(all_config.timeout, Some(30));
+        assert_eq!(all_config.auth, None);
+        assert_eq!(all_config.update_check, Some(release::UpdateCheck::Weekly));
+        assert_eq!(all_config.log_file, Some(PathBuf::from("some_log")));
+        assert_eq!(all_config.assume_yes, Some(true));
+        assert_eq!(all_config.gitignore_tree, Some(false));
+        assert_eq!(all_config.api_key, None);
+        assert_eq!(
+            all_config.pins.and_then(|pins| pins.get("say").cloned()),
+            Some(String::from("1.4.1-3"))
+        );
+        assert_eq!(all_config.folders, Some(vec![PathBuf::from("some_folder")]));
+        assert_eq!(all_config.store, Some(true));
+        assert_eq!(all_config.fallback_to_root, Some(true));
+        assert_eq!(all_config.servers, Some(vec![String::from("some_mirror")]));
+        assert_eq!(
+            all_config.hooks.and_then(|hooks| hooks.post_enable),
+            Some(String::from("some_hook"))
+        );
+    }
+
+    #[test]
+    fn settings_and_user_settings() {
+        let mut all_config = config::from_file(Path::new("tests/configs/all_args.toml")).unwrap();
+        all_config.resolve_user_settings().unwrap_err();
+    }
+
+    #[test]
+    fn secrets_only_from_the_user() {
+        for secret in ["auth", "api_key"] {
+            let path = format!("tests/configs/{secret}.toml");
+            let err = config::from_file(Path::new(&path)).unwrap_err();
+            assert_eq!(Failure::of(&err), Some(Failure::Settings));
+            assert!(err.to_string().contains(secret));
+        }
+        let user_config = config::from_user_file(Path::new("tests/configs/user.toml"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(user_config.auth.map(|auth| auth.len()), Some(1));
+        assert_eq!(user_config.api_key, Some(String::from("some_api_key")));
+        assert!(
+            config::from_user_file(Path::new("tests/configs/missing.toml"))
+                .unwrap()
+                .is_none()
+        );
+        config::from_user_file(Path::new("tests/configs/some_args.toml")).unwrap_err();
     }
 
     #[test]
     fn test_illegal() {
-        get_cli_config_file_overrides("tests/configs/illegal.toml").unwrap_err();
+        config::from_file(Path::new("tests/configs/illegal.toml")).unwrap_err();
+        config::from_file(Path::new("tests/configs/illegal_deps_mode.toml")).unwrap_err();
     }
 }
 
@@ -395,50 +2624,109 @@ mod test_config_extend {
     #[test]
     fn default() {
         let default_config = Config::default();
-        assert_eq!(default_config.luarocks, LUAROCKS_PATH);
-        assert_eq!(default_config.server, LUAROCKS_ENDPOINT);
-        assert_eq!(default_config.settings, SETTINGS_FILE);
-        assert_eq!(default_config.tree, ADDONS_DIR);
+        assert_eq!(default_config.luarocks, config::LUAROCKS_PATH);
+        assert_eq!(default_config.server, config::LUAROCKS_ENDPOINT);
+        assert_eq!(default_config.settings, config::SETTINGS_FILE);
+        assert_eq!(default_config.tree, config::ADDONS_DIR);
         assert_eq!(default_config.verbose, 0);
     }
 
     #[test]
     fn extend_some_args() {
-        let some_config = get_cli_config_file_overrides("tests/configs/some_args.toml").unwrap();
+        let some_config = config::from_file(Path::new("tests/configs/some_args.toml")).unwrap();
         let config = Config::default().extend(&some_config);
-        assert_eq!(config.luarocks, String::from("some_luarocks"));
-        assert_eq!(config.tree, String::from("some_tree"));
-        assert_eq!(config.server, LUAROCKS_ENDPOINT);
-        assert_eq!(config.settings, SETTINGS_FILE);
+        assert_eq!(config.luarocks, Path::new("some_luarocks"));
+        assert_eq!(config.tree, Path::new("some_tree"));
+        assert_eq!(config.server, config::LUAROCKS_ENDPOINT);
+        assert_eq!(config.settings, config::SETTINGS_FILE);
         assert_eq!(config.verbose, 0);
     }
 
     #[test]
     fn extend_all_args() {
-        let all_config = get_cli_config_file_overrides("tests/configs/all_args.toml").unwrap();
-        let config = Config::default().extend(&all_config);
-        assert_eq!(config.luarocks, String::from("some_luarocks"));
+        let all_config = config::from_file(Path::new("tests/configs/all_args.toml")).unwrap();
+        let user_config = config::from_user_file(Path::new("tests/configs/user.toml"))
+            .unwrap()
+            .unwrap();
+        let config = Config::default().extend(&user_config).extend(&all_config);
+        assert_eq!(config.luarocks, Path::new("some_luarocks"));
         assert_eq!(config.server, String::from("some_server"));
-        assert_eq!(config.settings, String::from("some_settings"));
-        assert_eq!(config.tree, String::from("some_tree"));
+        assert_eq!(config.settings, Path::new("some_settings"));
+        assert_eq!(config.tree, Path::new("some_tree"));
         assert_eq!(config.verbose, 8);
+        assert_eq!(config.deps_mode, Some(DepsMode::None));
+        assert_eq!(config.lua_version, Some("5.4"));
+        assert_eq!(config.luarocks_args, ["--force"]);
+        assert_eq!(
+            config.luarocks_config,
+            Some(Path::new("some_luarocks_config"))
+        );
+        assert_eq!(config.conflicts, addon_settings::ConflictPolicy::Error);
+        assert_eq!(config.path_style, enabled::PathStyle::Native);
+        assert_eq!(config.path_base, enabled::PathBase::WorkspaceFolder);
+        assert_eq!(config.enable_mode, enabled::EnableMode::Link);
+        assert_eq!(config.public_key, Some("some_key"));
+        assert_eq!(config.retries, 5);
+        assert_eq!(config.proxy, Some("some_proxy"));
+        assert_eq!(config.ca_bundle, Some(Path::new("some_bundle")));
+        assert!(config.insecure);
+        assert_eq!(config.timeout, Some(30));
+        assert!(
+            config
+                .auth
+                .is_some_and(|auth| auth.contains_key("some_server"))
+        );
+        assert_eq!(config.update_check, release::UpdateCheck::Weekly);
+        assert_eq!(config.log_file, Some(Path::new("some_log")));
+        assert!(config.assume_yes);
+        assert!(!config.gitignore_tree);
+        assert_eq!(config.api_key, Some("some_api_key"));
+        assert!(config.pins.is_some_and(|pins| pins["say"] == "1.4.1-3"));
+        assert_eq!(config.folders, [PathBuf::from("some_folder")]);
+        assert!(config.store);
+        assert!(config.fallback_to_root);
+        assert_eq!(
+            config
+                .hooks
+                .and_then(|hooks| hooks.command(llynx_core::hooks::Event::PostEnable)),
+            Some("some_hook")
+        );
     }
 }
 
 #[cfg(test)]
 mod test_list_online {
     use super::*;
+    use llynx_core::backend::{self, Mock};
+    use std::sync::Arc;
 
     #[test]
     fn one_addon() {
-        let addons =
-            list_online("file://./tests/servers/one_addon", "luarocks", Some("say")).unwrap();
-        assert_eq!(addons, vec![ONLINE_SAY_ADDON.clone()]);
+        let mock = Mock::new(
+            vec![],
+            vec![addon("say", "1.4.1-3"), addon("busted", "1.4.1-3")],
+        );
+        let addons = backend::with_backend(Arc::new(mock), || {
+            let config = Config {
+                server: "file://./tests/servers/one_addon",
+                ..Config::default()
+            };
+            list_online(&config, Some(&"say".into()))
+        })
+        .unwrap();
+        assert_eq!(addons, vec![addon("say", "1.4.1-3")]);
     }
 
     #[test]
     fn empty() {
-        let addons = list_online("file://./tests/servers/empty", "luarocks", Some("say")).unwrap();
+        let addons = backend::with_backend(Arc::new(Mock::default()), || {
+            let config = Config {
+                server: "file://./tests/servers/empty",
+                ..Config::default()
+            };
+            list_online(&config, Some(&"say".into()))
+        })
+        .unwrap();
         assert_eq!(addons, vec![]);
     }
 }
@@ -446,10 +2734,20 @@ mod test_list_online {
 #[cfg(test)]
 mod test_list_installed {
     use super::*;
+    use llynx_core::backend::{self, Mock};
+    use std::sync::Arc;
 
     #[test]
     fn one_addon() {
-        let addons = list_installed("tests/trees/one_addon", "luarocks", None).unwrap();
+        let mock = Mock::new(vec![SAY_ADDON.clone()], vec![]);
+        let addons = backend::with_backend(Arc::new(mock), || {
+            let config = Config {
+                tree: Path::new("tests/trees/one_addon"),
+                ..Config::default()
+            };
+            list_installed(&config, None)
+        })
+        .unwrap();
         assert_eq!(addons, vec![SAY_ADDON.clone()]);
     }
 }
@@ -458,39 +2756,35 @@ mod test_list_installed {
 mod test_list_enabled {
     use super::*;
 
+    fn config(settings: &str) -> Config<'_> {
+        Config {
+            tree: Path::new("tests/trees/one_addon"),
+            settings: Path::new(settings),
+            ..Config::default()
+        }
+    }
+
     #[test]
     fn not_found() {
-        let addons =
-            list_enabled("tests/trees/one_addon", "tests/settings/fake.json", None).unwrap();
+        let addons = list_enabled(&config("tests/settings/fake.json"), None).unwrap();
         assert_eq!(addons, vec![]);
     }
 
     #[test]
     fn empty() {
-        let addons =
-            list_enabled("tests/trees/one_addon", "tests/settings/empty.json", None).unwrap();
+        let addons = list_enabled(&config("tests/settings/empty.json"), None).unwrap();
         assert_eq!(addons, vec![]);
     }
 
     #[test]
     fn no_library() {
-        let addons = list_enabled(
-            "tests/trees/one_addon",
-            "tests/settings/no_library.json",
-            None,
-        )
-        .unwrap();
+        let addons = list_enabled(&config("tests/settings/no_library.json"), None).unwrap();
         assert_eq!(addons, vec![]);
     }
 
     #[test]
     fn empty_library() {
-        let addons = list_enabled(
-            "tests/trees/one_addon",
-            "tests/settings/no_library.json",
-            None,
-        )
-        .unwrap();
+        let addons = list_enabled(&config("tests/settings/no_library.json"), None).unwrap();
         assert_eq!(addons, vec![]);
     }
 
@@ -501,7 +2795,7 @@ mod test_list_enabled {
 
     #[test]
     fn one_addon() {
-        let addons = list_enabled("tests/trees/one_addon", ONE_ADDON_PATH, None).unwrap();
+        let addons = list_enabled(&config(ONE_ADDON_PATH), None).unwrap();
         assert_eq!(addons, vec![SAY_ADDON.clone()])
     }
 }
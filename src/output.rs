@@ -0,0 +1,1595 @@
+use clap::ValueEnum;
+use llynx_core::{
+    Addon,
+    addon_settings::Conflict,
+    cache::CacheSize,
+    deps::DependencyNode,
+    disk_usage::AddonUsage,
+    history::{Entry, LibraryChange},
+    licenses::AddonLicense,
+    rockspec::Description,
+    status::AddonStatus,
+    validate::Check,
+    version::{self, Version},
+};
+use std::{
+    borrow::Cow, cmp::Reverse, collections::BTreeMap, env, fs, io, path::Path, time::SystemTime,
+};
+use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
+
+use crate::template::Template;
+
+/// when to color output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorWhen {
+    /// Color output when writing to a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+
+    /// Always color output
+    Always,
+
+    /// Never color output
+    Never,
+}
+
+impl ColorWhen {
+    /// decide how to color a stream, given whether it is a terminal
+    pub fn choice(self, is_terminal: bool) -> ColorChoice {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        match self {
+            ColorWhen::Always => ColorChoice::Always,
+            ColorWhen::Never => ColorChoice::Never,
+            ColorWhen::Auto if !is_terminal || no_color => ColorChoice::Never,
+            ColorWhen::Auto => ColorChoice::Auto,
+        }
+    }
+}
+
+fn name_spec() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(Color::Cyan)).set_bold(true);
+    spec
+}
+
+fn version_spec() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(Color::Green));
+    spec
+}
+
+fn write_styled(out: &mut impl WriteColor, spec: &ColorSpec, text: &str) -> io::Result<()> {
+    out.set_color(spec)?;
+    write!(out, "{text}")?;
+    out.reset()
+}
+
+fn enabled_spec() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(Color::Yellow));
+    spec
+}
+
+/// how `list` prints addons
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// Each name followed by its versions, indented
+    #[default]
+    Grouped,
+
+    /// Aligned columns of name, version, enabled status, and location
+    Table,
+}
+
+fn summary_spec() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_dimmed(true);
+    spec
+}
+
+/// short descriptions of addons, by name
+pub type Summaries = BTreeMap<String, String>;
+
+fn write_summary(out: &mut impl WriteColor, summaries: &Summaries, name: &str) -> io::Result<()> {
+    if let Some(summary) = summaries.get(name) {
+        write!(out, "  ")?;
+        write_styled(out, &summary_spec(), summary)?;
+    }
+    Ok(())
+}
+
+/// how `list` orders addons
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Alphabetically by name
+    #[default]
+    Name,
+
+    /// Addons with the newest versions first
+    Version,
+
+    /// Most recently installed first. LuaRocks reports no dates for online
+    /// addons, so those are sorted by version instead
+    Recent,
+}
+
+/// when an installed addon's version directory was last changed
+fn modified(addon: &Addon) -> Option<SystemTime> {
+    let location = addon.location.as_deref()?;
+    fs::metadata(location.parent()?).ok()?.modified().ok()
+}
+
+/// sort addons by `key`, keeping versions of the same addon together and
+/// oldest first
+pub fn sort_addons(addons: &mut Vec<Addon>, key: SortKey, reverse: bool) {
+    let mut groups: BTreeMap<String, Vec<Addon>> = BTreeMap::new();
+    for addon in addons.drain(..) {
+        groups.entry(addon.name.clone()).or_default().push(addon);
+    }
+    let mut groups: Vec<Vec<Addon>> = groups
+        .into_values()
+        .map(|mut group| {
+            group.sort_by(|a, b| version::compare(&a.version, &b.version));
+            group
+        })
+        .collect();
+
+    // groups are already sorted by name, which breaks ties below
+    let newest = |group: &[Addon]| group.last().map(|addon| Version::parse(&addon.version));
+    match key {
+        SortKey::Name => {}
+        SortKey::Version => groups.sort_by_key(|group| Reverse(newest(group))),
+        SortKey::Recent => groups.sort_by_cached_key(|group| {
+            let modified = group.iter().filter_map(modified).max();
+            Reverse((modified, newest(group)))
+        }),
+    }
+
+    addons.extend(groups.into_iter().flatten());
+    if reverse {
+        addons.reverse();
+    }
+}
+
+/// sort statuses by `key`. Statuses carry no dates, so [`SortKey::Recent`]
+/// sorts by version
+pub fn sort_statuses(statuses: &mut [AddonStatus], key: SortKey, reverse: bool) {
+    statuses.sort_by(|a, b| a.name.cmp(&b.name));
+    if key != SortKey::Name {
+        statuses.sort_by_cached_key(|status| {
+            let version = status.latest.as_deref().or(status.newest_installed());
+            Reverse(version.map(Version::parse))
+        });
+    }
+    if reverse {
+        statuses.reverse();
+    }
+}
+
+/// the summaries of `descriptions`, followed by their labels when `labels` is
+/// set
+pub fn summaries(descriptions: BTreeMap<String, Description>, labels: bool) -> Summaries {
+    descriptions
+        .into_iter()
+        .filter_map(|(name, description)| {
+            let tags = format!("[{}]", description.labels.join(", "));
+            let summary = match (
+                description.summary,
+                labels && !description.labels.is_empty(),
+            ) {
+                (summary, false) => summary?,
+                (Some(summary), true) => format!("{summary} {tags}"),
+                (None, true) => tags,
+            };
+            Some((name, summary))
+        })
+        .collect()
+}
+
+/// the `page`th run of `limit` names in `items`, starting at 1, or every item
+/// without a limit. Items of the same name count once and stay on one page
+pub fn page<T>(
+    items: Vec<T>,
+    name: impl Fn(&T) -> &str,
+    limit: Option<u64>,
+    page: Option<u64>,
+) -> Vec<T> {
+    let Some(limit) = limit else {
+        return items;
+    };
+    let first = (page.unwrap_or(1) - 1).saturating_mul(limit);
+    let mut names = 0;
+    let mut last: Option<String> = None;
+    items
+        .into_iter()
+        .filter(|item| {
+            if last.as_deref() != Some(name(item)) {
+                last = Some(name(item).to_string());
+                names += 1;
+            }
+            (first..first.saturating_add(limit)).contains(&(names - 1))
+        })
+        .collect()
+}
+
+/// print each addon name and summary followed by its versions, indented.
+/// Addons are printed in the order given, so versions should be next to each
+/// other
+pub fn print_addons_list(
+    out: &mut impl WriteColor,
+    addons: Vec<Addon>,
+    summaries: &Summaries,
+) -> io::Result<()> {
+    if addons.is_empty() {
+        log::error!("no addons found matching criteria");
+        return Ok(());
+    }
+    let mut last_name: Option<&str> = None;
+    for addon in &addons {
+        if last_name != Some(&addon.name) {
+            if last_name.is_some() {
+                writeln!(out)?;
+            }
+            last_name = Some(&addon.name);
+            write_styled(out, &name_spec(), &addon.name)?;
+            write_summary(out, summaries, &addon.name)?;
+            writeln!(out)?;
+        }
+        write!(out, "\t")?;
+        write_styled(out, &version_spec(), &addon.version)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// spaces between table columns
+const GAP: usize = 2;
+
+/// shorten `text` to `width` characters, replacing its beginning with "..."
+fn truncate_start(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+    if width <= 3 {
+        return ".".repeat(width);
+    }
+    let kept: String = text.chars().skip(len - (width - 3)).collect();
+    format!("...{kept}")
+}
+
+/// print addons as aligned columns, shortening locations to fit in
+/// `max_width` when given. Summaries get their own column when there are any
+pub fn print_addons_table(
+    out: &mut impl WriteColor,
+    addons: Vec<Addon>,
+    enabled: &[Addon],
+    summaries: &Summaries,
+    max_width: Option<usize>,
+) -> io::Result<()> {
+    if addons.is_empty() {
+        log::error!("no addons found matching criteria");
+        return Ok(());
+    }
+    let mut headers = vec!["NAME", "VERSION", "ENABLED"];
+    let mut specs = vec![name_spec(), version_spec(), enabled_spec()];
+    if !summaries.is_empty() {
+        headers.push("SUMMARY");
+        specs.push(summary_spec());
+    }
+    headers.push("LOCATION");
+    specs.push(ColorSpec::new());
+    let last = headers.len() - 1;
+
+    let rows: Vec<Vec<Cow<str>>> = addons
+        .iter()
+        .map(|addon| {
+            let is_enabled = enabled
+                .iter()
+                .any(|other| other.name == addon.name && other.version == addon.version);
+            let mut row = vec![
+                Cow::from(addon.name.as_str()),
+                Cow::from(addon.version.as_str()),
+                Cow::from(if is_enabled { "yes" } else { "no" }),
+            ];
+            if !summaries.is_empty() {
+                row.push(Cow::from(
+                    summaries.get(&addon.name).map_or("-", String::as_str),
+                ));
+            }
+            row.push(
+                addon
+                    .location
+                    .as_deref()
+                    .map_or(Cow::from("-"), Path::to_string_lossy),
+            );
+            row
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    if let Some(max_width) = max_width {
+        let fixed: usize = widths[..last].iter().map(|width| width + GAP).sum();
+        widths[last] = widths[last].min(max_width.saturating_sub(fixed).max(headers[last].len()));
+    }
+
+    for (i, header) in headers.iter().enumerate() {
+        write_cell(out, &ColorSpec::new(), header, widths[i], i == last)?;
+    }
+    writeln!(out)?;
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            let text = truncate_start(cell, widths[i]);
+            let spec = match (i, cell.as_ref()) {
+                (2, "no") => ColorSpec::new(),
+                _ => specs[i].clone(),
+            };
+            write_cell(out, &spec, &text, widths[i], i == last)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_cell(
+    out: &mut impl WriteColor,
+    spec: &ColorSpec,
+    text: &str,
+    width: usize,
+    last: bool,
+) -> io::Result<()> {
+    write_styled(out, spec, text)?;
+    if !last {
+        let padding = width - text.chars().count() + GAP;
+        write!(out, "{:padding$}", "")?;
+    }
+    Ok(())
+}
+
+fn marker_spec(color: Color) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(color)).set_bold(true);
+    spec
+}
+
+/// print one line per addon with markers for whether it's installed, enabled,
+/// or has an update available
+pub fn print_status_list(
+    out: &mut impl WriteColor,
+    statuses: &[AddonStatus],
+    summaries: &Summaries,
+) -> io::Result<()> {
+    if statuses.is_empty() {
+        log::error!("no addons found matching criteria");
+        return Ok(());
+    }
+
+    let shown_version = |status: &AddonStatus| -> String {
+        status
+            .enabled
+            .as_deref()
+            .or(status.newest_installed())
+            .or(status.latest.as_deref())
+            .unwrap_or("-")
+            .to_string()
+    };
+    let name_width = statuses.iter().map(|s| s.name.chars().count()).max();
+    let version_width = statuses
+        .iter()
+        .map(|s| shown_version(s).chars().count())
+        .max();
+
+    for status in statuses {
+        write_cell(
+            out,
+            &name_spec(),
+            &status.name,
+            name_width.unwrap_or(0),
+            false,
+        )?;
+        let version = shown_version(status);
+        write_cell(
+            out,
+            &version_spec(),
+            &version,
+            version_width.unwrap_or(0),
+            false,
+        )?;
+
+        let mut markers: Vec<(String, ColorSpec)> = Vec::new();
+        if !status.installed.is_empty() {
+            markers.push((String::from("[installed]"), marker_spec(Color::Blue)));
+        }
+        if status.enabled.is_some() {
+            markers.push((String::from("[enabled]"), enabled_spec()));
+        }
+        if status.update_available() {
+            let latest = status.latest.as_deref().unwrap_or_default();
+            let marker = format!("[update available: {latest}]");
+            markers.push((marker, marker_spec(Color::Magenta)));
+        }
+        if status.installed.is_empty() && status.enabled.is_none() {
+            markers.push((String::from("[online]"), ColorSpec::new()));
+        }
+
+        for (i, (marker, spec)) in markers.iter().enumerate() {
+            if i > 0 {
+                write!(out, " ")?;
+            }
+            write_styled(out, spec, marker)?;
+        }
+        write_summary(out, summaries, &status.name)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// print an addon and its dependencies as a tree, marking which are installed
+/// and enabled
+pub fn print_dependency_tree(out: &mut impl WriteColor, root: &DependencyNode) -> io::Result<()> {
+    fn write_node(out: &mut impl WriteColor, node: &DependencyNode) -> io::Result<()> {
+        write_styled(out, &name_spec(), &node.name)?;
+        if let Some(constraint) = &node.constraint {
+            write!(out, " {constraint}")?;
+        }
+        match &node.version {
+            Some(version) => {
+                write!(out, " ")?;
+                write_styled(out, &version_spec(), version)?;
+            }
+            None if node.cycle => write!(out, " (cycle)")?,
+            None => write!(out, " (not found)")?,
+        }
+        if node.installed {
+            write!(out, " [installed]")?;
+        }
+        if node.enabled {
+            write!(out, " ")?;
+            write_styled(out, &enabled_spec(), "[enabled]")?;
+        }
+        writeln!(out)
+    }
+
+    fn write_children(
+        out: &mut impl WriteColor,
+        node: &DependencyNode,
+        prefix: &str,
+    ) -> io::Result<()> {
+        let count = node.dependencies.len();
+        for (i, child) in node.dependencies.iter().enumerate() {
+            let last = i + 1 == count;
+            write!(out, "{prefix}{}", if last { "└── " } else { "├── " })?;
+            write_node(out, child)?;
+            let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            write_children(out, child, &prefix)?;
+        }
+        Ok(())
+    }
+
+    write_node(out, root)?;
+    write_children(out, root, "")
+}
+
+/// format seconds since the Unix epoch as a UTC date and time, like
+/// `2024-03-01 12:30`
+fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let minutes = time % 86400 / 60;
+
+    // civil-from-days, counting eras of 400 years from 0000-03-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// print journal entries one per line, oldest first
+pub fn print_history(out: &mut impl WriteColor, entries: &[Entry]) -> io::Result<()> {
+    for entry in entries {
+        let operation = &entry.operation;
+        write!(out, "{}  {:<7} ", format_time(entry.time), operation.verb())?;
+        write_styled(out, &name_spec(), operation.name())?;
+        for version in operation.versions() {
+            write!(out, " ")?;
+            write_styled(out, &version_spec(), version)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// a line of a diff
+#[derive(Debug, PartialEq, Eq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// the lines to remove from `before` and add to turn it into `after`, by
+/// their longest common subsequence
+fn diff_lines<'a>(before: &'a [String], after: &'a [String]) -> Vec<DiffLine<'a>> {
+    // common[i][j] is the length of the common subsequence of before[i..] and
+    // after[j..]
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = match before[i] == after[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(DiffLine::Same(&before[i]));
+            i += 1;
+            j += 1;
+        } else if i < before.len() && (j == after.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(&before[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(&after[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// print how a change rewrites the library of a settings file, as a unified
+/// diff of its entries
+pub fn print_library_diff(out: &mut impl WriteColor, change: &LibraryChange) -> io::Result<()> {
+    let mut removed_spec = ColorSpec::new();
+    removed_spec.set_fg(Some(Color::Red));
+    let mut added_spec = ColorSpec::new();
+    added_spec.set_fg(Some(Color::Green));
+
+    writeln!(out, "--- {}", change.settings.display())?;
+    writeln!(out, "+++ {}", change.settings.display())?;
+    writeln!(out, "@@ Lua.workspace.library @@")?;
+    for line in diff_lines(&change.before, &change.after) {
+        match line {
+            DiffLine::Same(entry) => writeln!(out, " {entry}")?,
+            DiffLine::Removed(entry) => {
+                write_styled(out, &removed_spec, &format!("-{entry}"))?;
+                writeln!(out)?;
+            }
+            DiffLine::Added(entry) => {
+                write_styled(out, &added_spec, &format!("+{entry}"))?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// print one line for each setting enabled addons disagree on
+pub fn print_conflicts(out: &mut impl WriteColor, conflicts: &[Conflict]) -> io::Result<()> {
+    let mut warning_spec = ColorSpec::new();
+    warning_spec.set_fg(Some(Color::Yellow)).set_bold(true);
+    for conflict in conflicts {
+        write_styled(out, &warning_spec, "conflict")?;
+        write!(out, ": '{}' is set by", conflict.key)?;
+        for (i, (name, value)) in conflict.values.iter().enumerate() {
+            write!(out, "{} ", if i == 0 { "" } else { "," })?;
+            write_styled(out, &name_spec(), name)?;
+            write!(out, " ({value})")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// a number of bytes in the largest unit that keeps it at least 1
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// print the license of each addon as aligned columns, marking `denied` ones,
+/// followed by how many addons use each license
+pub fn print_licenses(
+    out: &mut impl WriteColor,
+    licenses: &[AddonLicense],
+    denied: &[&AddonLicense],
+) -> io::Result<()> {
+    const UNKNOWN: &str = "unknown";
+    let headers = ["NAME", "VERSION", "LICENSE"];
+    let mut widths = headers.map(str::len);
+    for addon in licenses {
+        widths[0] = widths[0].max(addon.name.chars().count());
+        widths[1] = widths[1].max(addon.version.chars().count());
+    }
+    for (i, header) in headers.iter().enumerate() {
+        write_cell(out, &ColorSpec::new(), header, widths[i], i == 2)?;
+    }
+    writeln!(out)?;
+
+    let denied_spec = marker_spec(Color::Red);
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for addon in licenses {
+        let license = addon.license.as_deref().unwrap_or(UNKNOWN);
+        *counts.entry(license).or_default() += 1;
+        write_cell(out, &name_spec(), &addon.name, widths[0], false)?;
+        write_cell(out, &version_spec(), &addon.version, widths[1], false)?;
+        let spec = if denied.contains(&addon) {
+            denied_spec.clone()
+        } else {
+            ColorSpec::new()
+        };
+        write_styled(out, &spec, license)?;
+        writeln!(out)?;
+    }
+
+    let counts: Vec<String> = counts
+        .into_iter()
+        .map(|(license, count)| format!("{license} ({count})"))
+        .collect();
+    writeln!(out)?;
+    writeln!(out, "{} addons: {}", licenses.len(), counts.join(", "))
+}
+
+/// versions of the `--porcelain` format. Lines only change shape in a new
+/// version, so scripts that ask for one keep working
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Porcelain {
+    /// Tab-separated fields, as described in the README
+    V1,
+}
+
+/// print how much space each addon takes as aligned columns, then the total
+/// and, when there is one, the size of the cache
+pub fn print_disk_usage(
+    out: &mut impl WriteColor,
+    usages: &[AddonUsage],
+    cache: Option<(&Path, CacheSize)>,
+) -> io::Result<()> {
+    let headers = ["NAME", "VERSION", "FILES", "SIZE"];
+    let sizes: Vec<String> = usages
+        .iter()
+        .map(|usage| format_size(usage.size.bytes))
+        .collect();
+    let mut widths = headers.map(str::len);
+    for usage in usages {
+        widths[0] = widths[0].max(usage.name.chars().count());
+        widths[1] = widths[1].max(usage.version.chars().count());
+        widths[2] = widths[2].max(usage.size.files.to_string().len());
+    }
+    for (i, header) in headers.iter().enumerate() {
+        write_cell(out, &ColorSpec::new(), header, widths[i], i == 3)?;
+    }
+    writeln!(out)?;
+    for (usage, size) in usages.iter().zip(&sizes) {
+        write_cell(out, &name_spec(), &usage.name, widths[0], false)?;
+        write_cell(out, &version_spec(), &usage.version, widths[1], false)?;
+        let files = usage.size.files.to_string();
+        write_cell(out, &ColorSpec::new(), &files, widths[2], false)?;
+        writeln!(out, "{size}")?;
+    }
+
+    let total: u64 = usages.iter().map(|usage| usage.size.bytes).sum();
+    writeln!(out)?;
+    writeln!(out, "{} addons: {}", usages.len(), format_size(total))?;
+    if let Some((dir, size)) = cache {
+        writeln!(
+            out,
+            "cache: {} in '{}'",
+            format_size(size.bytes),
+            dir.display()
+        )?;
+    }
+    Ok(())
+}
+
+/// print whether each check passed, with what's wrong with the ones that
+/// didn't indented under them
+pub fn print_checks(out: &mut impl WriteColor, checks: &[Check]) -> io::Result<()> {
+    for check in checks {
+        match &check.problem {
+            None => write_styled(out, &marker_spec(Color::Green), "ok  ")?,
+            Some(_) => write_styled(out, &marker_spec(Color::Red), "FAIL")?,
+        }
+        writeln!(out, " {}", check.name)?;
+        if let Some(problem) = &check.problem {
+            for line in problem.lines() {
+                writeln!(out, "     {line}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// write `fields` as one porcelain line, separated by tabs. Tabs and line
+/// breaks inside a field would split it, so they become spaces
+pub fn write_fields(out: &mut impl io::Write, fields: &[&str]) -> io::Result<()> {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| field.replace(['\t', '\n', '\r'], " "))
+        .collect();
+    writeln!(out, "{}", fields.join("\t"))
+}
+
+/// porcelain lines ended by NUL bytes instead of line breaks, for `--print0`.
+/// Fields never hold line breaks, so each one ends a line
+pub struct NulTerminated<W>(pub W);
+
+impl<W: io::Write> io::Write for NulTerminated<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let ended: Vec<u8> = buf
+            .iter()
+            .map(|&byte| if byte == b'\n' { 0 } else { byte })
+            .collect();
+        self.0.write_all(&ended)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// `yes` or `no`, the way porcelain lines spell flags
+fn flag(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+/// print one `name version location summary` line per addon
+pub fn porcelain_addons(
+    out: &mut impl io::Write,
+    addons: &[Addon],
+    summaries: &Summaries,
+) -> io::Result<()> {
+    for addon in addons {
+        let location = addon
+            .location
+            .as_deref()
+            .map_or(Cow::from(""), Path::to_string_lossy);
+        let summary = summaries.get(&addon.name).map_or("", String::as_str);
+        write_fields(out, &[&addon.name, &addon.version, &location, summary])?;
+    }
+    Ok(())
+}
+
+/// the fields `list --format` fills in for addons
+pub const ADDON_FIELDS: [&str; 4] = ["name", "version", "location", "summary"];
+
+/// the fields `list --format` fills in for `list all` and `--outdated`
+pub const STATUS_FIELDS: [&str; 5] = ["name", "installed", "enabled", "latest", "summary"];
+
+/// print each addon following `template`, ended by `end`
+pub fn format_addons(
+    out: &mut impl io::Write,
+    addons: &[Addon],
+    summaries: &Summaries,
+    template: &Template,
+    end: char,
+) -> io::Result<()> {
+    for addon in addons {
+        let location = addon
+            .location
+            .as_deref()
+            .map_or(Cow::from(""), Path::to_string_lossy);
+        let line = template.render(|field| match field {
+            "name" => &addon.name,
+            "version" => &addon.version,
+            "location" => &location,
+            "summary" => summaries.get(&addon.name).map_or("", String::as_str),
+            _ => "",
+        });
+        write!(out, "{line}{end}")?;
+    }
+    Ok(())
+}
+
+/// print each addon following `template`, ended by `end`, with installed
+/// versions separated by commas
+pub fn format_statuses(
+    out: &mut impl io::Write,
+    statuses: &[AddonStatus],
+    summaries: &Summaries,
+    template: &Template,
+    end: char,
+) -> io::Result<()> {
+    for status in statuses {
+        let installed = status.installed.join(",");
+        let line = template.render(|field| match field {
+            "name" => &status.name,
+            "installed" => &installed,
+            "enabled" => status.enabled.as_deref().unwrap_or_default(),
+            "latest" => status.latest.as_deref().unwrap_or_default(),
+            "summary" => summaries.get(&status.name).map_or("", String::as_str),
+            _ => "",
+        });
+        write!(out, "{line}{end}")?;
+    }
+    Ok(())
+}
+
+/// print one `name installed enabled latest summary` line per addon, with
+/// installed versions separated by commas
+pub fn porcelain_statuses(
+    out: &mut impl io::Write,
+    statuses: &[AddonStatus],
+    summaries: &Summaries,
+) -> io::Result<()> {
+    for status in statuses {
+        write_fields(
+            out,
+            &[
+                &status.name,
+                &status.installed.join(","),
+                status.enabled.as_deref().unwrap_or_default(),
+                status.latest.as_deref().unwrap_or_default(),
+                summaries.get(&status.name).map_or("", String::as_str),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// print one `depth name constraint version installed enabled` line per
+/// addon in the tree, parents first. The version is empty when none was found
+pub fn porcelain_dependency_tree(
+    out: &mut impl io::Write,
+    root: &DependencyNode,
+) -> io::Result<()> {
+    fn write_node(out: &mut impl io::Write, node: &DependencyNode, depth: usize) -> io::Result<()> {
+        write_fields(
+            out,
+            &[
+                &depth.to_string(),
+                &node.name,
+                node.constraint.as_deref().unwrap_or_default(),
+                node.version.as_deref().unwrap_or_default(),
+                flag(node.installed),
+                flag(node.enabled),
+            ],
+        )?;
+        for child in &node.dependencies {
+            write_node(out, child, depth + 1)?;
+        }
+        Ok(())
+    }
+    write_node(out, root, 0)
+}
+
+/// print one `time operation name versions` line per journal entry, with
+/// the time in seconds since the Unix epoch and versions separated by commas
+pub fn porcelain_history(out: &mut impl io::Write, entries: &[Entry]) -> io::Result<()> {
+    for entry in entries {
+        let operation = &entry.operation;
+        write_fields(
+            out,
+            &[
+                &entry.time.to_string(),
+                operation.verb(),
+                operation.name(),
+                &operation.versions().join(","),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// print one `added entry` or `removed entry` line per library entry a
+/// change adds or removes
+pub fn porcelain_library_diff(out: &mut impl io::Write, change: &LibraryChange) -> io::Result<()> {
+    for line in diff_lines(&change.before, &change.after) {
+        match line {
+            DiffLine::Same(_) => {}
+            DiffLine::Removed(entry) => write_fields(out, &["removed", entry])?,
+            DiffLine::Added(entry) => write_fields(out, &["added", entry])?,
+        }
+    }
+    Ok(())
+}
+
+/// print one `conflict key name value` line per addon involved in each
+/// conflict, with the value as JSON
+pub fn porcelain_conflicts(out: &mut impl io::Write, conflicts: &[Conflict]) -> io::Result<()> {
+    for conflict in conflicts {
+        for (name, value) in &conflict.values {
+            write_fields(out, &["conflict", &conflict.key, name, &value.to_string()])?;
+        }
+    }
+    Ok(())
+}
+
+/// print one `name version license denied` line per addon. The license is
+/// empty when the rockspec doesn't name one
+pub fn porcelain_licenses(
+    out: &mut impl io::Write,
+    licenses: &[AddonLicense],
+    denied: &[&AddonLicense],
+) -> io::Result<()> {
+    for addon in licenses {
+        write_fields(
+            out,
+            &[
+                &addon.name,
+                &addon.version,
+                addon.license.as_deref().unwrap_or_default(),
+                flag(denied.contains(&addon)),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// print one `addon name version files bytes` line per addon, then a
+/// `cache files bytes directory` line when there is a cache
+pub fn porcelain_disk_usage(
+    out: &mut impl io::Write,
+    usages: &[AddonUsage],
+    cache: Option<(&Path, CacheSize)>,
+) -> io::Result<()> {
+    for usage in usages {
+        write_fields(
+            out,
+            &[
+                "addon",
+                &usage.name,
+                &usage.version,
+                &usage.size.files.to_string(),
+                &usage.size.bytes.to_string(),
+            ],
+        )?;
+    }
+    if let Some((dir, size)) = cache {
+        write_fields(
+            out,
+            &[
+                "cache",
+                &size.files.to_string(),
+                &size.bytes.to_string(),
+                &dir.to_string_lossy(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// print one `name ok` or `name fail problem` line per check
+pub fn porcelain_checks(out: &mut impl io::Write, checks: &[Check]) -> io::Result<()> {
+    for check in checks {
+        let problem = check.problem.as_deref().unwrap_or_default();
+        let result = if check.passed() { "ok" } else { "fail" };
+        write_fields(out, &[&check.name, result, problem])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+use crate::addon;
+
+#[cfg(test)]
+mod test_sort_addons {
+    use super::*;
+
+    fn sorted(key: SortKey, reverse: bool) -> Vec<String> {
+        let mut addons = vec![
+            addon("say", "1.10-1"),
+            addon("busted", "2.2.0-1"),
+            addon("say", "1.9-1"),
+            addon("lua-cjson", "2.1.0-1"),
+        ];
+        sort_addons(&mut addons, key, reverse);
+        addons
+            .iter()
+            .map(|addon| format!("{} {}", addon.name, addon.version))
+            .collect()
+    }
+
+    #[test]
+    fn by_name() {
+        assert_eq!(
+            sorted(SortKey::Name, false),
+            [
+                "busted 2.2.0-1",
+                "lua-cjson 2.1.0-1",
+                "say 1.9-1",
+                "say 1.10-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn by_version() {
+        assert_eq!(
+            sorted(SortKey::Version, false),
+            [
+                "busted 2.2.0-1",
+                "lua-cjson 2.1.0-1",
+                "say 1.9-1",
+                "say 1.10-1"
+            ]
+        );
+        assert_eq!(
+            sorted(SortKey::Version, true),
+            [
+                "say 1.10-1",
+                "say 1.9-1",
+                "lua-cjson 2.1.0-1",
+                "busted 2.2.0-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn recent_without_dates() {
+        assert_eq!(
+            sorted(SortKey::Recent, false),
+            sorted(SortKey::Version, false)
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_summaries {
+    use super::*;
+
+    #[test]
+    fn adds_labels() {
+        let descriptions = BTreeMap::from([
+            (
+                String::from("say"),
+                Description {
+                    summary: Some(String::from("hashing")),
+                    labels: vec![String::from("lls-addon"), String::from("i18n")],
+                    ..Description::default()
+                },
+            ),
+            (
+                String::from("busted"),
+                Description {
+                    labels: vec![String::from("testing")],
+                    ..Description::default()
+                },
+            ),
+        ]);
+        assert_eq!(
+            summaries(descriptions.clone(), true),
+            Summaries::from([
+                (String::from("busted"), String::from("[testing]")),
+                (
+                    String::from("say"),
+                    String::from("hashing [lls-addon, i18n]")
+                ),
+            ])
+        );
+        assert_eq!(
+            summaries(descriptions, false),
+            Summaries::from([(String::from("say"), String::from("hashing"))])
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_page {
+    use super::*;
+
+    #[test]
+    fn keeps_versions_together() {
+        let addons = vec![
+            addon("busted", "2.2.0-1"),
+            addon("lua-cjson", "2.1.0-1"),
+            addon("say", "1.4.1-3"),
+            addon("say", "1.3-1"),
+        ];
+        let names = |page: Option<u64>| -> Vec<String> {
+            self::page(addons.clone(), |addon| &addon.name, Some(2), page)
+                .iter()
+                .map(|addon| format!("{} {}", addon.name, addon.version))
+                .collect()
+        };
+        assert_eq!(names(None), ["busted 2.2.0-1", "lua-cjson 2.1.0-1"]);
+        assert_eq!(names(Some(2)), ["say 1.4.1-3", "say 1.3-1"]);
+        assert!(names(Some(3)).is_empty());
+        assert_eq!(
+            page(addons.clone(), |addon| &addon.name, None, None),
+            addons
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_nul_terminated {
+    use super::*;
+
+    #[test]
+    fn ends_lines_with_nul() {
+        let mut out = NulTerminated(vec![]);
+        let say = Addon {
+            location: Some(Path::new("my types/say").to_path_buf()),
+            ..addon("say", "1.4.1-3")
+        };
+        porcelain_addons(
+            &mut out,
+            &[say, addon("busted", "2.2.0-1")],
+            &Summaries::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            out.0,
+            b"say\t1.4.1-3\tmy types/say\t\0busted\t2.2.0-1\t\t\0"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_addons_list {
+    use super::*;
+    use termcolor::Buffer;
+
+    #[test]
+    fn groups_versions() {
+        let mut buffer = Buffer::no_color();
+        let mut addons = vec![
+            addon("say", "1.4.1-3"),
+            addon("busted", "2.2.0-1"),
+            addon("say", "1.3-1"),
+        ];
+        sort_addons(&mut addons, SortKey::Name, false);
+        print_addons_list(&mut buffer, addons, &Summaries::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "busted\n\t2.2.0-1\n\nsay\n\t1.3-1\n\t1.4.1-3\n"
+        );
+    }
+
+    #[test]
+    fn shows_summaries() {
+        let mut buffer = Buffer::no_color();
+        let summaries = Summaries::from([(String::from("say"), String::from("hashing"))]);
+        print_addons_list(&mut buffer, vec![addon("say", "1.4.1-3")], &summaries).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "say  hashing\n\t1.4.1-3\n"
+        );
+    }
+
+    #[test]
+    fn colors_names() {
+        let mut buffer = Buffer::ansi();
+        print_addons_list(
+            &mut buffer,
+            vec![addon("say", "1.4.1-3")],
+            &Summaries::new(),
+        )
+        .unwrap();
+        let printed = String::from_utf8(buffer.into_inner()).unwrap();
+        assert!(printed.contains("\x1b[0m\x1b[1m\x1b[36msay\x1b[0m"));
+    }
+}
+
+#[cfg(test)]
+mod test_print_addons_table {
+    use super::*;
+    use std::path::PathBuf;
+    use termcolor::Buffer;
+
+    fn installed(name: &str, version: &str, location: &str) -> Addon {
+        Addon {
+            location: Some(PathBuf::from(location)),
+            ..addon(name, version)
+        }
+    }
+
+    fn print(addons: Vec<Addon>, enabled: &[Addon], max_width: Option<usize>) -> String {
+        let mut buffer = Buffer::no_color();
+        print_addons_table(&mut buffer, addons, enabled, &Summaries::new(), max_width).unwrap();
+        String::from_utf8(buffer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn aligns_columns() {
+        let say = installed("say", "1.4.1-3", "tree/say/1.4.1-3/types");
+        let busted = installed("busted", "2.2.0-1", "tree/busted/2.2.0-1/types");
+        let printed = print(vec![busted, say.clone()], &[say], None);
+        assert_eq!(
+            printed,
+            "NAME    VERSION  ENABLED  LOCATION\n\
+             busted  2.2.0-1  no       tree/busted/2.2.0-1/types\n\
+             say     1.4.1-3  yes      tree/say/1.4.1-3/types\n"
+        );
+    }
+
+    #[test]
+    fn truncates_locations() {
+        let say = installed("say", "1.4.1-3", "tree/say/1.4.1-3/types");
+        let printed = print(vec![say], &[], Some(32));
+        assert_eq!(
+            printed,
+            "NAME  VERSION  ENABLED  LOCATION\n\
+             say   1.4.1-3  no       ...types\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_status_list {
+    use super::*;
+    use termcolor::Buffer;
+
+    #[test]
+    fn shows_markers() {
+        let statuses = [
+            AddonStatus {
+                name: String::from("busted"),
+                latest: Some(String::from("2.2.0-1")),
+                ..AddonStatus::default()
+            },
+            AddonStatus {
+                name: String::from("say"),
+                installed: vec![String::from("1.4.1-3")],
+                enabled: Some(String::from("1.4.1-3")),
+                latest: Some(String::from("1.10-1")),
+            },
+        ];
+        let mut buffer = Buffer::no_color();
+        print_status_list(&mut buffer, &statuses, &Summaries::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "busted  2.2.0-1  [online]\n\
+             say     1.4.1-3  [installed] [enabled] [update available: 1.10-1]\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_dependency_tree {
+    use super::*;
+    use termcolor::Buffer;
+
+    fn node(name: &str, dependencies: Vec<DependencyNode>) -> DependencyNode {
+        DependencyNode {
+            name: String::from(name),
+            version: Some(String::from("1.0-1")),
+            dependencies,
+            ..DependencyNode::default()
+        }
+    }
+
+    #[test]
+    fn draws_branches() {
+        let root = DependencyNode {
+            installed: true,
+            enabled: true,
+            ..node(
+                "framework",
+                vec![
+                    node("types", vec![node("runtime", vec![])]),
+                    DependencyNode {
+                        name: String::from("missing"),
+                        constraint: Some(String::from(">= 2")),
+                        ..DependencyNode::default()
+                    },
+                ],
+            )
+        };
+        let mut buffer = Buffer::no_color();
+        print_dependency_tree(&mut buffer, &root).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "framework 1.0-1 [installed] [enabled]\n\
+             ├── types 1.0-1\n\
+             │   └── runtime 1.0-1\n\
+             └── missing >= 2 (not found)\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_history {
+    use super::*;
+    use llynx_core::history::Operation;
+    use termcolor::Buffer;
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_time(1_709_296_200), "2024-03-01 12:30");
+    }
+
+    #[test]
+    fn one_line_per_entry() {
+        let entries = [
+            Entry {
+                time: 1_709_296_200,
+                operation: Operation::Install {
+                    name: String::from("say"),
+                    versions: vec![String::from("1.4.1-3")],
+                },
+                library: None,
+                links: vec![],
+            },
+            Entry {
+                time: 1_709_296_260,
+                operation: Operation::Enable {
+                    name: String::from("say"),
+                },
+                library: None,
+                links: vec![],
+            },
+        ];
+        let mut buffer = Buffer::no_color();
+        print_history(&mut buffer, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "2024-03-01 12:30  install say 1.4.1-3\n\
+             2024-03-01 12:31  enable  say\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_library_diff {
+    use super::*;
+    use std::path::PathBuf;
+    use termcolor::Buffer;
+
+    fn lines(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|entry| entry.to_string()).collect()
+    }
+
+    #[test]
+    fn marks_changed_entries() {
+        let change = LibraryChange {
+            settings: PathBuf::from(".vscode/settings.json"),
+            before: lines(&["busted", "say/1.3-1", "luassert"]),
+            after: lines(&["busted", "say/1.4.1-3", "luassert", "penlight"]),
+        };
+        let mut buffer = Buffer::no_color();
+        print_library_diff(&mut buffer, &change).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "--- .vscode/settings.json\n\
+             +++ .vscode/settings.json\n\
+             @@ Lua.workspace.library @@\n \
+             busted\n\
+             -say/1.3-1\n\
+             +say/1.4.1-3\n \
+             luassert\n\
+             +penlight\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_conflicts {
+    use super::*;
+    use serde_json::json;
+    use termcolor::Buffer;
+
+    #[test]
+    fn lists_every_value() {
+        let conflicts = [Conflict {
+            key: String::from("Lua.runtime.version"),
+            values: vec![
+                (String::from("love2d"), json!("LuaJIT")),
+                (String::from("playdate"), json!("Lua 5.4")),
+            ],
+        }];
+        let mut buffer = Buffer::no_color();
+        print_conflicts(&mut buffer, &conflicts).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "conflict: 'Lua.runtime.version' is set by love2d (\"LuaJIT\"), playdate (\"Lua 5.4\")\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_licenses {
+    use super::*;
+    use termcolor::Buffer;
+
+    fn license(name: &str, license: Option<&str>) -> AddonLicense {
+        AddonLicense {
+            name: String::from(name),
+            version: String::from("1.0-1"),
+            license: license.map(String::from),
+        }
+    }
+
+    #[test]
+    fn counts_licenses() {
+        let licenses = [
+            license("say", Some("MIT")),
+            license("busted", Some("MIT")),
+            license("types", None),
+        ];
+        let mut buffer = Buffer::no_color();
+        print_licenses(&mut buffer, &licenses, &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "NAME    VERSION  LICENSE\n\
+             say     1.0-1    MIT\n\
+             busted  1.0-1    MIT\n\
+             types   1.0-1    unknown\n\
+             \n\
+             3 addons: MIT (2), unknown (1)\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_disk_usage {
+    use super::*;
+    use termcolor::Buffer;
+
+    fn usage(name: &str, files: u64, bytes: u64) -> AddonUsage {
+        AddonUsage {
+            name: String::from(name),
+            version: String::from("1.0-1"),
+            size: CacheSize { files, bytes },
+        }
+    }
+
+    #[test]
+    fn totals_addons() {
+        let usages = [usage("busted", 120, 3 * 1024 * 1024), usage("say", 4, 2048)];
+        let cache = CacheSize {
+            files: 3,
+            bytes: 512,
+        };
+        let mut buffer = Buffer::no_color();
+        print_disk_usage(&mut buffer, &usages, Some((Path::new("cache"), cache))).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "NAME    VERSION  FILES  SIZE\n\
+             busted  1.0-1    120    3.0 MiB\n\
+             say     1.0-1    4      2.0 KiB\n\
+             \n\
+             2 addons: 3.0 MiB\n\
+             cache: 512 B in 'cache'\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_checks {
+    use super::*;
+    use termcolor::Buffer;
+
+    #[test]
+    fn indents_problems() {
+        let checks = [
+            Check {
+                name: String::from("rockspec parses"),
+                problem: None,
+            },
+            Check {
+                name: String::from("installs"),
+                problem: Some(String::from("LuaRocks failed:\nno such file")),
+            },
+        ];
+        let mut buffer = Buffer::no_color();
+        print_checks(&mut buffer, &checks).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "ok   rockspec parses\n\
+             FAIL installs\n     \
+             LuaRocks failed:\n     \
+             no such file\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_format_size {
+    use super::*;
+
+    #[test]
+    fn picks_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
+
+#[cfg(test)]
+mod test_porcelain {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = vec![];
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn separates_fields_with_tabs() {
+        assert_eq!(
+            written(|out| write_fields(out, &["say", "a\tsummary\nthat wraps", ""])),
+            "say\ta summary that wraps\t\n"
+        );
+    }
+
+    #[test]
+    fn addons() {
+        let addons = [
+            Addon {
+                location: Some(PathBuf::from("tree/say")),
+                ..addon("say", "1.4.1-3")
+            },
+            addon("busted", "2.2.0-1"),
+        ];
+        let summaries =
+            Summaries::from([(String::from("say"), String::from("Lua string hashing"))]);
+        assert_eq!(
+            written(|out| porcelain_addons(out, &addons, &summaries)),
+            "say\t1.4.1-3\ttree/say\tLua string hashing\n\
+             busted\t2.2.0-1\t\t\n"
+        );
+    }
+
+    #[test]
+    fn dependency_depths() {
+        let root = DependencyNode {
+            name: String::from("framework"),
+            version: Some(String::from("1.0-1")),
+            installed: true,
+            dependencies: vec![DependencyNode {
+                name: String::from("missing"),
+                constraint: Some(String::from(">= 2")),
+                ..DependencyNode::default()
+            }],
+            ..DependencyNode::default()
+        };
+        assert_eq!(
+            written(|out| porcelain_dependency_tree(out, &root)),
+            "0\tframework\t\t1.0-1\tyes\tno\n\
+             1\tmissing\t>= 2\t\tno\tno\n"
+        );
+    }
+
+    #[test]
+    fn only_changed_entries() {
+        let change = LibraryChange {
+            settings: PathBuf::from(".vscode/settings.json"),
+            before: vec![String::from("busted"), String::from("say/1.3-1")],
+            after: vec![String::from("busted"), String::from("say/1.4.1-3")],
+        };
+        assert_eq!(
+            written(|out| porcelain_library_diff(out, &change)),
+            "removed\tsay/1.3-1\nadded\tsay/1.4.1-3\n"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::output::ColorWhen;
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Child, Command, Stdio},
+};
+use termcolor::{Ansi, ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
+
+/// stdout, or the input of the pager showing it
+pub struct Paged {
+    out: Box<dyn WriteColor>,
+    pager: Option<Child>,
+}
+
+/// the pager to use: `$PAGER`, or else `less` outside of Windows. An empty
+/// `$PAGER` or `cat` turns paging off
+fn pager_command() -> Option<String> {
+    match env::var("PAGER") {
+        Ok(pager) if pager.is_empty() || pager == "cat" => None,
+        Ok(pager) => Some(pager),
+        Err(_) if cfg!(windows) => None,
+        Err(_) => Some(String::from("less")),
+    }
+}
+
+/// run `command` through the shell, since `$PAGER` may hold arguments too
+fn spawn(command: &str) -> io::Result<Child> {
+    let mut shell = match cfg!(windows) {
+        true => Command::new("cmd"),
+        false => Command::new("sh"),
+    };
+    shell.arg(if cfg!(windows) { "/C" } else { "-c" });
+    // like git, let less quit when everything fits on one screen and keep
+    // colors, unless it's configured already
+    if env::var_os("LESS").is_none() {
+        shell.env("LESS", "FRX");
+    }
+    shell.arg(command).stdin(Stdio::piped()).spawn()
+}
+
+/// write to the pager when `page` is set and stdout is a terminal, or to
+/// stdout directly otherwise
+pub fn stdout(color: ColorWhen, page: bool) -> Paged {
+    let is_terminal = io::stdout().is_terminal();
+    let choice = color.choice(is_terminal);
+    let direct = || Paged {
+        out: Box::new(StandardStream::stdout(choice)),
+        pager: None,
+    };
+    let command = match pager_command() {
+        Some(command) if page && is_terminal => command,
+        _ => return direct(),
+    };
+    match spawn(&command) {
+        Ok(mut pager) => {
+            let input = pager.stdin.take().expect("the pager's stdin is piped");
+            let out: Box<dyn WriteColor> = match choice {
+                ColorChoice::Never => Box::new(NoColor::new(input)),
+                _ => Box::new(Ansi::new(input)),
+            };
+            Paged {
+                out,
+                pager: Some(pager),
+            }
+        }
+        Err(err) => {
+            log::debug!("could not start the pager '{command}': {err}");
+            direct()
+        }
+    }
+}
+
+/// quitting the pager before reading everything isn't an error
+fn closed_is_ok<T>(result: io::Result<T>, closed: T) -> io::Result<T> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(closed),
+        result => result,
+    }
+}
+
+impl Write for Paged {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        closed_is_ok(self.out.write(buf), buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        closed_is_ok(self.out.flush(), ())
+    }
+}
+
+impl WriteColor for Paged {
+    fn supports_color(&self) -> bool {
+        self.out.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        closed_is_ok(self.out.set_color(spec), ())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        closed_is_ok(self.out.reset(), ())
+    }
+}
+
+impl Drop for Paged {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+        if let Some(mut pager) = self.pager.take() {
+            // closing its input tells the pager everything was written, then
+            // it's left to the user until they quit it
+            self.out = Box::new(NoColor::new(io::sink()));
+            let _ = pager.wait();
+        }
+    }
+}
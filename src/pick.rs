@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use dialoguer::{Confirm, FuzzySelect, theme::ColorfulTheme};
+use llynx_core::{Addon, version};
+use std::{cmp::Ordering, collections::BTreeMap};
+
+/// the newest version of each addon, by name
+fn newest_versions(addons: &[Addon]) -> Vec<(&str, &str)> {
+    let mut newest: BTreeMap<&str, &str> = BTreeMap::new();
+    for addon in addons {
+        let entry = newest.entry(&addon.name).or_insert(&addon.version);
+        if version::compare(&addon.version, entry) == Ordering::Greater {
+            *entry = &addon.version;
+        }
+    }
+    newest.into_iter().collect()
+}
+
+/// ask for one of `addons` with a fuzzy-searchable picker on stderr. Returns
+/// `None` when the picker is cancelled
+pub fn pick_addon(addons: &[Addon]) -> Result<Option<String>> {
+    let choices = newest_versions(addons);
+    let items: Vec<String> = choices
+        .iter()
+        .map(|(name, version)| format!("{name} ({version})"))
+        .collect();
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Addon to install")
+        .items(&items)
+        .interact_opt()
+        .context("while picking an addon")?;
+    Ok(selection.map(|i| choices[i].0.to_string()))
+}
+
+/// ask a yes-or-no question on stderr, defaulting to no
+pub fn confirm(prompt: &str) -> Result<bool> {
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(false)
+        .interact()
+        .context("while asking for confirmation")
+}
+
+#[cfg(test)]
+mod test_newest_versions {
+    use super::*;
+    use crate::addon;
+
+    #[test]
+    fn one_per_name() {
+        let addons = [
+            addon("say", "1.9-1"),
+            addon("busted", "2.2.0-1"),
+            addon("say", "1.10-1"),
+        ];
+        assert_eq!(
+            newest_versions(&addons),
+            vec![("busted", "2.2.0-1"), ("say", "1.10-1")]
+        );
+    }
+}
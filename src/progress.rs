@@ -0,0 +1,60 @@
+use crate::events::{self, Event};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    borrow::Cow,
+    io::{self, IsTerminal},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// whether spinners are hidden for the rest of the process
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// never draw spinners from now on
+pub fn hide() {
+    HIDDEN.store(true, Ordering::Relaxed);
+}
+
+/// prints above a spinner, which is cleared while it prints and drawn again
+/// afterwards
+#[derive(Clone)]
+pub struct Printer(ProgressBar);
+
+impl Printer {
+    pub fn print<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.0.suspend(f)
+    }
+}
+
+/// show a spinner with `phase` and the elapsed time on stderr while `f` runs.
+/// Nothing is drawn when stdout isn't a terminal or spinners are hidden.
+/// Events mark when `phase` starts and finishes
+pub fn spin<T>(phase: impl Into<Cow<'static, str>>, f: impl FnOnce() -> T) -> T {
+    announce(phase, |_| f())
+}
+
+/// run `f` like [`spin`], for work that prints as it goes. What it prints
+/// through the [`Printer`] it's given goes above the spinner
+pub fn announce<T>(phase: impl Into<Cow<'static, str>>, f: impl FnOnce(Printer) -> T) -> T {
+    let phase = phase.into();
+    events::emit(Event::Started { phase: &phase });
+    let started = Instant::now();
+    let spinner = ProgressBar::new_spinner();
+    if HIDDEN.load(Ordering::Relaxed) || !io::stdout().is_terminal() {
+        spinner.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    spinner.set_style(
+        ProgressStyle::with_template("{spinner} {msg} [{elapsed}]")
+            .expect("spinner template is valid"),
+    );
+    spinner.set_message(phase.clone());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+
+    let result = f(Printer(spinner.clone()));
+    spinner.finish_and_clear();
+    events::emit(Event::Finished {
+        phase: &phase,
+        elapsed_ms: started.elapsed().as_millis(),
+    });
+    result
+}
@@ -0,0 +1,363 @@
+//! `llynx serve`: a JSON-RPC 2.0 server over stdio for editor integrations.
+//!
+//! Every message is a single line of JSON. Requests are answered in the order
+//! they arrive, and long operations send `progress` notifications tagged with
+//! the id of the request they belong to.
+
+use crate::ListSource;
+use llynx_core::{
+    Config,
+    catalog::{self, CatalogAddon},
+    disable, enable,
+    filter::Filter,
+    fuzzy, list_enabled, list_installed, list_online, remove, signature, status, switch,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    io::{self, BufRead, Write},
+    process::Output,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const OPERATION_FAILED: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        RpcError::new(OPERATION_FAILED, format!("{err:#}"))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListParams {
+    source: Option<ListSource>,
+    filter: Option<String>,
+    regex: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddonParams {
+    name: String,
+    version: Option<String>,
+}
+
+/// writes messages to stdout, one per line
+struct Connection<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Connection<W> {
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &message)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+
+    fn respond(&mut self, id: Value, response: Result<Value, RpcError>) -> io::Result<()> {
+        self.send(match response {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        })
+    }
+
+    fn progress(&mut self, id: &Value, state: &str, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "progress",
+            "params": { "id": id, "state": state, "message": message },
+        }))
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// report LuaRocks' captured output, failing if it exited unsuccessfully
+fn luarocks_result(output: Output) -> Result<Value, RpcError> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(RpcError::new(OPERATION_FAILED, stderr.trim()));
+    }
+    Ok(json!({ "stdout": stdout, "stderr": stderr }))
+}
+
+fn handle<W: Write>(
+    connection: &mut Connection<W>,
+    config: &Config,
+    id: &Value,
+    method: &str,
+    params: Value,
+) -> Result<Value, RpcError> {
+    let mut progress = |state: &str, message: &str| {
+        connection
+            .progress(id, state, message)
+            .map_err(|err| RpcError::new(OPERATION_FAILED, err.to_string()))
+    };
+
+    match method {
+        "list" => {
+            // no params lists everything installed
+            let ListParams {
+                source,
+                filter,
+                regex,
+            } = match params {
+                Value::Null => ListParams::default(),
+                params => parse_params(params)?,
+            };
+            let invalid = |err: anyhow::Error| RpcError::new(INVALID_PARAMS, format!("{err:#}"));
+            // plain filters match fuzzily and rank the results, like the CLI
+            let (filter, pattern) = match (regex, filter) {
+                (Some(regex), _) => (Some(Filter::regex(&regex).map_err(invalid)?), None),
+                (None, Some(filter)) => match Filter::parse(&filter).map_err(invalid)? {
+                    Filter::Contains(pattern) => (None, Some(pattern)),
+                    filter => (Some(filter), None),
+                },
+                (None, None) => (None, None),
+            };
+            let filter = filter.as_ref();
+            let addons = match source.unwrap_or(ListSource::Installed) {
+                ListSource::All => {
+                    progress("begin", "gathering addons")?;
+                    let statuses = status::list_all(config, filter);
+                    progress("end", "gathered addons")?;
+                    let mut statuses = statuses?;
+                    if let Some(pattern) = &pattern {
+                        statuses = fuzzy::rank(statuses, pattern, |status| &status.name);
+                    }
+                    let statuses = statuses
+                        .into_iter()
+                        .map(|status| {
+                            let update_available = status.update_available();
+                            let mut value = json!(status);
+                            value["update_available"] = json!(update_available);
+                            value
+                        })
+                        .collect::<Vec<_>>();
+                    return Ok(json!(statuses));
+                }
+                ListSource::Enabled => list_enabled(config, filter),
+                ListSource::Installed => list_installed(config, filter),
+                ListSource::Online => {
+                    progress("begin", "searching the server")?;
+                    let addons = list_online(config, filter);
+                    progress("end", "searched the server")?;
+                    addons
+                }
+                ListSource::Catalog => {
+                    progress("begin", "updating the catalog")?;
+                    let catalog = catalog::update(config).and_then(|_| catalog::list(config));
+                    progress("end", "updated the catalog")?;
+                    catalog.map(|catalog| {
+                        catalog
+                            .iter()
+                            .filter(|addon| filter.is_none_or(|f| f.matches(&addon.name)))
+                            .map(CatalogAddon::to_addon)
+                            .collect()
+                    })
+                }
+            }?;
+            let addons = match &pattern {
+                Some(pattern) => fuzzy::rank(addons, pattern, |addon| &addon.name),
+                None => addons,
+            };
+            Ok(json!(addons))
+        }
+        "install" | "remove" => {
+            let AddonParams { name, version } = parse_params(params)?;
+            let version = version.as_deref();
+            let (begin, end) = match method {
+                "install" => ("installing", "installed"),
+                _ => ("removing", "removed"),
+            };
+            progress("begin", &format!("{begin} '{name}'"))?;
+            let result = match method {
+                "install" => signature::install(config, &name, version),
+                _ => remove(config, &name, version),
+            };
+            progress("end", &format!("{end} '{name}'"))?;
+            luarocks_result(result?)
+        }
+        "enable" => {
+            let AddonParams { name, .. } = parse_params(params)?;
+            enable(config, &name)?;
+            Ok(Value::Null)
+        }
+        "switch" => {
+            let AddonParams { name, version } = parse_params(params)?;
+            let version = version.ok_or_else(|| {
+                RpcError::new(INVALID_PARAMS, String::from("missing field `version`"))
+            })?;
+            switch(config, &name, &version)?;
+            Ok(Value::Null)
+        }
+        "disable" => {
+            let AddonParams { name, .. } = parse_params(params)?;
+            disable(config, &name)?;
+            Ok(Value::Null)
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("method '{method}' not found"),
+        )),
+    }
+}
+
+/// answer requests from stdin until it closes or an `exit` notification arrives
+pub fn serve(config: &Config) -> anyhow::Result<()> {
+    let stdin = io::stdin();
+    let mut connection = Connection { out: io::stdout() };
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                let code = match serde_json::from_str::<Value>(&line) {
+                    Ok(_) => INVALID_REQUEST,
+                    Err(_) => PARSE_ERROR,
+                };
+                connection.respond(Value::Null, Err(RpcError::new(code, err.to_string())))?;
+                continue;
+            }
+        };
+
+        if request.jsonrpc != "2.0" {
+            let id = request.id.unwrap_or(Value::Null);
+            let error = RpcError::new(INVALID_REQUEST, "only JSON-RPC 2.0 is supported");
+            connection.respond(id, Err(error))?;
+            continue;
+        }
+
+        if request.method == "exit" {
+            break;
+        }
+
+        let id = request.id.unwrap_or(Value::Null);
+        let response = handle(
+            &mut connection,
+            config,
+            &id,
+            &request.method,
+            request.params,
+        );
+        if !id.is_null() {
+            connection.respond(id, response)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_serve {
+    use super::*;
+    use llynx_core::backend::{self, Mock};
+    use std::{path::Path, sync::Arc};
+
+    fn call(method: &str, params: Value) -> (Vec<Value>, Result<Value, RpcError>) {
+        let mut connection = Connection { out: Vec::new() };
+        let config = Config {
+            settings: Path::new("tests/settings/one_addon_linux.json"),
+            tree: Path::new("tests/trees/one_addon"),
+            ..Config::default()
+        };
+        let result = handle(&mut connection, &config, &json!(1), method, params);
+        let messages = String::from_utf8(connection.out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (messages, result)
+    }
+
+    #[test]
+    fn unknown_method() {
+        let (_, result) = call("frobnicate", Value::Null);
+        assert_eq!(result.unwrap_err().code, METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn invalid_params() {
+        let (_, result) = call("enable", json!({ "nam": "say" }));
+        assert_eq!(result.unwrap_err().code, INVALID_PARAMS);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_enabled() {
+        let (messages, result) = call("list", json!({ "source": "enabled" }));
+        assert_eq!(messages, vec![] as Vec<Value>);
+        let addons = result.unwrap();
+        assert_eq!(addons[0]["name"], "say");
+        assert_eq!(addons[0]["version"], "1.4.1-3");
+    }
+
+    #[test]
+    fn list_without_params() {
+        let say = llynx_core::Addon {
+            name: String::from("say"),
+            version: String::from("1.4.1-3"),
+            location: None,
+        };
+        let mock = Mock::new(vec![say], vec![]);
+        let (_, result) = backend::with_backend(Arc::new(mock), || call("list", Value::Null));
+        assert_eq!(result.unwrap()[0]["name"], "say");
+    }
+
+    #[test]
+    fn list_matches_fuzzily() {
+        let addon = |name: &str| llynx_core::Addon {
+            name: String::from(name),
+            version: String::from("1.0-1"),
+            location: None,
+        };
+        let mock = Mock::new(vec![addon("busted"), addon("say"), addon("bustd")], vec![]);
+        let (_, result) = backend::with_backend(Arc::new(mock), || {
+            call("list", json!({ "filter": "bustd" }))
+        });
+        let addons = result.unwrap();
+        let names: Vec<&str> = addons
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|addon| addon["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["bustd", "busted"]);
+    }
+}
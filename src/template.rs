@@ -0,0 +1,120 @@
+use anyhow::{Result, bail};
+use std::str::FromStr;
+
+/// a template for `list --format`, like `{name}\t{version}`. Fields are
+/// written in braces, `\t`, `\n` and `\\` are escapes, and `{{` and `}}`
+/// stand for braces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Piece>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(String),
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('\\') => text.push('\\'),
+                    Some(other) => text.extend(['\\', other]),
+                    None => text.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => bail!("'{{{field}' is never closed in '{s}'"),
+                        }
+                    }
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(field.trim().to_string()));
+                }
+                '}' => bail!("'}}' is never opened in '{s}', write '}}}}' for a brace"),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template(pieces))
+    }
+}
+
+impl Template {
+    /// fail if the template uses a field other than `fields`
+    pub fn check(&self, fields: &[&str]) -> Result<()> {
+        for piece in &self.0 {
+            if let Piece::Field(field) = piece
+                && !fields.contains(&field.as_str())
+            {
+                bail!(
+                    "there's no field '{field}' to format here, only {}",
+                    fields.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// whether the template uses `field`
+    pub fn uses(&self, field: &str) -> bool {
+        self.0.contains(&Piece::Field(field.to_string()))
+    }
+
+    /// the template with each field replaced by `value` of it
+    pub fn render<'a>(&self, value: impl Fn(&str) -> &'a str) -> String {
+        self.0
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.as_str(),
+                Piece::Field(field) => value(field),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::*;
+
+    fn render(template: &str) -> Result<String> {
+        let template: Template = template.parse()?;
+        template.check(&["name", "version"])?;
+        Ok(template.render(|field| match field {
+            "name" => "say",
+            _ => "1.4.1-3",
+        }))
+    }
+
+    #[test]
+    fn fills_in_fields() {
+        assert_eq!(render(r"{name}\t{version}").unwrap(), "say\t1.4.1-3");
+        assert_eq!(render("{{{name}}} { version }").unwrap(), "{say} 1.4.1-3");
+        assert_eq!(render(r"\\n\x").unwrap(), r"\n\x");
+        render("{name").unwrap_err();
+        render("name}").unwrap_err();
+        render("{location}").unwrap_err();
+    }
+}